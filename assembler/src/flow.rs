@@ -0,0 +1,252 @@
+use std::collections::BTreeSet;
+
+use crate::instruction::{Comp, Instruction, Jump};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    Static(usize),
+    Dynamic,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Exit {
+    Next(usize),
+    Goto(Target),
+    Branch {
+        condition: Jump,
+        target: Target,
+        otherwise: usize,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: usize,
+    pub end: usize,
+    pub exit: Exit,
+}
+
+impl BasicBlock {
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn successors(&self) -> Vec<usize> {
+        match self.exit {
+            Exit::Next(next) => vec![next],
+            Exit::Goto(Target::Static(target)) => vec![target],
+            Exit::Goto(Target::Dynamic) => vec![],
+            Exit::Branch {
+                target: Target::Static(target),
+                otherwise,
+                ..
+            } => vec![target, otherwise],
+            Exit::Branch {
+                target: Target::Dynamic,
+                otherwise,
+                ..
+            } => vec![otherwise],
+        }
+    }
+}
+
+pub struct ControlFlowGraph {
+    blocks: Vec<BasicBlock>,
+}
+
+impl ControlFlowGraph {
+    pub fn new(instructions: &[Instruction], labels: &[usize]) -> Self {
+        let len = instructions.len();
+        let mut leaders = BTreeSet::new();
+        leaders.insert(0);
+        leaders.extend(labels.iter().copied().filter(|&label| label < len));
+        instructions
+            .iter()
+            .enumerate()
+            .filter(|(_, instruction)| is_jump(instruction))
+            .for_each(|(address, _)| {
+                leaders.insert(address + 1);
+            });
+
+        let targets = Self::split(instructions, &leaders)
+            .iter()
+            .filter_map(|block| match block.exit {
+                Exit::Goto(Target::Static(target))
+                | Exit::Branch {
+                    target: Target::Static(target),
+                    ..
+                } => Some(target),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        leaders.extend(targets);
+
+        Self {
+            blocks: Self::split(instructions, &leaders),
+        }
+    }
+
+    fn split(instructions: &[Instruction], leaders: &BTreeSet<usize>) -> Vec<BasicBlock> {
+        let len = instructions.len();
+        let mut blocks = Vec::new();
+        let mut starts = leaders.iter().copied().filter(|&leader| leader < len);
+        let mut start = match starts.next() {
+            Some(start) => start,
+            None => return blocks,
+        };
+        for next in starts.chain(std::iter::once(len)) {
+            let mut a = None;
+            let mut end = start;
+            let mut exit = Exit::Next(next);
+            while end < next {
+                let instruction = &instructions[end];
+                end += 1;
+                if let Instruction::C {
+                    jump: Some(condition),
+                    ..
+                } = instruction
+                {
+                    let target = match a {
+                        Some(a) if (a as usize) < len => Target::Static(a as usize),
+                        _ => Target::Dynamic,
+                    };
+                    exit = if *condition == Jump::JMP {
+                        Exit::Goto(target)
+                    } else {
+                        Exit::Branch {
+                            condition: condition.clone(),
+                            target,
+                            otherwise: end,
+                        }
+                    };
+                    break;
+                }
+                a = next_a(instruction, a);
+            }
+            blocks.push(BasicBlock { start, end, exit });
+            start = next;
+        }
+        blocks
+    }
+
+    pub fn blocks(&self) -> &[BasicBlock] {
+        &self.blocks
+    }
+
+    pub fn block_at(&self, address: usize) -> Option<&BasicBlock> {
+        self.blocks
+            .binary_search_by_key(&address, |block| block.start)
+            .ok()
+            .map(|index| &self.blocks[index])
+    }
+
+    pub fn predecessors(&self, address: usize) -> Vec<usize> {
+        self.blocks
+            .iter()
+            .filter(|block| block.successors().contains(&address))
+            .map(|block| block.start)
+            .collect()
+    }
+}
+
+fn is_jump(instruction: &Instruction) -> bool {
+    matches!(instruction, Instruction::C { jump: Some(_), .. })
+}
+
+fn next_a(instruction: &Instruction, a: Option<u16>) -> Option<u16> {
+    match instruction {
+        Instruction::A { value } => Some(*value),
+        Instruction::C {
+            comp,
+            dest: Some(dest),
+            ..
+        } if dest.contains_a() => match comp {
+            Comp::Zero => Some(0),
+            Comp::One => Some(1),
+            Comp::MinusOne => Some(0xFFFF),
+            Comp::A => a,
+            Comp::NotA => a.map(|a| !a),
+            Comp::MinusA => a.map(|a| a.wrapping_neg()),
+            Comp::APlusOne => a.map(|a| a.wrapping_add(1)),
+            Comp::AMinusOne => a.map(|a| a.wrapping_sub(1)),
+            _ => None,
+        },
+        _ => a,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::parse, symbol::SymbolTable};
+
+    fn assemble(lines: &[&str]) -> (Vec<Instruction>, Vec<usize>) {
+        let mut symbols = SymbolTable::new();
+        let instructions = parse(lines, &mut symbols).unwrap();
+        let instructions = symbols.resolve_symbols(&instructions);
+        let labels = symbols
+            .labels()
+            .iter()
+            .map(|&(_, address)| address as usize)
+            .collect();
+        (instructions, labels)
+    }
+
+    #[test]
+    fn straight_line_code_is_a_single_block() {
+        let (instructions, labels) = assemble(&["@2", "D=A", "@3", "D=D+A", "@0", "M=D"]);
+        let graph = ControlFlowGraph::new(&instructions, &labels);
+        assert_eq!(
+            graph.blocks(),
+            [BasicBlock {
+                start: 0,
+                end: 6,
+                exit: Exit::Next(6)
+            }]
+        );
+    }
+
+    #[test]
+    fn jumps_split_blocks_at_their_targets() {
+        let (instructions, labels) = assemble(&[
+            "@0", "D=M", "@END", "D;JLE", "@1", "M=D", "(END)", "@END", "0;JMP",
+        ]);
+        let graph = ControlFlowGraph::new(&instructions, &labels);
+        assert_eq!(
+            graph.blocks(),
+            [
+                BasicBlock {
+                    start: 0,
+                    end: 4,
+                    exit: Exit::Branch {
+                        condition: Jump::JLE,
+                        target: Target::Static(6),
+                        otherwise: 4
+                    }
+                },
+                BasicBlock {
+                    start: 4,
+                    end: 6,
+                    exit: Exit::Next(6)
+                },
+                BasicBlock {
+                    start: 6,
+                    end: 8,
+                    exit: Exit::Goto(Target::Static(6))
+                },
+            ]
+        );
+        assert_eq!(graph.predecessors(6), [0, 4, 6]);
+    }
+
+    #[test]
+    fn jumps_through_memory_are_dynamic() {
+        let (instructions, labels) = assemble(&["@R14", "A=M", "0;JMP"]);
+        let graph = ControlFlowGraph::new(&instructions, &labels);
+        assert_eq!(graph.blocks()[0].exit, Exit::Goto(Target::Dynamic));
+    }
+}
@@ -0,0 +1,73 @@
+use thiserror::Error;
+
+use crate::instruction::Instruction;
+
+#[derive(Debug, Error)]
+pub enum HackError {
+    #[error("Line {0}: \"{1}\" is not a 16-bit binary word")]
+    InvalidWord(usize, String),
+
+    #[error("Line {0}: \"{1}\" is not a valid instruction")]
+    UnknownInstruction(usize, String),
+}
+pub type Result<T> = std::result::Result<T, HackError>;
+
+pub fn read<S: AsRef<str>>(lines: &[S]) -> Result<Vec<Instruction>> {
+    lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.as_ref().trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(line_number, line)| {
+            if line.len() != 16 {
+                return Err(HackError::InvalidWord(line_number, line.to_string()));
+            }
+            let raw = u16::from_str_radix(line, 2)
+                .map_err(|_| HackError::InvalidWord(line_number, line.to_string()))?;
+            Instruction::from_raw(raw)
+                .ok_or_else(|| HackError::UnknownInstruction(line_number, line.to_string()))
+        })
+        .collect()
+}
+
+pub fn write(instructions: &[Instruction]) -> String {
+    instructions
+        .iter()
+        .map(|instruction| {
+            instruction
+                .as_binary()
+                .iter()
+                .map(|b| if *b { "1" } else { "0" })
+                .collect::<String>()
+                + "\n"
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::{Comp, Dest};
+
+    #[test]
+    fn read_restores_written_instructions() {
+        let instructions = vec![
+            Instruction::A { value: 17 },
+            Instruction::C {
+                comp: Comp::DPlusM,
+                dest: Some(Dest::D),
+                jump: None,
+            },
+        ];
+        let binary = write(&instructions);
+        assert_eq!(binary, "0000000000010001\n1111000010010000\n");
+        let lines = binary.lines().collect::<Vec<_>>();
+        assert_eq!(read(&lines).unwrap(), instructions);
+    }
+
+    #[test]
+    fn read_denies_words_which_are_not_binary() {
+        assert!(read(&["0000000000010002"]).is_err());
+        assert!(read(&["101"]).is_err());
+    }
+}
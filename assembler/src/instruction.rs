@@ -1,3 +1,5 @@
+use std::fmt;
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Comp {
     Zero,
@@ -52,7 +54,164 @@ pub enum Jump {
     JMP,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl Comp {
+    const ALL: [Comp; 28] = [
+        Comp::Zero,
+        Comp::One,
+        Comp::MinusOne,
+        Comp::D,
+        Comp::A,
+        Comp::M,
+        Comp::NotD,
+        Comp::NotA,
+        Comp::NotM,
+        Comp::MinusD,
+        Comp::MinusA,
+        Comp::MinusM,
+        Comp::DPlusOne,
+        Comp::APlusOne,
+        Comp::MPlusOne,
+        Comp::DMinusOne,
+        Comp::AMinusOne,
+        Comp::MMinusOne,
+        Comp::DPlusA,
+        Comp::DPlusM,
+        Comp::DMinusA,
+        Comp::AMinusD,
+        Comp::DMinusM,
+        Comp::MMinusD,
+        Comp::DAndA,
+        Comp::DAndM,
+        Comp::DOrA,
+        Comp::DOrM,
+    ];
+
+    pub fn uses_a(&self) -> bool {
+        matches!(
+            self,
+            Comp::A
+                | Comp::NotA
+                | Comp::MinusA
+                | Comp::APlusOne
+                | Comp::AMinusOne
+                | Comp::DPlusA
+                | Comp::DMinusA
+                | Comp::AMinusD
+                | Comp::DAndA
+                | Comp::DOrA
+        )
+    }
+
+    pub fn uses_d(&self) -> bool {
+        matches!(
+            self,
+            Comp::D
+                | Comp::NotD
+                | Comp::MinusD
+                | Comp::DPlusOne
+                | Comp::DMinusOne
+                | Comp::DPlusA
+                | Comp::DPlusM
+                | Comp::DMinusA
+                | Comp::AMinusD
+                | Comp::DMinusM
+                | Comp::MMinusD
+                | Comp::DAndA
+                | Comp::DAndM
+                | Comp::DOrA
+                | Comp::DOrM
+        )
+    }
+
+    pub fn uses_m(&self) -> bool {
+        matches!(
+            self,
+            Comp::M
+                | Comp::NotM
+                | Comp::MinusM
+                | Comp::MPlusOne
+                | Comp::MMinusOne
+                | Comp::DPlusM
+                | Comp::DMinusM
+                | Comp::MMinusD
+                | Comp::DAndM
+                | Comp::DOrM
+        )
+    }
+}
+
+impl fmt::Display for Comp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let comp = match self {
+            Comp::Zero => "0",
+            Comp::One => "1",
+            Comp::MinusOne => "-1",
+            Comp::D => "D",
+            Comp::A => "A",
+            Comp::M => "M",
+            Comp::NotD => "!D",
+            Comp::NotA => "!A",
+            Comp::NotM => "!M",
+            Comp::MinusD => "-D",
+            Comp::MinusA => "-A",
+            Comp::MinusM => "-M",
+            Comp::DPlusOne => "D+1",
+            Comp::APlusOne => "A+1",
+            Comp::MPlusOne => "M+1",
+            Comp::DMinusOne => "D-1",
+            Comp::AMinusOne => "A-1",
+            Comp::MMinusOne => "M-1",
+            Comp::DPlusA => "D+A",
+            Comp::DPlusM => "D+M",
+            Comp::DMinusA => "D-A",
+            Comp::AMinusD => "A-D",
+            Comp::DMinusM => "D-M",
+            Comp::MMinusD => "M-D",
+            Comp::DAndA => "D&A",
+            Comp::DAndM => "D&M",
+            Comp::DOrA => "D|A",
+            Comp::DOrM => "D|M",
+        };
+        write!(f, "{}", comp)
+    }
+}
+
+impl Dest {
+    pub fn contains_a(&self) -> bool {
+        matches!(self, Dest::A | Dest::AM | Dest::AD | Dest::ADM)
+    }
+
+    pub fn contains_d(&self) -> bool {
+        matches!(self, Dest::D | Dest::DM | Dest::AD | Dest::ADM)
+    }
+
+    pub fn contains_m(&self) -> bool {
+        matches!(self, Dest::M | Dest::DM | Dest::AM | Dest::ADM)
+    }
+}
+
+impl fmt::Display for Dest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dest = match self {
+            Dest::M => "M",
+            Dest::D => "D",
+            Dest::DM => "DM",
+            Dest::A => "A",
+            Dest::AM => "AM",
+            Dest::AD => "AD",
+            Dest::ADM => "ADM",
+        };
+        write!(f, "{}", dest)
+    }
+}
+
+impl fmt::Display for Jump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Instruction {
     A {
         value: u16,
@@ -65,6 +224,62 @@ pub enum Instruction {
 }
 
 impl Instruction {
+    pub fn from_binary(binary: &[bool; 16]) -> Option<Self> {
+        if !binary[0] {
+            let value = binary.iter().fold(0, |acc, b| (acc << 1) | *b as u16);
+            return Some(Self::A { value });
+        }
+        // Like the CPU, ignore the two bits after the C-instruction marker.
+        let comp = Comp::ALL.iter().find(|comp| {
+            Self::C {
+                comp: (*comp).clone(),
+                dest: None,
+                jump: None,
+            }
+            .as_binary()[3..10]
+                == binary[3..10]
+        })?;
+        let dest = match (binary[10], binary[11], binary[12]) {
+            (false, false, false) => None,
+            (false, false, true) => Some(Dest::M),
+            (false, true, false) => Some(Dest::D),
+            (false, true, true) => Some(Dest::DM),
+            (true, false, false) => Some(Dest::A),
+            (true, false, true) => Some(Dest::AM),
+            (true, true, false) => Some(Dest::AD),
+            (true, true, true) => Some(Dest::ADM),
+        };
+        let jump = match (binary[13], binary[14], binary[15]) {
+            (false, false, false) => None,
+            (false, false, true) => Some(Jump::JGT),
+            (false, true, false) => Some(Jump::JEQ),
+            (false, true, true) => Some(Jump::JGE),
+            (true, false, false) => Some(Jump::JLT),
+            (true, false, true) => Some(Jump::JNE),
+            (true, true, false) => Some(Jump::JLE),
+            (true, true, true) => Some(Jump::JMP),
+        };
+        Some(Self::C {
+            comp: comp.clone(),
+            dest,
+            jump,
+        })
+    }
+
+    pub fn from_raw(raw: u16) -> Option<Self> {
+        let mut binary = [false; 16];
+        for (i, bit) in binary.iter_mut().enumerate() {
+            *bit = (raw >> (15 - i)) & 1 == 1;
+        }
+        Self::from_binary(&binary)
+    }
+
+    pub fn as_raw(&self) -> u16 {
+        self.as_binary()
+            .iter()
+            .fold(0, |acc, b| (acc << 1) | *b as u16)
+    }
+
     pub fn as_binary(&self) -> [bool; 16] {
         match self {
             Self::A { value } => {
@@ -326,3 +541,115 @@ impl Instruction {
         }
     }
 }
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::A { value } => write!(f, "@{}", value),
+            Self::C { comp, dest, jump } => {
+                if let Some(dest) = dest {
+                    write!(f, "{}=", dest)?;
+                }
+                write!(f, "{}", comp)?;
+                if let Some(jump) = jump {
+                    write!(f, ";{}", jump)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_binary_decodes_what_as_binary_encodes() {
+        let instructions = [
+            Instruction::A { value: 0 },
+            Instruction::A { value: 0x7FFF },
+            Instruction::C {
+                comp: Comp::MMinusOne,
+                dest: Some(Dest::AM),
+                jump: None,
+            },
+            Instruction::C {
+                comp: Comp::D,
+                dest: None,
+                jump: Some(Jump::JGT),
+            },
+            Instruction::C {
+                comp: Comp::DOrA,
+                dest: Some(Dest::ADM),
+                jump: Some(Jump::JMP),
+            },
+        ];
+        instructions.iter().for_each(|instruction| {
+            assert_eq!(
+                Instruction::from_binary(&instruction.as_binary()).as_ref(),
+                Some(instruction)
+            );
+            assert_eq!(
+                Instruction::from_raw(instruction.as_raw()).as_ref(),
+                Some(instruction)
+            );
+        });
+        Comp::ALL.iter().for_each(|comp| {
+            let instruction = Instruction::C {
+                comp: comp.clone(),
+                dest: None,
+                jump: None,
+            };
+            assert_eq!(
+                Instruction::from_binary(&instruction.as_binary()),
+                Some(instruction)
+            );
+        });
+    }
+
+    #[test]
+    fn from_binary_denies_unknown_comp() {
+        assert_eq!(Instruction::from_raw(0b1110_1000_0000_0000), None);
+    }
+
+    #[test]
+    fn from_binary_ignores_the_unused_c_instruction_bits() {
+        let instruction = Instruction::C {
+            comp: Comp::DPlusOne,
+            dest: Some(Dest::M),
+            jump: Some(Jump::JEQ),
+        };
+        assert_eq!(
+            Instruction::from_raw(0b1000_0111_1100_1010),
+            Some(instruction.clone())
+        );
+        assert_eq!(
+            Instruction::from_raw(0b1010_0111_1100_1010),
+            Some(instruction)
+        );
+    }
+
+    #[test]
+    fn instructions_are_displayed_in_assembly_syntax() {
+        assert_eq!(Instruction::A { value: 21 }.to_string(), "@21");
+        assert_eq!(
+            Instruction::C {
+                comp: Comp::MPlusOne,
+                dest: Some(Dest::AM),
+                jump: None,
+            }
+            .to_string(),
+            "AM=M+1"
+        );
+        assert_eq!(
+            Instruction::C {
+                comp: Comp::Zero,
+                dest: None,
+                jump: Some(Jump::JMP),
+            }
+            .to_string(),
+            "0;JMP"
+        );
+    }
+}
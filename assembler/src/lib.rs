@@ -0,0 +1,6 @@
+pub mod flow;
+pub mod hack;
+pub mod instruction;
+pub mod parser;
+pub mod program;
+pub mod symbol;
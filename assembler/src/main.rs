@@ -6,11 +6,7 @@ use std::{
 
 use clap::{app_from_crate, crate_authors, crate_description, crate_name, crate_version, Arg};
 
-mod instruction;
-mod parser;
-use parser::parse;
-mod symbol;
-use symbol::SymbolTable;
+use assembler::{hack, parser::parse, symbol::SymbolTable};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = app_from_crate!()
@@ -19,6 +15,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .help("The assembly file")
                 .required(true),
         )
+        .arg(
+            Arg::with_name("symbols")
+                .long("symbols")
+                .short("s")
+                .help("Also write the labels and variables into a .sym file"),
+        )
         .get_matches();
     let file_name = Path::new(args.value_of("file").unwrap());
     let reader = BufReader::new(File::open(file_name)?);
@@ -26,20 +28,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut symbols = SymbolTable::new();
     let symbol_instructions = parse(&lines, &mut symbols)?;
     let instructions = symbols.resolve_symbols(&symbol_instructions);
-    let binary = instructions
-        .iter()
-        .map(|instruction| {
-            instruction
-                .as_binary()
-                .iter()
-                .map(|b| if *b { "1" } else { "0" })
-                .collect::<String>()
-                + "\n"
-        })
-        .collect::<String>();
-    let file_name = file_name.with_extension("hack");
-    let mut file = BufWriter::new(File::create(file_name)?);
+    let binary = hack::write(&instructions);
+    let mut file = BufWriter::new(File::create(file_name.with_extension("hack"))?);
     file.write_all(binary.as_bytes())?;
+    if args.is_present("symbols") {
+        let mut file = BufWriter::new(File::create(file_name.with_extension("sym"))?);
+        file.write_all(symbols.symbol_file().to_string().as_bytes())?;
+    }
 
     Ok(())
 }
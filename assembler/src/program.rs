@@ -0,0 +1,160 @@
+use std::fmt;
+
+use crate::{
+    instruction::{Comp, Dest, Instruction, Jump},
+    symbol::{SymbolInstruction, SymbolTable},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Line {
+    Instruction(SymbolInstruction),
+    Label(String),
+    Comment(String),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Program {
+    lines: Vec<Line>,
+}
+
+impl Program {
+    pub fn new() -> Self {
+        Self { lines: vec![] }
+    }
+
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines
+            .iter()
+            .filter(|line| matches!(line, Line::Instruction(_)))
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn push(&mut self, instruction: SymbolInstruction) {
+        self.lines.push(Line::Instruction(instruction));
+    }
+
+    pub fn a(&mut self, symbol: &str) {
+        self.push(SymbolInstruction::ASymbol {
+            symbol: symbol.to_string(),
+        });
+    }
+
+    pub fn a_value(&mut self, value: u16) {
+        self.push(SymbolInstruction::AImmediate { value });
+    }
+
+    pub fn c(&mut self, dest: Option<Dest>, comp: Comp, jump: Option<Jump>) {
+        self.push(SymbolInstruction::C { comp, dest, jump });
+    }
+
+    pub fn label(&mut self, name: &str) {
+        self.lines.push(Line::Label(name.to_string()));
+    }
+
+    pub fn comment(&mut self, text: &str) {
+        self.lines.push(Line::Comment(text.to_string()));
+    }
+
+    pub fn extend(&mut self, other: Program) {
+        self.lines.extend(other.lines);
+    }
+
+    pub fn symbols(&self) -> SymbolTable {
+        let mut symbols = SymbolTable::new();
+        let mut address = 0;
+        for line in &self.lines {
+            match line {
+                Line::Instruction(instruction) => {
+                    if let SymbolInstruction::ASymbol { symbol } = instruction {
+                        symbols.insert_variable(symbol);
+                    }
+                    address += 1;
+                }
+                Line::Label(label) => symbols.insert_label(label, address),
+                Line::Comment(_) => {}
+            }
+        }
+        symbols
+    }
+
+    pub fn instructions(&self) -> Vec<SymbolInstruction> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Instruction(instruction) => Some(instruction.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn assemble(&self) -> (Vec<Instruction>, SymbolTable) {
+        let symbols = self.symbols();
+        (symbols.resolve_symbols(&self.instructions()), symbols)
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Instruction(instruction) => writeln!(f, "    {}", instruction)?,
+                Line::Label(label) => writeln!(f, "({})", label)?,
+                Line::Comment(text) => writeln!(f, "// {}", text)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn countdown() -> Program {
+        let mut program = Program::new();
+        program.comment("count down from 3");
+        program.a_value(3);
+        program.c(Some(Dest::D), Comp::A, None);
+        program.label("LOOP");
+        program.a("counter");
+        program.c(Some(Dest::M), Comp::D, None);
+        program.c(Some(Dest::D), Comp::DMinusOne, None);
+        program.a("LOOP");
+        program.c(None, Comp::D, Some(Jump::JGT));
+        program
+    }
+
+    #[test]
+    fn program_assembles_like_its_text() {
+        let program = countdown();
+        let text = program.to_string();
+        let lines = text.lines().collect::<Vec<_>>();
+        let mut symbols = SymbolTable::new();
+        let parsed = parse(&lines, &mut symbols).unwrap();
+        let (instructions, _) = program.assemble();
+        assert_eq!(instructions, symbols.resolve_symbols(&parsed));
+        assert_eq!(program.len(), 7);
+    }
+
+    #[test]
+    fn forward_labels_are_not_variables() {
+        let mut program = Program::new();
+        program.a("END");
+        program.c(None, Comp::Zero, Some(Jump::JMP));
+        program.a("x");
+        program.label("END");
+        let (instructions, symbols) = program.assemble();
+        assert_eq!(instructions[0], Instruction::A { value: 3 });
+        assert_eq!(instructions[2], Instruction::A { value: 16 });
+        assert_eq!(symbols.variables(), [("x", 16)]);
+    }
+}
@@ -1,8 +1,13 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use thiserror::Error;
 
 use crate::instruction::{Comp, Dest, Instruction, Jump};
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum SymbolInstruction {
     AImmediate {
         value: u16,
@@ -17,8 +22,28 @@ pub enum SymbolInstruction {
     },
 }
 
+impl fmt::Display for SymbolInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolInstruction::AImmediate { value } => write!(f, "@{}", value),
+            SymbolInstruction::ASymbol { symbol } => write!(f, "@{}", symbol),
+            SymbolInstruction::C { comp, dest, jump } => write!(
+                f,
+                "{}",
+                Instruction::C {
+                    comp: comp.clone(),
+                    dest: dest.clone(),
+                    jump: jump.clone(),
+                }
+            ),
+        }
+    }
+}
+
 pub struct SymbolTable {
     table: HashMap<String, u16>,
+    labels: HashSet<String>,
+    variables: Vec<String>,
     next_address: u16,
 }
 
@@ -50,6 +75,8 @@ impl SymbolTable {
         table.insert("KBD".to_string(), 0x6000);
         Self {
             table,
+            labels: HashSet::new(),
+            variables: Vec::new(),
             next_address: 0x0010,
         }
     }
@@ -57,12 +84,59 @@ impl SymbolTable {
     pub fn insert_variable(&mut self, name: &str) {
         if !self.table.contains_key(name) {
             self.table.insert(name.to_string(), self.next_address);
+            self.variables.push(name.to_string());
             self.next_address += 1;
         }
     }
 
     pub fn insert_label(&mut self, name: &str, value: u16) {
-        *self.table.entry(name.to_string()).or_insert(value) = value;
+        // A reference ahead of the label took it for a variable, whose
+        // address goes back to the variables after it.
+        if let Some(index) = self.variables.iter().position(|variable| variable == name) {
+            self.variables.remove(index);
+            for variable in &self.variables[index..] {
+                *self.table.get_mut(variable).unwrap() -= 1;
+            }
+            self.next_address -= 1;
+        }
+        self.table.insert(name.to_string(), value);
+        self.labels.insert(name.to_string());
+    }
+
+    pub fn get(&self, name: &str) -> Option<u16> {
+        self.table.get(name).copied()
+    }
+
+    pub fn labels(&self) -> Vec<(&str, u16)> {
+        let mut labels = self
+            .labels
+            .iter()
+            .map(|label| (label.as_str(), self.table[label]))
+            .collect::<Vec<_>>();
+        labels.sort_by_key(|&(label, address)| (address, label));
+        labels
+    }
+
+    pub fn variables(&self) -> Vec<(&str, u16)> {
+        self.variables
+            .iter()
+            .map(|variable| (variable.as_str(), self.table[variable]))
+            .collect()
+    }
+
+    pub fn symbol_file(&self) -> SymbolFile {
+        SymbolFile {
+            labels: self
+                .labels()
+                .iter()
+                .map(|&(label, address)| (label.to_string(), address))
+                .collect(),
+            variables: self
+                .variables()
+                .iter()
+                .map(|&(variable, address)| (variable.to_string(), address))
+                .collect(),
+        }
     }
 
     pub fn resolve_symbols(&self, instructions: &[SymbolInstruction]) -> Vec<Instruction> {
@@ -82,3 +156,122 @@ impl SymbolTable {
             .collect()
     }
 }
+
+#[derive(Debug, Error)]
+pub enum SymbolFileError {
+    #[error("Line {0}: Syntax error: {1}")]
+    InvalidSyntax(usize, String),
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SymbolFile {
+    pub labels: Vec<(String, u16)>,
+    pub variables: Vec<(String, u16)>,
+}
+
+impl SymbolFile {
+    pub fn parse<S: AsRef<str>>(lines: &[S]) -> Result<Self, SymbolFileError> {
+        let mut symbols = Self::default();
+        for (i, line) in lines.iter().enumerate() {
+            let line = line.as_ref().split("//").next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = || SymbolFileError::InvalidSyntax(i + 1, line.to_string());
+            let mut words = line.split_whitespace();
+            let (kind, address, name) = match (words.next(), words.next(), words.next()) {
+                (Some(kind), Some(address), Some(name)) if words.next().is_none() => {
+                    (kind, address, name)
+                }
+                _ => return Err(error()),
+            };
+            let address = address.parse().map_err(|_| error())?;
+            match kind {
+                "ROM" => symbols.labels.push((name.to_string(), address)),
+                "RAM" => symbols.variables.push((name.to_string(), address)),
+                _ => return Err(error()),
+            }
+        }
+        Ok(symbols)
+    }
+
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.labels
+            .iter()
+            .find(|&&(_, label)| label == address)
+            .map(|(name, _)| name.as_str())
+    }
+
+    pub fn variable(&self, address: u16) -> Option<&str> {
+        self.variables
+            .iter()
+            .find(|&&(_, variable)| variable == address)
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+impl fmt::Display for SymbolFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, address) in &self.labels {
+            writeln!(f, "ROM {} {}", address, name)?;
+        }
+        for (name, address) in &self.variables {
+            writeln!(f, "RAM {} {}", address, name)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    #[test]
+    fn symbol_file_lists_labels_and_variables_but_not_predefined_symbols() {
+        let lines = ["@i", "(LOOP)", "@END", "0;JMP", "@SP", "(END)", "@j"];
+        let mut symbols = SymbolTable::new();
+        parse(&lines, &mut symbols).unwrap();
+        let file = symbols.symbol_file();
+        assert_eq!(
+            file.labels,
+            [("LOOP".to_string(), 1), ("END".to_string(), 4)]
+        );
+        assert_eq!(
+            file.variables,
+            [("i".to_string(), 16), ("j".to_string(), 17)]
+        );
+        assert_eq!(
+            file.to_string(),
+            "ROM 1 LOOP\nROM 4 END\nRAM 16 i\nRAM 17 j\n"
+        );
+    }
+
+    #[test]
+    fn forward_references_to_labels_take_no_variable_address() {
+        let lines = ["@END", "0;JMP", "(END)", "@x", "M=1"];
+        let mut symbols = SymbolTable::new();
+        let instructions = parse(&lines, &mut symbols).unwrap();
+        assert_eq!(symbols.get("END"), Some(2));
+        assert_eq!(symbols.variables(), [("x", 16)]);
+        assert_eq!(
+            symbols.resolve_symbols(&instructions)[2],
+            Instruction::A { value: 16 }
+        );
+    }
+
+    #[test]
+    fn symbol_file_parses_what_it_prints() {
+        let file = SymbolFile {
+            labels: vec![("Main.main".to_string(), 12)],
+            variables: vec![("Main.0".to_string(), 16)],
+        };
+        let printed = file.to_string();
+        let lines = printed.lines().collect::<Vec<_>>();
+        let parsed = SymbolFile::parse(&lines).unwrap();
+        assert_eq!(parsed, file);
+        assert_eq!(parsed.label(12), Some("Main.main"));
+        assert_eq!(parsed.variable(16), Some("Main.0"));
+        assert!(SymbolFile::parse(&["ROM twelve Main.main"]).is_err());
+    }
+}
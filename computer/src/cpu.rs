@@ -7,8 +7,18 @@ use crate::{
     signal::{Signal, Word},
 };
 
+pub(crate) fn address_bits(a: Word) -> [bool; 15] {
+    let a = a.split();
+    [
+        a[1], a[2], a[3], a[4], a[5], a[6], a[7], a[8], a[9], a[10], a[11], a[12], a[13], a[14],
+        a[15],
+    ]
+}
+
 pub struct Cpu {
     address: [bool; 15],
+    // A as it was when the result was computed, which is where M is written.
+    write_address: [bool; 15],
     write_to_memory: bool,
     result: Word,
     a: Register<Word>,
@@ -20,6 +30,7 @@ impl Cpu {
     pub fn new() -> Self {
         Self {
             address: [false; 15],
+            write_address: [false; 15],
             write_to_memory: false,
             result: Word::zero(),
             a: Register::new(),
@@ -59,6 +70,7 @@ impl Cpu {
         );
         self.result = result;
         let a = self.a.get_output();
+        self.write_address = address_bits(a);
         self.pc.tick(
             reset,
             or(
@@ -80,11 +92,16 @@ impl Cpu {
         self.a
             .tick(write_to_a, mux(a_data, self.result, instruction[0]));
         self.d.tick(write_to_d, self.result);
-        let a = self.a.get_output().split();
-        self.address = [
-            a[1], a[2], a[3], a[4], a[5], a[6], a[7], a[8], a[9], a[10], a[11], a[12], a[13],
-            a[14], a[15],
-        ];
+        self.address = address_bits(self.a.get_output());
+    }
+
+    // Drops the write to M of the last instruction, once it's been made.
+    pub(crate) fn clear_write(&mut self) {
+        self.write_to_memory = false;
+    }
+
+    pub fn write_address(&self) -> [bool; 15] {
+        self.write_address
     }
 
     pub fn a(&self) -> Word {
@@ -198,4 +215,24 @@ mod tests {
             ])
         );
     }
+
+    #[test]
+    fn am_writes_m_where_a_pointed_before() {
+        let mut cpu = Cpu::new();
+        cpu.tick(true, Word::zero(), Word::zero()); // Reset
+        cpu.tick(false, Word::zero(), Word::from(7)); // A = 7
+        cpu.tick(
+            false,
+            Word::from(41),
+            Word::from([
+                true, true, true, true, true, true, false, true, true, true, true, false, true,
+                false, false, false,
+            ]),
+        ); // AM = M[A] + 1
+        let ((address, write_to_memory, result), _) = cpu.get_output();
+        assert!(write_to_memory);
+        assert_eq!(result, Word::from(42));
+        assert_eq!(cpu.write_address(), address_bits(Word::from(7)));
+        assert_eq!(address, address_bits(Word::from(42)));
+    }
 }
@@ -1,6 +1,6 @@
 pub mod chip;
 pub mod cpu;
-use cpu::Cpu;
+use cpu::{address_bits, Cpu};
 pub mod keyboard;
 use keyboard::Keyboard;
 pub mod memory;
@@ -10,7 +10,7 @@ use rom::Rom;
 pub mod screen;
 use screen::Screen;
 pub mod signal;
-use signal::Word;
+use signal::{Signal, Word};
 
 pub struct Computer<S: Screen, K: Keyboard> {
     rom: Rom,
@@ -29,7 +29,13 @@ impl<S: Screen, K: Keyboard> Computer<S, K> {
 
     pub fn tick(&mut self, reset: bool) {
         let ((address, write_to_memory, cpu_output), pc) = self.cpu.get_output();
-        self.memory.tick(&address, write_to_memory, cpu_output);
+        // Instructions like AM=M+1 write M where A pointed before they ran,
+        // while the next instruction reads M where A points now.
+        if write_to_memory {
+            self.memory
+                .tick(&self.cpu.write_address(), true, cpu_output);
+        }
+        self.memory.tick(&address, false, cpu_output);
         let memory_data = self.memory.get_output();
         let pc = pc.split();
         let pc = [
@@ -65,7 +71,96 @@ impl<S: Screen, K: Keyboard> Computer<S, K> {
         self.cpu.pc()
     }
 
+    // Reads RAM without disturbing what the CPU sees as M. The last
+    // instruction's write to M only reaches RAM on the next tick, so it's
+    // read from the CPU.
+    pub fn peek(&mut self, address: u16) -> u16 {
+        let ((current, write_to_memory, result), _) = self.cpu.get_output();
+        if write_to_memory && self.cpu.write_address() == address_bits(Word::from(address)) {
+            return result.as_raw();
+        }
+        self.memory
+            .tick(&address_bits(Word::from(address)), false, Word::zero());
+        let value = self.memory.get_output().as_raw();
+        self.memory.tick(&current, false, Word::zero());
+        value
+    }
+
+    // Writes RAM between instructions, leaving M where the CPU reads it. The
+    // last instruction's write to M is made first so it can't land on top.
+    pub fn poke(&mut self, address: u16, value: u16) {
+        let ((current, write_to_memory, result), _) = self.cpu.get_output();
+        if write_to_memory {
+            self.memory.tick(&self.cpu.write_address(), true, result);
+            self.cpu.clear_write();
+        }
+        self.memory
+            .tick(&address_bits(Word::from(address)), true, Word::from(value));
+        self.memory.tick(&current, false, Word::zero());
+    }
+
     pub fn screen(&self) -> &S {
         self.memory.screen()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use keyboard::DummyKeyboard;
+    use screen::DummyScreen;
+
+    #[test]
+    fn am_writes_m_where_a_pointed_before() {
+        // @7, AM=M+1, @7, D=M
+        let mut computer = Computer::<DummyScreen, DummyKeyboard>::new();
+        computer.set_rom(Rom::from_words(&[
+            7,
+            0b1111_1101_1110_1000,
+            7,
+            0b1111_1100_0001_0000,
+        ]));
+        computer.tick(true);
+        for _ in 0..4 {
+            computer.tick(false);
+        }
+        assert_eq!(computer.d(), Word::from(1));
+        assert_eq!(computer.peek(1), 0);
+    }
+
+    #[test]
+    fn peek_sees_the_write_of_the_last_instruction() {
+        // @5, M=-1
+        let mut computer = Computer::<DummyScreen, DummyKeyboard>::new();
+        computer.set_rom(Rom::from_words(&[5, 0b1110_1110_1000_1000]));
+        computer.tick(true);
+        computer.tick(false);
+        computer.tick(false);
+        assert_eq!(computer.peek(5), 0xFFFF);
+        assert_eq!(computer.peek(6), 0);
+        computer.tick(false);
+        assert_eq!(computer.peek(5), 0xFFFF);
+    }
+
+    #[test]
+    fn poke_writes_ram_the_program_reads() {
+        // @3, D=M
+        let mut computer = Computer::<DummyScreen, DummyKeyboard>::new();
+        computer.set_rom(Rom::from_words(&[3, 0b1111_1100_0001_0000]));
+        computer.tick(true);
+        computer.poke(3, 42);
+        assert_eq!(computer.peek(3), 42);
+        computer.tick(false);
+        computer.tick(false);
+        assert_eq!(computer.d(), Word::from(42));
+
+        // @5, M=-1
+        computer.set_rom(Rom::from_words(&[5, 0b1110_1110_1000_1000]));
+        computer.tick(true);
+        computer.tick(false);
+        computer.tick(false);
+        computer.poke(5, 7);
+        computer.tick(false);
+        assert_eq!(computer.peek(5), 7);
+    }
+}
@@ -35,6 +35,14 @@ impl Rom {
         })
     }
 
+    pub fn from_words(words: &[u16]) -> Self {
+        let mut rom = Self::new();
+        for (word, &value) in rom.data.iter_mut().zip(words) {
+            *word = Word::from(value);
+        }
+        rom
+    }
+
     pub fn set_address(&mut self, address: &[bool; 15]) {
         self.address = (address[0] as usize) << 14
             | (address[1] as usize) << 13
@@ -1,10 +1,24 @@
-use std::io::Write;
+use std::{fs, io::Write, path::Path};
 
 use clap::{app_from_crate, crate_authors, crate_description, crate_name, crate_version, Arg};
 
+use assembler::symbol::SymbolFile;
 use computer::{
     keyboard::DummyKeyboard as Keyboard, rom::Rom, screen::DummyScreen as Screen, Computer,
 };
+use jack::{
+    debug::{DebugInfo, Location},
+    heap::{self, Heap},
+};
+use vm::source_map::SourceMap;
+
+// Bounds `step` when the program never leaves the current VM command.
+const MAX_STEP_TICKS: usize = 1_000_000;
+
+// Bounds `continue` when the program never reaches a breakpoint.
+const MAX_RUN_TICKS: usize = 100_000_000;
+
+const SP: u16 = 0;
 
 fn print_help() {
     println!(
@@ -12,11 +26,51 @@ fn print_help() {
     help: Show this help
     show: Show the status
     next: Next step
+    step: Run until the next VM command (needs a source map)
+    into: Run until the next Jack line, into calls (needs debugging information)
+    over: Run until the next Jack line, over calls (needs debugging information)
+    break <file>:<line>: Stop at the Jack line when continuing
+    delete <file>:<line>: Remove the breakpoint
+    continue: Run until a breakpoint
+    print [<name>]: Print a variable of the current subroutine, or all of them
+    heap [<address>]: Walk the OS heap, with the free list kept at the address or found in the symbols
     load: Load the ROM file
     exit: Exit"#
     );
 }
 
+fn read<T, E: ToString>(path: &str, parse: impl Fn(&str) -> Result<T, E>) -> Result<T, String> {
+    fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| parse(&text).map_err(|e| e.to_string()))
+}
+
+// Whether a breakpoint's file names the file of a location, which may be
+// given with its directory or without.
+fn same_file(breakpoint: &str, file: &str) -> bool {
+    breakpoint == file || Path::new(file).file_name() == Some(breakpoint.as_ref())
+}
+
+// Prints the Jack line the computer is at, if any, along with its text when
+// the source file can be read.
+fn print_location(location: Option<Location>, function: Option<&str>) {
+    let location = match location {
+        Some(location) => location,
+        None => return,
+    };
+    match function {
+        Some(function) => println!("Jack: {} ({})", location, function),
+        None => println!("Jack: {}", location),
+    }
+    let text = fs::read_to_string(location.file).ok();
+    if let Some(line) = text
+        .as_deref()
+        .and_then(|text| text.lines().nth(location.line - 1))
+    {
+        println!("{:>5} {}", location.line, line.trim_end());
+    }
+}
+
 fn main() {
     let args = app_from_crate!()
         .arg(
@@ -26,6 +80,28 @@ fn main() {
                 .takes_value(true)
                 .help("Path to a ROM file"),
         )
+        .arg(
+            Arg::with_name("map")
+                .long("map")
+                .short("m")
+                .takes_value(true)
+                .help("Path to a source map written by the VM translator"),
+        )
+        .arg(
+            Arg::with_name("debug-info")
+                .long("debug-info")
+                .short("g")
+                .takes_value(true)
+                .requires("map")
+                .help("Path to the debugging information written by the Jack compiler"),
+        )
+        .arg(
+            Arg::with_name("symbols")
+                .long("symbols")
+                .short("s")
+                .takes_value(true)
+                .help("Path to a symbol file written by the VM translator"),
+        )
         .get_matches();
 
     let rom = args
@@ -38,6 +114,41 @@ fn main() {
             }
         })
         .unwrap_or(Rom::new());
+    let source_map =
+        args.value_of("map")
+            .and_then(|path| match read(path, |text| text.parse::<SourceMap>()) {
+                Ok(map) => Some(map),
+                Err(e) => {
+                    eprintln!("Couldn't read the source map (error: {})", e);
+                    None
+                }
+            });
+    let debug_info = args.value_of("debug-info").and_then(|path| {
+        match read(path, |text| text.parse::<DebugInfo>()) {
+            Ok(info) => Some(info),
+            Err(e) => {
+                eprintln!("Couldn't read the debugging information (error: {})", e);
+                None
+            }
+        }
+    });
+    let symbols = args.value_of("symbols").and_then(|path| {
+        let lines = |text: &str| SymbolFile::parse(&text.lines().collect::<Vec<_>>());
+        match read(path, lines) {
+            Ok(symbols) => Some(symbols),
+            Err(e) => {
+                eprintln!("Couldn't read the symbol file (error: {})", e);
+                None
+            }
+        }
+    });
+    // The Jack line and the subroutine of a ROM address.
+    let jack = |pc: u16| {
+        let source = source_map.as_ref()?.get(pc)?;
+        let location = debug_info.as_ref()?.locate(source)?;
+        Some((location, source.function.as_deref()))
+    };
+    let mut breakpoints: Vec<(String, usize)> = vec![];
 
     let mut computer = Computer::<Screen, Keyboard>::new();
     computer.set_rom(rom);
@@ -49,7 +160,10 @@ fn main() {
         print!(" PC = {} > ", computer.pc().as_raw());
         std::io::stdout().flush().unwrap();
         std::io::stdin().read_line(&mut line).unwrap();
-        match line.trim() {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let argument = words.next();
+        match command {
             "help" => print_help(),
             "show" => {
                 println!(
@@ -58,10 +172,168 @@ fn main() {
                     computer.d().as_raw(),
                     computer.m().as_raw(),
                 );
+                if let Some(source) = source_map
+                    .as_ref()
+                    .and_then(|map| map.get(computer.pc().as_raw()))
+                {
+                    println!("VM: {}", source);
+                }
+                if let Some((location, function)) = jack(computer.pc().as_raw()) {
+                    print_location(Some(location), function);
+                }
             }
             "next" => {
                 computer.tick(false);
             }
+            "step" => match &source_map {
+                Some(map) => {
+                    let current = map.get(computer.pc().as_raw());
+                    for _ in 0..MAX_STEP_TICKS {
+                        computer.tick(false);
+                        if map.get(computer.pc().as_raw()) != current {
+                            break;
+                        }
+                    }
+                }
+                None => eprintln!("No source map is loaded"),
+            },
+            // Both stop on entering another Jack line. Stepping over waits
+            // for the stack to be back where it was, so the calls made by
+            // the line and the rest of its statement run through.
+            "into" | "over" if debug_info.is_none() => {
+                eprintln!("No debugging information is loaded")
+            }
+            "into" | "over" => {
+                let mut last = jack(computer.pc().as_raw()).map(|(location, _)| location);
+                let sp = computer.peek(SP);
+                let mut stopped = false;
+                for _ in 0..MAX_STEP_TICKS {
+                    computer.tick(false);
+                    let location = match jack(computer.pc().as_raw()) {
+                        Some((location, _)) => location,
+                        None => continue,
+                    };
+                    if command == "over" && computer.peek(SP) > sp {
+                        continue;
+                    }
+                    if Some(location) != last {
+                        stopped = true;
+                        break;
+                    }
+                    last = Some(location);
+                }
+                if !stopped {
+                    eprintln!("No other Jack line was reached");
+                }
+                let (location, function) = jack(computer.pc().as_raw()).unzip();
+                print_location(location, function.flatten());
+            }
+            "break" | "delete" => {
+                let breakpoint = argument
+                    .and_then(|argument| argument.rsplit_once(':'))
+                    .and_then(|(file, line)| Some((file.to_string(), line.parse().ok()?)));
+                let (file, number) = match breakpoint {
+                    Some(breakpoint) => breakpoint,
+                    None => {
+                        eprintln!("Expected <file>:<line>");
+                        continue;
+                    }
+                };
+                if command == "delete" {
+                    breakpoints.retain(|(other, line)| (other, *line) != (&file, number));
+                    continue;
+                }
+                let found = debug_info
+                    .iter()
+                    .flat_map(|info| &info.classes)
+                    .any(|class| {
+                        same_file(&file, &class.file)
+                            && class.lines.iter().any(|&(_, line)| line == number)
+                    });
+                if !found {
+                    eprintln!("No code was compiled from {}:{}", file, number);
+                    continue;
+                }
+                breakpoints.push((file, number));
+            }
+            // Breakpoints stop where the code of their line starts, rather
+            // than on coming back to the line from a call made in it.
+            "continue" => {
+                let at_breakpoint = |pc: u16| {
+                    let location = jack(pc).map(|(location, _)| location);
+                    let previous = jack(pc.wrapping_sub(1)).map(|(location, _)| location);
+                    location.is_some_and(|location| {
+                        previous != Some(location)
+                            && breakpoints.iter().any(|(file, line)| {
+                                same_file(file, location.file) && *line == location.line
+                            })
+                    })
+                };
+                let mut stopped = false;
+                for _ in 0..MAX_RUN_TICKS {
+                    computer.tick(false);
+                    if at_breakpoint(computer.pc().as_raw()) {
+                        stopped = true;
+                        break;
+                    }
+                }
+                if stopped {
+                    let (location, function) = jack(computer.pc().as_raw()).unzip();
+                    print_location(location, function.flatten());
+                } else {
+                    eprintln!("No breakpoint was reached in {} ticks", MAX_RUN_TICKS);
+                }
+            }
+            "print" => {
+                let function = jack(computer.pc().as_raw()).and_then(|(_, function)| function);
+                let (info, function) = match (&debug_info, function) {
+                    (Some(info), Some(function)) => (info, function),
+                    _ => {
+                        eprintln!("Not in a Jack subroutine");
+                        continue;
+                    }
+                };
+                let variables = match argument {
+                    Some(name) => match info.variable(function, name) {
+                        Some(variable) => vec![variable],
+                        None => {
+                            eprintln!("No variable {} in {}", name, function);
+                            continue;
+                        }
+                    },
+                    None => info.variables(function),
+                };
+                for variable in variables {
+                    if let Some(pointer) = variable.segment_pointer() {
+                        let base = computer.peek(pointer);
+                        let value = computer.peek(base.wrapping_add(variable.index));
+                        println!("{} = {}", variable.name, variable.format(value));
+                    }
+                }
+            }
+            "heap" => {
+                let symbol = heap::free_list_symbol();
+                let pointer = match argument {
+                    Some(address) => address.parse().ok(),
+                    None => symbols.as_ref().and_then(|symbols| {
+                        symbols
+                            .variables
+                            .iter()
+                            .find(|(name, _)| *name == symbol)
+                            .map(|&(_, address)| address)
+                    }),
+                };
+                match pointer {
+                    Some(pointer) => {
+                        let free_list = computer.peek(pointer);
+                        print!(
+                            "{}",
+                            Heap::walk(|address| computer.peek(address), free_list)
+                        );
+                    }
+                    None => eprintln!("Expected the address of {} in RAM", symbol),
+                }
+            }
             "load" => {
                 let mut path = String::new();
                 print!("Path to a ROM file > ");
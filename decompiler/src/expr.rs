@@ -0,0 +1,267 @@
+use assembler::instruction::{Comp, Jump};
+
+pub const SP: u16 = 0;
+pub const LCL: u16 = 1;
+pub const ARG: u16 = 2;
+pub const THIS: u16 = 3;
+pub const THAT: u16 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    A,
+    D,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnaryOp {
+    Not,
+    Neg,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    And,
+    Or,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expr {
+    Const(u16),
+    Register(Register),
+    Temp(usize),
+    Load(Box<Expr>),
+    Pop,
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    pub fn load(address: Expr) -> Self {
+        Expr::Load(Box::new(address))
+    }
+
+    pub fn register(address: u16) -> Self {
+        Expr::load(Expr::Const(address))
+    }
+
+    pub fn stack_top_address() -> Self {
+        Expr::sum(Expr::register(SP), Expr::Const(0xFFFF))
+    }
+
+    pub fn stack_top() -> Self {
+        Expr::load(Expr::stack_top_address())
+    }
+
+    pub fn sum(a: Expr, b: Expr) -> Self {
+        match (a, b) {
+            (Expr::Const(a), Expr::Const(b)) => Expr::Const(a.wrapping_add(b)),
+            (Expr::Const(a), b) => Expr::sum(b, Expr::Const(a)),
+            (a, Expr::Const(0)) => a,
+            (Expr::Binary(BinaryOp::Add, a, c), Expr::Const(b)) => match *c {
+                Expr::Const(c) => Expr::sum(*a, Expr::Const(c.wrapping_add(b))),
+                c => Expr::Binary(
+                    BinaryOp::Add,
+                    Box::new(Expr::Binary(BinaryOp::Add, a, Box::new(c))),
+                    Box::new(Expr::Const(b)),
+                ),
+            },
+            (a, b) => Expr::Binary(BinaryOp::Add, Box::new(a), Box::new(b)),
+        }
+    }
+
+    pub fn difference(a: Expr, b: Expr) -> Self {
+        match (a, b) {
+            (a, Expr::Const(b)) => Expr::sum(a, Expr::Const(b.wrapping_neg())),
+            (a, b) if a == b => Expr::Const(0),
+            (a, b) => Expr::Binary(BinaryOp::Sub, Box::new(a), Box::new(b)),
+        }
+    }
+
+    pub fn and(a: Expr, b: Expr) -> Self {
+        match (a, b) {
+            (Expr::Const(a), Expr::Const(b)) => Expr::Const(a & b),
+            (a, b) => Expr::Binary(BinaryOp::And, Box::new(a), Box::new(b)),
+        }
+    }
+
+    pub fn or(a: Expr, b: Expr) -> Self {
+        match (a, b) {
+            (Expr::Const(a), Expr::Const(b)) => Expr::Const(a | b),
+            (a, b) => Expr::Binary(BinaryOp::Or, Box::new(a), Box::new(b)),
+        }
+    }
+
+    pub fn complement(a: Expr) -> Self {
+        match a {
+            Expr::Const(a) => Expr::Const(!a),
+            Expr::Unary(UnaryOp::Not, a) => *a,
+            a => Expr::Unary(UnaryOp::Not, Box::new(a)),
+        }
+    }
+
+    pub fn negation(a: Expr) -> Self {
+        match a {
+            Expr::Const(a) => Expr::Const(a.wrapping_neg()),
+            Expr::Unary(UnaryOp::Neg, a) => *a,
+            a => Expr::Unary(UnaryOp::Neg, Box::new(a)),
+        }
+    }
+
+    pub fn comp(comp: &Comp, a: &Expr, d: &Expr, m: impl FnOnce() -> Expr) -> Self {
+        let (a, d) = (a.clone(), d.clone());
+        match comp {
+            Comp::Zero => Expr::Const(0),
+            Comp::One => Expr::Const(1),
+            Comp::MinusOne => Expr::Const(0xFFFF),
+            Comp::D => d,
+            Comp::A => a,
+            Comp::M => m(),
+            Comp::NotD => Expr::complement(d),
+            Comp::NotA => Expr::complement(a),
+            Comp::NotM => Expr::complement(m()),
+            Comp::MinusD => Expr::negation(d),
+            Comp::MinusA => Expr::negation(a),
+            Comp::MinusM => Expr::negation(m()),
+            Comp::DPlusOne => Expr::sum(d, Expr::Const(1)),
+            Comp::APlusOne => Expr::sum(a, Expr::Const(1)),
+            Comp::MPlusOne => Expr::sum(m(), Expr::Const(1)),
+            Comp::DMinusOne => Expr::difference(d, Expr::Const(1)),
+            Comp::AMinusOne => Expr::difference(a, Expr::Const(1)),
+            Comp::MMinusOne => Expr::difference(m(), Expr::Const(1)),
+            Comp::DPlusA => Expr::sum(d, a),
+            Comp::DPlusM => Expr::sum(d, m()),
+            Comp::DMinusA => Expr::difference(d, a),
+            Comp::AMinusD => Expr::difference(a, d),
+            Comp::DMinusM => Expr::difference(d, m()),
+            Comp::MMinusD => Expr::difference(m(), d),
+            Comp::DAndA => Expr::and(d, a),
+            Comp::DAndM => Expr::and(d, m()),
+            Comp::DOrA => Expr::or(d, a),
+            Comp::DOrM => Expr::or(d, m()),
+        }
+    }
+
+    pub fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Load(a) | Expr::Unary(_, a) => vec![a],
+            Expr::Binary(_, a, b) => vec![a, b],
+            _ => vec![],
+        }
+    }
+
+    pub fn any(&self, predicate: &impl Fn(&Expr) -> bool) -> bool {
+        predicate(self) || self.children().iter().any(|child| child.any(predicate))
+    }
+
+    pub fn replace(&self, from: &Expr, to: &Expr) -> Expr {
+        if self == from {
+            return to.clone();
+        }
+        match self {
+            Expr::Load(a) => Expr::load(a.replace(from, to)),
+            Expr::Unary(op, a) => Expr::Unary(*op, Box::new(a.replace(from, to))),
+            Expr::Binary(op, a, b) => Expr::Binary(
+                *op,
+                Box::new(a.replace(from, to)),
+                Box::new(b.replace(from, to)),
+            ),
+            other => other.clone(),
+        }
+    }
+
+    pub fn count(&self, target: &Expr) -> usize {
+        if self == target {
+            return 1;
+        }
+        self.children()
+            .iter()
+            .map(|child| child.count(target))
+            .sum()
+    }
+
+    pub fn reads_aliasing(&self, address: &Expr) -> bool {
+        self.any(&|e| matches!(e, Expr::Load(loaded) if may_alias(loaded, address)))
+    }
+
+    fn base_and_offset(&self) -> (&Expr, u16) {
+        match self {
+            Expr::Binary(BinaryOp::Add, base, offset) => match **offset {
+                Expr::Const(offset) => (base, offset),
+                _ => (self, 0),
+            },
+            _ => (self, 0),
+        }
+    }
+}
+
+// Addresses computed at run time are assumed to point neither into the
+// registers and static variables below 256 nor, when one of them is relative
+// to SP, into another segment.
+pub fn may_alias(a: &Expr, b: &Expr) -> bool {
+    match (a, b) {
+        (Expr::Const(a), Expr::Const(b)) => a == b,
+        (Expr::Const(c), _) | (_, Expr::Const(c)) if *c < 256 => false,
+        (Expr::Const(_), _) | (_, Expr::Const(_)) => true,
+        _ => {
+            let (a_base, a_offset) = a.base_and_offset();
+            let (b_base, b_offset) = b.base_and_offset();
+            let stack = Expr::register(SP);
+            if a_base == b_base {
+                a_offset == b_offset
+            } else {
+                *a_base != stack && *b_base != stack
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Condition {
+    pub value: Expr,
+    pub jump: Jump,
+}
+
+impl Condition {
+    pub fn negate(&self) -> Self {
+        let jump = match self.jump {
+            Jump::JGT => Jump::JLE,
+            Jump::JEQ => Jump::JNE,
+            Jump::JGE => Jump::JLT,
+            Jump::JLT => Jump::JGE,
+            Jump::JNE => Jump::JEQ,
+            Jump::JLE => Jump::JGT,
+            Jump::JMP => Jump::JMP,
+        };
+        Self {
+            value: self.value.clone(),
+            jump,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_offsets_are_folded() {
+        let sp = Expr::register(SP);
+        let e = Expr::difference(Expr::difference(sp.clone(), Expr::Const(5)), Expr::Const(2));
+        assert_eq!(e, Expr::sum(sp.clone(), Expr::Const(0xFFF9)));
+        assert_eq!(Expr::sum(e, Expr::Const(7)), sp);
+    }
+
+    #[test]
+    fn stack_slots_do_not_alias_registers_or_segments() {
+        let sp = Expr::register(SP);
+        let local = Expr::sum(Expr::register(LCL), Expr::Const(2));
+        assert!(!may_alias(&sp, &Expr::Const(SP)));
+        assert!(!may_alias(&sp, &local));
+        assert!(!may_alias(&sp, &Expr::sum(sp.clone(), Expr::Const(0xFFFF))));
+        assert!(may_alias(&sp, &sp));
+        assert!(may_alias(&local, &Expr::register(THAT)));
+        assert!(may_alias(&local, &Expr::Const(0x4000)));
+    }
+}
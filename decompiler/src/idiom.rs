@@ -0,0 +1,312 @@
+use crate::{
+    expr::{BinaryOp, Expr, UnaryOp, ARG, LCL, SP, THAT, THIS},
+    lift::{Destination, LiftedBlock, Statement, Terminator},
+};
+
+pub fn fold(blocks: &mut [LiftedBlock]) -> bool {
+    let mut vm = false;
+    blocks.iter_mut().for_each(|block| {
+        remove_dead_temps(block);
+        vm |= fold_pushes(block);
+        vm |= fold_pops(block);
+        vm |= fold_unary_operations(block);
+        vm |= fold_call(block);
+        vm |= fold_return(block);
+        fold_halt(block);
+    });
+    vm
+}
+
+fn remove_dead_temps(block: &mut LiftedBlock) {
+    while let Some(index) = block
+        .statements
+        .iter()
+        .position(|statement| match statement {
+            Statement::Let { temp, .. } => !block.uses_temp(*temp),
+            _ => false,
+        })
+    {
+        block.statements.remove(index);
+    }
+}
+
+fn increment_sp(by: u16) -> Statement {
+    Statement::Store {
+        address: Expr::Const(SP),
+        value: Expr::sum(Expr::register(SP), Expr::Const(by)),
+    }
+}
+
+fn mentions_sp(e: &Expr) -> bool {
+    e.any(&|e| *e == Expr::register(SP))
+}
+
+fn fold_pushes(block: &mut LiftedBlock) -> bool {
+    let mut folded = false;
+    let mut i = 0;
+    while i + 1 < block.statements.len() {
+        let push = match (&block.statements[i], &block.statements[i + 1]) {
+            (Statement::Store { address, value }, increment)
+                if *address == Expr::register(SP) && *increment == increment_sp(1) =>
+            {
+                Some(value.clone())
+            }
+            (increment, Statement::Store { address, value })
+                if *increment == increment_sp(1)
+                    && *address == Expr::stack_top_address()
+                    && !mentions_sp(value) =>
+            {
+                Some(value.clone())
+            }
+            _ => None,
+        };
+        if let Some(value) = push {
+            block
+                .statements
+                .splice(i..i + 2, Some(Statement::Push(value)));
+            folded = true;
+        }
+        i += 1;
+    }
+    folded
+}
+
+fn fold_pops(block: &mut LiftedBlock) -> bool {
+    let popped = Expr::load(Expr::register(SP));
+    let top = Expr::stack_top();
+    let mut folded = false;
+    let mut i = 0;
+    while i + 1 < block.statements.len() {
+        if block.statements[i] != increment_sp(0xFFFF) {
+            i += 1;
+            continue;
+        }
+        let uses = block.statements[i + 1]
+            .expressions()
+            .iter()
+            .map(|e| e.count(&popped))
+            .sum::<usize>();
+        if uses != 1 {
+            i += 1;
+            continue;
+        }
+        let replacement = match &block.statements[i + 1] {
+            Statement::Store { address, value } if *value == popped && !mentions_sp(address) => {
+                Some(Statement::Pop {
+                    address: address.clone(),
+                })
+            }
+            Statement::Store { address, value } if *address == Expr::stack_top_address() => {
+                binary_operation(value, &top, &popped).map(Statement::Operation)
+            }
+            Statement::Assign { register, value } if *value == popped => Some(Statement::Assign {
+                register: *register,
+                value: Expr::Pop,
+            }),
+            Statement::Let { temp, value } if *value == popped => Some(Statement::Let {
+                temp: *temp,
+                value: Expr::Pop,
+            }),
+            _ => None,
+        };
+        if let Some(replacement) = replacement {
+            block.statements.splice(i..i + 2, Some(replacement));
+            folded = true;
+        }
+        i += 1;
+    }
+    folded
+}
+
+fn binary_operation(value: &Expr, top: &Expr, popped: &Expr) -> Option<&'static str> {
+    match value {
+        Expr::Binary(op, x, y) => {
+            let ordered = **x == *top && **y == *popped;
+            let swapped = **x == *popped && **y == *top;
+            match op {
+                BinaryOp::Add if ordered || swapped => Some("add"),
+                BinaryOp::And if ordered || swapped => Some("and"),
+                BinaryOp::Or if ordered || swapped => Some("or"),
+                BinaryOp::Sub if ordered => Some("sub"),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn fold_unary_operations(block: &mut LiftedBlock) -> bool {
+    let top = Expr::stack_top();
+    let mut folded = false;
+    block.statements.iter_mut().for_each(|statement| {
+        let operation = match statement {
+            Statement::Store { address, value } if *address == Expr::stack_top_address() => {
+                match value {
+                    Expr::Unary(UnaryOp::Neg, x) if **x == top => Some("neg"),
+                    Expr::Unary(UnaryOp::Not, x) if **x == top => Some("not"),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        if let Some(operation) = operation {
+            *statement = Statement::Operation(operation);
+            folded = true;
+        }
+    });
+    folded
+}
+
+fn fold_call(block: &mut LiftedBlock) -> bool {
+    let function = match block.terminator {
+        Terminator::Goto(Destination::Block(function)) => function,
+        _ => return false,
+    };
+    let len = block.statements.len();
+    if len < 7 {
+        return false;
+    }
+    let frame = [LCL, ARG, THIS, THAT]
+        .iter()
+        .map(|&register| Statement::Push(Expr::register(register)))
+        .collect::<Vec<_>>();
+    let return_address = match &block.statements[len - 7] {
+        Statement::Push(Expr::Const(address)) => *address as usize,
+        _ => return false,
+    };
+    if block.statements[len - 6..len - 2] != frame[..] {
+        return false;
+    }
+    let lcl = Statement::Store {
+        address: Expr::Const(LCL),
+        value: Expr::register(SP),
+    };
+    let arg = |statement: &Statement| match statement {
+        Statement::Store {
+            address: Expr::Const(ARG),
+            value: Expr::Binary(BinaryOp::Add, base, offset),
+        } if **base == Expr::register(SP) => match **offset {
+            Expr::Const(offset) => offset.wrapping_neg().checked_sub(5),
+            _ => None,
+        },
+        _ => None,
+    };
+    let arguments = match (&block.statements[len - 2], &block.statements[len - 1]) {
+        (first, second) if *second == lcl => arg(first),
+        (first, second) if *first == lcl => arg(second),
+        _ => None,
+    };
+    match arguments {
+        Some(arguments) => {
+            block.statements.truncate(len - 7);
+            block.statements.push(Statement::Call {
+                function,
+                arguments,
+            });
+            block.terminator = Terminator::Next(return_address);
+            true
+        }
+        None => false,
+    }
+}
+
+fn fold_return(block: &mut LiftedBlock) -> bool {
+    if !matches!(block.terminator, Terminator::Goto(Destination::Dynamic(_))) {
+        return false;
+    }
+    let restores = |register: u16| {
+        block.statements.iter().any(|statement| {
+            matches!(statement, Statement::Store { address: Expr::Const(address), .. } if *address == register)
+        })
+    };
+    if [SP, LCL, ARG, THIS, THAT]
+        .iter()
+        .all(|&register| restores(register))
+    {
+        block.statements.clear();
+        block.terminator = Terminator::Return;
+        true
+    } else {
+        false
+    }
+}
+
+fn fold_halt(block: &mut LiftedBlock) {
+    if block.statements.is_empty()
+        && block.terminator == Terminator::Goto(Destination::Block(block.start))
+    {
+        block.terminator = Terminator::Halt;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lift::lift;
+    use assembler::{flow::ControlFlowGraph, parser::parse, symbol::SymbolTable};
+
+    fn fold_asm(lines: &[&str]) -> Vec<LiftedBlock> {
+        let mut symbols = SymbolTable::new();
+        let instructions = parse(lines, &mut symbols).unwrap();
+        let instructions = symbols.resolve_symbols(&instructions);
+        let labels = symbols
+            .labels()
+            .iter()
+            .map(|&(_, address)| address as usize)
+            .collect::<Vec<_>>();
+        let graph = ControlFlowGraph::new(&instructions, &labels);
+        let mut blocks = lift(&instructions, &graph);
+        fold(&mut blocks);
+        blocks
+    }
+
+    #[test]
+    fn push_and_pop_sequences_are_folded() {
+        let blocks = fold_asm(&[
+            "@LCL", "D=M", "@2", "A=D+A", "D=M", "@SP", "A=M", "M=D", "@SP", "M=M+1", "@SP",
+            "AM=M-1", "D=M", "@THAT", "A=M", "M=D",
+        ]);
+        assert_eq!(
+            blocks[0].statements,
+            [
+                Statement::Push(Expr::load(Expr::sum(Expr::register(LCL), Expr::Const(2)))),
+                Statement::Pop {
+                    address: Expr::register(THAT)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn arithmetic_on_the_stack_is_folded() {
+        let blocks = fold_asm(&[
+            "@SP", "AM=M-1", "D=M", "A=A-1", "M=M-D", "@SP", "A=M-1", "M=-M",
+        ]);
+        assert_eq!(
+            blocks[0].statements,
+            [Statement::Operation("sub"), Statement::Operation("neg")]
+        );
+    }
+
+    #[test]
+    fn call_sequence_is_folded() {
+        let mut lines = vec!["@RET", "D=A", "@SP", "A=M", "M=D", "@SP", "M=M+1"];
+        for segment in &["@LCL", "@ARG", "@THIS", "@THAT"] {
+            lines.extend_from_slice(&[segment, "D=M", "@SP", "A=M", "M=D", "@SP", "M=M+1"]);
+        }
+        lines.extend_from_slice(&[
+            "@SP", "D=M", "@7", "D=D-A", "@ARG", "M=D", "@SP", "D=M", "@LCL", "M=D", "@F", "0;JMP",
+            "(RET)", "(END)", "@END", "0;JMP", "(F)", "@F", "0;JMP",
+        ]);
+        let blocks = fold_asm(&lines);
+        assert_eq!(
+            blocks[0].statements,
+            [Statement::Call {
+                function: 49,
+                arguments: 2
+            }]
+        );
+        assert_eq!(blocks[0].terminator, Terminator::Next(47));
+        assert_eq!(blocks[1].terminator, Terminator::Halt);
+    }
+}
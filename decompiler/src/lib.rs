@@ -0,0 +1,72 @@
+pub mod expr;
+pub mod idiom;
+pub mod lift;
+pub mod print;
+pub mod structure;
+
+use assembler::{flow::ControlFlowGraph, instruction::Instruction, symbol::SymbolFile};
+
+pub fn decompile(instructions: &[Instruction], symbols: &SymbolFile) -> String {
+    let labels = symbols
+        .labels
+        .iter()
+        .map(|&(_, address)| address as usize)
+        .filter(|&address| address < instructions.len())
+        .collect::<Vec<_>>();
+    let graph = ControlFlowGraph::new(instructions, &labels);
+    let mut blocks = lift::lift(instructions, &graph);
+    let vm = idiom::fold(&mut blocks);
+    let printer = print::Printer::new(symbols, vm);
+    let (functions, targets) = structure::structure(&blocks, &printer);
+    structure::write(&functions, &targets, &printer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assembler::{parser::parse, symbol::SymbolTable};
+
+    fn decompile_asm(lines: &[&str]) -> String {
+        let mut symbols = SymbolTable::new();
+        let instructions = parse(lines, &mut symbols).unwrap();
+        let instructions = symbols.resolve_symbols(&instructions);
+        decompile(&instructions, &symbols.symbol_file())
+    }
+
+    #[test]
+    fn counting_loop_becomes_while() {
+        let source = decompile_asm(&[
+            "@R2", "M=0", "@R0", "D=M", "@count", "M=D", "(LOOP)", "@count", "D=M", "@END",
+            "D;JLE", "@R1", "D=M", "@R2", "M=D+M", "@count", "M=M-1", "@LOOP", "0;JMP", "(END)",
+            "@END", "0;JMP",
+        ]);
+        assert_eq!(
+            source,
+            "start {\n    R2 = 0\n    count = R0\n    while (count > 0) {\n        R2 = R1 + R2\n        count = count - 1\n    }\n    halt\n}\n"
+        );
+    }
+
+    #[test]
+    fn branches_become_if_else_with_registers_live_across_them() {
+        let source = decompile_asm(&[
+            "@R0",
+            "D=M",
+            "@NEGATIVE",
+            "D;JLT",
+            "@R1",
+            "M=D",
+            "@END",
+            "0;JMP",
+            "(NEGATIVE)",
+            "@R1",
+            "M=-D",
+            "(END)",
+            "@END",
+            "0;JMP",
+        ]);
+        assert_eq!(
+            source,
+            "start {\n    D = R0\n    if (R0 < 0) {\n        R1 = -D\n    } else {\n        R1 = D\n    }\n    halt\n}\n"
+        );
+    }
+}
@@ -0,0 +1,412 @@
+use assembler::{
+    flow::{BasicBlock, ControlFlowGraph, Exit, Target},
+    instruction::Instruction,
+};
+
+use crate::expr::{Condition, Expr, Register};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Statement {
+    Store { address: Expr, value: Expr },
+    Assign { register: Register, value: Expr },
+    Let { temp: usize, value: Expr },
+    Push(Expr),
+    Pop { address: Expr },
+    Operation(&'static str),
+    Call { function: usize, arguments: u16 },
+}
+
+impl Statement {
+    pub fn expressions(&self) -> Vec<&Expr> {
+        match self {
+            Statement::Store { address, value } => vec![address, value],
+            Statement::Assign { value, .. } | Statement::Let { value, .. } => vec![value],
+            Statement::Push(value) => vec![value],
+            Statement::Pop { address } => vec![address],
+            Statement::Operation(_) | Statement::Call { .. } => vec![],
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Destination {
+    Block(usize),
+    Dynamic(Expr),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Terminator {
+    Next(usize),
+    Goto(Destination),
+    Branch {
+        condition: Condition,
+        target: Destination,
+        otherwise: usize,
+    },
+    Return,
+    Halt,
+}
+
+impl Terminator {
+    pub fn expressions(&self) -> Vec<&Expr> {
+        match self {
+            Terminator::Goto(Destination::Dynamic(target)) => vec![target],
+            Terminator::Branch {
+                condition,
+                target: Destination::Dynamic(target),
+                ..
+            } => vec![&condition.value, target],
+            Terminator::Branch { condition, .. } => vec![&condition.value],
+            _ => vec![],
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LiftedBlock {
+    pub start: usize,
+    pub statements: Vec<Statement>,
+    pub terminator: Terminator,
+}
+
+impl LiftedBlock {
+    pub fn successors(&self) -> Vec<usize> {
+        match self.terminator {
+            Terminator::Next(next) | Terminator::Goto(Destination::Block(next)) => vec![next],
+            Terminator::Branch {
+                target: Destination::Block(target),
+                otherwise,
+                ..
+            } => vec![target, otherwise],
+            Terminator::Branch { otherwise, .. } => vec![otherwise],
+            _ => vec![],
+        }
+    }
+
+    pub fn uses_temp(&self, temp: usize) -> bool {
+        self.statements
+            .iter()
+            .flat_map(|statement| statement.expressions())
+            .chain(self.terminator.expressions())
+            .any(|e| e.any(&|e| *e == Expr::Temp(temp)))
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+struct Registers {
+    a: bool,
+    d: bool,
+}
+
+fn registers_used(instruction: &Instruction) -> (Registers, Registers) {
+    match instruction {
+        Instruction::A { .. } => (Registers::default(), Registers { a: true, d: false }),
+        Instruction::C { comp, dest, jump } => {
+            let uses = Registers {
+                a: comp.uses_a()
+                    || comp.uses_m()
+                    || jump.is_some()
+                    || dest.as_ref().is_some_and(|dest| dest.contains_m()),
+                d: comp.uses_d(),
+            };
+            let defines = Registers {
+                a: dest.as_ref().is_some_and(|dest| dest.contains_a()),
+                d: dest.as_ref().is_some_and(|dest| dest.contains_d()),
+            };
+            (uses, defines)
+        }
+    }
+}
+
+fn liveness(instructions: &[Instruction], graph: &ControlFlowGraph) -> Vec<Registers> {
+    let blocks = graph.blocks();
+    let summaries = blocks
+        .iter()
+        .map(|block| {
+            let mut uses = Registers::default();
+            let mut defines = Registers::default();
+            instructions[block.start..block.end]
+                .iter()
+                .for_each(|instruction| {
+                    let (used, defined) = registers_used(instruction);
+                    uses.a |= used.a && !defines.a;
+                    uses.d |= used.d && !defines.d;
+                    defines.a |= defined.a;
+                    defines.d |= defined.d;
+                });
+            (uses, defines)
+        })
+        .collect::<Vec<_>>();
+    let index = |address: usize| {
+        blocks
+            .binary_search_by_key(&address, |block| block.start)
+            .ok()
+    };
+    let mut live_in = vec![Registers::default(); blocks.len()];
+    let mut live_out = vec![Registers::default(); blocks.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for i in (0..blocks.len()).rev() {
+            // Registers may be read wherever a computed jump lands.
+            let dynamic = matches!(
+                blocks[i].exit,
+                Exit::Goto(Target::Dynamic)
+                    | Exit::Branch {
+                        target: Target::Dynamic,
+                        ..
+                    }
+            );
+            let out = blocks[i]
+                .successors()
+                .iter()
+                .filter_map(|&successor| index(successor))
+                .fold(
+                    Registers {
+                        a: false,
+                        d: dynamic,
+                    },
+                    |acc, successor| Registers {
+                        a: acc.a || live_in[successor].a,
+                        d: acc.d || live_in[successor].d,
+                    },
+                );
+            let (uses, defines) = summaries[i];
+            let input = Registers {
+                a: uses.a || (out.a && !defines.a),
+                d: uses.d || (out.d && !defines.d),
+            };
+            if out != live_out[i] || input != live_in[i] {
+                live_out[i] = out;
+                live_in[i] = input;
+                changed = true;
+            }
+        }
+    }
+    live_out
+}
+
+struct Lifter<'a> {
+    a: Expr,
+    d: Expr,
+    statements: Vec<Statement>,
+    next_temp: &'a mut usize,
+}
+
+impl<'a> Lifter<'a> {
+    fn spill(&mut self, value: Expr) -> Expr {
+        let temp = *self.next_temp;
+        *self.next_temp += 1;
+        self.statements.push(Statement::Let { temp, value });
+        Expr::Temp(temp)
+    }
+
+    fn store(&mut self, address: Expr, value: Expr) {
+        if self.a.reads_aliasing(&address) {
+            self.a = self.spill(self.a.clone());
+        }
+        if self.d.reads_aliasing(&address) {
+            self.d = self.spill(self.d.clone());
+        }
+        self.statements.push(Statement::Store { address, value });
+    }
+}
+
+pub fn lift(instructions: &[Instruction], graph: &ControlFlowGraph) -> Vec<LiftedBlock> {
+    let live_out = liveness(instructions, graph);
+    let mut next_temp = 1;
+    graph
+        .blocks()
+        .iter()
+        .zip(live_out)
+        .map(|(block, live_out)| {
+            lift_block(
+                &instructions[block.start..block.end],
+                block,
+                live_out,
+                &mut next_temp,
+            )
+        })
+        .collect()
+}
+
+fn lift_block(
+    instructions: &[Instruction],
+    block: &BasicBlock,
+    live_out: Registers,
+    next_temp: &mut usize,
+) -> LiftedBlock {
+    let mut lifter = Lifter {
+        a: Expr::Register(Register::A),
+        d: Expr::Register(Register::D),
+        statements: vec![],
+        next_temp,
+    };
+    let mut exit = None;
+    instructions
+        .iter()
+        .for_each(|instruction| match instruction {
+            Instruction::A { value } => lifter.a = Expr::Const(*value),
+            Instruction::C { comp, dest, jump } => {
+                let address = lifter.a.clone();
+                let mut result =
+                    Expr::comp(comp, &lifter.a, &lifter.d, || Expr::load(address.clone()));
+                if dest.as_ref().is_some_and(|dest| dest.contains_m()) {
+                    let mut stored = result.clone();
+                    if result.reads_aliasing(&address) {
+                        if address.reads_aliasing(&address) {
+                            result = lifter.spill(result);
+                            stored = result.clone();
+                        } else {
+                            result = Expr::load(address.clone());
+                        }
+                    }
+                    lifter.store(address, stored);
+                }
+                if let Some(jump) = jump {
+                    exit = Some((
+                        Condition {
+                            value: result.clone(),
+                            jump: jump.clone(),
+                        },
+                        lifter.a.clone(),
+                    ));
+                }
+                if dest.as_ref().is_some_and(|dest| dest.contains_d()) {
+                    lifter.d = result.clone();
+                }
+                if dest.as_ref().is_some_and(|dest| dest.contains_a()) {
+                    lifter.a = result;
+                }
+            }
+        });
+
+    let destination = |target: Target, dynamic: Expr| match target {
+        Target::Static(target) => Destination::Block(target),
+        Target::Dynamic => Destination::Dynamic(dynamic),
+    };
+    let mut terminator = match (&block.exit, exit) {
+        (Exit::Next(next), _) => Terminator::Next(*next),
+        (Exit::Goto(target), Some((_, dynamic))) => Terminator::Goto(destination(*target, dynamic)),
+        (
+            Exit::Branch {
+                target, otherwise, ..
+            },
+            Some((condition, dynamic)),
+        ) => Terminator::Branch {
+            condition,
+            target: destination(*target, dynamic),
+            otherwise: *otherwise,
+        },
+        _ => unreachable!(),
+    };
+
+    let assignments = [
+        (Register::D, live_out.d, lifter.d.clone()),
+        (Register::A, live_out.a, lifter.a.clone()),
+    ];
+    for (register, live, value) in assignments.iter() {
+        if !*live || *value == Expr::Register(*register) {
+            continue;
+        }
+        let entry = Expr::Register(*register);
+        if terminator.expressions().iter().any(|e| e.count(&entry) > 0) {
+            let temp = lifter.spill(entry.clone());
+            terminator = replace_in_terminator(&terminator, &entry, &temp);
+        }
+        lifter.statements.push(Statement::Assign {
+            register: *register,
+            value: value.clone(),
+        });
+    }
+
+    LiftedBlock {
+        start: block.start,
+        statements: lifter.statements,
+        terminator,
+    }
+}
+
+fn replace_in_terminator(terminator: &Terminator, from: &Expr, to: &Expr) -> Terminator {
+    let destination = |destination: &Destination| match destination {
+        Destination::Dynamic(target) => Destination::Dynamic(target.replace(from, to)),
+        block => block.clone(),
+    };
+    match terminator {
+        Terminator::Goto(target) => Terminator::Goto(destination(target)),
+        Terminator::Branch {
+            condition,
+            target,
+            otherwise,
+        } => Terminator::Branch {
+            condition: Condition {
+                value: condition.value.replace(from, to),
+                jump: condition.jump.clone(),
+            },
+            target: destination(target),
+            otherwise: *otherwise,
+        },
+        other => other.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::SP;
+    use assembler::{parser::parse, symbol::SymbolTable};
+
+    fn lift_asm(lines: &[&str]) -> Vec<LiftedBlock> {
+        let mut symbols = SymbolTable::new();
+        let instructions = parse(lines, &mut symbols).unwrap();
+        let instructions = symbols.resolve_symbols(&instructions);
+        let graph = ControlFlowGraph::new(&instructions, &[]);
+        lift(&instructions, &graph)
+    }
+
+    #[test]
+    fn stores_are_lifted_into_expressions_over_memory() {
+        let blocks = lift_asm(&["@7", "D=A", "@SP", "A=M", "M=D", "@SP", "M=M+1"]);
+        assert_eq!(
+            blocks[0].statements,
+            [
+                Statement::Store {
+                    address: Expr::register(SP),
+                    value: Expr::Const(7),
+                },
+                Statement::Store {
+                    address: Expr::Const(SP),
+                    value: Expr::sum(Expr::register(SP), Expr::Const(1)),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn registers_written_together_with_memory_read_the_stored_value() {
+        let blocks = lift_asm(&["@SP", "AM=M-1", "D=M", "@R13", "M=D"]);
+        assert_eq!(
+            blocks[0].statements[1],
+            Statement::Store {
+                address: Expr::Const(13),
+                value: Expr::load(Expr::register(SP)),
+            }
+        );
+    }
+
+    #[test]
+    fn registers_live_in_the_next_block_are_assigned() {
+        let blocks = lift_asm(&["@5", "D=A", "@END", "0;JMP", "(END)", "@0", "M=D"]);
+        assert_eq!(
+            blocks[0].statements,
+            [Statement::Assign {
+                register: Register::D,
+                value: Expr::Const(5),
+            }]
+        );
+        assert_eq!(
+            blocks[0].terminator,
+            Terminator::Goto(Destination::Block(4))
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use clap::{app_from_crate, crate_authors, crate_description, crate_name, crate_version, Arg};
+
+use assembler::{
+    hack,
+    parser::parse,
+    symbol::{SymbolFile, SymbolTable},
+};
+use decompiler::decompile;
+
+fn read_lines(path: &Path) -> std::io::Result<Vec<String>> {
+    BufReader::new(File::open(path)?).lines().collect()
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = app_from_crate!()
+        .arg(
+            Arg::with_name("file")
+                .help("The machine language (.hack) or assembly (.asm) file")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("symbols")
+                .long("symbols")
+                .short("s")
+                .takes_value(true)
+                .help("Symbol file written by the assembler (defaults to <file>.sym if present)"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .short("o")
+                .takes_value(true)
+                .help("Path to the pseudo-code file (defaults to standard output)"),
+        )
+        .get_matches();
+    let file_name = Path::new(args.value_of("file").unwrap());
+    let lines = read_lines(file_name)?;
+    let (instructions, symbols) = if file_name.extension().is_some_and(|ext| ext == "asm") {
+        let mut symbols = SymbolTable::new();
+        let symbol_instructions = parse(&lines, &mut symbols)?;
+        (
+            symbols.resolve_symbols(&symbol_instructions),
+            symbols.symbol_file(),
+        )
+    } else {
+        let symbols = match args.value_of("symbols") {
+            Some(path) => Some(Path::new(path).to_path_buf()),
+            None => Some(file_name.with_extension("sym")).filter(|path| path.exists()),
+        };
+        let symbols = match symbols {
+            Some(path) => SymbolFile::parse(&read_lines(&path)?)?,
+            None => SymbolFile::default(),
+        };
+        (hack::read(&lines)?, symbols)
+    };
+    let source = decompile(&instructions, &symbols);
+    match args.value_of("output") {
+        Some(output) => BufWriter::new(File::create(output)?).write_all(source.as_bytes())?,
+        None => print!("{}", source),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,195 @@
+use assembler::{instruction::Jump, symbol::SymbolFile};
+
+use crate::{
+    expr::{BinaryOp, Condition, Expr, Register, UnaryOp, ARG, LCL, THAT, THIS},
+    lift::{Destination, Statement},
+};
+
+pub struct Printer<'a> {
+    symbols: &'a SymbolFile,
+    vm: bool,
+}
+
+impl<'a> Printer<'a> {
+    pub fn new(symbols: &'a SymbolFile, vm: bool) -> Self {
+        Self { symbols, vm }
+    }
+
+    pub fn label(&self, address: usize) -> String {
+        self.symbols
+            .label(address as u16)
+            .map_or_else(|| format!("L{}", address), |label| label.to_string())
+    }
+
+    pub fn variable(&self, address: u16) -> String {
+        match address {
+            0..=4 if self.vm => ["SP", "LCL", "ARG", "THIS", "THAT"][address as usize].to_string(),
+            0..=15 => format!("R{}", address),
+            0x4000 => "SCREEN".to_string(),
+            0x6000 => "KBD".to_string(),
+            _ => self
+                .symbols
+                .variable(address)
+                .map_or_else(|| format!("RAM[{}]", address), |name| name.to_string()),
+        }
+    }
+
+    pub fn expr(&self, e: &Expr) -> String {
+        match e {
+            Expr::Const(value) => (*value as i16).to_string(),
+            Expr::Register(Register::A) => "A".to_string(),
+            Expr::Register(Register::D) => "D".to_string(),
+            Expr::Temp(temp) => format!("t{}", temp),
+            Expr::Pop => "pop()".to_string(),
+            Expr::Load(address) => self.load(address),
+            Expr::Unary(UnaryOp::Not, a) => format!("!{}", self.operand(a)),
+            Expr::Unary(UnaryOp::Neg, a) => format!("-{}", self.operand(a)),
+            Expr::Binary(BinaryOp::Add, a, b) => match **b {
+                Expr::Const(b) if (b as i16) < 0 => {
+                    format!("{} - {}", self.operand(a), (b as i16).unsigned_abs())
+                }
+                _ => format!("{} + {}", self.operand(a), self.operand(b)),
+            },
+            Expr::Binary(op, a, b) => {
+                let op = match op {
+                    BinaryOp::Add => "+",
+                    BinaryOp::Sub => "-",
+                    BinaryOp::And => "&",
+                    BinaryOp::Or => "|",
+                };
+                format!("{} {} {}", self.operand(a), op, self.operand(b))
+            }
+        }
+    }
+
+    fn operand(&self, e: &Expr) -> String {
+        match e {
+            Expr::Binary(..) => format!("({})", self.expr(e)),
+            _ => self.expr(e),
+        }
+    }
+
+    fn load(&self, address: &Expr) -> String {
+        if let Expr::Const(address) = address {
+            return self.variable(*address);
+        }
+        if self.vm {
+            if *address == Expr::stack_top_address() {
+                return "top".to_string();
+            }
+            let segment = |base: &Expr, index: u16| match base {
+                Expr::Load(base) => match **base {
+                    Expr::Const(LCL) => Some(format!("local[{}]", index)),
+                    Expr::Const(ARG) => Some(format!("argument[{}]", index)),
+                    Expr::Const(THIS) => Some(format!("this[{}]", index)),
+                    Expr::Const(THAT) => Some(format!("that[{}]", index)),
+                    _ => None,
+                },
+                _ => None,
+            };
+            let segment = match address {
+                Expr::Binary(BinaryOp::Add, base, index) => match **index {
+                    Expr::Const(index) => segment(base, index),
+                    _ => None,
+                },
+                base => segment(base, 0),
+            };
+            if let Some(segment) = segment {
+                return segment;
+            }
+        }
+        format!("RAM[{}]", self.expr(address))
+    }
+
+    pub fn condition(&self, condition: &Condition) -> String {
+        let op = match condition.jump {
+            Jump::JGT => ">",
+            Jump::JEQ => "==",
+            Jump::JGE => ">=",
+            Jump::JLT => "<",
+            Jump::JNE => "!=",
+            Jump::JLE => "<=",
+            Jump::JMP => return "true".to_string(),
+        };
+        match &condition.value {
+            Expr::Binary(BinaryOp::Sub, a, b) => {
+                format!("{} {} {}", self.operand(a), op, self.operand(b))
+            }
+            Expr::Binary(BinaryOp::Add, a, b) => match **b {
+                Expr::Const(b) => {
+                    format!("{} {} {}", self.operand(a), op, (b as i16).wrapping_neg())
+                }
+                _ => format!("{} {} 0", self.operand(&condition.value), op),
+            },
+            value => format!("{} {} 0", self.operand(value), op),
+        }
+    }
+
+    pub fn destination(&self, destination: &Destination) -> String {
+        match destination {
+            Destination::Block(address) => self.label(*address),
+            Destination::Dynamic(target) => self.expr(target),
+        }
+    }
+
+    pub fn statement(&self, statement: &Statement) -> String {
+        match statement {
+            Statement::Store { address, value } => format!(
+                "{} = {}",
+                self.expr(&Expr::load(address.clone())),
+                self.expr(value)
+            ),
+            Statement::Assign { register, value } => format!(
+                "{} = {}",
+                self.expr(&Expr::Register(*register)),
+                self.expr(value)
+            ),
+            Statement::Let { temp, value } => format!("t{} = {}", temp, self.expr(value)),
+            Statement::Push(value) => format!("push {}", self.expr(value)),
+            Statement::Pop { address } => {
+                format!("pop {}", self.expr(&Expr::load(address.clone())))
+            }
+            Statement::Operation(operation) => operation.to_string(),
+            Statement::Call {
+                function,
+                arguments,
+            } => format!("call {} {}", self.label(*function), arguments),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::SP;
+
+    #[test]
+    fn segments_are_named_only_in_vm_code() {
+        let symbols = SymbolFile::default();
+        let local = Expr::load(Expr::sum(Expr::register(LCL), Expr::Const(3)));
+        assert_eq!(Printer::new(&symbols, true).expr(&local), "local[3]");
+        assert_eq!(Printer::new(&symbols, false).expr(&local), "RAM[R1 + 3]");
+        assert_eq!(Printer::new(&symbols, true).expr(&Expr::stack_top()), "top");
+        assert_eq!(Printer::new(&symbols, true).expr(&Expr::register(SP)), "SP");
+    }
+
+    #[test]
+    fn conditions_compare_both_sides_of_a_subtraction() {
+        let symbols = SymbolFile {
+            labels: vec![],
+            variables: vec![("i".to_string(), 16), ("n".to_string(), 17)],
+        };
+        let printer = Printer::new(&symbols, false);
+        let condition = Condition {
+            value: Expr::difference(Expr::register(16), Expr::register(17)),
+            jump: Jump::JGE,
+        };
+        assert_eq!(printer.condition(&condition), "i >= n");
+        assert_eq!(printer.condition(&condition.negate()), "i < n");
+        let condition = Condition {
+            value: Expr::difference(Expr::register(16), Expr::Const(10)),
+            jump: Jump::JEQ,
+        };
+        assert_eq!(printer.condition(&condition), "i == 10");
+    }
+}
@@ -0,0 +1,526 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    expr::Expr,
+    lift::{Destination, LiftedBlock, Statement, Terminator},
+    print::Printer,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Node {
+    Line(String),
+    Label(usize),
+    If {
+        condition: String,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    Loop {
+        condition: Option<String>,
+        body: Vec<Node>,
+    },
+    Break,
+    Continue,
+    Goto(usize),
+}
+
+pub struct Function {
+    pub header: String,
+    pub body: Vec<Node>,
+}
+
+// Immediate dominators by the iterative algorithm of Cooper, Harvey and
+// Kennedy. Nodes unreachable from the entry have no dominator.
+fn dominators(
+    entry: usize,
+    successors: &[Vec<usize>],
+    predecessors: &[Vec<usize>],
+) -> Vec<Option<usize>> {
+    let mut order = vec![];
+    let mut seen = vec![false; successors.len()];
+    let mut stack = vec![(entry, 0)];
+    seen[entry] = true;
+    while let Some((node, next)) = stack.pop() {
+        match successors[node].get(next) {
+            Some(&successor) => {
+                stack.push((node, next + 1));
+                if !seen[successor] {
+                    seen[successor] = true;
+                    stack.push((successor, 0));
+                }
+            }
+            None => order.push(node),
+        }
+    }
+    order.reverse();
+    let mut rank = vec![usize::MAX; successors.len()];
+    order
+        .iter()
+        .enumerate()
+        .for_each(|(i, &node)| rank[node] = i);
+
+    let mut idom = vec![None; successors.len()];
+    idom[entry] = Some(entry);
+    let intersect = |idom: &[Option<usize>], mut a: usize, mut b: usize| {
+        while a != b {
+            while rank[a] > rank[b] {
+                a = idom[a].unwrap();
+            }
+            while rank[b] > rank[a] {
+                b = idom[b].unwrap();
+            }
+        }
+        a
+    };
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &node in order.iter().skip(1) {
+            let new = predecessors[node]
+                .iter()
+                .filter(|&&predecessor| idom[predecessor].is_some())
+                .fold(None, |acc, &predecessor| match acc {
+                    None => Some(predecessor),
+                    Some(acc) => Some(intersect(&idom, acc, predecessor)),
+                });
+            if new != idom[node] {
+                idom[node] = new;
+                changed = true;
+            }
+        }
+    }
+    idom
+}
+
+struct Loop {
+    body: HashSet<usize>,
+    follow: Option<usize>,
+}
+
+struct Context {
+    header: usize,
+    follow: Option<usize>,
+}
+
+struct Structurer<'a> {
+    blocks: &'a [LiftedBlock],
+    printer: &'a Printer<'a>,
+    index: &'a HashMap<usize, usize>,
+    visited: &'a mut HashSet<usize>,
+    targets: &'a mut HashSet<usize>,
+    merges: HashMap<usize, Option<usize>>,
+    loops: HashMap<usize, Loop>,
+}
+
+impl<'a> Structurer<'a> {
+    fn new(
+        root: usize,
+        blocks: &'a [LiftedBlock],
+        printer: &'a Printer<'a>,
+        index: &'a HashMap<usize, usize>,
+        visited: &'a mut HashSet<usize>,
+        targets: &'a mut HashSet<usize>,
+    ) -> Self {
+        let mut nodes = vec![root];
+        let mut local = HashMap::new();
+        local.insert(root, 0);
+        let mut successors: Vec<Vec<usize>> = vec![];
+        let mut i = 0;
+        while i < nodes.len() {
+            let mut edges = vec![];
+            for successor in blocks[index[&nodes[i]]].successors() {
+                if !index.contains_key(&successor) {
+                    continue;
+                }
+                let next = *local.entry(successor).or_insert_with(|| {
+                    nodes.push(successor);
+                    nodes.len() - 1
+                });
+                if !edges.contains(&next) {
+                    edges.push(next);
+                }
+            }
+            successors.push(edges);
+            i += 1;
+        }
+        let mut predecessors = vec![vec![]; nodes.len()];
+        successors.iter().enumerate().for_each(|(node, edges)| {
+            edges
+                .iter()
+                .for_each(|&successor| predecessors[successor].push(node))
+        });
+        let idom = dominators(0, &successors, &predecessors);
+
+        // Post-dominators, with a virtual exit after every block that leaves the function.
+        let exit = nodes.len();
+        let mut reversed = predecessors.clone();
+        let mut forward = successors.clone();
+        reversed.push(vec![]);
+        forward.push(vec![]);
+        (0..nodes.len())
+            .filter(|&node| successors[node].is_empty())
+            .for_each(|node| {
+                reversed[exit].push(node);
+                forward[node].push(exit);
+            });
+        let ipdom = dominators(exit, &reversed, &forward);
+        let merges = (0..nodes.len())
+            .map(|node| {
+                let merge = ipdom[node]
+                    .filter(|&merge| merge != exit)
+                    .map(|merge| nodes[merge]);
+                (nodes[node], merge)
+            })
+            .collect();
+
+        let dominates = |a: usize, mut b: usize| loop {
+            if a == b {
+                return true;
+            }
+            match idom[b] {
+                Some(next) if next != b => b = next,
+                _ => return false,
+            }
+        };
+        let mut bodies: HashMap<usize, HashSet<usize>> = HashMap::new();
+        for (node, edges) in successors.iter().enumerate() {
+            for &header in edges.iter().filter(|&&header| dominates(header, node)) {
+                let body = bodies.entry(header).or_default();
+                body.insert(header);
+                let mut stack = vec![node];
+                while let Some(node) = stack.pop() {
+                    if body.insert(node) {
+                        stack.extend(&predecessors[node]);
+                    }
+                }
+            }
+        }
+        let loops = bodies
+            .into_iter()
+            .map(|(header, body)| {
+                let mut exits = body
+                    .iter()
+                    .flat_map(|&node| successors[node].iter().copied())
+                    .filter(|successor| !body.contains(successor))
+                    .map(|successor| nodes[successor])
+                    .collect::<Vec<_>>();
+                exits.sort_unstable();
+                let follow = ipdom[header]
+                    .filter(|&merge| merge != exit && !body.contains(&merge))
+                    .map(|merge| nodes[merge])
+                    .or_else(|| exits.first().copied());
+                let body = body.iter().map(|&node| nodes[node]).collect();
+                (nodes[header], Loop { body, follow })
+            })
+            .collect();
+
+        Self {
+            blocks,
+            printer,
+            index,
+            visited,
+            targets,
+            merges,
+            loops,
+        }
+    }
+
+    fn block(&self, address: usize) -> &'a LiftedBlock {
+        &self.blocks[self.index[&address]]
+    }
+
+    fn region(
+        &mut self,
+        start: usize,
+        stop: Option<usize>,
+        context: Option<&Context>,
+        mut in_header: bool,
+    ) -> Vec<Node> {
+        let mut nodes = vec![];
+        let mut current = start;
+        loop {
+            if !in_header {
+                if Some(current) == stop {
+                    break;
+                }
+                if let Some(context) = context {
+                    if current == context.header {
+                        nodes.push(Node::Continue);
+                        break;
+                    }
+                    if Some(current) == context.follow {
+                        nodes.push(Node::Break);
+                        break;
+                    }
+                }
+                if self.visited.contains(&current) || !self.index.contains_key(&current) {
+                    self.targets.insert(current);
+                    nodes.push(Node::Goto(current));
+                    break;
+                }
+                if self.loops.contains_key(&current) {
+                    let follow = self.loops[&current].follow;
+                    nodes.push(self.structure_loop(current));
+                    match follow {
+                        Some(follow) => {
+                            current = follow;
+                            continue;
+                        }
+                        None => break,
+                    }
+                }
+            }
+            in_header = false;
+            self.visited.insert(current);
+            let block = self.block(current);
+            nodes.push(Node::Label(current));
+            nodes.extend(
+                block
+                    .statements
+                    .iter()
+                    .map(|statement| Node::Line(self.printer.statement(statement))),
+            );
+            match &block.terminator {
+                Terminator::Next(next) | Terminator::Goto(Destination::Block(next)) => {
+                    current = *next
+                }
+                Terminator::Goto(Destination::Dynamic(target)) => {
+                    nodes.push(Node::Line(format!("goto {}", self.printer.expr(target))));
+                    break;
+                }
+                Terminator::Return => {
+                    nodes.push(Node::Line("return".to_string()));
+                    break;
+                }
+                Terminator::Halt => {
+                    nodes.push(Node::Line("halt".to_string()));
+                    break;
+                }
+                Terminator::Branch {
+                    condition,
+                    target: Destination::Dynamic(target),
+                    otherwise,
+                } => {
+                    nodes.push(Node::Line(format!(
+                        "if ({}) goto {}",
+                        self.printer.condition(condition),
+                        self.printer.expr(target)
+                    )));
+                    current = *otherwise;
+                }
+                Terminator::Branch {
+                    condition,
+                    target: Destination::Block(target),
+                    otherwise,
+                } => {
+                    let merge = self.merges.get(&current).copied().flatten();
+                    let then = self.region(*target, merge, context, false);
+                    let mut otherwise = self.region(*otherwise, merge, context, false);
+                    let node = if then.is_empty() {
+                        Node::If {
+                            condition: self.printer.condition(&condition.negate()),
+                            then: otherwise,
+                            otherwise: vec![],
+                        }
+                    } else {
+                        if otherwise.iter().all(|node| matches!(node, Node::Label(_))) {
+                            otherwise.clear();
+                        }
+                        Node::If {
+                            condition: self.printer.condition(condition),
+                            then,
+                            otherwise,
+                        }
+                    };
+                    nodes.push(node);
+                    match merge {
+                        Some(merge) => current = merge,
+                        None => break,
+                    }
+                }
+            }
+        }
+        nodes
+    }
+
+    fn structure_loop(&mut self, header: usize) -> Node {
+        let follow = self.loops[&header].follow;
+        let context = Context { header, follow };
+        let block = self.block(header);
+        let mut body = match &block.terminator {
+            Terminator::Branch {
+                condition,
+                target: Destination::Block(target),
+                otherwise,
+            } if block.statements.is_empty() && follow.is_some() => {
+                let inside = |address: &usize| self.loops[&header].body.contains(address);
+                let stay = if Some(*target) == follow && inside(otherwise) {
+                    Some((condition.negate(), *otherwise))
+                } else if Some(*otherwise) == follow && inside(target) {
+                    Some((condition.clone(), *target))
+                } else {
+                    None
+                };
+                if let Some((condition, next)) = stay {
+                    self.visited.insert(header);
+                    let mut body = vec![Node::Label(header)];
+                    body.extend(self.region(next, None, Some(&context), false));
+                    if body.last() == Some(&Node::Continue) {
+                        body.pop();
+                    }
+                    return Node::Loop {
+                        condition: Some(self.printer.condition(&condition)),
+                        body,
+                    };
+                }
+                self.region(header, None, Some(&context), true)
+            }
+            _ => self.region(header, None, Some(&context), true),
+        };
+        if body.last() == Some(&Node::Continue) {
+            body.pop();
+        }
+        Node::Loop {
+            condition: None,
+            body,
+        }
+    }
+}
+
+fn roots(blocks: &[LiftedBlock], printer: &Printer, index: &HashMap<usize, usize>) -> Vec<usize> {
+    let mut roots = blocks
+        .iter()
+        .flat_map(|block| block.statements.iter())
+        .filter_map(|statement| match statement {
+            Statement::Call { function, .. } => Some(*function),
+            _ => None,
+        })
+        .chain(blocks.iter().map(|block| block.start).filter(|&start| {
+            let label = printer.label(start);
+            label.contains('.') && !label.contains('$')
+        }))
+        .filter(|root| index.contains_key(root) && *root != 0)
+        .collect::<Vec<_>>();
+    roots.sort_unstable();
+    roots.dedup();
+    roots
+}
+
+pub fn structure(blocks: &[LiftedBlock], printer: &Printer) -> (Vec<Function>, HashSet<usize>) {
+    let index = blocks
+        .iter()
+        .enumerate()
+        .map(|(i, block)| (block.start, i))
+        .collect::<HashMap<_, _>>();
+    let mut visited = HashSet::new();
+    let mut targets = HashSet::new();
+    let mut functions = vec![];
+    if blocks.is_empty() {
+        return (functions, targets);
+    }
+    let function_roots = roots(blocks, printer, &index);
+    let mut pending = function_roots.clone();
+    pending.push(0);
+    pending.reverse();
+    loop {
+        let root = match pending.pop() {
+            Some(root) => root,
+            None => match blocks.iter().find(|block| !visited.contains(&block.start)) {
+                Some(block) => block.start,
+                None => break,
+            },
+        };
+        if visited.contains(&root) {
+            continue;
+        }
+        let mut structurer =
+            Structurer::new(root, blocks, printer, &index, &mut visited, &mut targets);
+        let mut body = structurer.region(root, None, None, false);
+        let header = if root == 0 {
+            "start".to_string()
+        } else if function_roots.contains(&root) {
+            let locals = leading_locals(&blocks[index[&root]], &mut body);
+            format!("function {} {}", printer.label(root), locals)
+        } else {
+            format!("block {}", printer.label(root))
+        };
+        functions.push(Function { header, body });
+    }
+    (functions, targets)
+}
+
+// Functions translated from the VM begin by pushing a zero for each local.
+fn leading_locals(entry: &LiftedBlock, body: &mut Vec<Node>) -> usize {
+    let locals = entry
+        .statements
+        .iter()
+        .take_while(|statement| **statement == Statement::Push(Expr::Const(0)))
+        .count();
+    let start = body
+        .iter()
+        .position(|node| !matches!(node, Node::Label(_)))
+        .unwrap_or(body.len());
+    body.drain(start..start + locals);
+    locals
+}
+
+pub fn write(functions: &[Function], targets: &HashSet<usize>, printer: &Printer) -> String {
+    let mut out = String::new();
+    for (i, function) in functions.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        out.push_str(&format!("{} {{\n", function.header));
+        write_nodes(&mut out, &function.body, 1, targets, printer);
+        out.push_str("}\n");
+    }
+    out
+}
+
+fn write_nodes(
+    out: &mut String,
+    nodes: &[Node],
+    depth: usize,
+    targets: &HashSet<usize>,
+    printer: &Printer,
+) {
+    let indent = "    ".repeat(depth);
+    for node in nodes {
+        match node {
+            Node::Line(line) => out.push_str(&format!("{}{}\n", indent, line)),
+            Node::Label(address) => {
+                if targets.contains(address) {
+                    out.push_str(&format!("{}:\n", printer.label(*address)));
+                }
+            }
+            Node::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                out.push_str(&format!("{}if ({}) {{\n", indent, condition));
+                write_nodes(out, then, depth + 1, targets, printer);
+                if !otherwise.is_empty() {
+                    out.push_str(&format!("{}}} else {{\n", indent));
+                    write_nodes(out, otherwise, depth + 1, targets, printer);
+                }
+                out.push_str(&format!("{}}}\n", indent));
+            }
+            Node::Loop { condition, body } => {
+                match condition {
+                    Some(condition) => {
+                        out.push_str(&format!("{}while ({}) {{\n", indent, condition))
+                    }
+                    None => out.push_str(&format!("{}loop {{\n", indent)),
+                }
+                write_nodes(out, body, depth + 1, targets, printer);
+                out.push_str(&format!("{}}}\n", indent));
+            }
+            Node::Break => out.push_str(&format!("{}break\n", indent)),
+            Node::Continue => out.push_str(&format!("{}continue\n", indent)),
+            Node::Goto(address) => {
+                out.push_str(&format!("{}goto {}\n", indent, printer.label(*address)))
+            }
+        }
+    }
+}
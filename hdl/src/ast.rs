@@ -0,0 +1,181 @@
+use std::fmt;
+
+use crate::span::Span;
+
+// Buses are at most as wide as a Hack word.
+pub const MAX_WIDTH: u16 = 16;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Identifier {
+    pub name: String,
+    pub span: Span,
+}
+
+// An input or output of a chip and its width, 1 unless declared as a bus.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pin {
+    pub name: Identifier,
+    pub width: u16,
+}
+
+// The bits from `start` to `end` of a bus, both included.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Slice {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl Slice {
+    pub fn width(&self) -> u16 {
+        self.end - self.start + 1
+    }
+}
+
+// A pin, or some of its bits.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bus {
+    pub name: Identifier,
+    pub slice: Option<Slice>,
+    pub span: Span,
+}
+
+// What a pin of a part is connected to: a pin of the chip, a wire between
+// parts named by the first part that outputs it, or a constant.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Bus(Bus),
+    Constant(bool, Span),
+}
+
+impl Value {
+    pub fn span(&self) -> Span {
+        match self {
+            Value::Bus(bus) => bus.span,
+            Value::Constant(_, span) => *span,
+        }
+    }
+}
+
+// `pin = value` in the connections of a part, `pin` belonging to the part.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Connection {
+    pub pin: Bus,
+    pub value: Value,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Part {
+    pub chip: Identifier,
+    pub connections: Vec<Connection>,
+    pub span: Span,
+}
+
+// A chip is made of parts, or built into the simulator. The outputs of a
+// clocked built-in chip only change on the clock, and its clocked inputs
+// only take effect then.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Implementation {
+    Parts(Vec<Part>),
+    Builtin {
+        name: Identifier,
+        clocked: Vec<Identifier>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chip {
+    pub name: Identifier,
+    pub inputs: Vec<Pin>,
+    pub outputs: Vec<Pin>,
+    pub implementation: Implementation,
+    pub span: Span,
+}
+
+impl Chip {
+    pub fn input(&self, name: &str) -> Option<&Pin> {
+        self.inputs.iter().find(|pin| pin.name.name == name)
+    }
+
+    pub fn output(&self, name: &str) -> Option<&Pin> {
+        self.outputs.iter().find(|pin| pin.name.name == name)
+    }
+}
+
+impl fmt::Display for Pin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.width {
+            1 => write!(f, "{}", self.name.name),
+            width => write!(f, "{}[{}]", self.name.name, width),
+        }
+    }
+}
+
+impl fmt::Display for Bus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name.name)?;
+        match self.slice {
+            Some(Slice { start, end }) if start == end => write!(f, "[{}]", start),
+            Some(Slice { start, end }) => write!(f, "[{}..{}]", start, end),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Bus(bus) => write!(f, "{}", bus),
+            Value::Constant(value, _) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl fmt::Display for Part {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let connections = self
+            .connections
+            .iter()
+            .map(|connection| format!("{}={}", connection.pin, connection.value))
+            .collect::<Vec<_>>();
+        write!(f, "{}({});", self.chip.name, connections.join(", "))
+    }
+}
+
+// Prints the chip as HDL in the layout of the course's chips.
+impl fmt::Display for Chip {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pins = |pins: &[Pin]| {
+            pins.iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        writeln!(f, "CHIP {} {{", self.name.name)?;
+        if !self.inputs.is_empty() {
+            writeln!(f, "    IN {};", pins(&self.inputs))?;
+        }
+        if !self.outputs.is_empty() {
+            writeln!(f, "    OUT {};", pins(&self.outputs))?;
+        }
+        writeln!(f)?;
+        match &self.implementation {
+            Implementation::Parts(parts) => {
+                writeln!(f, "    PARTS:")?;
+                for part in parts {
+                    writeln!(f, "    {}", part)?;
+                }
+            }
+            Implementation::Builtin { name, clocked } => {
+                writeln!(f, "    BUILTIN {};", name.name)?;
+                if !clocked.is_empty() {
+                    let names = clocked
+                        .iter()
+                        .map(|name| name.name.as_str())
+                        .collect::<Vec<_>>();
+                    writeln!(f, "    CLOCKED {};", names.join(", "))?;
+                }
+            }
+        }
+        writeln!(f, "}}")
+    }
+}
@@ -0,0 +1,13 @@
+pub mod ast;
+pub mod library;
+pub mod native;
+pub mod netlist;
+pub mod parser;
+pub mod script;
+pub mod simulator;
+pub mod span;
+#[cfg(test)]
+mod temp;
+pub mod tester;
+pub mod token;
+pub mod tokenizer;
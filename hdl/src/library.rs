@@ -0,0 +1,57 @@
+use crate::{ast::Chip, parser::parse};
+
+// The chips of the course, used for parts missing from a chip's directory.
+// Nand and DFF are the primitives. The memories, the screen, the keyboard and
+// the ROM are built in as words, and the others are made of parts.
+pub const CHIPS: [(&str, &str); 38] = [
+    ("Nand", include_str!("../chips/Nand.hdl")),
+    ("DFF", include_str!("../chips/DFF.hdl")),
+    ("RAM8", include_str!("../chips/RAM8.hdl")),
+    ("RAM64", include_str!("../chips/RAM64.hdl")),
+    ("RAM512", include_str!("../chips/RAM512.hdl")),
+    ("RAM4K", include_str!("../chips/RAM4K.hdl")),
+    ("RAM16K", include_str!("../chips/RAM16K.hdl")),
+    ("Screen", include_str!("../chips/Screen.hdl")),
+    ("Keyboard", include_str!("../chips/Keyboard.hdl")),
+    ("ROM32K", include_str!("../chips/ROM32K.hdl")),
+    ("Not", include_str!("../chips/Not.hdl")),
+    ("And", include_str!("../chips/And.hdl")),
+    ("Or", include_str!("../chips/Or.hdl")),
+    ("Xor", include_str!("../chips/Xor.hdl")),
+    ("Mux", include_str!("../chips/Mux.hdl")),
+    ("DMux", include_str!("../chips/DMux.hdl")),
+    ("Not16", include_str!("../chips/Not16.hdl")),
+    ("And16", include_str!("../chips/And16.hdl")),
+    ("Or16", include_str!("../chips/Or16.hdl")),
+    ("Mux16", include_str!("../chips/Mux16.hdl")),
+    ("Or8Way", include_str!("../chips/Or8Way.hdl")),
+    ("Mux4Way16", include_str!("../chips/Mux4Way16.hdl")),
+    ("Mux8Way16", include_str!("../chips/Mux8Way16.hdl")),
+    ("DMux4Way", include_str!("../chips/DMux4Way.hdl")),
+    ("DMux8Way", include_str!("../chips/DMux8Way.hdl")),
+    ("HalfAdder", include_str!("../chips/HalfAdder.hdl")),
+    ("FullAdder", include_str!("../chips/FullAdder.hdl")),
+    ("Add16", include_str!("../chips/Add16.hdl")),
+    ("Inc16", include_str!("../chips/Inc16.hdl")),
+    ("ALU", include_str!("../chips/ALU.hdl")),
+    ("Bit", include_str!("../chips/Bit.hdl")),
+    ("Register", include_str!("../chips/Register.hdl")),
+    ("ARegister", include_str!("../chips/ARegister.hdl")),
+    ("DRegister", include_str!("../chips/DRegister.hdl")),
+    ("PC", include_str!("../chips/PC.hdl")),
+    ("CPU", include_str!("../chips/CPU.hdl")),
+    ("Memory", include_str!("../chips/Memory.hdl")),
+    ("Computer", include_str!("../chips/Computer.hdl")),
+];
+
+pub fn source(name: &str) -> Option<&'static str> {
+    CHIPS
+        .iter()
+        .find(|(chip, _)| *chip == name)
+        .map(|(_, source)| *source)
+}
+
+pub fn chip(name: &str) -> Option<Chip> {
+    source(name)
+        .map(|source| parse(source).unwrap_or_else(|errors| panic!("{}.hdl:{}", name, errors[0])))
+}
@@ -0,0 +1,133 @@
+use std::{
+    error::Error,
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+
+use clap::{app_from_crate, crate_authors, crate_description, crate_name, crate_version, Arg};
+
+use hdl::{
+    netlist::elaborate_file,
+    parser::parse,
+    tester::{test, Options},
+};
+
+// A file, or every file in a directory with the extension in name order.
+fn sources(path: &Path, extension: &str) -> io::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    files.retain(|file| file.is_file() && file.extension().is_some_and(|ext| ext == extension));
+    files.sort();
+    Ok(files)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = app_from_crate!()
+        .arg(
+            Arg::with_name("path")
+                .help("The HDL file, or a directory of HDL files, to check, or the test scripts to run")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("print")
+                .long("print")
+                .short("p")
+                .help("Print each chip as canonical HDL"),
+        )
+        .arg(
+            Arg::with_name("netlist")
+                .long("netlist")
+                .short("n")
+                .conflicts_with("print")
+                .help("Flatten each chip into gates, taking its parts from its directory or else the built-in chips, and count them"),
+        )
+        .arg(
+            Arg::with_name("test")
+                .long("test")
+                .short("t")
+                .conflicts_with_all(&["print", "netlist"])
+                .help("Run .tst scripts, writing their .out files and comparing them to their .cmp files"),
+        )
+        .arg(
+            Arg::with_name("native")
+                .long("native")
+                .requires("test")
+                .help("Run the scripts on the Rust chips of the computer crate instead of the HDL"),
+        )
+        .arg(
+            Arg::with_name("key")
+                .long("key")
+                .takes_value(true)
+                .requires("test")
+                .help("The code of the key held down on the keyboard"),
+        )
+        .get_matches();
+
+    let path = Path::new(args.value_of("path").unwrap());
+    let mut failed = false;
+    if args.is_present("test") {
+        let options = Options {
+            native: args.is_present("native"),
+            key: args.value_of("key").map_or(Ok(0), str::parse)?,
+        };
+        for script in sources(path, "tst")? {
+            match test(&script, options) {
+                Ok(report) => {
+                    for echo in report.echoes {
+                        println!("{}: {}", script.display(), echo);
+                    }
+                    match report.compared {
+                        true => println!(
+                            "{}: End of script - Comparison ended successfully",
+                            script.display()
+                        ),
+                        false => println!("{}: End of script", script.display()),
+                    }
+                }
+                Err(error) => {
+                    eprintln!("{}", error);
+                    failed = true;
+                }
+            }
+        }
+        if failed {
+            process::exit(1);
+        }
+        return Ok(());
+    }
+    for path in sources(path, "hdl")? {
+        let source = fs::read_to_string(&path)?;
+        match parse(&source) {
+            Ok(_) if args.is_present("netlist") => match elaborate_file(&path) {
+                Ok(netlist) => println!(
+                    "{}: {} Nand gates, {} DFFs, {} memories",
+                    path.display(),
+                    netlist.nands(),
+                    netlist.dffs.len(),
+                    netlist.memories.len()
+                ),
+                Err(error) => {
+                    eprintln!("{}", error);
+                    failed = true;
+                }
+            },
+            Ok(chip) if args.is_present("print") => print!("{}", chip),
+            Ok(_) => {}
+            Err(errors) => {
+                for error in errors {
+                    eprintln!("{}:{}", path.display(), error);
+                }
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,521 @@
+use std::path::Path;
+
+use computer::{
+    chip::{
+        arith::{add, alu, full_adder, half_adder, inc},
+        basic::{and, dmux, dmux4way, dmux8way, mux, mux4way, mux8way, nand, not, or, xor},
+        mem::{Dff, Pc, Ram16k, Ram4k, Ram512, Ram64, Ram8, Ram8k, Register},
+    },
+    keyboard::Keyboard,
+    memory::Memory,
+    rom::Rom,
+    screen::DummyScreen,
+    signal::Word,
+    Computer,
+};
+
+use crate::{
+    ast::Chip,
+    library,
+    script::Variable,
+    tester::{program, Target, TargetError},
+};
+
+// The keyboard of the Rust Memory and Computer, holding down the key given to
+// the tester.
+pub struct HeldKey(u16);
+
+impl Keyboard for HeldKey {
+    type State = u16;
+
+    fn new() -> Self {
+        HeldKey(0)
+    }
+
+    fn get_output(&self) -> Word {
+        Word::from(self.0)
+    }
+
+    fn set_state(&mut self, key: u16) {
+        self.0 = key;
+    }
+}
+
+fn bit(value: u16) -> bool {
+    value & 1 == 1
+}
+
+fn word(value: u16) -> Word {
+    Word::from(value)
+}
+
+// An address as the Rust chips take it, most significant bit first.
+fn address<const N: usize>(value: u16) -> [bool; N] {
+    let mut bits = [false; N];
+    for (i, bit) in bits.iter_mut().enumerate() {
+        *bit = value >> (N - 1 - i) & 1 == 1;
+    }
+    bits
+}
+
+// The outputs of a combinational chip from its inputs, in the order of its
+// pins.
+type Function = fn(&[u16]) -> Vec<u16>;
+
+fn function(name: &str) -> Option<Function> {
+    let function: Function = match name {
+        "Nand" => |i| vec![nand(bit(i[0]), bit(i[1])) as u16],
+        "Not" => |i| vec![not(bit(i[0])) as u16],
+        "And" => |i| vec![and(bit(i[0]), bit(i[1])) as u16],
+        "Or" => |i| vec![or(bit(i[0]), bit(i[1])) as u16],
+        "Xor" => |i| vec![xor(bit(i[0]), bit(i[1])) as u16],
+        "Mux" => |i| vec![mux(bit(i[0]), bit(i[1]), bit(i[2])) as u16],
+        "DMux" => |i| {
+            let (a, b) = dmux(bit(i[0]), bit(i[1]));
+            vec![a as u16, b as u16]
+        },
+        "Not16" => |i| vec![not(word(i[0])).as_raw()],
+        "And16" => |i| vec![and(word(i[0]), word(i[1])).as_raw()],
+        "Or16" => |i| vec![or(word(i[0]), word(i[1])).as_raw()],
+        "Mux16" => |i| vec![mux(word(i[0]), word(i[1]), bit(i[2])).as_raw()],
+        "Or8Way" => |i| vec![(0..8).fold(false, |out, k| or(out, bit(i[0] >> k))) as u16],
+        "Mux4Way16" => |i| {
+            let (s1, s0) = (bit(i[4] >> 1), bit(i[4]));
+            vec![mux4way(word(i[0]), word(i[1]), word(i[2]), word(i[3]), s1, s0).as_raw()]
+        },
+        "Mux8Way16" => |i| {
+            let (s2, s1, s0) = (bit(i[8] >> 2), bit(i[8] >> 1), bit(i[8]));
+            let w = |k: usize| word(i[k]);
+            vec![mux8way(w(0), w(1), w(2), w(3), w(4), w(5), w(6), w(7), s2, s1, s0).as_raw()]
+        },
+        "DMux4Way" => |i| {
+            let (a, b, c, d) = dmux4way(bit(i[0]), bit(i[1] >> 1), bit(i[1]));
+            vec![a as u16, b as u16, c as u16, d as u16]
+        },
+        "DMux8Way" => |i| {
+            let (a, b, c, d, e, f, g, h) =
+                dmux8way(bit(i[0]), bit(i[1] >> 2), bit(i[1] >> 1), bit(i[1]));
+            [a, b, c, d, e, f, g, h]
+                .iter()
+                .map(|&out| out as u16)
+                .collect()
+        },
+        "HalfAdder" => |i| {
+            let (carry, sum) = half_adder(bit(i[0]), bit(i[1]));
+            vec![sum as u16, carry as u16]
+        },
+        "FullAdder" => |i| {
+            let (carry, sum) = full_adder(bit(i[0]), bit(i[1]), bit(i[2]));
+            vec![sum as u16, carry as u16]
+        },
+        "Add16" => |i| vec![add(word(i[0]), word(i[1])).as_raw()],
+        "Inc16" => |i| vec![inc(word(i[0])).as_raw()],
+        "ALU" => |i| {
+            let (out, zr, ng) = alu(
+                word(i[0]),
+                word(i[1]),
+                bit(i[2]),
+                bit(i[3]),
+                bit(i[4]),
+                bit(i[5]),
+                bit(i[6]),
+                bit(i[7]),
+            );
+            vec![out.as_raw(), zr as u16, ng as u16]
+        },
+        _ => return None,
+    };
+    Some(function)
+}
+
+// The memories of the course, the screen's included.
+enum Words {
+    Ram8(Box<Ram8<Word>>),
+    Ram64(Box<Ram64<Word>>),
+    Ram512(Box<Ram512<Word>>),
+    Ram4k(Box<Ram4k<Word>>),
+    Screen(Box<Ram8k<Word>>),
+    Ram16k(Box<Ram16k<Word>>),
+}
+
+impl Words {
+    fn size(&self) -> usize {
+        match self {
+            Words::Ram8(_) => 8,
+            Words::Ram64(_) => 64,
+            Words::Ram512(_) => 512,
+            Words::Ram4k(_) => 4096,
+            Words::Screen(_) => 8192,
+            Words::Ram16k(_) => 16384,
+        }
+    }
+
+    // Selects a word, loading it first if asked, and outputs it.
+    fn access(&mut self, at: u16, load: bool, input: u16) -> u16 {
+        let input = word(input);
+        match self {
+            Words::Ram8(ram) => {
+                ram.tick(&address(at), load, input);
+                ram.get_output()
+            }
+            Words::Ram64(ram) => {
+                ram.tick(&address(at), load, input);
+                ram.get_output()
+            }
+            Words::Ram512(ram) => {
+                ram.tick(&address(at), load, input);
+                ram.get_output()
+            }
+            Words::Ram4k(ram) => {
+                ram.tick(&address(at), load, input);
+                ram.get_output()
+            }
+            Words::Screen(ram) => {
+                ram.tick(&address(at), load, input);
+                ram.get_output()
+            }
+            Words::Ram16k(ram) => {
+                ram.tick(&address(at), load, input);
+                ram.get_output()
+            }
+        }
+        .as_raw()
+    }
+}
+
+// The registers of CPU.hdl around the ALU and the jump logic.
+struct Cpu {
+    a: Register<Word>,
+    d: Register<Word>,
+    pc: Pc,
+}
+
+// What the CPU computes from its inputs before the clock.
+struct Cycle {
+    out: Word,
+    write: bool,
+    load_a: bool,
+    a: Word,
+    load_d: bool,
+    jump: bool,
+}
+
+impl Cpu {
+    fn cycle(&self, inputs: &[u16]) -> Cycle {
+        let (in_m, instruction) = (word(inputs[0]), word(inputs[1]));
+        let i = instruction.split();
+        let c = i[0];
+        let (out, zr, ng) = alu(
+            self.d.get_output(),
+            mux(self.a.get_output(), in_m, i[3]),
+            i[4],
+            i[5],
+            i[6],
+            i[7],
+            i[8],
+            i[9],
+        );
+        let positive = not(or(zr, ng));
+        let jumps = or(or(and(i[13], ng), and(i[14], zr)), and(i[15], positive));
+        Cycle {
+            out,
+            write: and(c, i[12]),
+            load_a: or(not(c), i[10]),
+            a: mux(instruction, out, c),
+            load_d: and(c, i[11]),
+            jump: and(c, jumps),
+        }
+    }
+}
+
+// The Rust counterpart of a chip. Clocked parts take in their inputs on
+// tick, and their outputs change on tock.
+enum Part {
+    Gates(Function),
+    Dff(Dff<bool>),
+    Bit(Register<bool>),
+    Register(Register<Word>),
+    Pc(Pc),
+    Ram(Words),
+    Keyboard(u16),
+    Rom(Box<Rom>),
+    Memory(Box<Memory<DummyScreen, HeldKey>>),
+    Cpu(Cpu),
+    Computer(Box<Computer<DummyScreen, HeldKey>>),
+}
+
+// A chip run by the chips of the computer crate, with the pins of the chip
+// of the same name among the built-in chips.
+pub struct Native {
+    chip: Chip,
+    // The inputs then the outputs.
+    values: Vec<u16>,
+    // The address a memory outputs the word at.
+    address: u16,
+    part: Part,
+}
+
+impl Native {
+    pub fn new(name: &str, key: u16) -> Option<Self> {
+        let chip = library::chip(name)?;
+        let part = match name {
+            "DFF" => Part::Dff(Dff::new()),
+            "Bit" => Part::Bit(Register::new()),
+            "Register" | "ARegister" | "DRegister" => Part::Register(Register::new()),
+            "PC" => Part::Pc(Pc::new()),
+            "RAM8" => Part::Ram(Words::Ram8(Box::new(Ram8::new()))),
+            "RAM64" => Part::Ram(Words::Ram64(Box::new(Ram64::new()))),
+            "RAM512" => Part::Ram(Words::Ram512(Box::new(Ram512::new()))),
+            "RAM4K" => Part::Ram(Words::Ram4k(Box::new(Ram4k::new()))),
+            "Screen" => Part::Ram(Words::Screen(Box::new(Ram8k::new()))),
+            "RAM16K" => Part::Ram(Words::Ram16k(Box::new(Ram16k::new()))),
+            "Keyboard" => Part::Keyboard(key),
+            "ROM32K" => Part::Rom(Box::new(Rom::new())),
+            "Memory" => {
+                let mut memory = Box::new(Memory::new());
+                memory.set_keystate(key);
+                Part::Memory(memory)
+            }
+            "CPU" => Part::Cpu(Cpu {
+                a: Register::new(),
+                d: Register::new(),
+                pc: Pc::new(),
+            }),
+            "Computer" => {
+                let mut computer = Box::new(Computer::new());
+                computer.set_keystate(key);
+                Part::Computer(computer)
+            }
+            _ => Part::Gates(function(name)?),
+        };
+        let values = vec![0; chip.inputs.len() + chip.outputs.len()];
+        let mut native = Self {
+            chip,
+            values,
+            address: 0,
+            part,
+        };
+        native.eval();
+        Some(native)
+    }
+
+    fn pin(&self, name: &str) -> Option<usize> {
+        self.chip
+            .inputs
+            .iter()
+            .chain(&self.chip.outputs)
+            .position(|pin| pin.name.name == name)
+    }
+
+    // The bits of a pin.
+    fn mask(&self, index: usize) -> u16 {
+        let pin = match index.checked_sub(self.chip.inputs.len()) {
+            Some(output) => &self.chip.outputs[output],
+            None => &self.chip.inputs[index],
+        };
+        ((1u32 << pin.width) - 1) as u16
+    }
+
+    fn outputs(&mut self) -> Vec<u16> {
+        let i = &self.values[..self.chip.inputs.len()];
+        match &mut self.part {
+            Part::Gates(function) => function(i),
+            Part::Dff(dff) => vec![dff.get_output() as u16],
+            Part::Bit(bit) => vec![bit.get_output() as u16],
+            Part::Register(register) => vec![register.get_output().as_raw()],
+            Part::Pc(pc) => vec![pc.get_output().as_raw()],
+            Part::Ram(ram) => {
+                self.address = i[2];
+                vec![ram.access(i[2], false, 0)]
+            }
+            Part::Keyboard(key) => vec![*key],
+            Part::Rom(rom) => {
+                rom.set_address(&address(i[0]));
+                vec![rom.get_output().as_raw()]
+            }
+            Part::Memory(memory) => {
+                memory.tick(&address(i[2]), false, word(i[0]));
+                vec![memory.get_output().as_raw()]
+            }
+            Part::Cpu(cpu) => {
+                let cycle = cpu.cycle(i);
+                let (a, pc) = (cpu.a.get_output().as_raw(), cpu.pc.get_output().as_raw());
+                vec![cycle.out.as_raw(), cycle.write as u16, a, pc]
+            }
+            Part::Computer(_) => vec![],
+        }
+    }
+}
+
+impl Target for Native {
+    fn get(&mut self, variable: &Variable) -> Result<u16, TargetError> {
+        let unknown = || TargetError::UnknownVariable(variable.text.clone());
+        let index = match variable.index {
+            Some(index) => index,
+            None => {
+                return self
+                    .pin(&variable.name)
+                    .map(|pin| self.values[pin])
+                    .ok_or_else(unknown)
+            }
+        };
+        let own = variable.name == self.chip.name.name;
+        Ok(match (&mut self.part, variable.name.as_str(), index) {
+            (Part::Cpu(cpu), "ARegister", 0) => cpu.a.get_output().as_raw(),
+            (Part::Cpu(cpu), "DRegister", 0) => cpu.d.get_output().as_raw(),
+            (Part::Cpu(cpu), "PC", 0) => cpu.pc.get_output().as_raw(),
+            (Part::Computer(computer), "ARegister", 0) => computer.a().as_raw(),
+            (Part::Computer(computer), "DRegister", 0) => computer.d().as_raw(),
+            (Part::Computer(computer), "PC", 0) => computer.pc().as_raw(),
+            (Part::Computer(computer), "RAM16K", index) if index < 16384 => {
+                computer.peek(index as u16)
+            }
+            (Part::Register(register), _, 0) if own => register.get_output().as_raw(),
+            (Part::Pc(pc), _, 0) if own => pc.get_output().as_raw(),
+            (Part::Ram(ram), _, index) if own && index < ram.size() => {
+                let value = ram.access(index as u16, false, 0);
+                ram.access(self.address, false, 0);
+                value
+            }
+            _ => return Err(unknown()),
+        })
+    }
+
+    fn set(&mut self, variable: &Variable, value: u16) -> Result<(), TargetError> {
+        let index = match (variable.index, self.pin(&variable.name)) {
+            (Some(index), _) => index,
+            (None, Some(pin)) if pin < self.chip.inputs.len() => {
+                self.values[pin] = value & self.mask(pin);
+                return Ok(());
+            }
+            (None, Some(_)) => return Err(TargetError::NotInput(variable.text.clone())),
+            (None, None) => return Err(TargetError::UnknownVariable(variable.text.clone())),
+        };
+        let own = variable.name == self.chip.name.name;
+        match (&mut self.part, variable.name.as_str(), index) {
+            (Part::Computer(computer), "RAM16K", index) if index < 16384 => {
+                computer.poke(index as u16, value)
+            }
+            (Part::Ram(ram), _, index) if own && index < ram.size() => {
+                ram.access(index as u16, true, value);
+                ram.access(self.address, false, 0);
+            }
+            _ => return Err(TargetError::NotInput(variable.text.clone())),
+        }
+        Ok(())
+    }
+
+    fn eval(&mut self) {
+        let outputs = self.outputs();
+        let inputs = self.chip.inputs.len();
+        for (index, value) in outputs.into_iter().enumerate() {
+            self.values[inputs + index] = value & self.mask(inputs + index);
+        }
+    }
+
+    // The outputs stay as they were computed until the tock.
+    fn tick(&mut self) {
+        self.eval();
+        let i = &self.values[..self.chip.inputs.len()];
+        match &mut self.part {
+            Part::Dff(dff) => dff.tick(bit(i[0])),
+            Part::Bit(register) => register.tick(bit(i[1]), bit(i[0])),
+            Part::Register(register) => register.tick(bit(i[1]), word(i[0])),
+            Part::Pc(pc) => pc.tick(bit(i[3]), bit(i[1]), bit(i[2]), word(i[0])),
+            Part::Ram(ram) => {
+                ram.access(i[2], bit(i[1]), i[0]);
+            }
+            Part::Memory(memory) => memory.tick(&address(i[2]), bit(i[1]), word(i[0])),
+            Part::Cpu(cpu) => {
+                let cycle = cpu.cycle(i);
+                let a = cpu.a.get_output();
+                cpu.pc.tick(bit(i[2]), cycle.jump, true, a);
+                cpu.a.tick(cycle.load_a, cycle.a);
+                cpu.d.tick(cycle.load_d, cycle.out);
+            }
+            Part::Computer(computer) => computer.tick(bit(i[0])),
+            Part::Gates(_) | Part::Keyboard(_) | Part::Rom(_) => {}
+        }
+    }
+
+    fn tock(&mut self) {
+        self.eval();
+    }
+
+    fn command(&mut self, part: &str, command: &str, file: &Path) -> Result<(), TargetError> {
+        let rom = || program(file).map(|program| Rom::from_words(&program));
+        match (&mut self.part, part, command) {
+            (Part::Rom(loaded), "ROM32K", "load") => **loaded = rom()?,
+            (Part::Computer(computer), "ROM32K", "load") => computer.set_rom(rom()?),
+            _ => {
+                return Err(TargetError::UnknownCommand(
+                    part.to_string(),
+                    command.to_string(),
+                ))
+            }
+        }
+        self.eval();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{netlist::elaborate, simulator::Simulator};
+
+    #[test]
+    fn rust_chips_match_the_hdl_chips() {
+        let mut random = 0x2545_f491u32;
+        for (name, source) in library::CHIPS.iter() {
+            let mut native = match Native::new(name, 0) {
+                Some(native) => native,
+                None => panic!("no Rust chip {}", name),
+            };
+            let netlist = elaborate(source, &format!("{}.hdl", name), None).unwrap();
+            let mut hdl = Simulator::new(netlist);
+            let chip = library::chip(name).unwrap();
+            for step in 0..50 {
+                for pin in &chip.inputs {
+                    random ^= random << 13;
+                    random ^= random >> 17;
+                    random ^= random << 5;
+                    // Mostly low addresses, so the memories read back words
+                    // written earlier.
+                    let value = match pin.name.name.as_str() {
+                        "address" | "reset" => random as u16 & 7,
+                        _ => random as u16,
+                    };
+                    let variable = Variable {
+                        name: pin.name.name.clone(),
+                        index: None,
+                        text: pin.name.name.clone(),
+                        span: Default::default(),
+                    };
+                    native.set(&variable, value).unwrap();
+                    hdl.set(&pin.name.name, value).unwrap();
+                }
+                native.tick();
+                hdl.tick();
+                native.tock();
+                hdl.tock();
+                for pin in &chip.outputs {
+                    let variable = Variable {
+                        name: pin.name.name.clone(),
+                        index: None,
+                        text: pin.name.name.clone(),
+                        span: Default::default(),
+                    };
+                    assert_eq!(
+                        native.get(&variable).unwrap(),
+                        hdl.get(&pin.name.name).unwrap(),
+                        "{}.{} at step {}",
+                        name,
+                        pin.name.name,
+                        step
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,1070 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt, fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use thiserror::Error;
+
+use crate::{
+    ast::{Chip, Identifier, Implementation, Slice, Value},
+    library,
+    parser::{parse, ParseError},
+    span::Span,
+};
+
+// A net of the netlist, connecting the pins wired together. The first two
+// are the constants.
+pub type Wire = u32;
+pub const FALSE: Wire = 0;
+pub const TRUE: Wire = 1;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Located {
+    pub file: String,
+    pub span: Span,
+}
+
+impl fmt::Display for Located {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.span)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ElaborateError {
+    #[error("{0}: {1}")]
+    Io(String, io::Error),
+
+    #[error("{}", parse_errors(.0, .1))]
+    Parse(String, Vec<ParseError>),
+
+    #[error("{0}: No chip named {1} next to the chip or among the built-in chips")]
+    UnknownChip(Located, String),
+
+    #[error("{0}: Expected chip {1}, found chip {2}")]
+    WrongChip(Located, String, String),
+
+    #[error("{0}: There is no built-in chip named {1}")]
+    UnknownBuiltin(Located, String),
+
+    #[error("{0}: Built-in chip {1} needs a pin {2} {3} bits wide")]
+    BuiltinPin(Located, String, String, u16),
+
+    #[error("{0}: Chip {1} contains itself")]
+    Recursive(Located, String),
+
+    #[error("{0}: Chip {1} has no pin {2}")]
+    UnknownPin(Located, String, String),
+
+    #[error("{0}: Pin {1} of {2} has no bit {3}")]
+    OutsidePin(Located, String, String, u16),
+
+    #[error("{0}: {1} is {2} bits wide but {3} is {4}")]
+    WidthMismatch(Located, String, usize, String, usize),
+
+    #[error("{0}: Output pin {1} of {2} cannot be connected to a constant")]
+    ConstantOutput(Located, String, String),
+
+    #[error("{0}: Input pin {1} cannot be driven by a part")]
+    DrivenInput(Located, String),
+
+    #[error("{0}: Output pin {1} cannot be read by a part")]
+    ReadOutput(Located, String),
+
+    #[error("{0}: {1} is driven by more than one part")]
+    DrivenTwice(Located, String),
+
+    #[error("{0}: Pin {1} of {2} is connected twice")]
+    ConnectedTwice(Located, String, String),
+
+    #[error("{0}: {1} is not an output of any part")]
+    Undriven(Located, String),
+
+    #[error("Combinational loop through {}", .0.join(", "))]
+    CombinationalLoop(Vec<String>),
+}
+
+fn parse_errors(file: &str, errors: &[ParseError]) -> String {
+    errors
+        .iter()
+        .map(|error| format!("{}:{}", file, error))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub type Result<T> = std::result::Result<T, ElaborateError>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Nand {
+    pub a: Wire,
+    pub b: Wire,
+    pub out: Wire,
+}
+
+// Takes in its input on tick and outputs it from tock.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dff {
+    pub input: Wire,
+    pub out: Wire,
+}
+
+// A built-in chip holding words: the RAMs, the screen, the ROM and the
+// keyboard. It outputs the word at its address as soon as the address
+// changes, and if it has an input, writes it on the clock when load is 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Memory {
+    pub instance: usize,
+    pub size: usize,
+    pub address: Vec<Wire>,
+    pub input: Vec<Wire>,
+    pub load: Wire,
+    pub out: Vec<Wire>,
+}
+
+// A combinational part of the netlist.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Node {
+    Nand(Nand),
+    Read(usize),
+}
+
+#[derive(Debug)]
+pub struct Definition {
+    pub file: String,
+    pub chip: Chip,
+}
+
+// A chip in the hierarchy of parts, other than the Nand gates and DFFs.
+#[derive(Clone, Debug)]
+pub struct Instance {
+    pub definition: Rc<Definition>,
+    pub parent: Option<usize>,
+    // Where the parent has it as a part.
+    pub span: Span,
+    // The wires of its inputs then its outputs, from bit 0 up.
+    pub pins: Vec<Vec<Wire>>,
+}
+
+impl Instance {
+    pub fn name(&self) -> &str {
+        &self.definition.chip.name.name
+    }
+
+    pub fn pin(&self, name: &str) -> Option<&[Wire]> {
+        let chip = &self.definition.chip;
+        chip.inputs
+            .iter()
+            .chain(&chip.outputs)
+            .position(|pin| pin.name.name == name)
+            .map(|position| self.pins[position].as_slice())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Netlist {
+    pub wires: usize,
+    // The Nand gates and memory reads, each after the nodes driving it.
+    pub order: Vec<Node>,
+    pub dffs: Vec<Dff>,
+    pub memories: Vec<Memory>,
+    // The chip simulated comes first, then every chip in it depth first.
+    pub instances: Vec<Instance>,
+    // The pins connecting the parts of the chip simulated.
+    pub internal: Vec<(String, Vec<Wire>)>,
+}
+
+impl Netlist {
+    pub fn chip(&self) -> &Chip {
+        &self.instances[0].definition.chip
+    }
+
+    // A pin of the chip, or a pin between its parts.
+    pub fn pin(&self, name: &str) -> Option<&[Wire]> {
+        self.instances[0].pin(name).or_else(|| {
+            self.internal
+                .iter()
+                .find(|(internal, _)| internal == name)
+                .map(|(_, wires)| wires.as_slice())
+        })
+    }
+
+    pub fn nands(&self) -> usize {
+        self.order
+            .iter()
+            .filter(|node| matches!(node, Node::Nand(_)))
+            .count()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Input,
+    Output,
+    Internal,
+}
+
+struct Signal {
+    kind: Kind,
+    wires: Vec<Wire>,
+    driven: Vec<bool>,
+}
+
+fn bits(slice: Option<Slice>, width: usize) -> Range<usize> {
+    slice.map_or(0..width, |slice| {
+        slice.start as usize..slice.end as usize + 1
+    })
+}
+
+// The wires of a pin a built-in chip needs, failing if its declaration
+// doesn't have it.
+fn port(
+    definition: &Definition,
+    pins: &[Vec<Wire>],
+    builtin: &Identifier,
+    name: &str,
+    width: u16,
+) -> Result<Vec<Wire>> {
+    let chip = &definition.chip;
+    chip.inputs
+        .iter()
+        .chain(&chip.outputs)
+        .zip(pins)
+        .find(|(pin, _)| pin.name.name == name && pin.width == width)
+        .map(|(_, wires)| wires.clone())
+        .ok_or_else(|| {
+            ElaborateError::BuiltinPin(
+                Located {
+                    file: definition.file.clone(),
+                    span: builtin.span,
+                },
+                builtin.name.clone(),
+                name.to_string(),
+                width,
+            )
+        })
+}
+
+struct Elaborator {
+    directory: Option<PathBuf>,
+    definitions: HashMap<String, Rc<Definition>>,
+    library: HashMap<String, Rc<Definition>>,
+    // Union-find over the wires, the root of a net being its lowest wire so
+    // that the constants stay 0 and 1.
+    parents: Vec<Wire>,
+    nands: Vec<Nand>,
+    // The instance each Nand gate is a part of, and where.
+    owners: Vec<(usize, Span)>,
+    dffs: Vec<Dff>,
+    memories: Vec<Memory>,
+    instances: Vec<Instance>,
+    internal: Vec<(String, Vec<Wire>)>,
+    // The chips being elaborated, outermost first.
+    stack: Vec<String>,
+}
+
+impl Elaborator {
+    fn new(directory: Option<&Path>) -> Self {
+        Self {
+            directory: directory.map(Path::to_path_buf),
+            definitions: HashMap::new(),
+            library: HashMap::new(),
+            parents: vec![FALSE, TRUE],
+            nands: vec![],
+            owners: vec![],
+            dffs: vec![],
+            memories: vec![],
+            instances: vec![],
+            internal: vec![],
+            stack: vec![],
+        }
+    }
+
+    fn wires(&mut self, width: u16) -> Vec<Wire> {
+        let start = self.parents.len() as Wire;
+        let wires = (start..start + width as Wire).collect::<Vec<_>>();
+        self.parents.extend(&wires);
+        wires
+    }
+
+    fn find(&mut self, wire: Wire) -> Wire {
+        let mut root = wire;
+        while self.parents[root as usize] != root {
+            root = self.parents[root as usize];
+        }
+        let mut wire = wire;
+        while wire != root {
+            let parent = self.parents[wire as usize];
+            self.parents[wire as usize] = root;
+            wire = parent;
+        }
+        root
+    }
+
+    fn union(&mut self, a: Wire, b: Wire) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a < b {
+            self.parents[b as usize] = a;
+        } else {
+            self.parents[a as usize] = b;
+        }
+    }
+
+    fn library(&mut self, name: &str) -> Option<Rc<Definition>> {
+        if let Some(definition) = self.library.get(name) {
+            return Some(definition.clone());
+        }
+        let definition = Rc::new(Definition {
+            file: format!("built-in {}.hdl", name),
+            chip: library::chip(name)?,
+        });
+        self.library.insert(name.to_string(), definition.clone());
+        Some(definition)
+    }
+
+    // The chip in the directory, or else in the library.
+    fn load(&mut self, name: &Identifier, file: &str) -> Result<Rc<Definition>> {
+        if let Some(definition) = self.definitions.get(&name.name) {
+            return Ok(definition.clone());
+        }
+        let path = self
+            .directory
+            .as_ref()
+            .map(|directory| directory.join(format!("{}.hdl", name.name)))
+            .filter(|path| path.is_file());
+        let path = match path {
+            Some(path) => path,
+            None => {
+                return self.library(&name.name).ok_or_else(|| {
+                    ElaborateError::UnknownChip(
+                        Located {
+                            file: file.to_string(),
+                            span: name.span,
+                        },
+                        name.name.clone(),
+                    )
+                })
+            }
+        };
+        let file = path.display().to_string();
+        let source =
+            fs::read_to_string(&path).map_err(|error| ElaborateError::Io(file.clone(), error))?;
+        let chip = parse(&source).map_err(|errors| ElaborateError::Parse(file.clone(), errors))?;
+        if chip.name.name != name.name {
+            return Err(ElaborateError::WrongChip(
+                Located {
+                    file,
+                    span: chip.name.span,
+                },
+                name.name.clone(),
+                chip.name.name,
+            ));
+        }
+        let definition = Rc::new(Definition { file, chip });
+        self.definitions
+            .insert(name.name.clone(), definition.clone());
+        Ok(definition)
+    }
+
+    // Adds the chip to the netlist, wired to `pins`.
+    fn add(
+        &mut self,
+        definition: Rc<Definition>,
+        pins: Vec<Vec<Wire>>,
+        parent: Option<usize>,
+        span: Span,
+    ) -> Result<()> {
+        let builtin = match &definition.chip.implementation {
+            Implementation::Builtin { name, .. } => Some(name),
+            Implementation::Parts(_) => None,
+        };
+        let gate = builtin.filter(|name| name.name == "Nand" || name.name == "DFF");
+        // Gates aren't instances, there being too many of them.
+        if let (Some(builtin), Some(parent)) = (gate, parent) {
+            return self.gate(&definition, &pins, builtin, parent, span);
+        }
+        let name = &definition.chip.name.name;
+        if let Some(parent) = parent {
+            if self.stack.contains(name) {
+                return Err(ElaborateError::Recursive(
+                    Located {
+                        file: self.instances[parent].definition.file.clone(),
+                        span,
+                    },
+                    name.clone(),
+                ));
+            }
+        }
+        let index = self.instances.len();
+        self.instances.push(Instance {
+            definition: definition.clone(),
+            parent,
+            span,
+            pins: pins.clone(),
+        });
+        self.stack.push(name.clone());
+        match builtin {
+            Some(builtin) if gate.is_some() => {
+                self.gate(&definition, &pins, builtin, index, definition.chip.span)?
+            }
+            Some(builtin) => self.builtin(index, builtin)?,
+            None => self.parts(index)?,
+        }
+        self.stack.pop();
+        Ok(())
+    }
+
+    fn gate(
+        &mut self,
+        definition: &Definition,
+        pins: &[Vec<Wire>],
+        builtin: &Identifier,
+        owner: usize,
+        span: Span,
+    ) -> Result<()> {
+        let port = |name, width| port(definition, pins, builtin, name, width);
+        if builtin.name == "Nand" {
+            self.nands.push(Nand {
+                a: port("a", 1)?[0],
+                b: port("b", 1)?[0],
+                out: port("out", 1)?[0],
+            });
+            self.owners.push((owner, span));
+        } else {
+            self.dffs.push(Dff {
+                input: port("in", 1)?[0],
+                out: port("out", 1)?[0],
+            });
+        }
+        Ok(())
+    }
+
+    fn builtin(&mut self, index: usize, builtin: &Identifier) -> Result<()> {
+        let definition = self.instances[index].definition.clone();
+        let (size, writable): (usize, bool) = match builtin.name.as_str() {
+            "RAM8" => (8, true),
+            "RAM64" => (64, true),
+            "RAM512" => (512, true),
+            "RAM4K" => (4096, true),
+            "RAM16K" => (16384, true),
+            "Screen" => (8192, true),
+            "ROM32K" => (32768, false),
+            "Keyboard" => (1, false),
+            _ => {
+                // The library makes the course's other built-in chips of
+                // parts, which the instance takes on, keeping its pins.
+                let library = self
+                    .library(&builtin.name)
+                    .filter(|library| {
+                        matches!(library.chip.implementation, Implementation::Parts(_))
+                    })
+                    .ok_or_else(|| {
+                        ElaborateError::UnknownBuiltin(
+                            Located {
+                                file: definition.file.clone(),
+                                span: builtin.span,
+                            },
+                            builtin.name.clone(),
+                        )
+                    })?;
+                let pins = &self.instances[index].pins;
+                let pins = library
+                    .chip
+                    .inputs
+                    .iter()
+                    .chain(&library.chip.outputs)
+                    .map(|pin| port(&definition, pins, builtin, &pin.name.name, pin.width))
+                    .collect::<Result<Vec<_>>>()?;
+                self.instances[index].definition = library;
+                self.instances[index].pins = pins;
+                return self.parts(index);
+            }
+        };
+        let pins = &self.instances[index].pins;
+        let port = |name, width| port(&definition, pins, builtin, name, width);
+        let address = match size {
+            1 => vec![],
+            _ => port("address", size.trailing_zeros() as u16)?,
+        };
+        let (input, load) = match writable {
+            true => (port("in", 16)?, port("load", 1)?[0]),
+            false => (vec![], FALSE),
+        };
+        let out = port("out", 16)?;
+        self.memories.push(Memory {
+            instance: index,
+            size,
+            address,
+            input,
+            load,
+            out,
+        });
+        Ok(())
+    }
+
+    fn parts(&mut self, index: usize) -> Result<()> {
+        let definition = self.instances[index].definition.clone();
+        let chip = &definition.chip;
+        let parts = match &chip.implementation {
+            Implementation::Parts(parts) => parts,
+            Implementation::Builtin { .. } => unreachable!("parts of a built-in chip"),
+        };
+        let locate = |span| Located {
+            file: definition.file.clone(),
+            span,
+        };
+        let mut signals = HashMap::new();
+        let pins = chip
+            .inputs
+            .iter()
+            .map(|pin| (pin, Kind::Input))
+            .chain(chip.outputs.iter().map(|pin| (pin, Kind::Output)));
+        for ((pin, kind), wires) in pins.zip(&self.instances[index].pins) {
+            let signal = Signal {
+                kind,
+                wires: wires.clone(),
+                driven: vec![false; wires.len()],
+            };
+            signals.insert(pin.name.name.as_str(), signal);
+        }
+        let mut internal = vec![];
+
+        // The outputs of the parts come first, as they make the internal
+        // pins the inputs read.
+        let mut wired = vec![];
+        for part in parts {
+            let part_definition = self.load(&part.chip, &definition.file)?;
+            let part_chip = &part_definition.chip;
+            let part_pins = part_chip
+                .inputs
+                .iter()
+                .chain(&part_chip.outputs)
+                .map(|pin| self.wires(pin.width))
+                .collect::<Vec<_>>();
+            for connection in &part.connections {
+                let name = &connection.pin.name.name;
+                let position = part_chip
+                    .inputs
+                    .iter()
+                    .chain(&part_chip.outputs)
+                    .position(|pin| pin.name.name == *name)
+                    .ok_or_else(|| {
+                        ElaborateError::UnknownPin(
+                            locate(connection.pin.name.span),
+                            part_chip.name.name.clone(),
+                            name.clone(),
+                        )
+                    })?;
+                let range = bits(connection.pin.slice, part_pins[position].len());
+                if range.end > part_pins[position].len() {
+                    return Err(ElaborateError::OutsidePin(
+                        locate(connection.pin.span),
+                        name.clone(),
+                        part_chip.name.name.clone(),
+                        range.end as u16 - 1,
+                    ));
+                }
+                if position < part_chip.inputs.len() {
+                    continue;
+                }
+                let bus = match &connection.value {
+                    Value::Bus(bus) => bus,
+                    Value::Constant(_, span) => {
+                        return Err(ElaborateError::ConstantOutput(
+                            locate(connection.pin.span.to(*span)),
+                            name.clone(),
+                            part_chip.name.name.clone(),
+                        ))
+                    }
+                };
+                let wires = part_pins[position][range].to_vec();
+                if !signals.contains_key(bus.name.name.as_str()) {
+                    let signal = Signal {
+                        kind: Kind::Internal,
+                        driven: vec![true; wires.len()],
+                        wires: wires.clone(),
+                    };
+                    signals.insert(bus.name.name.as_str(), signal);
+                    internal.push((bus.name.name.clone(), wires));
+                    continue;
+                }
+                let signal = signals.get_mut(bus.name.name.as_str()).unwrap();
+                let range = bits(bus.slice, signal.wires.len());
+                match signal.kind {
+                    Kind::Input => {
+                        return Err(ElaborateError::DrivenInput(
+                            locate(bus.span),
+                            bus.name.name.clone(),
+                        ))
+                    }
+                    Kind::Internal => {
+                        return Err(ElaborateError::DrivenTwice(
+                            locate(bus.span),
+                            bus.name.name.clone(),
+                        ))
+                    }
+                    Kind::Output if range.len() != wires.len() => {
+                        return Err(ElaborateError::WidthMismatch(
+                            locate(connection.pin.span.to(bus.span)),
+                            connection.pin.to_string(),
+                            wires.len(),
+                            bus.to_string(),
+                            range.len(),
+                        ))
+                    }
+                    Kind::Output => {}
+                }
+                if range.clone().any(|bit| signal.driven[bit]) {
+                    return Err(ElaborateError::DrivenTwice(
+                        locate(bus.span),
+                        bus.to_string(),
+                    ));
+                }
+                let mut unions = vec![];
+                for (bit, wire) in range.zip(wires) {
+                    signal.driven[bit] = true;
+                    unions.push((signal.wires[bit], wire));
+                }
+                for (a, b) in unions {
+                    self.union(a, b);
+                }
+            }
+            wired.push((part_definition, part_pins));
+        }
+
+        for (part, (part_definition, part_pins)) in parts.iter().zip(wired) {
+            let part_chip = &part_definition.chip;
+            let inputs = part_chip.inputs.len();
+            let mut connected = part_pins
+                .iter()
+                .map(|wires| vec![false; wires.len()])
+                .collect::<Vec<_>>();
+            for connection in &part.connections {
+                let name = &connection.pin.name.name;
+                let position = part_chip
+                    .inputs
+                    .iter()
+                    .position(|pin| pin.name.name == *name);
+                let position = match position {
+                    Some(position) => position,
+                    None => continue,
+                };
+                let range = bits(connection.pin.slice, part_pins[position].len());
+                if range.clone().any(|bit| connected[position][bit]) {
+                    return Err(ElaborateError::ConnectedTwice(
+                        locate(connection.pin.span),
+                        name.clone(),
+                        part_chip.name.name.clone(),
+                    ));
+                }
+                for bit in range.clone() {
+                    connected[position][bit] = true;
+                }
+                let bus = match &connection.value {
+                    Value::Constant(value, _) => {
+                        let constant = if *value { TRUE } else { FALSE };
+                        for bit in range {
+                            self.union(part_pins[position][bit], constant);
+                        }
+                        continue;
+                    }
+                    Value::Bus(bus) => bus,
+                };
+                let signal = signals.get(bus.name.name.as_str()).ok_or_else(|| {
+                    ElaborateError::Undriven(locate(bus.span), bus.name.name.clone())
+                })?;
+                if signal.kind == Kind::Output {
+                    return Err(ElaborateError::ReadOutput(
+                        locate(bus.span),
+                        bus.name.name.clone(),
+                    ));
+                }
+                let source = bits(bus.slice, signal.wires.len());
+                if source.len() != range.len() {
+                    return Err(ElaborateError::WidthMismatch(
+                        locate(connection.pin.span.to(bus.span)),
+                        connection.pin.to_string(),
+                        range.len(),
+                        bus.to_string(),
+                        source.len(),
+                    ));
+                }
+                let unions = range
+                    .zip(source)
+                    .map(|(bit, source)| (part_pins[position][bit], signal.wires[source]))
+                    .collect::<Vec<_>>();
+                for (a, b) in unions {
+                    self.union(a, b);
+                }
+            }
+            // Inputs left unconnected are false.
+            for (wires, connected) in part_pins[..inputs].iter().zip(&connected) {
+                for (&wire, _) in wires.iter().zip(connected).filter(|(_, &done)| !done) {
+                    self.union(wire, FALSE);
+                }
+            }
+            self.add(part_definition, part_pins, Some(index), part.span)?;
+        }
+        if index == 0 {
+            self.internal = internal;
+        }
+        Ok(())
+    }
+
+    // How a chip in the hierarchy is described in errors, from the chip
+    // simulated down.
+    fn path(&self, instance: usize) -> Vec<String> {
+        let mut path = vec![];
+        let mut current = Some(instance);
+        while let Some(index) = current {
+            let instance = &self.instances[index];
+            current = instance.parent;
+            path.push(match current {
+                Some(parent) => format!(
+                    "{} ({}:{})",
+                    instance.name(),
+                    self.instances[parent].definition.file,
+                    instance.span
+                ),
+                None => instance.name().to_string(),
+            });
+        }
+        path.reverse();
+        path
+    }
+
+    // Numbers the nets and orders the nodes, failing on the first loop.
+    fn finish(mut self) -> Result<Netlist> {
+        let mut ids = vec![0; self.parents.len()];
+        let mut wires = 0;
+        for wire in 0..self.parents.len() {
+            let root = self.find(wire as Wire) as usize;
+            if root == wire {
+                ids[wire] = wires;
+                wires += 1;
+            } else {
+                ids[wire] = ids[root];
+            }
+        }
+        let id = |wire: &mut Wire| *wire = ids[*wire as usize];
+        for nand in &mut self.nands {
+            id(&mut nand.a);
+            id(&mut nand.b);
+            id(&mut nand.out);
+        }
+        for dff in &mut self.dffs {
+            id(&mut dff.input);
+            id(&mut dff.out);
+        }
+        for memory in &mut self.memories {
+            memory.address.iter_mut().for_each(id);
+            memory.input.iter_mut().for_each(id);
+            id(&mut memory.load);
+            memory.out.iter_mut().for_each(id);
+        }
+        for instance in &mut self.instances {
+            instance.pins.iter_mut().flatten().for_each(id);
+        }
+        for (_, wires) in &mut self.internal {
+            wires.iter_mut().for_each(id);
+        }
+
+        let nodes = self
+            .nands
+            .iter()
+            .map(|&nand| Node::Nand(nand))
+            .chain((0..self.memories.len()).map(Node::Read))
+            .collect::<Vec<_>>();
+        let inputs = |node: &Node| match *node {
+            Node::Nand(nand) => vec![nand.a, nand.b],
+            Node::Read(memory) => self.memories[memory].address.clone(),
+        };
+        let mut driver = vec![None; wires as usize];
+        for (index, node) in nodes.iter().enumerate() {
+            match *node {
+                Node::Nand(nand) => driver[nand.out as usize] = Some(index),
+                Node::Read(memory) => {
+                    for &wire in &self.memories[memory].out {
+                        driver[wire as usize] = Some(index);
+                    }
+                }
+            }
+        }
+        let mut waiting = vec![0; nodes.len()];
+        let mut readers = vec![vec![]; wires as usize];
+        for (index, node) in nodes.iter().enumerate() {
+            for wire in inputs(node) {
+                if driver[wire as usize].is_some() {
+                    waiting[index] += 1;
+                    readers[wire as usize].push(index);
+                }
+            }
+        }
+        let mut ready = (0..nodes.len())
+            .filter(|&index| waiting[index] == 0)
+            .collect::<VecDeque<_>>();
+        let mut order = Vec::with_capacity(nodes.len());
+        while let Some(index) = ready.pop_front() {
+            let node = nodes[index];
+            order.push(node);
+            let outputs = match node {
+                Node::Nand(nand) => vec![nand.out],
+                Node::Read(memory) => self.memories[memory].out.clone(),
+            };
+            for wire in outputs {
+                for &reader in &readers[wire as usize] {
+                    waiting[reader] -= 1;
+                    if waiting[reader] == 0 {
+                        ready.push_back(reader);
+                    }
+                }
+            }
+        }
+
+        if order.len() < nodes.len() {
+            // Every node left waits on another one left, so walking back
+            // through them comes round to a loop.
+            let mut walk = vec![(0..nodes.len()).find(|&index| waiting[index] > 0).unwrap()];
+            let start = loop {
+                let last = *walk.last().unwrap();
+                let previous = inputs(&nodes[last])
+                    .into_iter()
+                    .filter_map(|wire| driver[wire as usize])
+                    .find(|&index| waiting[index] > 0)
+                    .unwrap();
+                if let Some(start) = walk.iter().position(|&index| index == previous) {
+                    break start;
+                }
+                walk.push(previous);
+            };
+            let paths = walk[start..]
+                .iter()
+                .map(|&index| match nodes[index] {
+                    Node::Nand(_) => {
+                        let (owner, span) = self.owners[index];
+                        let mut path = self.path(owner);
+                        let file = &self.instances[owner].definition.file;
+                        path.push(format!("Nand ({}:{})", file, span));
+                        path
+                    }
+                    Node::Read(memory) => self.path(self.memories[memory].instance),
+                })
+                .collect::<Vec<_>>();
+            // The parts wired into a loop are where the paths part ways.
+            let common = (0..)
+                .find(|&depth| {
+                    paths
+                        .iter()
+                        .any(|path| path.get(depth) != paths[0].get(depth))
+                })
+                .unwrap_or(paths[0].len());
+            let mut parts = vec![];
+            for path in &paths {
+                let part = &path[common.min(path.len() - 1)];
+                if !parts.contains(part) {
+                    parts.push(part.clone());
+                }
+            }
+            return Err(ElaborateError::CombinationalLoop(parts));
+        }
+
+        Ok(Netlist {
+            wires: wires as usize,
+            order,
+            dffs: self.dffs,
+            memories: self.memories,
+            instances: self.instances,
+            internal: self.internal,
+        })
+    }
+}
+
+// Flattens the chip into Nand gates, DFFs and memories, finding its parts in
+// `directory` or else among the built-in chips.
+pub fn elaborate(source: &str, file: &str, directory: Option<&Path>) -> Result<Netlist> {
+    let chip = parse(source).map_err(|errors| ElaborateError::Parse(file.to_string(), errors))?;
+    let mut elaborator = Elaborator::new(directory);
+    let pins = chip
+        .inputs
+        .iter()
+        .chain(&chip.outputs)
+        .map(|pin| elaborator.wires(pin.width))
+        .collect();
+    let definition = Rc::new(Definition {
+        file: file.to_string(),
+        chip,
+    });
+    elaborator
+        .definitions
+        .insert(definition.chip.name.name.clone(), definition.clone());
+    elaborator.add(definition, pins, None, Span::default())?;
+    elaborator.finish()
+}
+
+pub fn elaborate_file(path: &Path) -> Result<Netlist> {
+    let file = path.display().to_string();
+    let source =
+        fs::read_to_string(path).map_err(|error| ElaborateError::Io(file.clone(), error))?;
+    elaborate(&source, &file, path.parent())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempDir;
+
+    fn error(source: &str) -> String {
+        elaborate(source, "Test.hdl", None).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn elaborate_flattens_chips_into_gates() {
+        let netlist = elaborate(library::source("Bit").unwrap(), "Bit.hdl", None).unwrap();
+        // The Mux is a Not and three Nand gates.
+        assert_eq!(netlist.nands(), 4);
+        assert_eq!(netlist.dffs.len(), 1);
+        let names = netlist
+            .instances
+            .iter()
+            .map(Instance::name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["Bit", "Mux", "Not"]);
+        assert_eq!(netlist.pin("out"), Some(&[netlist.dffs[0].out][..]));
+        assert_eq!(netlist.pin("held"), netlist.pin("out"));
+
+        let computer = elaborate(library::source("Computer").unwrap(), "Computer.hdl", None);
+        let computer = computer.unwrap();
+        assert_eq!(computer.dffs.len(), 48);
+        assert_eq!(computer.memories.len(), 4);
+    }
+
+    #[test]
+    fn elaborate_finds_parts_next_to_the_chip() {
+        let directory = TempDir::new("hdl-elaborate-test");
+        let write = |name: &str, source: &str| {
+            fs::write(directory.join(format!("{}.hdl", name)), source).unwrap()
+        };
+        // Not with its own gates instead of the library's.
+        write(
+            "Not",
+            "CHIP Not { IN in; OUT out; PARTS: Nand(a=in, b=true, out=out); }",
+        );
+        write(
+            "Twice",
+            "CHIP Twice { IN in; OUT out; PARTS: Not(in=in, out=x); Not(in=x, out=out); }",
+        );
+        write(
+            "Self",
+            "CHIP Self { IN in; OUT out; PARTS: Twice(in=in, out=x); Self(in=x, out=out); }",
+        );
+        write("Wrong", "CHIP Right { IN in; OUT out; BUILTIN Not; }");
+        let netlist = elaborate_file(&directory.join("Twice.hdl")).unwrap();
+        assert_eq!(netlist.nands(), 2);
+        assert!(matches!(netlist.order[0], Node::Nand(Nand { b: TRUE, .. })));
+        let error = elaborate_file(&directory.join("Self.hdl")).unwrap_err();
+        assert!(error
+            .to_string()
+            .ends_with("Self.hdl:1:57: Chip Self contains itself"));
+        let error = elaborate(
+            "CHIP X { IN in; OUT out; PARTS: Wrong(in=in, out=out); }",
+            "X.hdl",
+            Some(&*directory),
+        );
+        assert!(error
+            .unwrap_err()
+            .to_string()
+            .ends_with("Wrong.hdl:1:6: Expected chip Wrong, found chip Right"));
+    }
+
+    #[test]
+    fn elaborate_reports_wiring_errors() {
+        let chip = |parts| {
+            format!(
+                "CHIP X {{ IN a, b[4]; OUT out, wide[4]; PARTS: {} }}",
+                parts
+            )
+        };
+        assert_eq!(
+            error(&chip("Foo(a=a);")),
+            "Test.hdl:1:47: No chip named Foo next to the chip or among the built-in chips"
+        );
+        assert_eq!(
+            error(&chip("Not(a=a);")),
+            "Test.hdl:1:51: Chip Not has no pin a"
+        );
+        assert_eq!(
+            error(&chip("Not(in[1]=a);")),
+            "Test.hdl:1:51: Pin in of Not has no bit 1"
+        );
+        assert_eq!(
+            error(&chip("Not(in=b, out=out);")),
+            "Test.hdl:1:51: in is 1 bits wide but b is 4"
+        );
+        assert_eq!(
+            error(&chip("Not16(in=b, out=x);")),
+            "Test.hdl:1:53: in is 16 bits wide but b is 4"
+        );
+        assert_eq!(
+            error(&chip("Not(in=a, out=true);")),
+            "Test.hdl:1:57: Output pin out of Not cannot be connected to a constant"
+        );
+        assert_eq!(
+            error(&chip("Not(in=a, out=a);")),
+            "Test.hdl:1:61: Input pin a cannot be driven by a part"
+        );
+        assert_eq!(
+            error(&chip("Not(in=a, out=out); Not(in=b[0], out=out);")),
+            "Test.hdl:1:84: out is driven by more than one part"
+        );
+        assert_eq!(
+            error(&chip("Not(in=a, out=x); Not(in=b[0], out=x);")),
+            "Test.hdl:1:82: x is driven by more than one part"
+        );
+        assert_eq!(
+            error(&chip("Not(in=a, out=out); Not(in=out, out=x);")),
+            "Test.hdl:1:74: Output pin out cannot be read by a part"
+        );
+        assert_eq!(
+            error(&chip("And(a=a, a=b[0], out=out);")),
+            "Test.hdl:1:56: Pin a of And is connected twice"
+        );
+        assert_eq!(
+            error(&chip("Not(in=x, out=out);")),
+            "Test.hdl:1:54: x is not an output of any part"
+        );
+        assert_eq!(
+            error("CHIP X { IN a; OUT out; BUILTIN Adder; }"),
+            "Test.hdl:1:33: There is no built-in chip named Adder"
+        );
+        assert_eq!(
+            error("CHIP X { IN a; OUT out; BUILTIN Nand; }"),
+            "Test.hdl:1:33: Built-in chip Nand needs a pin b 1 bits wide"
+        );
+        assert_eq!(
+            error("CHIP X { IN a; OUT out; PARTS: Not(in=a, out=out) }"),
+            "Test.hdl:1:51: Expected \";\", found \"}\""
+        );
+    }
+
+    #[test]
+    fn elaborate_finds_combinational_loops() {
+        let loop_ = "CHIP Loop {
+    IN a;
+    OUT out;
+
+    PARTS:
+    And(a=a, b=x, out=y);
+    Or(a=a, b=a, out=z);
+    Not(in=y, out=x, out=out);
+}";
+        assert_eq!(
+            elaborate(loop_, "Loop.hdl", None).unwrap_err().to_string(),
+            "Combinational loop through And (Loop.hdl:6:5), Not (Loop.hdl:8:5)"
+        );
+        // A loop through a DFF is a register.
+        let register = "CHIP Hold { IN a; OUT out; PARTS: Or(a=a, b=held, out=x); DFF(in=x, out=held, out=out); }";
+        assert!(elaborate(register, "Hold.hdl", None).is_ok());
+    }
+}
@@ -0,0 +1,495 @@
+use thiserror::Error;
+
+use crate::{
+    ast::{Bus, Chip, Connection, Identifier, Implementation, Part, Pin, Slice, Value, MAX_WIDTH},
+    span::Span,
+    token::{Keyword, Token, TokenKind},
+    tokenizer::{TokenizeError, Tokenizer},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum ParseError {
+    #[error(transparent)]
+    Tokenize(#[from] TokenizeError),
+
+    #[error("{0}: Expected {1}, found \"{2}\"")]
+    Expected(Span, String, String),
+
+    #[error("{0}: Expected {1}, found the end of the file")]
+    UnexpectedEnd(Span, String),
+
+    #[error("{0}: Pin {1} is declared twice")]
+    DuplicatePin(Span, String),
+
+    #[error("{0}: A bus is 1 to 16 bits wide, not {1}")]
+    InvalidWidth(Span, u16),
+
+    #[error("{0}: Invalid sub-bus [{1}..{2}]")]
+    InvalidSlice(Span, u16, u16),
+
+    #[error("{0}: Pin {1} has no bit {2}")]
+    OutsideBus(Span, String, u16),
+
+    #[error("{0}: Internal pin {1} cannot be sub-bused")]
+    InternalSlice(Span, String),
+
+    #[error("{0}: {1} is not a pin of the chip")]
+    UnknownPin(Span, String),
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::Tokenize(error) => error.span(),
+            ParseError::Expected(span, ..)
+            | ParseError::UnexpectedEnd(span, _)
+            | ParseError::DuplicatePin(span, _)
+            | ParseError::InvalidWidth(span, _)
+            | ParseError::InvalidSlice(span, ..)
+            | ParseError::OutsideBus(span, ..)
+            | ParseError::InternalSlice(span, _)
+            | ParseError::UnknownPin(span, _) => *span,
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, ParseError>;
+
+// Syntax errors end parsing. The errors found in a chip that parses, like
+// pins declared twice or sub-buses out of range, are all kept.
+pub struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    errors: Vec<ParseError>,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(tokens: &'a [Token]) -> Self {
+        Self {
+            tokens,
+            position: 0,
+            errors: vec![],
+        }
+    }
+
+    fn peek(&self) -> Option<&'a TokenKind> {
+        self.tokens.get(self.position).map(|token| &token.kind)
+    }
+
+    fn is_symbol(&self, symbol: char) -> bool {
+        self.peek() == Some(&TokenKind::Symbol(symbol))
+    }
+
+    fn is_keyword(&self, keyword: Keyword) -> bool {
+        self.peek() == Some(&TokenKind::Keyword(keyword))
+    }
+
+    // The span of the next token, or an empty one after the last.
+    fn span(&self) -> Span {
+        match self.tokens.get(self.position) {
+            Some(token) => token.span,
+            None => self.tokens.last().map_or_else(Span::default, |token| Span {
+                start: token.span.end,
+                column: token.span.column + (token.span.end - token.span.start),
+                ..token.span
+            }),
+        }
+    }
+
+    // The span from `start` to the end of the last consumed token.
+    fn since(&self, start: Span) -> Span {
+        match self.position.checked_sub(1).map(|i| self.tokens[i].span) {
+            Some(last) if last.end >= start.start => start.to(last),
+            _ => start,
+        }
+    }
+
+    fn error(&self, expected: &str) -> ParseError {
+        match self.peek() {
+            Some(kind) => ParseError::Expected(self.span(), expected.to_string(), kind.to_string()),
+            None => ParseError::UnexpectedEnd(self.span(), expected.to_string()),
+        }
+    }
+
+    fn symbol(&mut self, symbol: char) -> Result<Span> {
+        if self.is_symbol(symbol) {
+            let span = self.span();
+            self.position += 1;
+            Ok(span)
+        } else {
+            Err(self.error(&format!("\"{}\"", symbol)))
+        }
+    }
+
+    fn keyword(&mut self, keyword: Keyword) -> Result<Span> {
+        if self.is_keyword(keyword) {
+            let span = self.span();
+            self.position += 1;
+            Ok(span)
+        } else {
+            Err(self.error(&format!("\"{}\"", keyword)))
+        }
+    }
+
+    fn identifier(&mut self) -> Result<Identifier> {
+        match self.peek() {
+            Some(TokenKind::Identifier(name)) => {
+                let span = self.span();
+                self.position += 1;
+                Ok(Identifier {
+                    name: name.clone(),
+                    span,
+                })
+            }
+            _ => Err(self.error("a name")),
+        }
+    }
+
+    fn number(&mut self) -> Result<u16> {
+        match self.peek() {
+            Some(&TokenKind::Number(value)) => {
+                self.position += 1;
+                Ok(value)
+            }
+            _ => Err(self.error("a number")),
+        }
+    }
+
+    // A comma separated list of one or more items.
+    fn list<T>(&mut self, mut item: impl FnMut(&mut Self) -> Result<T>) -> Result<Vec<T>> {
+        let mut items = vec![item(self)?];
+        while self.is_symbol(',') {
+            self.position += 1;
+            items.push(item(self)?);
+        }
+        Ok(items)
+    }
+
+    pub fn chip(&mut self) -> Result<Chip> {
+        let start = self.span();
+        self.keyword(Keyword::Chip)?;
+        let name = self.identifier()?;
+        self.symbol('{')?;
+        let mut inputs = vec![];
+        if self.is_keyword(Keyword::In) {
+            self.position += 1;
+            inputs = self.list(Self::pin)?;
+            self.symbol(';')?;
+        }
+        let mut outputs = vec![];
+        if self.is_keyword(Keyword::Out) {
+            self.position += 1;
+            outputs = self.list(Self::pin)?;
+            self.symbol(';')?;
+        }
+        let implementation = if self.is_keyword(Keyword::Builtin) {
+            self.builtin()?
+        } else if self.is_keyword(Keyword::Parts) {
+            self.position += 1;
+            self.symbol(':')?;
+            let mut parts = vec![];
+            while !self.is_symbol('}') && self.peek().is_some() {
+                parts.push(self.part()?);
+            }
+            Implementation::Parts(parts)
+        } else {
+            return Err(self.error("\"PARTS:\" or \"BUILTIN\""));
+        };
+        self.symbol('}')?;
+        if self.peek().is_some() {
+            return Err(self.error("the end of the file"));
+        }
+        Ok(Chip {
+            name,
+            inputs,
+            outputs,
+            implementation,
+            span: self.since(start),
+        })
+    }
+
+    fn pin(&mut self) -> Result<Pin> {
+        let name = self.identifier()?;
+        let mut width = 1;
+        if self.is_symbol('[') {
+            self.position += 1;
+            let span = self.span();
+            width = self.number()?;
+            if !(1..=MAX_WIDTH).contains(&width) {
+                self.errors.push(ParseError::InvalidWidth(span, width));
+            }
+            self.symbol(']')?;
+        }
+        Ok(Pin { name, width })
+    }
+
+    fn builtin(&mut self) -> Result<Implementation> {
+        self.keyword(Keyword::Builtin)?;
+        let name = self.identifier()?;
+        self.symbol(';')?;
+        let mut clocked = vec![];
+        if self.is_keyword(Keyword::Clocked) {
+            self.position += 1;
+            clocked = self.list(Self::identifier)?;
+            self.symbol(';')?;
+        }
+        Ok(Implementation::Builtin { name, clocked })
+    }
+
+    fn part(&mut self) -> Result<Part> {
+        let start = self.span();
+        let chip = self.identifier()?;
+        self.symbol('(')?;
+        let connections = self.list(Self::connection)?;
+        self.symbol(')')?;
+        self.symbol(';')?;
+        Ok(Part {
+            chip,
+            connections,
+            span: self.since(start),
+        })
+    }
+
+    fn connection(&mut self) -> Result<Connection> {
+        let pin = self.bus()?;
+        self.symbol('=')?;
+        let value = match self.peek() {
+            Some(TokenKind::Keyword(keyword @ (Keyword::True | Keyword::False))) => {
+                let span = self.span();
+                self.position += 1;
+                Value::Constant(*keyword == Keyword::True, span)
+            }
+            Some(TokenKind::Identifier(_)) => Value::Bus(self.bus()?),
+            _ => return Err(self.error("a pin, \"true\" or \"false\"")),
+        };
+        Ok(Connection { pin, value })
+    }
+
+    // A pin, then `[bit]` or `[start..end]` for some of its bits.
+    fn bus(&mut self) -> Result<Bus> {
+        let name = self.identifier()?;
+        let mut slice = None;
+        if self.is_symbol('[') {
+            self.position += 1;
+            let start = self.number()?;
+            let mut end = start;
+            if self.peek() == Some(&TokenKind::Range) {
+                self.position += 1;
+                end = self.number()?;
+            }
+            self.symbol(']')?;
+            slice = Some(Slice { start, end });
+        }
+        let span = self.since(name.span);
+        if let Some(Slice { start, end }) = slice {
+            if start > end || end >= MAX_WIDTH {
+                self.errors.push(ParseError::InvalidSlice(span, start, end));
+            }
+        }
+        Ok(Bus { name, slice, span })
+    }
+}
+
+// The errors in a chip that parsed, which the parser can't see until it has
+// all of its pins.
+fn check(chip: &Chip, errors: &mut Vec<ParseError>) {
+    let pins = chip.inputs.iter().chain(&chip.outputs).collect::<Vec<_>>();
+    for (i, pin) in pins.iter().enumerate() {
+        if pins[..i]
+            .iter()
+            .any(|other| other.name.name == pin.name.name)
+        {
+            errors.push(ParseError::DuplicatePin(
+                pin.name.span,
+                pin.name.name.clone(),
+            ));
+        }
+    }
+    let pin = |name: &str| pins.iter().find(|pin| pin.name.name == name);
+    match &chip.implementation {
+        Implementation::Parts(parts) => {
+            let values = parts
+                .iter()
+                .flat_map(|part| &part.connections)
+                .filter_map(|connection| match &connection.value {
+                    Value::Bus(bus) => Some(bus),
+                    Value::Constant(..) => None,
+                });
+            for bus in values {
+                let slice = match bus.slice {
+                    Some(slice) if slice.start <= slice.end && slice.end < MAX_WIDTH => slice,
+                    _ => continue,
+                };
+                match pin(&bus.name.name) {
+                    Some(pin) if slice.end >= pin.width => errors.push(ParseError::OutsideBus(
+                        bus.span,
+                        bus.name.name.clone(),
+                        slice.end,
+                    )),
+                    Some(_) => {}
+                    None => errors.push(ParseError::InternalSlice(bus.span, bus.name.name.clone())),
+                }
+            }
+        }
+        Implementation::Builtin { clocked, .. } => {
+            for name in clocked {
+                if pin(&name.name).is_none() {
+                    errors.push(ParseError::UnknownPin(name.span, name.name.clone()));
+                }
+            }
+        }
+    }
+}
+
+// Tokenizes and parses a chip, failing with every error found, in source
+// order.
+pub fn parse(source: &str) -> std::result::Result<Chip, Vec<ParseError>> {
+    let mut tokens = vec![];
+    let mut errors = vec![];
+    for result in Tokenizer::new(source) {
+        match result {
+            Ok(token) => tokens.push(token),
+            Err(error) => errors.push(ParseError::from(error)),
+        }
+    }
+    // Tokens that failed to scan would only add follow-on parse errors.
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let mut parser = Parser::new(&tokens);
+    let chip = parser.chip();
+    let mut errors = parser.errors;
+    match chip {
+        Ok(chip) => {
+            check(&chip, &mut errors);
+            errors.sort_by_key(|error| error.span().start);
+            if errors.is_empty() {
+                Ok(chip)
+            } else {
+                Err(errors)
+            }
+        }
+        Err(error) => {
+            errors.push(error);
+            errors.sort_by_key(|error| error.span().start);
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MUX: &str = "/** Selects bytes. */
+CHIP Swap {
+    IN a[16], sel;
+    OUT out[16], low;
+
+    PARTS:
+    // Swap the halves.
+    Mux16(a=a, b[0..7]=a[8..15], b[8..15]=a[0..7], sel=sel, out=out, out[0]=low);
+    Not(in=true, out=unused);
+}
+";
+
+    #[test]
+    fn parser_reads_a_chip_of_parts() {
+        let chip = parse(MUX).unwrap();
+        assert_eq!(chip.name.name, "Swap");
+        assert_eq!(chip.input("a").map(|pin| pin.width), Some(16));
+        assert_eq!(chip.output("low").map(|pin| pin.width), Some(1));
+        let parts = match &chip.implementation {
+            Implementation::Parts(parts) => parts,
+            _ => panic!("not made of parts"),
+        };
+        assert_eq!(parts.len(), 2);
+        let connection = &parts[0].connections[1];
+        assert_eq!(connection.pin.slice, Some(Slice { start: 0, end: 7 }));
+        match &connection.value {
+            Value::Bus(bus) => {
+                assert_eq!(bus.slice.map(|slice| slice.width()), Some(8));
+                assert_eq!((bus.span.line, bus.span.column), (8, 24));
+            }
+            value => panic!("{:?}", value),
+        }
+        assert!(matches!(
+            parts[1].connections[0].value,
+            Value::Constant(true, _)
+        ));
+        let printed = "CHIP Swap {
+    IN a[16], sel;
+    OUT out[16], low;
+
+    PARTS:
+    Mux16(a=a, b[0..7]=a[8..15], b[8..15]=a[0..7], sel=sel, out=out, out[0]=low);
+    Not(in=true, out=unused);
+}
+";
+        assert_eq!(chip.to_string(), printed);
+        assert_eq!(parse(printed).unwrap().to_string(), printed);
+    }
+
+    #[test]
+    fn parser_reads_a_builtin_chip() {
+        let chip =
+            parse("CHIP Bit { IN in, load; OUT out; BUILTIN Bit; CLOCKED in, load; }").unwrap();
+        match &chip.implementation {
+            Implementation::Builtin { name, clocked } => {
+                assert_eq!(name.name, "Bit");
+                assert_eq!(clocked.len(), 2);
+            }
+            _ => panic!("not built in"),
+        }
+        assert!(chip
+            .to_string()
+            .ends_with("    BUILTIN Bit;\n    CLOCKED in, load;\n}\n"));
+    }
+
+    #[test]
+    fn parser_reports_errors_with_their_location() {
+        let errors = |source| {
+            parse(source)
+                .unwrap_err()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            errors("CHIP And {\n    IN a, b;\n    OUT out\n    PARTS:\n}"),
+            ["4:5: Expected \";\", found \"PARTS\""]
+        );
+        assert_eq!(
+            errors("CHIP And { IN a; OUT out; PARTS: Nand(a=a, b=b, out=out) }"),
+            ["1:58: Expected \";\", found \"}\""]
+        );
+        assert_eq!(
+            errors("CHIP And { IN a; OUT out; PARTS: Nand(a=a, b=b, out=out);"),
+            ["1:58: Expected \"}\", found the end of the file"]
+        );
+        assert_eq!(
+            errors(
+                "CHIP X { IN a[4], a, b[17]; OUT out; PARTS: Y(i=a[2..5], j=w[0], k=a[3..1]); }"
+            ),
+            [
+                "1:19: Pin a is declared twice",
+                "1:24: A bus is 1 to 16 bits wide, not 17",
+                "1:49: Pin a has no bit 5",
+                "1:60: Internal pin w cannot be sub-bused",
+                "1:68: Invalid sub-bus [3..1]",
+            ]
+        );
+        assert_eq!(
+            errors("CHIP X { IN a; BUILTIN X; CLOCKED b; }"),
+            ["1:35: b is not a pin of the chip"]
+        );
+        assert_eq!(
+            errors("CHIP X { IN a; PARTS: Y(true=a); }"),
+            ["1:25: Expected a name, found \"true\""]
+        );
+        assert_eq!(
+            errors("CHIP X { IN a; }")[0],
+            "1:16: Expected \"PARTS:\" or \"BUILTIN\", found \"}\""
+        );
+    }
+}
@@ -0,0 +1,682 @@
+use std::{fmt, iter::Peekable, str::CharIndices};
+
+use thiserror::Error;
+
+use crate::span::Span;
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum ScriptError {
+    #[error("{0}: Unterminated comment")]
+    UnterminatedComment(Span),
+
+    #[error("{0}: Unterminated string")]
+    UnterminatedString(Span),
+
+    #[error("{0}: Expected {1}, found \"{2}\"")]
+    Expected(Span, String, String),
+
+    #[error("{0}: Expected {1}, found the end of the file")]
+    UnexpectedEnd(Span, String),
+
+    #[error("{0}: Unknown command {1}")]
+    UnknownCommand(Span, String),
+
+    #[error("{0}: Invalid value {1}")]
+    InvalidValue(Span, String),
+
+    #[error("{0}: Invalid output format {1}")]
+    InvalidFormat(Span, String),
+
+    #[error("{0}: Invalid variable {1}")]
+    InvalidVariable(Span, String),
+}
+
+pub type Result<T> = std::result::Result<T, ScriptError>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum TokenKind {
+    // Anything up to whitespace or a symbol: commands, variables, values,
+    // file names and output formats.
+    Word(String),
+    String(String),
+    Symbol(char),
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenKind::Word(word) => write!(f, "{}", word),
+            TokenKind::String(text) => write!(f, "\"{}\"", text),
+            TokenKind::Symbol(symbol) => write!(f, "{}", symbol),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Token {
+    kind: TokenKind,
+    span: Span,
+}
+
+const SYMBOLS: &str = ",;{}";
+
+struct Tokenizer<'a> {
+    source: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            chars: source.char_indices().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn offset(&mut self) -> usize {
+        self.chars
+            .peek()
+            .map_or(self.source.len(), |&(offset, _)| offset)
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|&(_, c)| c)
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        let mut chars = self.chars.clone();
+        chars.next();
+        chars.next().map(|(_, c)| c)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let (_, c) = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn start(&mut self) -> Span {
+        let start = self.offset();
+        Span {
+            start,
+            end: start,
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    fn finish(&mut self, span: Span) -> Span {
+        Span {
+            end: self.offset(),
+            ..span
+        }
+    }
+
+    fn skip_trivia(&mut self) -> Result<()> {
+        loop {
+            match (self.peek(), self.peek_second()) {
+                (Some(c), _) if c.is_whitespace() => {
+                    self.bump();
+                }
+                (Some('/'), Some('/')) => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.bump();
+                    }
+                }
+                (Some('/'), Some('*')) => {
+                    let span = self.start();
+                    self.bump();
+                    self.bump();
+                    loop {
+                        match self.bump() {
+                            Some('*') if self.peek() == Some('/') => {
+                                self.bump();
+                                break;
+                            }
+                            Some(_) => {}
+                            None => {
+                                return Err(ScriptError::UnterminatedComment(self.finish(span)))
+                            }
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn token(&mut self) -> Option<Result<Token>> {
+        if let Err(error) = self.skip_trivia() {
+            return Some(Err(error));
+        }
+        let span = self.start();
+        let c = self.bump()?;
+        let kind = if SYMBOLS.contains(c) {
+            TokenKind::Symbol(c)
+        } else if c == '"' {
+            let start = self.offset();
+            while self.peek().is_some_and(|c| c != '"' && c != '\n') {
+                self.bump();
+            }
+            let end = self.offset();
+            if self.bump() != Some('"') {
+                return Some(Err(ScriptError::UnterminatedString(self.finish(span))));
+            }
+            TokenKind::String(self.source[start..end].to_string())
+        } else {
+            while self
+                .peek()
+                .is_some_and(|c| !c.is_whitespace() && !SYMBOLS.contains(c) && c != '"')
+            {
+                self.bump();
+            }
+            let span = self.finish(span);
+            TokenKind::Word(self.source[span.start..span.end].to_string())
+        };
+        Some(Ok(Token {
+            kind,
+            span: self.finish(span),
+        }))
+    }
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = Result<Token>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.token()
+    }
+}
+
+// A pin of the chip, or a word of one of its built-in parts like RAM16K[3]
+// or DRegister[].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Variable {
+    pub name: String,
+    pub index: Option<usize>,
+    pub text: String,
+    pub span: Span,
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Binary,
+    Decimal,
+    Hex,
+    // Only for time.
+    String,
+}
+
+// A column of the output: `name%Fleft.width.right`, the value taking `width`
+// characters between `left` and `right` spaces.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Column {
+    pub variable: Variable,
+    pub format: Format,
+    pub left: usize,
+    pub width: usize,
+    pub right: usize,
+}
+
+impl Column {
+    // The name of the column, centered and cut to fit.
+    pub fn header(&self) -> String {
+        let width = self.left + self.width + self.right;
+        let name = self.variable.text.chars().take(width).collect::<String>();
+        let left = (width - name.chars().count()) / 2;
+        format!(
+            "{:left$}{:width$}",
+            "",
+            name,
+            left = left,
+            width = width - left
+        )
+    }
+
+    pub fn cell(&self, value: Option<u16>, time: &str) -> String {
+        let value = match (self.format, value) {
+            (Format::String, _) | (_, None) => format!("{:width$}", time, width = self.width),
+            (Format::Binary, Some(value)) => {
+                let bits = format!("{:016b}", value);
+                format!(
+                    "{:0>width$}",
+                    &bits[16 - self.width.min(16)..],
+                    width = self.width
+                )
+            }
+            (Format::Hex, Some(value)) => {
+                let digits = format!("{:04X}", value);
+                format!(
+                    "{:0>width$}",
+                    &digits[4 - self.width.min(4)..],
+                    width = self.width
+                )
+            }
+            (Format::Decimal, Some(value)) => {
+                format!("{:>width$}", value as i16, width = self.width)
+            }
+        };
+        format!(
+            "{:left$}{}{:right$}",
+            "",
+            value,
+            "",
+            left = self.left,
+            right = self.right
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessOrEqual,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    const ALL: [(&'static str, Comparison); 6] = [
+        ("=", Comparison::Equal),
+        ("<>", Comparison::NotEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+        ("<=", Comparison::LessOrEqual),
+        (">=", Comparison::GreaterOrEqual),
+    ];
+
+    // Compares the values as signed words, as the course's simulator does.
+    pub fn holds(&self, a: u16, b: u16) -> bool {
+        let (a, b) = (a as i16, b as i16);
+        match self {
+            Comparison::Equal => a == b,
+            Comparison::NotEqual => a != b,
+            Comparison::Less => a < b,
+            Comparison::Greater => a > b,
+            Comparison::LessOrEqual => a <= b,
+            Comparison::GreaterOrEqual => a >= b,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Load(String),
+    OutputFile(String),
+    CompareTo(String),
+    OutputList(Vec<Column>),
+    Set(Variable, u16),
+    Eval,
+    Tick,
+    Tock,
+    Output,
+    Echo(String),
+    ClearEcho,
+    // Forever without a count.
+    Repeat(Option<u32>, Vec<Statement>),
+    While(Variable, Comparison, u16, Vec<Statement>),
+    // A command of a built-in part, like `ROM32K load Prog.hack`.
+    Part {
+        part: String,
+        command: String,
+        argument: String,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Statement {
+    pub command: Command,
+    pub span: Span,
+}
+
+// A number in decimal, or in binary, hex or decimal after %B, %X or %D.
+pub fn parse_value(text: &str) -> Option<u16> {
+    let (radix, digits) = match text.get(..2) {
+        Some("%B") => (2, &text[2..]),
+        Some("%X") => (16, &text[2..]),
+        Some("%D") => (10, &text[2..]),
+        _ => (10, text),
+    };
+    match radix {
+        10 => match digits.parse::<i32>().ok()? {
+            value @ -32768..=65535 => Some(value as u16),
+            _ => None,
+        },
+        _ => u16::from_str_radix(digits, radix).ok(),
+    }
+}
+
+fn parse_variable(text: &str, span: Span) -> Result<Variable> {
+    let invalid = || ScriptError::InvalidVariable(span, text.to_string());
+    let (name, index) = match text.find('[') {
+        Some(open) => {
+            let index = text[open + 1..].strip_suffix(']').ok_or_else(invalid)?;
+            let index = match index {
+                "" => 0,
+                index => index.parse().map_err(|_| invalid())?,
+            };
+            (&text[..open], Some(index))
+        }
+        None => (text, None),
+    };
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+    {
+        return Err(invalid());
+    }
+    Ok(Variable {
+        name: name.to_string(),
+        index,
+        text: text.to_string(),
+        span,
+    })
+}
+
+fn parse_column(text: &str, span: Span) -> Result<Column> {
+    let (variable, format) = text.split_once('%').unwrap_or((text, "B1.1.1"));
+    let variable = parse_variable(variable, span)?;
+    let invalid = || ScriptError::InvalidFormat(span, text.to_string());
+    let mut chars = format.chars();
+    let format = match chars.next() {
+        Some('B') => Format::Binary,
+        Some('D') => Format::Decimal,
+        Some('X') => Format::Hex,
+        Some('S') => Format::String,
+        _ => return Err(invalid()),
+    };
+    let numbers = chars
+        .as_str()
+        .split('.')
+        .map(str::parse)
+        .collect::<std::result::Result<Vec<usize>, _>>()
+        .map_err(|_| invalid())?;
+    match numbers[..] {
+        [left, width, right] if width > 0 => Ok(Column {
+            variable,
+            format,
+            left,
+            width,
+            right,
+        }),
+        _ => Err(invalid()),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.position).map(|token| &token.kind)
+    }
+
+    fn span(&self) -> Span {
+        match self.tokens.get(self.position) {
+            Some(token) => token.span,
+            None => self.tokens.last().map_or_else(Span::default, |token| Span {
+                start: token.span.end,
+                column: token.span.column + (token.span.end - token.span.start),
+                ..token.span
+            }),
+        }
+    }
+
+    fn error(&self, expected: &str) -> ScriptError {
+        match self.peek() {
+            Some(kind) => {
+                ScriptError::Expected(self.span(), expected.to_string(), kind.to_string())
+            }
+            None => ScriptError::UnexpectedEnd(self.span(), expected.to_string()),
+        }
+    }
+
+    fn is_symbol(&self, symbol: char) -> bool {
+        self.peek() == Some(&TokenKind::Symbol(symbol))
+    }
+
+    fn symbol(&mut self, symbol: char) -> Result<()> {
+        if self.is_symbol(symbol) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("\"{}\"", symbol)))
+        }
+    }
+
+    fn word(&mut self, expected: &str) -> Result<(String, Span)> {
+        match self.peek() {
+            Some(TokenKind::Word(word)) => {
+                let word = (word.clone(), self.span());
+                self.position += 1;
+                Ok(word)
+            }
+            _ => Err(self.error(expected)),
+        }
+    }
+
+    fn value(&mut self) -> Result<u16> {
+        let (text, span) = self.word("a value")?;
+        parse_value(&text).ok_or(ScriptError::InvalidValue(span, text))
+    }
+
+    fn block(&mut self) -> Result<Vec<Statement>> {
+        self.symbol('{')?;
+        let mut statements = vec![];
+        while !self.is_symbol('}') {
+            if self.peek().is_none() {
+                return Err(self.error("\"}\""));
+            }
+            statements.push(self.statement()?);
+        }
+        self.position += 1;
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Statement> {
+        let (name, span) = self.word("a command")?;
+        let command = match name.as_str() {
+            "load" => Command::Load(self.word("a file name")?.0),
+            "output-file" => Command::OutputFile(self.word("a file name")?.0),
+            "compare-to" => Command::CompareTo(self.word("a file name")?.0),
+            "output-list" => {
+                let mut columns = vec![];
+                while let Some(TokenKind::Word(_)) = self.peek() {
+                    let (text, span) = self.word("a column")?;
+                    columns.push(parse_column(&text, span)?);
+                }
+                Command::OutputList(columns)
+            }
+            "set" => {
+                let (text, span) = self.word("a variable")?;
+                Command::Set(parse_variable(&text, span)?, self.value()?)
+            }
+            "eval" => Command::Eval,
+            "tick" => Command::Tick,
+            "tock" => Command::Tock,
+            "output" => Command::Output,
+            "echo" => match self.peek() {
+                Some(TokenKind::String(text)) => {
+                    let text = text.clone();
+                    self.position += 1;
+                    Command::Echo(text)
+                }
+                _ => return Err(self.error("a string")),
+            },
+            "clear-echo" => Command::ClearEcho,
+            "repeat" => {
+                let count = match self.peek() {
+                    Some(TokenKind::Word(_)) => {
+                        let (text, span) = self.word("a count")?;
+                        Some(
+                            text.parse()
+                                .map_err(|_| ScriptError::InvalidValue(span, text))?,
+                        )
+                    }
+                    _ => None,
+                };
+                let block = self.block()?;
+                return Ok(Statement {
+                    command: Command::Repeat(count, block),
+                    span,
+                });
+            }
+            "while" => {
+                let (text, variable_span) = self.word("a variable")?;
+                let variable = parse_variable(&text, variable_span)?;
+                let (text, comparison_span) = self.word("a comparison")?;
+                let comparison = Comparison::ALL
+                    .iter()
+                    .find(|(name, _)| *name == text)
+                    .map(|&(_, comparison)| comparison)
+                    .ok_or_else(|| {
+                        ScriptError::Expected(comparison_span, "a comparison".to_string(), text)
+                    })?;
+                let value = self.value()?;
+                let block = self.block()?;
+                return Ok(Statement {
+                    command: Command::While(variable, comparison, value, block),
+                    span,
+                });
+            }
+            _ => match self.peek() {
+                Some(TokenKind::Word(command))
+                    if name.chars().all(|c| c.is_ascii_alphanumeric()) =>
+                {
+                    let command = command.clone();
+                    self.position += 1;
+                    Command::Part {
+                        part: name,
+                        command,
+                        argument: self.word("an argument")?.0,
+                    }
+                }
+                _ => return Err(ScriptError::UnknownCommand(span, name)),
+            },
+        };
+        // Commands end with a comma, or a semicolon to end the step.
+        if !self.is_symbol(',') && !self.is_symbol(';') {
+            return Err(self.error("\",\" or \";\""));
+        }
+        self.position += 1;
+        Ok(Statement { command, span })
+    }
+}
+
+pub fn parse(source: &str) -> Result<Vec<Statement>> {
+    let tokens = Tokenizer::new(source).collect::<Result<Vec<_>>>()?;
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+    };
+    let mut statements = vec![];
+    while parser.peek().is_some() {
+        statements.push(parser.statement()?);
+    }
+    Ok(statements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reads_the_course_scripts() {
+        let script = "// Tests Bit.
+load Bit.hdl,
+output-file Bit.out,
+compare-to Bit.cmp,
+output-list time%S1.4.1 in%B2.1.2 load%D2.1.2 out%X1.4.1 RAM16K[12] PC[];
+set in %B1, set load %X1,
+tick, output; tock;
+repeat 2 { eval, }
+while out <> -1 { echo \"Press a key\", tick; }
+ROM32K load Max.hack,
+";
+        let statements = parse(script).unwrap();
+        let commands = statements
+            .iter()
+            .map(|statement| &statement.command)
+            .collect::<Vec<_>>();
+        assert_eq!(commands[0], &Command::Load("Bit.hdl".to_string()));
+        let columns = match commands[3] {
+            Command::OutputList(columns) => columns,
+            command => panic!("{:?}", command),
+        };
+        assert_eq!(columns.len(), 6);
+        assert_eq!(columns[1].format, Format::Binary);
+        assert_eq!(
+            (columns[1].left, columns[1].width, columns[1].right),
+            (2, 1, 2)
+        );
+        assert_eq!(columns[4].variable.name, "RAM16K");
+        assert_eq!(columns[4].variable.index, Some(12));
+        assert_eq!(columns[5].variable.index, Some(0));
+        assert!(matches!(commands[4], Command::Set(variable, 1) if variable.name == "in"));
+        assert!(matches!(commands[9], Command::Repeat(Some(2), block) if block.len() == 1));
+        assert!(matches!(
+            commands[10],
+            Command::While(_, Comparison::NotEqual, 0xFFFF, block) if block.len() == 2
+        ));
+        assert_eq!(
+            commands[11],
+            &Command::Part {
+                part: "ROM32K".to_string(),
+                command: "load".to_string(),
+                argument: "Max.hack".to_string(),
+            }
+        );
+        let cells = columns
+            .iter()
+            .zip([None, Some(1), Some(1), Some(0xBEEF), Some(1), Some(0)].iter())
+            .map(|(column, &value)| format!("{}|{}", column.header(), column.cell(value, "3+")))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            cells,
+            [
+                " time | 3+   ",
+                " in  |  1  ",
+                "load |  1  ",
+                " out  | BEEF ",
+                "RAM| 1 ",
+                "PC[| 0 "
+            ]
+        );
+
+        let errors = |script| parse(script).unwrap_err().to_string();
+        assert_eq!(
+            errors("load Bit.hdl"),
+            "1:13: Expected \",\" or \";\", found the end of the file"
+        );
+        assert_eq!(errors("set a 70000,"), "1:7: Invalid value 70000");
+        assert_eq!(
+            errors("output-list a%Q1.1.1;"),
+            "1:13: Invalid output format a%Q1.1.1"
+        );
+        assert_eq!(errors("tick, fly;"), "1:7: Unknown command fly");
+        assert_eq!(
+            errors("repeat 3 { tick,"),
+            "1:17: Expected \"}\", found the end of the file"
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use thiserror::Error;
+
+use crate::netlist::{Netlist, Node, Wire, TRUE};
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum SimulateError {
+    #[error("{0} is not a pin of the chip")]
+    UnknownPin(String),
+
+    #[error("{0} is not an input of the chip")]
+    NotInput(String),
+}
+
+pub type Result<T> = std::result::Result<T, SimulateError>;
+
+// Runs a netlist. The value of a pin is the number whose bit i is bit i of
+// the pin. Setting inputs leaves the outputs as they are until the next eval,
+// tick or tock, and the clocked parts change only on the clock: DFFs and
+// memories take in their inputs on tick and output them from tock.
+#[derive(Clone, Debug)]
+pub struct Simulator {
+    netlist: Netlist,
+    values: Vec<bool>,
+    // What each DFF took in on the last tick.
+    held: Vec<bool>,
+    contents: Vec<Vec<u16>>,
+    // The address and word each memory took in on the last tick, if loading.
+    writes: Vec<Option<(usize, u16)>>,
+}
+
+impl Simulator {
+    pub fn new(netlist: Netlist) -> Self {
+        let mut values = vec![false; netlist.wires];
+        values[TRUE as usize] = true;
+        let mut simulator = Self {
+            values,
+            held: vec![false; netlist.dffs.len()],
+            contents: netlist
+                .memories
+                .iter()
+                .map(|memory| vec![0; memory.size])
+                .collect(),
+            writes: vec![None; netlist.memories.len()],
+            netlist,
+        };
+        simulator.eval();
+        simulator
+    }
+
+    pub fn netlist(&self) -> &Netlist {
+        &self.netlist
+    }
+
+    pub fn read(&self, wires: &[Wire]) -> u16 {
+        wires.iter().enumerate().fold(0, |value, (bit, &wire)| {
+            value | (self.values[wire as usize] as u16) << bit
+        })
+    }
+
+    fn write(values: &mut [bool], wires: &[Wire], value: u16) {
+        for (bit, &wire) in wires.iter().enumerate() {
+            values[wire as usize] = value >> bit & 1 == 1;
+        }
+    }
+
+    // A pin of the chip, or a pin between its parts.
+    pub fn get(&self, pin: &str) -> Result<u16> {
+        self.netlist
+            .pin(pin)
+            .map(|wires| self.read(wires))
+            .ok_or_else(|| SimulateError::UnknownPin(pin.to_string()))
+    }
+
+    pub fn set(&mut self, pin: &str, value: u16) -> Result<()> {
+        if self.netlist.chip().input(pin).is_none() {
+            return Err(match self.netlist.pin(pin) {
+                Some(_) => SimulateError::NotInput(pin.to_string()),
+                None => SimulateError::UnknownPin(pin.to_string()),
+            });
+        }
+        let wires = self.netlist.instances[0].pin(pin).unwrap();
+        Self::write(&mut self.values, wires, value);
+        Ok(())
+    }
+
+    pub fn eval(&mut self) {
+        for node in &self.netlist.order {
+            match *node {
+                Node::Nand(nand) => {
+                    self.values[nand.out as usize] =
+                        !(self.values[nand.a as usize] && self.values[nand.b as usize])
+                }
+                Node::Read(index) => {
+                    let memory = &self.netlist.memories[index];
+                    let address =
+                        memory
+                            .address
+                            .iter()
+                            .enumerate()
+                            .fold(0, |address, (bit, &wire)| {
+                                address | (self.values[wire as usize] as usize) << bit
+                            });
+                    let word = self.contents[index][address];
+                    Self::write(&mut self.values, &memory.out, word);
+                }
+            }
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.eval();
+        for (held, dff) in self.held.iter_mut().zip(&self.netlist.dffs) {
+            *held = self.values[dff.input as usize];
+        }
+        for (index, memory) in self.netlist.memories.iter().enumerate() {
+            self.writes[index] = match self.values[memory.load as usize] {
+                true => Some((
+                    self.read(&memory.address) as usize,
+                    self.read(&memory.input),
+                )),
+                false => None,
+            };
+        }
+    }
+
+    pub fn tock(&mut self) {
+        for (&held, dff) in self.held.iter().zip(&self.netlist.dffs) {
+            self.values[dff.out as usize] = held;
+        }
+        for (contents, write) in self.contents.iter_mut().zip(&mut self.writes) {
+            if let Some((address, word)) = write.take() {
+                contents[address] = word;
+            }
+        }
+        self.eval();
+    }
+
+    // The words of a memory of the netlist. Changes to them show in the
+    // outputs from the next eval.
+    pub fn memory(&self, index: usize) -> &[u16] {
+        &self.contents[index]
+    }
+
+    pub fn memory_mut(&mut self, index: usize) -> &mut [u16] {
+        &mut self.contents[index]
+    }
+
+    // A word of a memory with the write the last tick took in already made,
+    // as it will be from the next tock.
+    pub fn word(&self, memory: usize, address: usize) -> u16 {
+        match self.writes[memory] {
+            Some((write, word)) if write == address => word,
+            _ => self.contents[memory][address],
+        }
+    }
+
+    // What a DFF took in on the last tick, which it outputs from the next
+    // tock.
+    pub fn held(&self, dff: usize) -> bool {
+        self.held[dff]
+    }
+
+    // Makes a DFF hold and output a value straight away.
+    pub fn hold(&mut self, dff: usize, value: bool) {
+        self.held[dff] = value;
+        self.values[self.netlist.dffs[dff].out as usize] = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{library, netlist::elaborate};
+
+    type Function = fn(i16, i16) -> i16;
+
+    fn simulator(chip: &str) -> Simulator {
+        let file = format!("{}.hdl", chip);
+        Simulator::new(elaborate(library::source(chip).unwrap(), &file, None).unwrap())
+    }
+
+    #[test]
+    fn alu_computes_every_function() {
+        let mut alu = simulator("ALU");
+        // zx nx zy ny f no, and what they compute.
+        let functions: [(u16, Function); 18] = [
+            (0b101010, |_, _| 0),
+            (0b111111, |_, _| 1),
+            (0b111010, |_, _| -1),
+            (0b001100, |x, _| x),
+            (0b110000, |_, y| y),
+            (0b001101, |x, _| !x),
+            (0b110001, |_, y| !y),
+            (0b001111, |x, _| x.wrapping_neg()),
+            (0b110011, |_, y| y.wrapping_neg()),
+            (0b011111, |x, _| x.wrapping_add(1)),
+            (0b110111, |_, y| y.wrapping_add(1)),
+            (0b001110, |x, _| x.wrapping_sub(1)),
+            (0b110010, |_, y| y.wrapping_sub(1)),
+            (0b000010, |x, y| x.wrapping_add(y)),
+            (0b010011, |x, y| x.wrapping_sub(y)),
+            (0b000111, |x, y| y.wrapping_sub(x)),
+            (0b000000, |x, y| x & y),
+            (0b010101, |x, y| x | y),
+        ];
+        let pins = ["zx", "nx", "zy", "ny", "f", "no"];
+        for &(x, y) in &[(0, 0), (17, 3), (-5, 12345), (i16::MIN, -1)] {
+            for (bits, function) in &functions {
+                alu.set("x", x as u16).unwrap();
+                alu.set("y", y as u16).unwrap();
+                for (i, pin) in pins.iter().enumerate() {
+                    alu.set(pin, bits >> (5 - i) & 1).unwrap();
+                }
+                alu.eval();
+                let expected = function(x, y);
+                assert_eq!(alu.get("out").unwrap() as i16, expected, "{:06b}", bits);
+                assert_eq!(alu.get("zr").unwrap(), (expected == 0) as u16);
+                assert_eq!(alu.get("ng").unwrap(), (expected < 0) as u16);
+            }
+        }
+    }
+
+    #[test]
+    fn clocked_chips_change_on_tock() {
+        let mut pc = simulator("PC");
+        pc.set("in", 7).unwrap();
+        pc.set("load", 1).unwrap();
+        pc.tick();
+        assert_eq!(pc.get("out"), Ok(0));
+        pc.tock();
+        assert_eq!(pc.get("out"), Ok(7));
+        pc.set("load", 0).unwrap();
+        pc.set("inc", 1).unwrap();
+        pc.tick();
+        pc.tock();
+        assert_eq!(pc.get("out"), Ok(8));
+        pc.set("reset", 1).unwrap();
+        pc.tick();
+        pc.tock();
+        assert_eq!(pc.get("out"), Ok(0));
+        assert_eq!(pc.get("current"), Ok(0));
+        assert_eq!(
+            pc.set("out", 1),
+            Err(SimulateError::NotInput("out".to_string()))
+        );
+        assert_eq!(
+            pc.get("carry"),
+            Err(SimulateError::UnknownPin("carry".to_string()))
+        );
+    }
+
+    #[test]
+    fn computer_runs_its_program() {
+        let mut computer = simulator("Computer");
+        let memory = |name| {
+            computer
+                .netlist()
+                .memories
+                .iter()
+                .position(|memory| computer.netlist().instances[memory.instance].name() == name)
+                .unwrap()
+        };
+        let (rom, ram) = (memory("ROM32K"), memory("RAM16K"));
+        // @0, D=M, @1, D=D+M, @2, M=D
+        let program = [0, 0xFC10, 1, 0xF090, 2, 0xE308];
+        computer.memory_mut(rom)[..6].copy_from_slice(&program);
+        computer.memory_mut(ram)[..2].copy_from_slice(&[2, 3]);
+        computer.set("reset", 1).unwrap();
+        computer.tick();
+        computer.tock();
+        computer.set("reset", 0).unwrap();
+        for _ in 0..6 {
+            computer.tick();
+            computer.tock();
+        }
+        assert_eq!(computer.memory(ram)[2], 5);
+        assert_eq!(computer.get("pc"), Ok(6));
+    }
+}
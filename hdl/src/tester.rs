@@ -0,0 +1,560 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::{
+    library,
+    native::Native,
+    netlist::{elaborate, ElaborateError, Located, Wire},
+    script::{parse, Column, Command, ScriptError, Statement, Variable},
+    simulator::{SimulateError, Simulator},
+    span::Span,
+};
+
+// Loops without an end give up after this many rounds.
+const ROUNDS: usize = 1_000_000;
+
+const ROM: usize = 32768;
+
+#[derive(Debug, Error)]
+pub enum TargetError {
+    #[error("{0} is not a pin or part of the chip")]
+    UnknownVariable(String),
+
+    #[error("{0} can't be set")]
+    NotInput(String),
+
+    #[error("{0} has no command {1}")]
+    UnknownCommand(String, String),
+
+    #[error("{0}: {1}")]
+    Io(String, io::Error),
+
+    #[error("{0}:{1}: Invalid instruction {2}")]
+    InvalidInstruction(String, usize, String),
+
+    #[error("{0} has more than {1} instructions")]
+    TooLong(String, usize),
+}
+
+#[derive(Debug, Error)]
+pub enum TestError {
+    #[error("{0}: {1}")]
+    Io(String, io::Error),
+
+    #[error("{0}:{1}")]
+    Script(String, ScriptError),
+
+    #[error("{0}: {1}: {2}")]
+    Open(Located, String, io::Error),
+
+    #[error("{0}: {1}")]
+    Elaborate(Located, Box<ElaborateError>),
+
+    #[error("{0}: There is no Rust chip {1}")]
+    UnknownNative(Located, String),
+
+    #[error("{0}: Only .hdl files can be loaded, not {1}")]
+    UnknownFile(Located, String),
+
+    #[error("{0}: No chip is loaded")]
+    NotLoaded(Located),
+
+    #[error("{0}: {1}")]
+    Target(Located, TargetError),
+
+    #[error("{0}: Gave up on the loop after {1} rounds")]
+    Endless(Located, usize),
+
+    #[error("{}", mismatch(.0, *.1, .2, .3, *.4))]
+    Mismatch(Located, usize, String, String, usize),
+}
+
+fn mismatch(located: &Located, line: usize, expected: &str, actual: &str, column: usize) -> String {
+    format!(
+        "{}: Comparison failure at line {}\nexpected: {}\nactual:   {}\n{:>width$}",
+        located,
+        line,
+        expected,
+        actual,
+        '^',
+        width = column + 11
+    )
+}
+
+// A chip the script drives.
+pub trait Target {
+    fn get(&mut self, variable: &Variable) -> Result<u16, TargetError>;
+    fn set(&mut self, variable: &Variable, value: u16) -> Result<(), TargetError>;
+    fn eval(&mut self);
+    fn tick(&mut self);
+    fn tock(&mut self);
+    // A command of a part, like loading a program into ROM32K.
+    fn command(&mut self, part: &str, command: &str, file: &Path) -> Result<(), TargetError>;
+}
+
+// The words of a .hack file, which fit in ROM32K.
+pub fn program(path: &Path) -> Result<Vec<u16>, TargetError> {
+    let file = path.display().to_string();
+    let source = fs::read_to_string(path).map_err(|error| TargetError::Io(file.clone(), error))?;
+    let program = source
+        .lines()
+        .map(str::trim)
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(number, line)| {
+            match line.len() {
+                16 => u16::from_str_radix(line, 2).ok(),
+                _ => None,
+            }
+            .ok_or_else(|| {
+                TargetError::InvalidInstruction(file.clone(), number + 1, line.to_string())
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    match program.len() {
+        0..=ROM => Ok(program),
+        _ => Err(TargetError::TooLong(file, ROM)),
+    }
+}
+
+// An HDL chip flattened into gates. Its parts are reached by name: the words
+// of a memory like RAM16K[5], and what a register like DRegister[] took in on
+// the last tick.
+pub struct Hdl {
+    simulator: Simulator,
+    // The DFF driving each wire it drives.
+    dffs: HashMap<Wire, usize>,
+}
+
+impl Hdl {
+    pub fn new(simulator: Simulator, key: u16) -> Self {
+        let netlist = simulator.netlist();
+        let dffs = netlist
+            .dffs
+            .iter()
+            .enumerate()
+            .map(|(index, dff)| (dff.out, index))
+            .collect();
+        let keyboard = netlist
+            .memories
+            .iter()
+            .position(|memory| netlist.instances[memory.instance].name() == "Keyboard");
+        let mut hdl = Self { simulator, dffs };
+        if let Some(keyboard) = keyboard {
+            hdl.simulator.memory_mut(keyboard)[0] = key;
+            hdl.simulator.eval();
+        }
+        hdl
+    }
+
+    fn memory(&self, name: &str) -> Option<usize> {
+        let netlist = self.simulator.netlist();
+        netlist
+            .memories
+            .iter()
+            .position(|memory| netlist.instances[memory.instance].name() == name)
+    }
+}
+
+impl Target for Hdl {
+    fn get(&mut self, variable: &Variable) -> Result<u16, TargetError> {
+        let unknown = || TargetError::UnknownVariable(variable.text.clone());
+        let index = match variable.index {
+            Some(index) => index,
+            None => return self.simulator.get(&variable.name).map_err(|_| unknown()),
+        };
+        if let Some(memory) = self.memory(&variable.name) {
+            return match index < self.simulator.memory(memory).len() {
+                true => Ok(self.simulator.word(memory, index)),
+                false => Err(unknown()),
+            };
+        }
+        let netlist = self.simulator.netlist();
+        let out = netlist
+            .instances
+            .iter()
+            .skip(1)
+            .find(|instance| instance.name() == variable.name)
+            .and_then(|instance| instance.pin("out"))
+            .filter(|_| index == 0)
+            .ok_or_else(unknown)?;
+        Ok(out.iter().enumerate().fold(0, |value, (bit, wire)| {
+            let held = match self.dffs.get(wire) {
+                Some(&dff) => self.simulator.held(dff),
+                None => self.simulator.read(&[*wire]) == 1,
+            };
+            value | (held as u16) << bit
+        }))
+    }
+
+    fn set(&mut self, variable: &Variable, value: u16) -> Result<(), TargetError> {
+        let index = match variable.index {
+            Some(index) => index,
+            None => {
+                return self
+                    .simulator
+                    .set(&variable.name, value)
+                    .map_err(|error| match error {
+                        SimulateError::UnknownPin(_) => {
+                            TargetError::UnknownVariable(variable.text.clone())
+                        }
+                        SimulateError::NotInput(_) => TargetError::NotInput(variable.text.clone()),
+                    })
+            }
+        };
+        match self.memory(&variable.name) {
+            Some(memory) if index < self.simulator.memory(memory).len() => {
+                self.simulator.memory_mut(memory)[index] = value;
+                Ok(())
+            }
+            _ => Err(TargetError::NotInput(variable.text.clone())),
+        }
+    }
+
+    fn eval(&mut self) {
+        self.simulator.eval();
+    }
+
+    fn tick(&mut self) {
+        self.simulator.tick();
+    }
+
+    fn tock(&mut self) {
+        self.simulator.tock();
+    }
+
+    fn command(&mut self, part: &str, command: &str, file: &Path) -> Result<(), TargetError> {
+        let memory = self
+            .memory(part)
+            .filter(|_| part == "ROM32K" && command == "load")
+            .ok_or_else(|| TargetError::UnknownCommand(part.to_string(), command.to_string()))?;
+        let program = program(file)?;
+        let contents = self.simulator.memory_mut(memory);
+        contents.iter_mut().for_each(|word| *word = 0);
+        contents[..program.len()].copy_from_slice(&program);
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Options {
+    // Runs the chips of the computer crate instead of the HDL.
+    pub native: bool,
+    // The key held down on the keyboard.
+    pub key: u16,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    // The lines output, and whether they matched a compare file.
+    pub lines: usize,
+    pub compared: bool,
+    pub echoes: Vec<String>,
+}
+
+// Where an output line first differs from the line of the compare file, in
+// which * matches any character.
+fn matches(expected: &str, actual: &str) -> Option<usize> {
+    let (expected, actual) = (expected.trim_end(), actual.trim_end());
+    let mut chars = expected.chars().zip(actual.chars());
+    match chars.position(|(expected, actual)| expected != '*' && expected != actual) {
+        Some(column) => Some(column),
+        None if expected.chars().count() != actual.chars().count() => {
+            Some(expected.chars().count().min(actual.chars().count()))
+        }
+        None => None,
+    }
+}
+
+struct Tester {
+    file: String,
+    directory: PathBuf,
+    options: Options,
+    target: Option<Box<dyn Target>>,
+    output: Option<PathBuf>,
+    compare: Option<Vec<String>>,
+    columns: Vec<Column>,
+    lines: Vec<String>,
+    time: usize,
+    ticked: bool,
+    echoes: Vec<String>,
+}
+
+impl Tester {
+    fn locate(&self, span: Span) -> Located {
+        Located {
+            file: self.file.clone(),
+            span,
+        }
+    }
+
+    fn load(&mut self, file: &str, span: Span) -> Result<Box<dyn Target>, TestError> {
+        let path = self.directory.join(file);
+        let name = match (path.file_stem(), path.extension()) {
+            (Some(name), Some(extension)) if extension == "hdl" => name.to_string_lossy(),
+            _ => return Err(TestError::UnknownFile(self.locate(span), file.to_string())),
+        };
+        if self.options.native {
+            return match Native::new(&name, self.options.key) {
+                Some(native) => Ok(Box::new(native)),
+                None => Err(TestError::UnknownNative(
+                    self.locate(span),
+                    name.to_string(),
+                )),
+            };
+        }
+        // A chip missing from the directory is taken from the built-in chips.
+        let source = match (fs::read_to_string(&path), library::source(&name)) {
+            (Ok(source), _) => source,
+            (Err(error), None) => {
+                return Err(TestError::Open(
+                    self.locate(span),
+                    path.display().to_string(),
+                    error,
+                ))
+            }
+            (Err(_), Some(source)) => source.to_string(),
+        };
+        let netlist = elaborate(&source, &path.display().to_string(), Some(&self.directory))
+            .map_err(|error| TestError::Elaborate(self.locate(span), Box::new(error)))?;
+        Ok(Box::new(Hdl::new(
+            Simulator::new(netlist),
+            self.options.key,
+        )))
+    }
+
+    fn target(&mut self, span: Span) -> Result<&mut (dyn Target + 'static), TestError> {
+        let located = self.locate(span);
+        self.target
+            .as_deref_mut()
+            .ok_or(TestError::NotLoaded(located))
+    }
+
+    fn output(&mut self, line: String, span: Span) -> Result<(), TestError> {
+        self.lines.push(line);
+        let (number, actual) = (self.lines.len(), &self.lines[self.lines.len() - 1]);
+        if let Some(compare) = &self.compare {
+            let expected = compare.get(number - 1).map_or("", String::as_str);
+            if let Some(column) = matches(expected, actual) {
+                return Err(TestError::Mismatch(
+                    self.locate(span),
+                    number,
+                    expected.trim_end().to_string(),
+                    actual.trim_end().to_string(),
+                    column,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn run(&mut self, statements: &[Statement]) -> Result<(), TestError> {
+        for statement in statements {
+            self.statement(statement)?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<(), TestError> {
+        let span = statement.span;
+        match &statement.command {
+            Command::Load(file) => self.target = Some(self.load(file, span)?),
+            Command::OutputFile(file) => self.output = Some(self.directory.join(file)),
+            Command::CompareTo(file) => {
+                let path = self.directory.join(file);
+                let compare = fs::read_to_string(&path).map_err(|error| {
+                    TestError::Open(self.locate(span), path.display().to_string(), error)
+                })?;
+                self.compare = Some(compare.lines().map(str::to_string).collect());
+            }
+            Command::OutputList(columns) => {
+                self.columns = columns.clone();
+                let header = columns
+                    .iter()
+                    .map(|column| format!("|{}", column.header()))
+                    .collect::<String>();
+                self.output(header + "|", span)?;
+            }
+            Command::Set(variable, value) => self
+                .target(span)?
+                .set(variable, *value)
+                .map_err(|error| TestError::Target(self.locate(variable.span), error))?,
+            Command::Eval => self.target(span)?.eval(),
+            Command::Tick => {
+                self.target(span)?.tick();
+                self.ticked = true;
+            }
+            Command::Tock => {
+                self.target(span)?.tock();
+                self.time += 1;
+                self.ticked = false;
+            }
+            Command::Output => {
+                let time = format!("{}{}", self.time, if self.ticked { "+" } else { "" });
+                let columns = self.columns.clone();
+                let mut line = String::new();
+                for column in &columns {
+                    let value = match (column.variable.name.as_str(), column.variable.index) {
+                        ("time", None) => None,
+                        _ => Some(self.target(span)?.get(&column.variable).map_err(|error| {
+                            TestError::Target(self.locate(column.variable.span), error)
+                        })?),
+                    };
+                    line += &format!("|{}", column.cell(value, &time));
+                }
+                self.output(line + "|", span)?;
+            }
+            Command::Echo(text) => self.echoes.push(text.clone()),
+            Command::ClearEcho => {}
+            Command::Repeat(Some(count), statements) => {
+                for _ in 0..*count {
+                    self.run(statements)?;
+                }
+            }
+            Command::Repeat(None, statements) => {
+                for _ in 0..ROUNDS {
+                    self.run(statements)?;
+                }
+                return Err(TestError::Endless(self.locate(span), ROUNDS));
+            }
+            Command::While(variable, comparison, value, statements) => {
+                for _ in 0..ROUNDS {
+                    let current = self
+                        .target(span)?
+                        .get(variable)
+                        .map_err(|error| TestError::Target(self.locate(variable.span), error))?;
+                    if !comparison.holds(current, *value) {
+                        return Ok(());
+                    }
+                    self.run(statements)?;
+                }
+                return Err(TestError::Endless(self.locate(span), ROUNDS));
+            }
+            Command::Part {
+                part,
+                command,
+                argument,
+            } => {
+                let file = self.directory.join(argument);
+                self.target(span)?
+                    .command(part, command, &file)
+                    .map_err(|error| TestError::Target(self.locate(span), error))?
+            }
+        }
+        Ok(())
+    }
+}
+
+// Runs a test script, writing its output file even when a line of the output
+// doesn't match the compare file.
+pub fn test(path: &Path, options: Options) -> Result<Report, TestError> {
+    let file = path.display().to_string();
+    let source = fs::read_to_string(path).map_err(|error| TestError::Io(file.clone(), error))?;
+    let statements = parse(&source).map_err(|error| TestError::Script(file.clone(), error))?;
+    let mut tester = Tester {
+        file,
+        directory: path.parent().unwrap_or_else(|| Path::new("")).to_path_buf(),
+        options,
+        target: None,
+        output: None,
+        compare: None,
+        columns: vec![],
+        lines: vec![],
+        time: 0,
+        ticked: false,
+        echoes: vec![],
+    };
+    let result = tester.run(&statements);
+    if let Some(output) = &tester.output {
+        let mut text = tester.lines.join("\n");
+        text.push('\n');
+        fs::write(output, text)
+            .map_err(|error| TestError::Io(output.display().to_string(), error))?;
+    }
+    result.map(|_| Report {
+        lines: tester.lines.len(),
+        compared: tester.compare.is_some(),
+        echoes: tester.echoes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempDir;
+
+    const ADD: &str = "load Computer.hdl,
+output-file Add.out,
+compare-to Add.cmp,
+output-list time%S1.4.1 reset%B2.1.2 ARegister[]%D1.7.1 DRegister[]%D1.7.1 PC[]%D0.4.0 RAM16K[0]%D1.7.1 RAM16K[2]%D1.7.1;
+ROM32K load Add.hack,
+set RAM16K[0] 7,
+repeat 6 {
+    tick, tock, output;
+}
+";
+
+    const CMP: &str = "| time |reset|ARegister|DRegister|PC[]|RAM16K[0]|RAM16K[2]|
+| 1    |  0  |       2 |       0 |   1|       7 |       0 |
+| 2    |  0  |       2 |       2 |   2|       7 |       0 |
+| 3    |  0  |       3 |       2 |   3|       7 |       0 |
+| 4    |  0  |       3 |       5 |   4|       7 |       0 |
+| 5    |  0  |       0 |       5 |   5|       7 |       0 |
+| 6    |  0  |       0 |       5 |   6|       5 |       * |
+";
+
+    #[test]
+    fn scripts_run_the_same_on_hdl_and_rust_chips() {
+        let directory = TempDir::new("hdl-tester-test");
+        let write = |name: &str, contents: &str| fs::write(directory.join(name), contents).unwrap();
+        // @2, D=A, @3, D=D+A, @0, M=D
+        write(
+            "Add.hack",
+            "0000000000000010\n1110110000010000\n0000000000000011\n\
+             1110000010010000\n0000000000000000\n1110001100001000\n",
+        );
+        write("Add.tst", ADD);
+        write("Add.cmp", CMP);
+        for &native in &[false, true] {
+            let report = test(&directory.join("Add.tst"), Options { native, key: 0 }).unwrap();
+            assert_eq!(report.lines, 7);
+            assert!(report.compared);
+            let out = fs::read_to_string(directory.join("Add.out")).unwrap();
+            assert_eq!(
+                out.lines().last(),
+                Some("| 6    |  0  |       0 |       5 |   6|       5 |       0 |")
+            );
+        }
+
+        write(
+            "Add.cmp",
+            &CMP.replace("|   4|       7 |", "|   4|       8 |"),
+        );
+        for &native in &[false, true] {
+            let error = test(&directory.join("Add.tst"), Options { native, key: 0 }).unwrap_err();
+            assert!(error.to_string().ends_with(
+                "Add.tst:8:17: Comparison failure at line 5
+expected: | 4    |  0  |       3 |       5 |   4|       8 |       0 |
+actual:   | 4    |  0  |       3 |       5 |   4|       7 |       0 |
+                                                        ^"
+            ));
+            let out = fs::read_to_string(directory.join("Add.out")).unwrap();
+            assert_eq!(out.lines().count(), 5);
+        }
+
+        write("Loop.tst", "load Keyboard.hdl, while out <> 75 { eval; }");
+        for &native in &[false, true] {
+            let loop_test = |key| test(&directory.join("Loop.tst"), Options { native, key });
+            assert!(loop_test(75).is_ok());
+            assert!(loop_test(0)
+                .unwrap_err()
+                .to_string()
+                .ends_with("Loop.tst:1:20: Gave up on the loop after 1000000 rounds"));
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::fmt;
+
+use crate::span::Span;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Keyword {
+    Chip,
+    In,
+    Out,
+    Parts,
+    Builtin,
+    Clocked,
+    True,
+    False,
+}
+
+impl Keyword {
+    pub const ALL: [Keyword; 8] = [
+        Keyword::Chip,
+        Keyword::In,
+        Keyword::Out,
+        Keyword::Parts,
+        Keyword::Builtin,
+        Keyword::Clocked,
+        Keyword::True,
+        Keyword::False,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|keyword| keyword.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Keyword::Chip => "CHIP",
+            Keyword::In => "IN",
+            Keyword::Out => "OUT",
+            Keyword::Parts => "PARTS",
+            Keyword::Builtin => "BUILTIN",
+            Keyword::Clocked => "CLOCKED",
+            Keyword::True => "true",
+            Keyword::False => "false",
+        }
+    }
+}
+
+impl fmt::Display for Keyword {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+pub const SYMBOLS: &str = "{}()[],;:=";
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    Keyword(Keyword),
+    Symbol(char),
+    // The `..` between the bounds of a sub-bus.
+    Range,
+    Number(u16),
+    Identifier(String),
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenKind::Keyword(keyword) => write!(f, "{}", keyword),
+            TokenKind::Symbol(symbol) => write!(f, "{}", symbol),
+            TokenKind::Range => write!(f, ".."),
+            TokenKind::Number(value) => write!(f, "{}", value),
+            TokenKind::Identifier(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
@@ -0,0 +1,242 @@
+use std::{iter::Peekable, str::CharIndices};
+
+use thiserror::Error;
+
+use crate::{
+    span::Span,
+    token::{Keyword, Token, TokenKind, SYMBOLS},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum TokenizeError {
+    #[error("{0}: Unterminated comment")]
+    UnterminatedComment(Span),
+
+    #[error("{0}: Invalid character {1:?}")]
+    InvalidCharacter(Span, char),
+
+    #[error("{0}: {1} is too large")]
+    NumberOutOfRange(Span, String),
+}
+
+impl TokenizeError {
+    pub fn span(&self) -> Span {
+        match self {
+            TokenizeError::UnterminatedComment(span)
+            | TokenizeError::InvalidCharacter(span, _)
+            | TokenizeError::NumberOutOfRange(span, _) => *span,
+        }
+    }
+}
+
+// Yields tokens and errors in source order. Tokenizing carries on after an
+// error, skipping the offending characters.
+pub struct Tokenizer<'a> {
+    source: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            chars: source.char_indices().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn offset(&mut self) -> usize {
+        self.chars
+            .peek()
+            .map_or(self.source.len(), |&(offset, _)| offset)
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|&(_, c)| c)
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        let mut chars = self.chars.clone();
+        chars.next();
+        chars.next().map(|(_, c)| c)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let (_, c) = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn start(&mut self) -> Span {
+        let start = self.offset();
+        Span {
+            start,
+            end: start,
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    fn finish(&mut self, span: Span) -> Span {
+        Span {
+            end: self.offset(),
+            ..span
+        }
+    }
+
+    // Skips whitespace and comments.
+    fn skip_trivia(&mut self) -> Result<(), TokenizeError> {
+        loop {
+            match (self.peek(), self.peek_second()) {
+                (Some(c), _) if c.is_whitespace() => {
+                    self.bump();
+                }
+                (Some('/'), Some('/')) => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.bump();
+                    }
+                }
+                (Some('/'), Some('*')) => {
+                    let span = self.start();
+                    self.bump();
+                    self.bump();
+                    loop {
+                        match self.bump() {
+                            Some('*') if self.peek() == Some('/') => {
+                                self.bump();
+                                break;
+                            }
+                            Some(_) => {}
+                            None => {
+                                return Err(TokenizeError::UnterminatedComment(self.finish(span)))
+                            }
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn token(&mut self) -> Option<Result<Token, TokenizeError>> {
+        if let Err(error) = self.skip_trivia() {
+            return Some(Err(error));
+        }
+        let span = self.start();
+        let c = self.bump()?;
+        let kind = if SYMBOLS.contains(c) {
+            TokenKind::Symbol(c)
+        } else if c == '.' && self.peek() == Some('.') {
+            self.bump();
+            TokenKind::Range
+        } else if c.is_ascii_digit() {
+            while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                self.bump();
+            }
+            let span = self.finish(span);
+            let digits = &self.source[span.start..span.end];
+            match digits.parse::<u16>() {
+                Ok(value) => TokenKind::Number(value),
+                Err(_) => {
+                    return Some(Err(TokenizeError::NumberOutOfRange(
+                        span,
+                        digits.to_string(),
+                    )))
+                }
+            }
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            while self
+                .peek()
+                .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+            {
+                self.bump();
+            }
+            let span = self.finish(span);
+            let word = &self.source[span.start..span.end];
+            match Keyword::from_name(word) {
+                Some(keyword) => TokenKind::Keyword(keyword),
+                None => TokenKind::Identifier(word.to_string()),
+            }
+        } else {
+            return Some(Err(TokenizeError::InvalidCharacter(self.finish(span), c)));
+        };
+        Some(Ok(Token {
+            kind,
+            span: self.finish(span),
+        }))
+    }
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = Result<Token, TokenizeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.token()
+    }
+}
+
+// Stops at the first error.
+pub fn tokenize(source: &str) -> Result<Vec<Token>, TokenizeError> {
+    Tokenizer::new(source).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenizer_reads_every_kind_of_token() {
+        let source = "/** And */\nCHIP And {\n  // Pins\n  IN a[16]; PARTS: X(a[0..7]=true);\n}";
+        let kinds = tokenize(source)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.kind)
+            .collect::<Vec<_>>();
+        let identifier = |name: &str| TokenKind::Identifier(name.to_string());
+        assert_eq!(
+            kinds,
+            [
+                TokenKind::Keyword(Keyword::Chip),
+                identifier("And"),
+                TokenKind::Symbol('{'),
+                TokenKind::Keyword(Keyword::In),
+                identifier("a"),
+                TokenKind::Symbol('['),
+                TokenKind::Number(16),
+                TokenKind::Symbol(']'),
+                TokenKind::Symbol(';'),
+                TokenKind::Keyword(Keyword::Parts),
+                TokenKind::Symbol(':'),
+                identifier("X"),
+                TokenKind::Symbol('('),
+                identifier("a"),
+                TokenKind::Symbol('['),
+                TokenKind::Number(0),
+                TokenKind::Range,
+                TokenKind::Number(7),
+                TokenKind::Symbol(']'),
+                TokenKind::Symbol('='),
+                TokenKind::Keyword(Keyword::True),
+                TokenKind::Symbol(')'),
+                TokenKind::Symbol(';'),
+                TokenKind::Symbol('}'),
+            ]
+        );
+        let errors = Tokenizer::new("a.b 99999 /*")
+            .filter_map(Result::err)
+            .collect::<Vec<_>>();
+        assert!(
+            matches!(errors[0], TokenizeError::InvalidCharacter(span, '.') if span.column == 2)
+        );
+        assert!(matches!(errors[1], TokenizeError::NumberOutOfRange(..)));
+        assert!(matches!(errors[2], TokenizeError::UnterminatedComment(..)));
+    }
+}
@@ -0,0 +1,263 @@
+use crate::span::Span;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Identifier {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Type {
+    Int,
+    Char,
+    Boolean,
+    Class(String),
+}
+
+impl Type {
+    pub fn name(&self) -> &str {
+        match self {
+            Type::Int => "int",
+            Type::Char => "char",
+            Type::Boolean => "boolean",
+            Type::Class(name) => name,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "int" => Type::Int,
+            "char" => Type::Char,
+            "boolean" => Type::Boolean,
+            _ => Type::Class(name.to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClassVarKind {
+    Static,
+    Field,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClassVarDec {
+    pub kind: ClassVarKind,
+    pub ty: Type,
+    pub names: Vec<Identifier>,
+    pub span: Span,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SubroutineKind {
+    Constructor,
+    Function,
+    Method,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Parameter {
+    pub ty: Type,
+    pub name: Identifier,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VarDec {
+    pub ty: Type,
+    pub names: Vec<Identifier>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Subroutine {
+    pub kind: SubroutineKind,
+    // None for void.
+    pub return_type: Option<Type>,
+    pub name: Identifier,
+    pub parameters: Vec<Parameter>,
+    pub locals: Vec<VarDec>,
+    pub body: Vec<Statement>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Class {
+    pub name: Identifier,
+    pub variables: Vec<ClassVarDec>,
+    pub subroutines: Vec<Subroutine>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Statement {
+    Let {
+        name: Identifier,
+        index: Option<Box<Expression>>,
+        value: Expression,
+        span: Span,
+    },
+    If {
+        condition: Expression,
+        then: Vec<Statement>,
+        otherwise: Option<Vec<Statement>>,
+        span: Span,
+    },
+    While {
+        condition: Expression,
+        body: Vec<Statement>,
+        span: Span,
+    },
+    Do {
+        call: SubroutineCall,
+        span: Span,
+    },
+    Return {
+        value: Option<Expression>,
+        span: Span,
+    },
+    // Only in the extended dialect, and only inside a loop.
+    Break {
+        span: Span,
+    },
+    Continue {
+        span: Span,
+    },
+}
+
+impl Statement {
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::Let { span, .. }
+            | Statement::If { span, .. }
+            | Statement::While { span, .. }
+            | Statement::Do { span, .. }
+            | Statement::Return { span, .. }
+            | Statement::Break { span }
+            | Statement::Continue { span } => *span,
+        }
+    }
+}
+
+// `receiver` is the class or variable before the dot, if any.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubroutineCall {
+    pub receiver: Option<Identifier>,
+    pub name: Identifier,
+    pub arguments: Vec<Expression>,
+    pub span: Span,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    And,
+    Or,
+    Lt,
+    Gt,
+    Eq,
+}
+
+impl BinaryOp {
+    pub const ALL: [BinaryOp; 9] = [
+        BinaryOp::Add,
+        BinaryOp::Sub,
+        BinaryOp::Mul,
+        BinaryOp::Div,
+        BinaryOp::And,
+        BinaryOp::Or,
+        BinaryOp::Lt,
+        BinaryOp::Gt,
+        BinaryOp::Eq,
+    ];
+
+    pub fn from_symbol(symbol: char) -> Option<Self> {
+        Self::ALL.iter().copied().find(|op| op.symbol() == symbol)
+    }
+
+    pub fn symbol(&self) -> char {
+        match self {
+            BinaryOp::Add => '+',
+            BinaryOp::Sub => '-',
+            BinaryOp::Mul => '*',
+            BinaryOp::Div => '/',
+            BinaryOp::And => '&',
+            BinaryOp::Or => '|',
+            BinaryOp::Lt => '<',
+            BinaryOp::Gt => '>',
+            BinaryOp::Eq => '=',
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+impl UnaryOp {
+    pub fn symbol(&self) -> char {
+        match self {
+            UnaryOp::Neg => '-',
+            UnaryOp::Not => '~',
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeywordConstant {
+    True,
+    False,
+    Null,
+    This,
+}
+
+impl KeywordConstant {
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeywordConstant::True => "true",
+            KeywordConstant::False => "false",
+            KeywordConstant::Null => "null",
+            KeywordConstant::This => "this",
+        }
+    }
+}
+
+// Jack has no operator precedence: an expression is its terms combined from
+// left to right.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Expression {
+    pub first: Term,
+    pub rest: Vec<(BinaryOp, Term)>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Term {
+    Integer(u16, Span),
+    String(String, Span),
+    Keyword(KeywordConstant, Span),
+    Variable(Identifier),
+    Index(Identifier, Box<Expression>, Span),
+    Call(SubroutineCall),
+    Parenthesized(Box<Expression>, Span),
+    Unary(UnaryOp, Box<Term>, Span),
+}
+
+impl Term {
+    pub fn span(&self) -> Span {
+        match self {
+            Term::Integer(_, span)
+            | Term::String(_, span)
+            | Term::Keyword(_, span)
+            | Term::Index(_, _, span)
+            | Term::Parenthesized(_, span)
+            | Term::Unary(_, _, span) => *span,
+            Term::Variable(identifier) => identifier.span,
+            Term::Call(call) => call.span,
+        }
+    }
+}
@@ -0,0 +1,461 @@
+use std::collections::{HashMap, HashSet};
+
+use thiserror::Error;
+
+use crate::{
+    ast::{
+        Class, ClassVarKind, Expression, Identifier, Statement, Subroutine, SubroutineCall,
+        SubroutineKind, Term, Type,
+    },
+    optimize::constant_expression,
+    span::Span,
+    symbols::{Kind, Symbol, SymbolTable},
+};
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CheckError {
+    #[error("{0}: Undeclared identifier {1}")]
+    UndeclaredIdentifier(Span, String),
+
+    #[error("{0}: Class {1} is not defined")]
+    UndefinedClass(Span, String),
+
+    #[error("{0}: Field {1} used in a function")]
+    FieldInFunction(Span, String),
+
+    #[error("{0}: {1} is not an object")]
+    NotAnObject(Span, String),
+
+    #[error("{0}: {1} is not defined")]
+    UndefinedSubroutine(Span, String),
+
+    #[error("{0}: {1} takes {2} arguments but is given {3}")]
+    ArgumentCount(Span, String, usize, usize),
+
+    #[error("{0}: {1} returns void and has no value")]
+    VoidValue(Span, String),
+
+    #[error("{0}: Method {1} called without an object")]
+    MethodWithoutObject(Span, String),
+
+    #[error("{0}: {1} is not a method and must be called through its class")]
+    NotAMethod(Span, String),
+
+    #[error("{0}: {1} does not return on every path")]
+    MissingReturn(Span, String),
+
+    #[error("{0}: Unused local variable {1}")]
+    UnusedLocal(Span, String),
+}
+
+impl CheckError {
+    pub fn span(&self) -> Span {
+        match self {
+            CheckError::UndeclaredIdentifier(span, _)
+            | CheckError::UndefinedClass(span, _)
+            | CheckError::FieldInFunction(span, _)
+            | CheckError::NotAnObject(span, _)
+            | CheckError::UndefinedSubroutine(span, _)
+            | CheckError::ArgumentCount(span, _, _, _)
+            | CheckError::VoidValue(span, _)
+            | CheckError::MethodWithoutObject(span, _)
+            | CheckError::NotAMethod(span, _)
+            | CheckError::MissingReturn(span, _)
+            | CheckError::UnusedLocal(span, _) => *span,
+        }
+    }
+
+    // Warnings point at dead code rather than at code that will misbehave.
+    pub fn is_warning(&self) -> bool {
+        matches!(self, CheckError::UnusedLocal(..))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Signature {
+    kind: SubroutineKind,
+    parameters: usize,
+    void: bool,
+}
+
+// Checks classes against the subroutines of every class in the program, so
+// that calls between them can be matched up. The program includes the OS
+// classes it calls.
+pub struct Checker {
+    classes: HashMap<String, HashMap<String, Signature>>,
+}
+
+impl Checker {
+    // Later classes replace earlier ones of the same name.
+    pub fn new(classes: &[Class]) -> Self {
+        let mut signatures = HashMap::new();
+        for class in classes {
+            let subroutines = class
+                .subroutines
+                .iter()
+                .map(|subroutine| {
+                    let signature = Signature {
+                        kind: subroutine.kind,
+                        parameters: subroutine.parameters.len(),
+                        void: subroutine.return_type.is_none(),
+                    };
+                    (subroutine.name.name.clone(), signature)
+                })
+                .collect();
+            signatures.insert(class.name.name.clone(), subroutines);
+        }
+        Self {
+            classes: signatures,
+        }
+    }
+
+    // Every problem found in the class, in source order.
+    pub fn check(&self, class: &Class) -> Vec<CheckError> {
+        let mut symbols = SymbolTable::new();
+        for variable in &class.variables {
+            let kind = match variable.kind {
+                ClassVarKind::Static => Kind::Static,
+                ClassVarKind::Field => Kind::Field,
+            };
+            for name in &variable.names {
+                symbols.define(&name.name, variable.ty.clone(), kind);
+            }
+        }
+        let mut scope = Scope {
+            checker: self,
+            class,
+            kind: SubroutineKind::Function,
+            symbols,
+            used: HashSet::new(),
+            errors: vec![],
+        };
+        for subroutine in &class.subroutines {
+            scope.subroutine(subroutine);
+        }
+        let mut errors = scope.errors;
+        errors.sort_by_key(|error| error.span().start);
+        errors
+    }
+}
+
+struct Scope<'a> {
+    checker: &'a Checker,
+    class: &'a Class,
+    kind: SubroutineKind,
+    symbols: SymbolTable,
+    // The locals referred to so far.
+    used: HashSet<String>,
+    errors: Vec<CheckError>,
+}
+
+// Whether control can't fall off the end of the statements. A loop whose
+// condition is constantly true only ends at a break.
+fn returns(statements: &[Statement]) -> bool {
+    statements.iter().any(|statement| match statement {
+        Statement::Return { .. } => true,
+        Statement::If {
+            then,
+            otherwise: Some(otherwise),
+            ..
+        } => returns(then) && returns(otherwise),
+        Statement::While {
+            condition, body, ..
+        } => constant_expression(condition).is_some_and(|value| value != 0) && !breaks(body),
+        _ => false,
+    })
+}
+
+// Whether the statements break out of the loop they are in.
+fn breaks(statements: &[Statement]) -> bool {
+    statements.iter().any(|statement| match statement {
+        Statement::Break { .. } => true,
+        Statement::If {
+            then, otherwise, ..
+        } => breaks(then) || otherwise.as_deref().is_some_and(breaks),
+        _ => false,
+    })
+}
+
+impl<'a> Scope<'a> {
+    fn subroutine(&mut self, subroutine: &Subroutine) {
+        self.kind = subroutine.kind;
+        self.symbols.start_subroutine();
+        self.used.clear();
+        for parameter in &subroutine.parameters {
+            self.symbols
+                .define(&parameter.name.name, parameter.ty.clone(), Kind::Argument);
+        }
+        for local in &subroutine.locals {
+            for name in &local.names {
+                self.symbols
+                    .define(&name.name, local.ty.clone(), Kind::Local);
+            }
+        }
+        self.statements(&subroutine.body);
+        if !returns(&subroutine.body) {
+            self.errors.push(CheckError::MissingReturn(
+                subroutine.name.span,
+                subroutine.name.name.clone(),
+            ));
+        }
+        for local in &subroutine.locals {
+            for name in &local.names {
+                if !self.used.contains(&name.name) {
+                    self.errors
+                        .push(CheckError::UnusedLocal(name.span, name.name.clone()));
+                }
+            }
+        }
+    }
+
+    fn variable(&mut self, name: &Identifier) -> Option<Symbol> {
+        let symbol = match self.symbols.get(&name.name) {
+            Some(symbol) => symbol.clone(),
+            None => {
+                self.errors.push(CheckError::UndeclaredIdentifier(
+                    name.span,
+                    name.name.clone(),
+                ));
+                return None;
+            }
+        };
+        match symbol.kind {
+            Kind::Local => {
+                self.used.insert(name.name.clone());
+            }
+            Kind::Field if self.kind == SubroutineKind::Function => {
+                self.errors
+                    .push(CheckError::FieldInFunction(name.span, name.name.clone()));
+            }
+            _ => {}
+        }
+        Some(symbol)
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let {
+                name, index, value, ..
+            } => {
+                self.variable(name);
+                if let Some(index) = index {
+                    self.expression(index);
+                }
+                self.expression(value);
+            }
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                self.expression(condition);
+                self.statements(then);
+                if let Some(otherwise) = otherwise {
+                    self.statements(otherwise);
+                }
+            }
+            Statement::While {
+                condition, body, ..
+            } => {
+                self.expression(condition);
+                self.statements(body);
+            }
+            Statement::Do { call, .. } => self.call(call, false),
+            Statement::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            Statement::Break { .. } | Statement::Continue { .. } => {}
+        }
+    }
+
+    fn call(&mut self, call: &SubroutineCall, value: bool) {
+        for argument in &call.arguments {
+            self.expression(argument);
+        }
+        // The class called into, and whether the call passes an object.
+        let (class, object) = match &call.receiver {
+            None => (self.class.name.name.clone(), true),
+            Some(receiver) if self.symbols.get(&receiver.name).is_some() => {
+                match self.variable(receiver).map(|symbol| symbol.ty) {
+                    Some(Type::Class(class)) => (class, true),
+                    _ => {
+                        self.errors.push(CheckError::NotAnObject(
+                            receiver.span,
+                            receiver.name.clone(),
+                        ));
+                        return;
+                    }
+                }
+            }
+            Some(receiver) if self.checker.classes.contains_key(&receiver.name) => {
+                (receiver.name.clone(), false)
+            }
+            Some(receiver) => {
+                self.errors.push(CheckError::UndeclaredIdentifier(
+                    receiver.span,
+                    receiver.name.clone(),
+                ));
+                return;
+            }
+        };
+        let subroutines = match self.checker.classes.get(&class) {
+            Some(subroutines) => subroutines,
+            None => {
+                // Only an object's class can be missing here.
+                let receiver = call.receiver.as_ref().unwrap();
+                self.errors
+                    .push(CheckError::UndefinedClass(receiver.span, class));
+                return;
+            }
+        };
+        let name = format!("{}.{}", class, call.name.name);
+        let signature = match subroutines.get(&call.name.name) {
+            Some(signature) => *signature,
+            None => {
+                self.errors
+                    .push(CheckError::UndefinedSubroutine(call.name.span, name));
+                return;
+            }
+        };
+        let method = signature.kind == SubroutineKind::Method;
+        let implicit = call.receiver.is_none();
+        if method && (!object || (implicit && self.kind == SubroutineKind::Function)) {
+            self.errors
+                .push(CheckError::MethodWithoutObject(call.span, name.clone()));
+        } else if !method && object {
+            self.errors
+                .push(CheckError::NotAMethod(call.span, name.clone()));
+        }
+        if signature.parameters != call.arguments.len() {
+            self.errors.push(CheckError::ArgumentCount(
+                call.span,
+                name.clone(),
+                signature.parameters,
+                call.arguments.len(),
+            ));
+        }
+        if value && signature.void {
+            self.errors.push(CheckError::VoidValue(call.span, name));
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        self.term(&expression.first);
+        for (_, term) in &expression.rest {
+            self.term(term);
+        }
+    }
+
+    fn term(&mut self, term: &Term) {
+        match term {
+            Term::Integer(..) | Term::String(..) | Term::Keyword(..) => {}
+            Term::Variable(name) => {
+                self.variable(name);
+            }
+            Term::Index(name, index, _) => {
+                self.variable(name);
+                self.expression(index);
+            }
+            Term::Call(call) => self.call(call, true),
+            Term::Parenthesized(expression, _) => self.expression(expression),
+            Term::Unary(_, term, _) => self.term(term),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        os,
+        parser::{parse, parse_dialect},
+        token::Dialect,
+    };
+
+    #[test]
+    fn checker_reports_misuse_across_classes() {
+        let main = parse_dialect(
+            "class Main {
+                field int count;
+                function void main() {
+                    var Point p;
+                    var int unused, n;
+                    let p = Point.new(1);
+                    let n = p.getX() + Point.getX() + p.origin();
+                    let n = Output.printInt(n) + missing;
+                    do p.move();
+                    do helper();
+                    let count = n;
+                    do n.foo();
+                    do Nowhere.go();
+                    return;
+                }
+                method int helper() {
+                    if (count > 0) { return count; }
+                }
+                function void ghost() {
+                    var Foo foo;
+                    do foo.bar();
+                    return;
+                }
+                function int forever() {
+                    while (true) { return 1; }
+                }
+                function int escapes() {
+                    while (true) {
+                        if (Main.forever() = 1) { break; }
+                        while (true) { break; }
+                    }
+                }
+            }",
+            Dialect::Extended,
+        )
+        .unwrap();
+        let point = parse(
+            "class Point {
+                field int x;
+                constructor Point new(int ax, int ay) { let x = ax; return this; }
+                method int getX() { return x; }
+                function Point origin() { return Point.new(0, 0); }
+            }",
+        )
+        .unwrap();
+        let mut classes = os::classes();
+        classes.extend(vec![main.clone(), point.clone()]);
+        let checker = Checker::new(&classes);
+        assert!(checker.check(&point).is_empty());
+        let errors: Vec<String> = checker
+            .check(&main)
+            .iter()
+            .map(|error| error.to_string())
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                "5:29: Unused local variable unused",
+                "6:29: Point.new takes 2 arguments but is given 1",
+                "7:40: Method Point.getX called without an object",
+                "7:55: Point.origin is not a method and must be called through its class",
+                "8:29: Output.printInt returns void and has no value",
+                "8:50: Undeclared identifier missing",
+                "9:26: Point.move is not defined",
+                "10:24: Method Main.helper called without an object",
+                "11:25: Field count used in a function",
+                "12:24: n is not an object",
+                "13:24: Undeclared identifier Nowhere",
+                "16:28: helper does not return on every path",
+                "21:24: Class Foo is not defined",
+                "27:30: escapes does not return on every path",
+            ]
+        );
+    }
+}
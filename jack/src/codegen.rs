@@ -0,0 +1,707 @@
+use thiserror::Error;
+
+use vm::{
+    command::{Arithmetic, Command, Segment},
+    loader::Module,
+};
+
+use crate::{
+    ast::{
+        BinaryOp, Class, ClassVarKind, Expression, KeywordConstant, Statement, Subroutine,
+        SubroutineCall, SubroutineKind, Term, Type, UnaryOp,
+    },
+    debug::ClassInfo,
+    optimize::{constant, constant_expression},
+    span::Span,
+    symbols::{Kind, SymbolTable},
+};
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CompileError {
+    #[error("{0}: Undeclared variable {1}")]
+    UndeclaredVariable(Span, String),
+
+    #[error("{0}: {1} is not an object")]
+    NotAnObject(Span, String),
+
+    #[error("{0}: Field {1} used in a function")]
+    FieldInFunction(Span, String),
+
+    #[error("{0}: {1} is not defined")]
+    UndefinedSubroutine(Span, String),
+
+    #[error("{0}: {1} takes {2} arguments but is given {3}")]
+    ArgumentCount(Span, String, usize, usize),
+
+    #[error("{0}: The frame of {1} does not fit below the heap")]
+    OutOfRam(Span, String),
+}
+
+impl CompileError {
+    pub fn span(&self) -> Span {
+        match self {
+            CompileError::UndeclaredVariable(span, _)
+            | CompileError::NotAnObject(span, _)
+            | CompileError::FieldInFunction(span, _)
+            | CompileError::UndefinedSubroutine(span, _)
+            | CompileError::ArgumentCount(span, _, _, _)
+            | CompileError::OutOfRam(span, _) => *span,
+        }
+    }
+}
+
+struct Generator<'a> {
+    class: &'a Class,
+    symbols: SymbolTable,
+    commands: Vec<Command>,
+    // The Jack line each command was compiled from.
+    lines: Vec<usize>,
+    line: usize,
+    errors: Vec<CompileError>,
+    // Numbers the if, while and division labels within a subroutine.
+    ifs: usize,
+    whiles: usize,
+    divisions: usize,
+    // The numbers of the loops the statement being compiled is in.
+    loops: Vec<usize>,
+    // Whether to multiply and divide by constants without calling the OS.
+    optimize: bool,
+}
+
+impl<'a> Generator<'a> {
+    fn emit(&mut self, command: Command) {
+        self.commands.push(command);
+        self.lines.push(self.line);
+    }
+
+    fn push(&mut self, segment: Segment, index: u16) {
+        self.emit(Command::Push(segment, index));
+    }
+
+    fn pop(&mut self, segment: Segment, index: u16) {
+        self.emit(Command::Pop(segment, index));
+    }
+
+    fn arithmetic(&mut self, op: Arithmetic) {
+        self.emit(Command::Arithmetic(op));
+    }
+
+    fn call(&mut self, function: String, arguments: usize) {
+        self.emit(Command::Call(function, arguments as u16));
+    }
+
+    fn label(&mut self, label: String) {
+        self.emit(Command::Label(label));
+    }
+
+    fn variable(&mut self, name: &str, span: Span) -> Option<(Segment, u16)> {
+        match self.symbols.get(name) {
+            Some(symbol) => Some((symbol.kind.segment(), symbol.index)),
+            None => {
+                self.errors
+                    .push(CompileError::UndeclaredVariable(span, name.to_string()));
+                None
+            }
+        }
+    }
+
+    // Constants past 32767 don't fit in a push and are built from one that does.
+    fn push_constant(&mut self, value: u16) {
+        match value {
+            0..=32767 => self.push(Segment::Constant, value),
+            0x8000 => {
+                self.push(Segment::Constant, 32767);
+                self.arithmetic(Arithmetic::Not);
+            }
+            _ => {
+                self.push(Segment::Constant, value.wrapping_neg());
+                self.arithmetic(Arithmetic::Neg);
+            }
+        }
+    }
+
+    // Multiplies the top of the stack by doubling it and adding it back, bit
+    // by bit from the top of the factor. Factors with many bits set are
+    // cheaper to multiply in Math.multiply.
+    fn multiply(&mut self, factor: u16) {
+        let magnitude = (factor as i16).unsigned_abs();
+        if magnitude.count_ones() > 4 {
+            self.push_constant(factor);
+            self.call("Math.multiply".to_string(), 2);
+            return;
+        }
+        if magnitude == 0 {
+            self.pop(Segment::Temp, 1);
+            self.push(Segment::Constant, 0);
+            return;
+        }
+        if magnitude > 1 {
+            self.pop(Segment::Temp, 1);
+            self.push(Segment::Temp, 1);
+            for bit in (0..15 - magnitude.leading_zeros()).rev() {
+                self.pop(Segment::Temp, 2);
+                self.push(Segment::Temp, 2);
+                self.push(Segment::Temp, 2);
+                self.arithmetic(Arithmetic::Add);
+                if magnitude & (1 << bit) != 0 {
+                    self.push(Segment::Temp, 1);
+                    self.arithmetic(Arithmetic::Add);
+                }
+            }
+        }
+        if (factor as i16) < 0 {
+            self.arithmetic(Arithmetic::Neg);
+        }
+    }
+
+    // Divides the top of the stack by a power of two by shifting its
+    // magnitude right one bit at a time, and restoring the sign after.
+    fn divide(&mut self, divisor: u16) {
+        let magnitude = (divisor as i16).unsigned_abs();
+        if !magnitude.is_power_of_two() {
+            self.push_constant(divisor);
+            self.call("Math.divide".to_string(), 2);
+            return;
+        }
+        let shift = magnitude.trailing_zeros() as u16;
+        if shift > 0 {
+            let n = self.divisions;
+            self.divisions += 1;
+            self.pop(Segment::Temp, 1);
+            self.push(Segment::Temp, 1);
+            self.push(Segment::Constant, 0);
+            self.arithmetic(Arithmetic::Lt);
+            self.pop(Segment::Temp, 2);
+            self.push(Segment::Temp, 2);
+            self.arithmetic(Arithmetic::Not);
+            self.emit(Command::IfGoto(format!("DIV_POSITIVE{}", n)));
+            self.push(Segment::Temp, 1);
+            self.arithmetic(Arithmetic::Neg);
+            self.pop(Segment::Temp, 1);
+            self.label(format!("DIV_POSITIVE{}", n));
+            self.push(Segment::Constant, 0);
+            for bit in shift..16 {
+                self.push(Segment::Temp, 1);
+                self.push_constant(1 << bit);
+                self.arithmetic(Arithmetic::And);
+                self.push(Segment::Constant, 0);
+                self.arithmetic(Arithmetic::Eq);
+                self.arithmetic(Arithmetic::Not);
+                self.push_constant(1 << (bit - shift));
+                self.arithmetic(Arithmetic::And);
+                self.arithmetic(Arithmetic::Add);
+            }
+            self.push(Segment::Temp, 2);
+            self.arithmetic(Arithmetic::Not);
+            self.emit(Command::IfGoto(format!("DIV_END{}", n)));
+            self.arithmetic(Arithmetic::Neg);
+            self.label(format!("DIV_END{}", n));
+        }
+        if (divisor as i16) < 0 {
+            self.arithmetic(Arithmetic::Neg);
+        }
+    }
+
+    fn push_variable(&mut self, name: &str, span: Span) {
+        if let Some((segment, index)) = self.variable(name, span) {
+            self.push(segment, index);
+        }
+    }
+
+    fn class(&mut self) {
+        for variable in &self.class.variables {
+            let kind = match variable.kind {
+                ClassVarKind::Static => Kind::Static,
+                ClassVarKind::Field => Kind::Field,
+            };
+            for name in &variable.names {
+                self.symbols.define(&name.name, variable.ty.clone(), kind);
+            }
+        }
+        for subroutine in &self.class.subroutines {
+            self.subroutine(subroutine);
+        }
+    }
+
+    fn subroutine(&mut self, subroutine: &Subroutine) {
+        self.line = subroutine.span.line;
+        self.symbols.start_subroutine();
+        self.ifs = 0;
+        self.whiles = 0;
+        self.divisions = 0;
+        // A method's object is its hidden first argument.
+        if subroutine.kind == SubroutineKind::Method {
+            let this = Type::Class(self.class.name.name.clone());
+            self.symbols.define("this", this, Kind::Argument);
+        }
+        for parameter in &subroutine.parameters {
+            self.symbols
+                .define(&parameter.name.name, parameter.ty.clone(), Kind::Argument);
+        }
+        for local in &subroutine.locals {
+            for name in &local.names {
+                self.symbols
+                    .define(&name.name, local.ty.clone(), Kind::Local);
+            }
+        }
+
+        let name = format!("{}.{}", self.class.name.name, subroutine.name.name);
+        self.emit(Command::Function(name, self.symbols.count(Kind::Local)));
+        match subroutine.kind {
+            SubroutineKind::Constructor => {
+                self.push(Segment::Constant, self.symbols.count(Kind::Field));
+                self.call("Memory.alloc".to_string(), 1);
+                self.pop(Segment::Pointer, 0);
+            }
+            SubroutineKind::Method => {
+                self.push(Segment::Argument, 0);
+                self.pop(Segment::Pointer, 0);
+            }
+            SubroutineKind::Function => {}
+        }
+        self.statements(&subroutine.body);
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    // Code emitted for a statement after the statements in it, such as the
+    // jump back to the test of a loop, belongs to the statement itself.
+    fn statement(&mut self, statement: &Statement) {
+        let outer = std::mem::replace(&mut self.line, statement.span().line);
+        self.compile_statement(statement);
+        self.line = outer;
+    }
+
+    fn compile_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let {
+                name, index, value, ..
+            } => match index {
+                // The value is parked in temp 0 because evaluating it may
+                // itself move pointer 1.
+                Some(index) => {
+                    self.push_variable(&name.name, name.span);
+                    self.expression(index);
+                    self.arithmetic(Arithmetic::Add);
+                    self.expression(value);
+                    self.pop(Segment::Temp, 0);
+                    self.pop(Segment::Pointer, 1);
+                    self.push(Segment::Temp, 0);
+                    self.pop(Segment::That, 0);
+                }
+                None => {
+                    self.expression(value);
+                    if let Some((segment, index)) = self.variable(&name.name, name.span) {
+                        self.pop(segment, index);
+                    }
+                }
+            },
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                let n = self.ifs;
+                self.ifs += 1;
+                self.expression(condition);
+                self.arithmetic(Arithmetic::Not);
+                self.emit(Command::IfGoto(format!("IF_FALSE{}", n)));
+                self.statements(then);
+                match otherwise {
+                    Some(otherwise) => {
+                        self.emit(Command::Goto(format!("IF_END{}", n)));
+                        self.label(format!("IF_FALSE{}", n));
+                        self.statements(otherwise);
+                        self.label(format!("IF_END{}", n));
+                    }
+                    None => self.label(format!("IF_FALSE{}", n)),
+                }
+            }
+            Statement::While {
+                condition, body, ..
+            } => {
+                let n = self.whiles;
+                self.whiles += 1;
+                self.label(format!("WHILE_EXP{}", n));
+                // Loops that never end need no test.
+                let forever =
+                    self.optimize && constant_expression(condition).is_some_and(|value| value != 0);
+                if !forever {
+                    self.expression(condition);
+                    self.arithmetic(Arithmetic::Not);
+                    self.emit(Command::IfGoto(format!("WHILE_END{}", n)));
+                }
+                self.loops.push(n);
+                self.statements(body);
+                self.loops.pop();
+                self.emit(Command::Goto(format!("WHILE_EXP{}", n)));
+                self.label(format!("WHILE_END{}", n));
+            }
+            Statement::Do { call, .. } => {
+                self.subroutine_call(call);
+                self.pop(Segment::Temp, 0);
+            }
+            Statement::Break { .. } => {
+                let n = self.loops.last().expect("break outside a loop");
+                self.emit(Command::Goto(format!("WHILE_END{}", n)));
+            }
+            Statement::Continue { .. } => {
+                let n = self.loops.last().expect("continue outside a loop");
+                self.emit(Command::Goto(format!("WHILE_EXP{}", n)));
+            }
+            Statement::Return { value, .. } => {
+                match value {
+                    Some(value) => self.expression(value),
+                    None => self.push(Segment::Constant, 0),
+                }
+                self.emit(Command::Return);
+            }
+        }
+    }
+
+    // A call through a variable or with no receiver is a method call, and
+    // passes the object first. Any other receiver names a class.
+    fn subroutine_call(&mut self, call: &SubroutineCall) {
+        let (class, arguments) = match &call.receiver {
+            None => {
+                self.push(Segment::Pointer, 0);
+                (self.class.name.name.clone(), call.arguments.len() + 1)
+            }
+            Some(receiver) => match self.symbols.get(&receiver.name).cloned() {
+                Some(symbol) => {
+                    if let Type::Class(class) = &symbol.ty {
+                        self.push(symbol.kind.segment(), symbol.index);
+                        (class.clone(), call.arguments.len() + 1)
+                    } else {
+                        self.errors.push(CompileError::NotAnObject(
+                            receiver.span,
+                            receiver.name.clone(),
+                        ));
+                        (symbol.ty.name().to_string(), call.arguments.len())
+                    }
+                }
+                None => (receiver.name.clone(), call.arguments.len()),
+            },
+        };
+        for argument in &call.arguments {
+            self.expression(argument);
+        }
+        self.call(format!("{}.{}", class, call.name.name), arguments);
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        let mut rest = expression.rest.iter();
+        match (expression.rest.first(), constant(&expression.first)) {
+            // A constant factor is moved to the right, where it can be
+            // multiplied by without calling the OS.
+            (Some((BinaryOp::Mul, term)), Some(factor)) if self.optimize => {
+                self.term(term);
+                self.multiply(factor);
+                rest.next();
+            }
+            _ => self.term(&expression.first),
+        }
+        for (op, term) in rest {
+            let value = constant(term).filter(|_| self.optimize);
+            match (op, value) {
+                (BinaryOp::Mul, Some(factor)) => {
+                    self.multiply(factor);
+                    continue;
+                }
+                (BinaryOp::Div, Some(divisor)) if divisor != 0 => {
+                    self.divide(divisor);
+                    continue;
+                }
+                _ => self.term(term),
+            }
+            match op {
+                BinaryOp::Add => self.arithmetic(Arithmetic::Add),
+                BinaryOp::Sub => self.arithmetic(Arithmetic::Sub),
+                BinaryOp::Mul => self.call("Math.multiply".to_string(), 2),
+                BinaryOp::Div => self.call("Math.divide".to_string(), 2),
+                BinaryOp::And => self.arithmetic(Arithmetic::And),
+                BinaryOp::Or => self.arithmetic(Arithmetic::Or),
+                BinaryOp::Lt => self.arithmetic(Arithmetic::Lt),
+                BinaryOp::Gt => self.arithmetic(Arithmetic::Gt),
+                BinaryOp::Eq => self.arithmetic(Arithmetic::Eq),
+            }
+        }
+    }
+
+    fn term(&mut self, term: &Term) {
+        match term {
+            Term::Integer(value, _) => self.push_constant(*value),
+            Term::String(string, _) => {
+                let chars: Vec<u16> = string.encode_utf16().collect();
+                self.push(Segment::Constant, chars.len() as u16);
+                self.call("String.new".to_string(), 1);
+                for c in chars {
+                    self.push(Segment::Constant, c);
+                    self.call("String.appendChar".to_string(), 2);
+                }
+            }
+            Term::Keyword(constant, _) => match constant {
+                KeywordConstant::True => {
+                    self.push(Segment::Constant, 0);
+                    self.arithmetic(Arithmetic::Not);
+                }
+                KeywordConstant::False | KeywordConstant::Null => self.push(Segment::Constant, 0),
+                KeywordConstant::This => self.push(Segment::Pointer, 0),
+            },
+            Term::Variable(name) => self.push_variable(&name.name, name.span),
+            Term::Index(name, index, _) => {
+                self.push_variable(&name.name, name.span);
+                self.expression(index);
+                self.arithmetic(Arithmetic::Add);
+                self.pop(Segment::Pointer, 1);
+                self.push(Segment::That, 0);
+            }
+            Term::Call(call) => self.subroutine_call(call),
+            Term::Parenthesized(expression, _) => self.expression(expression),
+            Term::Unary(op, term, _) => {
+                self.term(term);
+                self.arithmetic(match op {
+                    UnaryOp::Neg => Arithmetic::Neg,
+                    UnaryOp::Not => Arithmetic::Not,
+                });
+            }
+        }
+    }
+}
+
+fn generate(class: &Class, optimize: bool) -> Result<(Module, Vec<usize>), Vec<CompileError>> {
+    let mut generator = Generator {
+        class,
+        symbols: SymbolTable::new(),
+        commands: vec![],
+        lines: vec![],
+        line: 0,
+        errors: vec![],
+        ifs: 0,
+        whiles: 0,
+        loops: vec![],
+        divisions: 0,
+        optimize,
+    };
+    generator.class();
+    if !generator.errors.is_empty() {
+        generator.errors.sort_by_key(|error| error.span().start);
+        return Err(generator.errors);
+    }
+    let module = Module {
+        name: class.name.name.clone(),
+        lines: (1..=generator.commands.len()).collect(),
+        commands: generator.commands,
+    };
+    Ok((module, generator.lines))
+}
+
+// Compiles a class into the VM module of the same name. Each command's line
+// is its position in the module, as if read back from the written .vm file.
+pub fn compile(class: &Class) -> Result<Module, Vec<CompileError>> {
+    generate(class, false).map(|(module, _)| module)
+}
+
+// Compiles a class run through optimize::optimize, multiplying and dividing
+// by constants in place rather than through the OS.
+pub fn compile_optimized(class: &Class) -> Result<Module, Vec<CompileError>> {
+    generate(class, true).map(|(module, _)| module)
+}
+
+// Compiles a class read from `file`, optimized or not, along with the
+// debugging information that leads from its VM code back to the source.
+pub fn compile_debug(
+    class: &Class,
+    optimize: bool,
+    file: &str,
+) -> Result<(Module, ClassInfo), Vec<CompileError>> {
+    let (module, lines) = generate(class, optimize)?;
+    let info = ClassInfo::new(class, file, &lines);
+    Ok((module, info))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parser::{parse, parse_dialect},
+        token::Dialect,
+    };
+    use computer::{keyboard::DummyKeyboard, screen::DummyScreen};
+    use vm::interpreter::{Interpreter, State};
+
+    fn compile_source(source: &str) -> Result<Module, Vec<CompileError>> {
+        compile(&parse(source).unwrap())
+    }
+
+    #[test]
+    fn methods_and_constructors_set_up_this() {
+        let module = compile_source(
+            "class Point {
+                field int x, y;
+                constructor Point new(int ax) { let x = ax; return this; }
+                method int getX() { return x; }
+            }",
+        )
+        .unwrap();
+        let commands: Vec<String> = module.commands.iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            commands,
+            [
+                "function Point.new 0",
+                "push constant 2",
+                "call Memory.alloc 1",
+                "pop pointer 0",
+                "push argument 0",
+                "pop this 0",
+                "push pointer 0",
+                "return",
+                "function Point.getX 0",
+                "push argument 0",
+                "pop pointer 0",
+                "push this 0",
+                "return",
+            ]
+        );
+    }
+
+    #[test]
+    fn undeclared_variables_are_reported() {
+        let errors =
+            compile_source("class A { function int f() { var int x; let y = x + z; return 0; } }")
+                .unwrap_err();
+        let names: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            names,
+            ["1:45: Undeclared variable y", "1:53: Undeclared variable z"]
+        );
+    }
+
+    // Runs the compiled program on the VM interpreter, with a bump allocator
+    // and multiplication by repeated addition standing in for the OS.
+    #[test]
+    fn compiled_programs_run() {
+        let main = compile_source(
+            "class Main {
+                static int result;
+                function void main() {
+                    var Array a;
+                    var List list;
+                    var int i, sum;
+                    let a = Memory.alloc(5);
+                    while (i < 5) { let a[i] = i * i; let i = i + 1; }
+                    let list = List.new(a[4], List.new(a[3], null));
+                    let sum = list.sum();
+                    if (~(sum = 25)) { let sum = -1; }
+                    let result = sum;
+                    return;
+                }
+            }",
+        )
+        .unwrap();
+        let list = compile_source(
+            "class List {
+                field int head;
+                field List tail;
+                constructor List new(int h, List t) { let head = h; let tail = t; return this; }
+                method int sum() {
+                    if (tail = null) { return head; } else { return head + tail.sum(); }
+                }
+            }",
+        )
+        .unwrap();
+        let os = Module::parse(
+            "Os",
+            &[
+                "function Memory.alloc 0",
+                "push static 0",
+                "push constant 2048",
+                "add",
+                "push static 0",
+                "push argument 0",
+                "add",
+                "pop static 0",
+                "return",
+                "function Math.multiply 1",
+                "push constant 0",
+                "pop local 0",
+                "label LOOP",
+                "push argument 1",
+                "if-goto ADD",
+                "push local 0",
+                "return",
+                "label ADD",
+                "push local 0",
+                "push argument 0",
+                "add",
+                "pop local 0",
+                "push argument 1",
+                "push constant 1",
+                "sub",
+                "pop argument 1",
+                "goto LOOP",
+                "function Sys.init 0",
+                "call Main.main 0",
+                "label HALT",
+                "goto HALT",
+            ],
+        )
+        .unwrap();
+        let mut vm = Interpreter::<DummyScreen, DummyKeyboard>::new(&[main, list, os]).unwrap();
+        vm.bootstrap().unwrap();
+        assert_eq!(vm.run(Some(100_000)), State::Halted);
+        // Main's only static comes first, before the allocator's.
+        assert_eq!(vm.peek(16), 25);
+    }
+
+    #[test]
+    fn break_and_continue_leave_and_restart_the_innermost_loop() {
+        let main = compile(
+            &parse_dialect(
+                "class Main {
+                    static int result;
+                    const int LIMIT = 'A' - 60;
+                    function void main() {
+                        var int i, j, sum;
+                        for (i = 0; i < 100; i += 1) {
+                            if (i = LIMIT) { break; }
+                            else if (i = 2) { continue; }
+                            let j = 0;
+                            while (true) {
+                                let j += 1;
+                                if (j > i) { break; }
+                                let sum += 10;
+                            }
+                            let sum += 1;
+                        }
+                        let result = sum;
+                        return;
+                    }
+                }",
+                Dialect::Extended,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let sys = Module::parse(
+            "Sys",
+            &[
+                "function Sys.init 0",
+                "call Main.main 0",
+                "label HALT",
+                "goto HALT",
+            ],
+        )
+        .unwrap();
+        let mut vm = Interpreter::<DummyScreen, DummyKeyboard>::new(&[main, sys]).unwrap();
+        vm.bootstrap().unwrap();
+        assert_eq!(vm.run(Some(100_000)), State::Halted);
+        // 0, 1, 3 and 4 go round the inner loop that many times.
+        assert_eq!(vm.peek(16), 84);
+    }
+}
@@ -0,0 +1,437 @@
+use std::{fmt, str::FromStr};
+
+use thiserror::Error;
+
+use vm::source_map::Source;
+
+use crate::{
+    ast::{Class, ClassVarKind, SubroutineKind, Type},
+    symbols::{Kind, SymbolTable},
+};
+
+#[derive(Debug, Error)]
+pub enum DebugInfoError {
+    #[error("Line {0}: Invalid entry \"{1}\"")]
+    InvalidEntry(usize, String),
+
+    #[error("Line {0}: Entry outside a class or subroutine")]
+    Orphan(usize),
+}
+
+// A variable of a subroutine or a field of its class, at `index` in the
+// argument, local or this segment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Variable {
+    pub name: String,
+    pub ty: Type,
+    pub kind: Kind,
+    pub index: u16,
+}
+
+impl Variable {
+    // The RAM address of the pointer to the variable's segment: LCL, ARG or
+    // THIS. Statics have none.
+    pub fn segment_pointer(&self) -> Option<u16> {
+        match self.kind {
+            Kind::Local => Some(1),
+            Kind::Argument => Some(2),
+            Kind::Field => Some(3),
+            Kind::Static => None,
+        }
+    }
+
+    // The value as its type reads: a number, a character, a boolean or an
+    // object's address.
+    pub fn format(&self, value: u16) -> String {
+        match &self.ty {
+            Type::Int => (value as i16).to_string(),
+            Type::Char => match value {
+                32..=126 => format!("{:?}", value as u8 as char),
+                _ => value.to_string(),
+            },
+            Type::Boolean => match value {
+                0 => "false".to_string(),
+                0xFFFF => "true".to_string(),
+                _ => value.to_string(),
+            },
+            Type::Class(_) if value == 0 => "null".to_string(),
+            Type::Class(class) => format!("{}@{}", class, value),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubroutineInfo {
+    // The VM function name, Class.subroutine.
+    pub name: String,
+    // The arguments, with a method's object first, then the locals.
+    pub variables: Vec<Variable>,
+}
+
+// Where the VM code of a class came from, and where its subroutines keep
+// their variables.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClassInfo {
+    pub name: String,
+    pub file: String,
+    pub fields: Vec<Variable>,
+    pub subroutines: Vec<SubroutineInfo>,
+    // Pairs of a line of the class's .vm file and the Jack line its command
+    // was compiled from. Each covers the VM lines up to the next.
+    pub lines: Vec<(usize, usize)>,
+}
+
+impl ClassInfo {
+    // `lines` holds the Jack line of each VM command of the class, which is
+    // on the VM line after its position.
+    pub fn new(class: &Class, file: &str, lines: &[usize]) -> Self {
+        let mut symbols = SymbolTable::new();
+        let mut fields = vec![];
+        for variable in &class.variables {
+            if variable.kind == ClassVarKind::Field {
+                for name in &variable.names {
+                    fields.push(Variable {
+                        name: name.name.clone(),
+                        ty: variable.ty.clone(),
+                        kind: Kind::Field,
+                        index: symbols.count(Kind::Field),
+                    });
+                    symbols.define(&name.name, variable.ty.clone(), Kind::Field);
+                }
+            }
+        }
+        let subroutines = class
+            .subroutines
+            .iter()
+            .map(|subroutine| {
+                symbols.start_subroutine();
+                let mut variables = vec![];
+                let mut define = |name: &str, ty: &Type, kind| {
+                    variables.push(Variable {
+                        name: name.to_string(),
+                        ty: ty.clone(),
+                        kind,
+                        index: symbols.count(kind),
+                    });
+                    symbols.define(name, ty.clone(), kind);
+                };
+                if subroutine.kind == SubroutineKind::Method {
+                    define(
+                        "this",
+                        &Type::Class(class.name.name.clone()),
+                        Kind::Argument,
+                    );
+                }
+                for parameter in &subroutine.parameters {
+                    define(&parameter.name.name, &parameter.ty, Kind::Argument);
+                }
+                for local in &subroutine.locals {
+                    for name in &local.names {
+                        define(&name.name, &local.ty, Kind::Local);
+                    }
+                }
+                SubroutineInfo {
+                    name: format!("{}.{}", class.name.name, subroutine.name.name),
+                    variables,
+                }
+            })
+            .collect();
+        let mut runs: Vec<(usize, usize)> = vec![];
+        for (i, &line) in lines.iter().enumerate() {
+            if runs.last().map(|&(_, last)| last) != Some(line) {
+                runs.push((i + 1, line));
+            }
+        }
+        Self {
+            name: class.name.name.clone(),
+            file: file.to_string(),
+            fields,
+            subroutines,
+            lines: runs,
+        }
+    }
+
+    pub fn line(&self, vm_line: usize) -> Option<usize> {
+        let index = self
+            .lines
+            .partition_point(|&(start, _)| start <= vm_line)
+            .checked_sub(1)?;
+        Some(self.lines[index].1)
+    }
+}
+
+// A line of Jack source.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Location<'a> {
+    pub file: &'a str,
+    pub line: usize,
+}
+
+impl fmt::Display for Location<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+// The debugging information of a compiled program, read by the debugger
+// along with the source map of the translated VM code.
+//
+// The text format has one tab separated entry per line. A class entry
+// `class <name> <file>` is followed by its fields as
+// `field <name> <type> <index>`, its subroutines as `subroutine <name>` each
+// followed by its `argument` and `local` entries, and its line table as
+// `line <VM line> <Jack line>` entries.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DebugInfo {
+    pub classes: Vec<ClassInfo>,
+}
+
+impl DebugInfo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The Jack line a VM command was compiled from.
+    pub fn locate(&self, source: &Source) -> Option<Location<'_>> {
+        let name = source.file.strip_suffix(".vm")?;
+        let class = self.classes.iter().find(|class| class.name == name)?;
+        Some(Location {
+            file: &class.file,
+            line: class.line(source.line)?,
+        })
+    }
+
+    pub fn subroutine(&self, name: &str) -> Option<&SubroutineInfo> {
+        self.classes
+            .iter()
+            .flat_map(|class| &class.subroutines)
+            .find(|subroutine| subroutine.name == name)
+    }
+
+    // The variables seen from inside a subroutine: its arguments and locals,
+    // then the fields of its class they don't hide.
+    pub fn variables(&self, subroutine: &str) -> Vec<&Variable> {
+        let mut variables = self
+            .subroutine(subroutine)
+            .map_or_else(Vec::new, |info| info.variables.iter().collect());
+        let class = subroutine.split('.').next();
+        for info in &self.classes {
+            if Some(info.name.as_str()) == class {
+                for field in &info.fields {
+                    if variables.iter().all(|other| other.name != field.name) {
+                        variables.push(field);
+                    }
+                }
+            }
+        }
+        variables
+    }
+
+    pub fn variable(&self, subroutine: &str, name: &str) -> Option<&Variable> {
+        self.variables(subroutine)
+            .into_iter()
+            .find(|variable| variable.name == name)
+    }
+}
+
+fn kind_name(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Static => "static",
+        Kind::Field => "field",
+        Kind::Argument => "argument",
+        Kind::Local => "local",
+    }
+}
+
+impl fmt::Display for DebugInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let variable = |f: &mut fmt::Formatter, variable: &Variable| {
+            writeln!(
+                f,
+                "{}\t{}\t{}\t{}",
+                kind_name(variable.kind),
+                variable.name,
+                variable.ty.name(),
+                variable.index
+            )
+        };
+        for class in &self.classes {
+            writeln!(f, "class\t{}\t{}", class.name, class.file)?;
+            for field in &class.fields {
+                variable(f, field)?;
+            }
+            for subroutine in &class.subroutines {
+                writeln!(f, "subroutine\t{}", subroutine.name)?;
+                for local in &subroutine.variables {
+                    variable(f, local)?;
+                }
+            }
+            for (vm_line, line) in &class.lines {
+                writeln!(f, "line\t{}\t{}", vm_line, line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for DebugInfo {
+    type Err = DebugInfoError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut info = DebugInfo::new();
+        for (i, line) in text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.is_empty())
+        {
+            let invalid = || DebugInfoError::InvalidEntry(i + 1, line.to_string());
+            let fields = line.split('\t').collect::<Vec<_>>();
+            if let ["class", name, file] = fields.as_slice() {
+                info.classes.push(ClassInfo {
+                    name: name.to_string(),
+                    file: file.to_string(),
+                    fields: vec![],
+                    subroutines: vec![],
+                    lines: vec![],
+                });
+                continue;
+            }
+            let class = info
+                .classes
+                .last_mut()
+                .ok_or(DebugInfoError::Orphan(i + 1))?;
+            match fields.as_slice() {
+                ["subroutine", name] => class.subroutines.push(SubroutineInfo {
+                    name: name.to_string(),
+                    variables: vec![],
+                }),
+                ["line", vm_line, line] => {
+                    let vm_line = vm_line.parse().map_err(|_| invalid())?;
+                    let line = line.parse().map_err(|_| invalid())?;
+                    class.lines.push((vm_line, line));
+                }
+                [kind @ ("field" | "argument" | "local"), name, ty, index] => {
+                    let variable = Variable {
+                        name: name.to_string(),
+                        ty: Type::from_name(ty),
+                        kind: match *kind {
+                            "field" => Kind::Field,
+                            "argument" => Kind::Argument,
+                            _ => Kind::Local,
+                        },
+                        index: index.parse().map_err(|_| invalid())?,
+                    };
+                    if variable.kind == Kind::Field {
+                        class.fields.push(variable);
+                    } else {
+                        class
+                            .subroutines
+                            .last_mut()
+                            .ok_or(DebugInfoError::Orphan(i + 1))?
+                            .variables
+                            .push(variable);
+                    }
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{codegen::compile_debug, parser::parse};
+
+    #[test]
+    fn debug_info_maps_vm_lines_to_jack_and_round_trips_through_text() {
+        let class = parse(
+            "class Point {
+                field int x, y;
+                method int add(int dx) {
+                    var int sum;
+                    let sum = x + dx;
+                    if (sum > 0) {
+                        let y = sum;
+                    }
+                    return sum;
+                }
+            }",
+        )
+        .unwrap();
+        let (module, class) = compile_debug(&class, false, "Point.jack").unwrap();
+        let mut info = DebugInfo::new();
+        info.classes.push(class);
+        let line = |vm_line| {
+            info.locate(&Source {
+                file: "Point.vm".to_string(),
+                line: vm_line,
+                function: Some("Point.add".to_string()),
+            })
+            .map(|location| location.line)
+        };
+        let lines = (1..=module.commands.len()).map(line).collect::<Vec<_>>();
+        // function, then the method's prologue.
+        assert_eq!(lines[..3], [Some(3); 3]);
+        assert_eq!(module.commands[3].to_string(), "push this 0");
+        assert_eq!(lines[3], Some(5));
+        let branch = module
+            .commands
+            .iter()
+            .position(|command| command.to_string() == "label IF_FALSE0")
+            .unwrap();
+        // The branch is part of the if, after the statement in it.
+        assert_eq!(lines[branch - 1], Some(7));
+        assert_eq!(lines[branch], Some(6));
+        assert_eq!(lines.last(), Some(&Some(9)));
+
+        let variable = |name| info.variable("Point.add", name).cloned().unwrap();
+        assert_eq!(
+            (variable("this").kind, variable("this").index),
+            (Kind::Argument, 0)
+        );
+        assert_eq!(
+            (variable("dx").kind, variable("dx").index),
+            (Kind::Argument, 1)
+        );
+        assert_eq!(
+            (variable("sum").kind, variable("sum").index),
+            (Kind::Local, 0)
+        );
+        assert_eq!((variable("y").kind, variable("y").index), (Kind::Field, 1));
+        assert_eq!(variable("this").ty, Type::Class("Point".to_string()));
+        assert!(info.variable("Point.add", "z").is_none());
+        let names = info
+            .variables("Point.add")
+            .iter()
+            .map(|variable| variable.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["this", "dx", "sum", "x", "y"]);
+        assert_eq!(variable("y").segment_pointer(), Some(3));
+        assert_eq!(variable("this").format(2048), "Point@2048");
+        assert_eq!(variable("this").format(0), "null");
+        assert_eq!(variable("sum").format(0xFFFE), "-2");
+        let mut flag = variable("sum");
+        flag.ty = Type::Boolean;
+        assert_eq!(
+            (flag.format(0), flag.format(0xFFFF)),
+            ("false".into(), "true".into())
+        );
+        flag.ty = Type::Char;
+        assert_eq!(
+            (flag.format(65), flag.format(128)),
+            ("'A'".into(), "128".into())
+        );
+
+        assert_eq!(info.to_string().parse::<DebugInfo>().unwrap(), info);
+        assert!(matches!(
+            "local\tx\tint\t0".parse::<DebugInfo>(),
+            Err(DebugInfoError::Orphan(1))
+        ));
+        assert!(matches!(
+            "class\tMain\tMain.jack\nline\t1".parse::<DebugInfo>(),
+            Err(DebugInfoError::InvalidEntry(2, _))
+        ));
+    }
+}
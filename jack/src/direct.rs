@@ -0,0 +1,1620 @@
+use std::collections::{HashMap, HashSet};
+
+use assembler::{
+    instruction::{Comp, Dest, Jump},
+    program::Program,
+    symbol::SymbolFile,
+};
+use computer::{keyboard::Keyboard, rom::Rom, screen::Screen, Computer};
+
+use crate::{
+    ast::{
+        BinaryOp, Class, ClassVarKind, Expression, KeywordConstant, Statement, Subroutine,
+        SubroutineCall, SubroutineKind, Term, Type, UnaryOp,
+    },
+    codegen::CompileError,
+    optimize::{constant, constant_expression},
+    span::Span,
+};
+
+// Statics are numbered from here, and the frames that never share their
+// place follow them. The stack takes the rest of the RAM below the heap.
+const STATICS: u16 = 16;
+const HEAP: u16 = 2048;
+
+// The longest walk of A=A+1 taken to reach a word without going through D.
+const WALK: u16 = 6;
+
+// A word of a frame: at a fixed address, or at an offset from the base of a
+// frame on the stack, which ARG points to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Slot {
+    Fixed(u16),
+    Stack(u16),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Place {
+    Slot(Slot),
+    // A field of the object whose address is in the slot.
+    Field(Slot, u16),
+}
+
+// Functions that can be active only once at a time keep their frame at a
+// fixed address, laid out so that it overlaps no frame of a function that
+// can be active at the same time. Recursive functions push a frame on the
+// stack at each call. Either frame holds the arguments, the return address,
+// the caller's ARG (on the stack only), the locals, `this` in constructors
+// and then the temporaries.
+#[derive(Clone, Copy, Debug)]
+struct Frame {
+    parameters: u16,
+    base: Option<u16>,
+}
+
+impl Frame {
+    fn slot(&self, index: u16) -> Slot {
+        match self.base {
+            Some(base) => Slot::Fixed(base + index),
+            None => Slot::Stack(index),
+        }
+    }
+
+    fn locals(&self) -> u16 {
+        self.parameters + 1 + self.base.is_none() as u16
+    }
+}
+
+// What a call passes, evaluated into D.
+enum Argument<'a> {
+    // Already in D, which only the first argument can be.
+    D,
+    Constant(u16),
+    Place(Place),
+    Expression(&'a Expression),
+    Term(&'a Term),
+}
+
+fn has_call(term: &Term) -> bool {
+    match term {
+        Term::Call(_) | Term::String(..) => true,
+        Term::Index(_, index, _) => expression_has_call(index),
+        Term::Parenthesized(expression, _) => expression_has_call(expression),
+        Term::Unary(_, term, _) => has_call(term),
+        _ => false,
+    }
+}
+
+// Multiplication and division may call the OS.
+fn expression_has_call(expression: &Expression) -> bool {
+    has_call(&expression.first)
+        || expression
+            .rest
+            .iter()
+            .any(|(op, term)| matches!(op, BinaryOp::Mul | BinaryOp::Div) || has_call(term))
+}
+
+impl Argument<'_> {
+    fn has_call(&self) -> bool {
+        match self {
+            Argument::D | Argument::Constant(_) | Argument::Place(_) => false,
+            Argument::Expression(expression) => expression_has_call(expression),
+            Argument::Term(term) => has_call(term),
+        }
+    }
+}
+
+fn jump(op: BinaryOp) -> Jump {
+    match op {
+        BinaryOp::Lt => Jump::JLT,
+        BinaryOp::Gt => Jump::JGT,
+        _ => Jump::JEQ,
+    }
+}
+
+fn negate(jump: Jump) -> Jump {
+    match jump {
+        Jump::JLT => Jump::JGE,
+        Jump::JGT => Jump::JLE,
+        _ => Jump::JNE,
+    }
+}
+
+fn is_comparison(op: BinaryOp) -> bool {
+    matches!(op, BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Eq)
+}
+
+// The same operation with M and D swapped.
+fn swap(comp: Comp) -> Comp {
+    match comp {
+        Comp::DMinusM => Comp::MMinusD,
+        Comp::MMinusD => Comp::DMinusM,
+        comp => comp,
+    }
+}
+
+// Multiplying in place takes a doubling per bit of the factor, and an
+// addition per bit set.
+fn multiplies_in_place(factor: u16) -> bool {
+    (factor as i16).unsigned_abs().count_ones() <= 4
+}
+
+fn divides_in_place(divisor: u16) -> bool {
+    (divisor as i16).unsigned_abs().is_power_of_two()
+}
+
+struct Context<'a> {
+    statics: HashMap<String, u16>,
+    frames: HashMap<String, Frame>,
+    fields: HashMap<&'a str, u16>,
+    // Whether to multiply and divide by constants without calling the OS.
+    optimize: bool,
+}
+
+struct Generator<'a> {
+    context: &'a Context<'a>,
+    class: &'a Class,
+    name: String,
+    frame: Frame,
+    variables: HashMap<&'a str, (Place, &'a Type)>,
+    this: Option<Slot>,
+    temps: u16,
+    first_temp: u16,
+    size: u16,
+    labels: usize,
+    // The top and end labels of the loops the statement is in.
+    loops: Vec<(String, String)>,
+    program: Program,
+    callees: Vec<String>,
+    errors: Vec<CompileError>,
+}
+
+impl<'a> Generator<'a> {
+    fn new(context: &'a Context<'a>, class: &'a Class, subroutine: &'a Subroutine) -> Self {
+        let name = format!("{}.{}", class.name.name, subroutine.name.name);
+        let frame = context.frames[&name];
+        let mut variables = HashMap::new();
+        let mut fields = 0;
+        let mut this = None;
+        if subroutine.kind == SubroutineKind::Method {
+            this = Some(frame.slot(0));
+        }
+        let first = this.is_some() as u16;
+        for (index, parameter) in subroutine.parameters.iter().enumerate() {
+            let place = Place::Slot(frame.slot(first + index as u16));
+            variables.insert(parameter.name.name.as_str(), (place, &parameter.ty));
+        }
+        let mut index = frame.locals();
+        for local in &subroutine.locals {
+            for name in &local.names {
+                let place = Place::Slot(frame.slot(index));
+                variables.insert(name.name.as_str(), (place, &local.ty));
+                index += 1;
+            }
+        }
+        if subroutine.kind == SubroutineKind::Constructor {
+            this = Some(frame.slot(index));
+            index += 1;
+        }
+        // Class variables are hidden by arguments and locals of the same name.
+        for variable in &class.variables {
+            for name in &variable.names {
+                let place = match variable.kind {
+                    ClassVarKind::Static => {
+                        let key = format!("{}.{}", class.name.name, name.name);
+                        Some(Place::Slot(Slot::Fixed(context.statics[&key])))
+                    }
+                    ClassVarKind::Field => {
+                        fields += 1;
+                        this.map(|this| Place::Field(this, fields - 1))
+                    }
+                };
+                if let Some(place) = place {
+                    variables
+                        .entry(name.name.as_str())
+                        .or_insert((place, &variable.ty));
+                }
+            }
+        }
+        Self {
+            context,
+            class,
+            name,
+            frame,
+            variables,
+            this,
+            temps: 0,
+            first_temp: index,
+            size: index,
+            labels: 0,
+            loops: vec![],
+            program: Program::new(),
+            callees: vec![],
+            errors: vec![],
+        }
+    }
+
+    fn a(&mut self, symbol: &str) {
+        self.program.a(symbol);
+    }
+
+    fn a_value(&mut self, value: u16) {
+        self.program.a_value(value);
+    }
+
+    fn c(&mut self, dest: Dest, comp: Comp) {
+        self.program.c(Some(dest), comp, None);
+    }
+
+    fn jump(&mut self, comp: Comp, jump: Jump) {
+        self.program.c(None, comp, Some(jump));
+    }
+
+    fn goto(&mut self, label: &str) {
+        self.a(label);
+        self.jump(Comp::Zero, Jump::JMP);
+    }
+
+    // Labels are scoped by the function they appear in.
+    fn new_label(&mut self, kind: &str) -> String {
+        let label = format!("{}${}{}", self.name, kind, self.labels);
+        self.labels += 1;
+        label
+    }
+
+    fn temp(&mut self) -> Slot {
+        let slot = self.frame.slot(self.first_temp + self.temps);
+        self.temps += 1;
+        self.size = self.size.max(self.first_temp + self.temps);
+        slot
+    }
+
+    fn release(&mut self) {
+        self.temps -= 1;
+    }
+
+    // Moves A from a word to the one `offset` words past where it points.
+    fn follow(&mut self, offset: u16) {
+        if offset == 0 {
+            self.c(Dest::A, Comp::M);
+            return;
+        }
+        self.c(Dest::A, Comp::MPlusOne);
+        for _ in 1..offset {
+            self.c(Dest::A, Comp::APlusOne);
+        }
+    }
+
+    fn walkable(place: Place) -> bool {
+        match place {
+            Place::Slot(Slot::Fixed(_)) => true,
+            Place::Slot(Slot::Stack(index)) => index <= WALK,
+            Place::Field(slot, index) => Self::walkable(Place::Slot(slot)) && index <= WALK,
+        }
+    }
+
+    fn point_slot(&mut self, slot: Slot) {
+        match slot {
+            Slot::Fixed(address) => self.a_value(address),
+            Slot::Stack(index) => {
+                self.a("ARG");
+                self.follow(index);
+            }
+        }
+    }
+
+    // Points A at the place without touching D, if the walk there is short.
+    fn point(&mut self, place: Place) -> bool {
+        if !Self::walkable(place) {
+            return false;
+        }
+        match place {
+            Place::Slot(slot) => self.point_slot(slot),
+            Place::Field(slot, index) => {
+                self.point_slot(slot);
+                self.follow(index);
+            }
+        }
+        true
+    }
+
+    // Adds a constant to D.
+    fn offset(&mut self, offset: u16) {
+        match offset {
+            0 => {}
+            1 => self.c(Dest::D, Comp::DPlusOne),
+            _ => {
+                self.a_value(offset);
+                self.c(Dest::D, Comp::DPlusA);
+            }
+        }
+    }
+
+    fn address_in_d(&mut self, place: Place) {
+        match place {
+            Place::Slot(Slot::Fixed(address)) => {
+                self.a_value(address);
+                self.c(Dest::D, Comp::A);
+            }
+            Place::Slot(Slot::Stack(index)) => {
+                self.a("ARG");
+                self.c(Dest::D, Comp::M);
+                self.offset(index);
+            }
+            Place::Field(slot, index) => {
+                self.load(Place::Slot(slot));
+                self.offset(index);
+            }
+        }
+    }
+
+    fn load(&mut self, place: Place) {
+        if !self.point(place) {
+            self.address_in_d(place);
+            self.c(Dest::A, Comp::D);
+        }
+        self.c(Dest::D, Comp::M);
+    }
+
+    // Stores D, keeping it in R13 while the address is worked out in R14
+    // when the place is too far to walk to.
+    fn store(&mut self, place: Place) {
+        if self.point(place) {
+            self.c(Dest::M, Comp::D);
+            return;
+        }
+        self.a("R13");
+        self.c(Dest::M, Comp::D);
+        self.address_in_d(place);
+        self.a("R14");
+        self.c(Dest::M, Comp::D);
+        self.a("R13");
+        self.c(Dest::D, Comp::M);
+        self.a("R14");
+        self.c(Dest::A, Comp::M);
+        self.c(Dest::M, Comp::D);
+    }
+
+    // Computes `comp` from D and the word at the place.
+    fn with(&mut self, place: Place, dest: Dest, comp: Comp) {
+        if self.point(place) {
+            self.c(dest, comp);
+            return;
+        }
+        self.a("R13");
+        self.c(Dest::M, Comp::D);
+        self.load(place);
+        self.a("R13");
+        self.c(dest, swap(comp));
+    }
+
+    fn constant(&mut self, value: u16) {
+        match value {
+            0 => self.c(Dest::D, Comp::Zero),
+            1 => self.c(Dest::D, Comp::One),
+            0xFFFF => self.c(Dest::D, Comp::MinusOne),
+            2..=32767 => {
+                self.a_value(value);
+                self.c(Dest::D, Comp::A);
+            }
+            _ => {
+                self.a_value(!value);
+                self.c(Dest::D, Comp::NotA);
+            }
+        }
+    }
+
+    fn variable(&mut self, name: &str, span: Span) -> Option<(Place, &'a Type)> {
+        if let Some(variable) = self.variables.get(name) {
+            return Some(*variable);
+        }
+        let field = self.class.variables.iter().any(|variable| {
+            variable.kind == ClassVarKind::Field
+                && variable.names.iter().any(|other| other.name == name)
+        });
+        self.errors.push(match field {
+            true => CompileError::FieldInFunction(span, name.to_string()),
+            false => CompileError::UndeclaredVariable(span, name.to_string()),
+        });
+        None
+    }
+
+    fn subroutine(&mut self, subroutine: &'a Subroutine) {
+        let name = self.name.clone();
+        self.program.comment(&format!("function {}", name));
+        self.program.label(&name);
+        let parameters = self.frame.parameters;
+        let locals: u16 = subroutine
+            .locals
+            .iter()
+            .map(|local| local.names.len() as u16)
+            .sum();
+        // The return address arrives in D.
+        match self.frame.base {
+            Some(base) => {
+                self.a_value(base + parameters);
+                self.c(Dest::M, Comp::D);
+                if locals > 0 {
+                    self.a_value(base + parameters + 1);
+                    self.zero(locals);
+                }
+            }
+            None => {
+                // The arguments are at the top of the stack.
+                self.a("SP");
+                self.c(Dest::A, Comp::M);
+                self.c(Dest::M, Comp::D);
+                self.a("ARG");
+                self.c(Dest::D, Comp::M);
+                self.a("SP");
+                self.c(Dest::A, Comp::MPlusOne);
+                self.c(Dest::M, Comp::D);
+                self.a("SP");
+                self.c(Dest::D, Comp::M);
+                if parameters > 0 {
+                    self.a_value(parameters);
+                    self.c(Dest::D, Comp::DMinusA);
+                }
+                self.a("ARG");
+                self.c(Dest::M, Comp::D);
+                if locals > 0 {
+                    self.a_value(parameters + 2);
+                    self.c(Dest::A, Comp::DPlusA);
+                    self.zero(locals);
+                }
+            }
+        }
+        // The stack pointer is moved past the frame once its size is known.
+        let prologue = self.program.clone();
+        self.program = Program::new();
+        if subroutine.kind == SubroutineKind::Constructor {
+            let fields = self.context.fields[self.class.name.name.as_str()];
+            self.call(
+                "Memory.alloc",
+                vec![Argument::Constant(fields)],
+                subroutine.name.span,
+            );
+            let this = self.this.unwrap();
+            self.store(Place::Slot(this));
+        }
+        self.statements(&subroutine.body);
+        let body = std::mem::replace(&mut self.program, prologue);
+        // D still holds the base of a frame on the stack.
+        if self.frame.base.is_none() {
+            self.a_value(self.size);
+            self.c(Dest::D, Comp::DPlusA);
+            self.a("SP");
+            self.c(Dest::M, Comp::D);
+        }
+        self.program.extend(body);
+    }
+
+    // Clears `count` words from where A points.
+    fn zero(&mut self, count: u16) {
+        self.c(Dest::M, Comp::Zero);
+        for _ in 1..count {
+            self.c(Dest::A, Comp::APlusOne);
+            self.c(Dest::M, Comp::Zero);
+        }
+    }
+
+    fn statements(&mut self, statements: &'a [Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &'a Statement) {
+        match statement {
+            Statement::Let {
+                name,
+                index: None,
+                value,
+                ..
+            } => {
+                let (place, _) = match self.variable(&name.name, name.span) {
+                    Some(variable) => variable,
+                    None => return,
+                };
+                if let Some(comp) = self.step(&name.name, value) {
+                    if self.point(place) {
+                        self.c(Dest::M, comp);
+                        return;
+                    }
+                }
+                self.expression(value);
+                self.store(place);
+            }
+            Statement::Let {
+                name,
+                index: Some(index),
+                value,
+                ..
+            } => {
+                // The element is located before the value is worked out, as
+                // the VM code does.
+                self.element(&name.name, name.span, index);
+                let simple = matches!(
+                    value.first,
+                    Term::Integer(..) | Term::Keyword(..) | Term::Variable(_)
+                ) && value.rest.is_empty();
+                if simple {
+                    self.c(Dest::D, Comp::A);
+                    self.a("R13");
+                    self.c(Dest::M, Comp::D);
+                    self.expression(value);
+                    self.a("R13");
+                    self.c(Dest::A, Comp::M);
+                    self.c(Dest::M, Comp::D);
+                } else {
+                    let temp = self.temp();
+                    self.c(Dest::D, Comp::A);
+                    self.store(Place::Slot(temp));
+                    self.expression(value);
+                    self.store(Place::Field(temp, 0));
+                    self.release();
+                }
+            }
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                let otherwise_label = self.new_label("IF_FALSE");
+                self.branch_unless(condition, &otherwise_label);
+                self.statements(then);
+                match otherwise {
+                    Some(otherwise) => {
+                        let end = self.new_label("IF_END");
+                        self.goto(&end);
+                        self.program.label(&otherwise_label);
+                        self.statements(otherwise);
+                        self.program.label(&end);
+                    }
+                    None => self.program.label(&otherwise_label),
+                }
+            }
+            Statement::While {
+                condition, body, ..
+            } => {
+                let top = self.new_label("WHILE_EXP");
+                let end = self.new_label("WHILE_END");
+                self.program.label(&top);
+                self.branch_unless(condition, &end);
+                self.loops.push((top.clone(), end.clone()));
+                self.statements(body);
+                self.loops.pop();
+                self.goto(&top);
+                self.program.label(&end);
+            }
+            Statement::Do { call, .. } => self.subroutine_call(call),
+            Statement::Break { .. } => {
+                let (_, end) = self.loops.last().expect("break outside a loop").clone();
+                self.goto(&end);
+            }
+            Statement::Continue { .. } => {
+                let (top, _) = self.loops.last().expect("continue outside a loop").clone();
+                self.goto(&top);
+            }
+            Statement::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+                self.return_(value.is_some());
+            }
+        }
+    }
+
+    // `let x = x + 1` and `let x = x - 1` step x in place.
+    fn step(&self, name: &str, value: &Expression) -> Option<Comp> {
+        match (&value.first, value.rest.as_slice()) {
+            (Term::Variable(variable), [(op, term)]) if variable.name == name => {
+                match (op, constant(term)?) {
+                    (BinaryOp::Add, 1) | (BinaryOp::Sub, 0xFFFF) => Some(Comp::MPlusOne),
+                    (BinaryOp::Sub, 1) | (BinaryOp::Add, 0xFFFF) => Some(Comp::MMinusOne),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    // Points A at an array element.
+    fn element(&mut self, name: &str, span: Span, index: &'a Expression) {
+        let (place, _) = match self.variable(name, span) {
+            Some(variable) => variable,
+            None => return,
+        };
+        // The array is read before the index, which may change it.
+        if expression_has_call(index) {
+            let temp = self.temp();
+            self.load(place);
+            self.store(Place::Slot(temp));
+            self.expression(index);
+            self.with(Place::Slot(temp), Dest::A, Comp::DPlusM);
+            self.release();
+        } else {
+            self.expression(index);
+            self.with(place, Dest::A, Comp::DPlusM);
+        }
+    }
+
+    fn return_(&mut self, value: bool) {
+        let parameters = self.frame.parameters;
+        match self.frame.base {
+            Some(base) => {
+                self.a_value(base + parameters);
+                self.c(Dest::A, Comp::M);
+                self.jump(Comp::Zero, Jump::JMP);
+            }
+            // The frame and the arguments under it are popped, and the
+            // return address kept in R14.
+            None => {
+                if value {
+                    self.a("R13");
+                    self.c(Dest::M, Comp::D);
+                }
+                self.a("ARG");
+                self.c(Dest::D, Comp::M);
+                self.a("SP");
+                self.c(Dest::M, Comp::D);
+                self.a_value(parameters);
+                self.c(Dest::A, Comp::DPlusA);
+                self.c(Dest::D, Comp::M);
+                self.a("R14");
+                self.c(Dest::M, Comp::D);
+                self.a("SP");
+                self.c(Dest::D, Comp::M);
+                self.a_value(parameters + 1);
+                self.c(Dest::A, Comp::DPlusA);
+                self.c(Dest::D, Comp::M);
+                self.a("ARG");
+                self.c(Dest::M, Comp::D);
+                if value {
+                    self.a("R13");
+                    self.c(Dest::D, Comp::M);
+                }
+                self.a("R14");
+                self.c(Dest::A, Comp::M);
+                self.jump(Comp::Zero, Jump::JMP);
+            }
+        }
+    }
+
+    // Jumps to the label when the condition is false, testing comparisons
+    // directly rather than turning them into true or false first.
+    fn branch_unless(&mut self, condition: &'a Expression, label: &str) {
+        match constant_expression(condition) {
+            Some(0) => return self.goto(label),
+            Some(_) => return,
+            None => {}
+        }
+        if let Some(jump) = self.comparison(condition) {
+            self.a(label);
+            return self.jump(Comp::D, negate(jump));
+        }
+        if let (Term::Unary(UnaryOp::Not, term, _), []) = (&condition.first, &condition.rest[..]) {
+            if let Term::Parenthesized(inner, _) = term.as_ref() {
+                if let Some(jump) = self.comparison(inner) {
+                    self.a(label);
+                    return self.jump(Comp::D, jump);
+                }
+            }
+        }
+        if let (Term::Parenthesized(inner, _), []) = (&condition.first, &condition.rest[..]) {
+            return self.branch_unless(inner, label);
+        }
+        self.expression(condition);
+        self.a(label);
+        self.jump(Comp::D, Jump::JEQ);
+    }
+
+    // Leaves the difference of the operands of a final comparison in D, and
+    // gives the jump taken when it holds.
+    fn comparison(&mut self, expression: &'a Expression) -> Option<Jump> {
+        let (op, term) = expression.rest.last()?;
+        if !is_comparison(*op) {
+            return None;
+        }
+        self.operations(expression, expression.rest.len() - 1);
+        self.compare(*op, term);
+        Some(jump(*op))
+    }
+
+    // Leaves x - y in D for x in D and the term y, or x | 1 when the
+    // operands have opposite signs and the subtraction could overflow. That
+    // is nonzero and has the sign of x, which is the sign of x - y then.
+    fn compare(&mut self, op: BinaryOp, term: &'a Term) {
+        let value = constant(term);
+        if op == BinaryOp::Eq || value == Some(0) {
+            return self.combine(BinaryOp::Sub, term);
+        }
+        let end = self.new_label("END");
+        match value {
+            // A negative x already has the sign of x - y.
+            Some(0..=32767) => {
+                self.a(&end);
+                self.jump(Comp::D, Jump::JLT);
+                self.combine(BinaryOp::Sub, term);
+                self.program.label(&end);
+                return;
+            }
+            Some(_) => {
+                let same = self.new_label("SAME");
+                self.a(&same);
+                self.jump(Comp::D, Jump::JLT);
+                self.a_value(1);
+                self.c(Dest::D, Comp::DOrA);
+                self.goto(&end);
+                self.program.label(&same);
+                self.combine(BinaryOp::Sub, term);
+                self.program.label(&end);
+                return;
+            }
+            None => {}
+        }
+        // One operand goes to R14 and the other stays at a place: x and y
+        // when y is a variable, or y and x waiting in a temporary.
+        let (place, temp) = match term {
+            Term::Variable(name) => match self.variable(&name.name, name.span) {
+                Some((place, _)) => (place, false),
+                None => return,
+            },
+            _ => {
+                let temp = self.temp();
+                self.store(Place::Slot(temp));
+                self.term(term);
+                (Place::Slot(temp), true)
+            }
+        };
+        self.a("R14");
+        self.c(Dest::M, Comp::D);
+        let same = self.new_label("SAME");
+        self.with(place, Dest::D, Comp::DOrM);
+        self.a(&same);
+        self.jump(Comp::D, Jump::JGE);
+        self.a("R14");
+        self.c(Dest::D, Comp::M);
+        self.with(place, Dest::D, Comp::DAndM);
+        self.a(&same);
+        self.jump(Comp::D, Jump::JLT);
+        match temp {
+            true => self.load(place),
+            false => {
+                self.a("R14");
+                self.c(Dest::D, Comp::M);
+            }
+        }
+        self.a_value(1);
+        self.c(Dest::D, Comp::DOrA);
+        self.goto(&end);
+        self.program.label(&same);
+        self.a("R14");
+        self.c(Dest::D, Comp::M);
+        let comp = match temp {
+            true => Comp::MMinusD,
+            false => Comp::DMinusM,
+        };
+        self.with(place, Dest::D, comp);
+        self.program.label(&end);
+        if temp {
+            self.release();
+        }
+    }
+
+    fn expression(&mut self, expression: &'a Expression) {
+        self.operations(expression, expression.rest.len());
+    }
+
+    // Evaluates the first term and the first `count` operations into D.
+    fn operations(&mut self, expression: &'a Expression, count: usize) {
+        let mut rest = expression.rest[..count].iter();
+        match (rest.as_slice().first(), constant(&expression.first)) {
+            // A constant factor is moved to the right, where it can be
+            // multiplied by in place.
+            (Some((BinaryOp::Mul, term)), Some(factor))
+                if self.context.optimize && multiplies_in_place(factor) =>
+            {
+                self.term(term);
+                self.multiply(factor);
+                rest.next();
+            }
+            _ => self.term(&expression.first),
+        }
+        for (op, term) in rest {
+            self.operation(*op, term);
+        }
+    }
+
+    fn operation(&mut self, op: BinaryOp, term: &'a Term) {
+        let value = constant(term).filter(|_| self.context.optimize);
+        match (op, value) {
+            (BinaryOp::Mul, Some(factor)) if multiplies_in_place(factor) => self.multiply(factor),
+            (BinaryOp::Div, Some(divisor)) if divides_in_place(divisor) => self.divide(divisor),
+            (BinaryOp::Mul, _) => self.call(
+                "Math.multiply",
+                vec![Argument::D, Argument::Term(term)],
+                term.span(),
+            ),
+            (BinaryOp::Div, _) => self.call(
+                "Math.divide",
+                vec![Argument::D, Argument::Term(term)],
+                term.span(),
+            ),
+            (BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Eq, _) => {
+                self.compare(op, term);
+                let yes = self.new_label("TRUE");
+                let end = self.new_label("END");
+                self.a(&yes);
+                self.jump(Comp::D, jump(op));
+                self.c(Dest::D, Comp::Zero);
+                self.goto(&end);
+                self.program.label(&yes);
+                self.c(Dest::D, Comp::MinusOne);
+                self.program.label(&end);
+            }
+            _ => self.combine(op, term),
+        }
+    }
+
+    // Applies an addition, subtraction, and or or to D and the term.
+    fn combine(&mut self, op: BinaryOp, term: &'a Term) {
+        let (with_a, with_m) = match op {
+            BinaryOp::Add => (Comp::DPlusA, Comp::DPlusM),
+            BinaryOp::Sub => (Comp::DMinusA, Comp::DMinusM),
+            BinaryOp::And => (Comp::DAndA, Comp::DAndM),
+            _ => (Comp::DOrA, Comp::DOrM),
+        };
+        match (op, constant(term)) {
+            (BinaryOp::Add | BinaryOp::Sub | BinaryOp::Or, Some(0))
+            | (BinaryOp::And, Some(0xFFFF)) => return,
+            (BinaryOp::Add, Some(1)) | (BinaryOp::Sub, Some(0xFFFF)) => {
+                return self.c(Dest::D, Comp::DPlusOne)
+            }
+            (BinaryOp::Sub, Some(1)) | (BinaryOp::Add, Some(0xFFFF)) => {
+                return self.c(Dest::D, Comp::DMinusOne)
+            }
+            (_, Some(value @ 0..=32767)) => {
+                self.a_value(value);
+                return self.c(Dest::D, with_a);
+            }
+            (BinaryOp::Add | BinaryOp::Sub, Some(value)) if value != 0x8000 => {
+                self.a_value(value.wrapping_neg());
+                let comp = match op {
+                    BinaryOp::Add => Comp::DMinusA,
+                    _ => Comp::DPlusA,
+                };
+                return self.c(Dest::D, comp);
+            }
+            _ => {}
+        }
+        if let Term::Variable(name) = term {
+            if let Some((place, _)) = self.variable(&name.name, name.span) {
+                self.with(place, Dest::D, with_m);
+            }
+            return;
+        }
+        // The left operand waits in a temporary while the term is worked out.
+        let temp = self.temp();
+        self.store(Place::Slot(temp));
+        self.term(term);
+        self.with(Place::Slot(temp), Dest::D, swap(with_m));
+        self.release();
+    }
+
+    // Multiplies D by doubling it and adding it back, bit by bit from the
+    // top of the factor, with the multiplicand in R14 and the doubling done
+    // through R13.
+    fn multiply(&mut self, factor: u16) {
+        let magnitude = (factor as i16).unsigned_abs();
+        if magnitude == 0 {
+            return self.c(Dest::D, Comp::Zero);
+        }
+        if magnitude > 1 {
+            self.a("R14");
+            self.c(Dest::M, Comp::D);
+            for bit in (0..15 - magnitude.leading_zeros()).rev() {
+                self.a("R13");
+                self.c(Dest::M, Comp::D);
+                self.c(Dest::D, Comp::DPlusM);
+                if magnitude & (1 << bit) != 0 {
+                    self.a("R14");
+                    self.c(Dest::D, Comp::DPlusM);
+                }
+            }
+        }
+        if (factor as i16) < 0 {
+            self.c(Dest::D, Comp::MinusD);
+        }
+    }
+
+    // Divides D by a power of two by collecting the bits of its magnitude
+    // above the shift into R15, and restoring the sign after. The dividend
+    // is kept in R13 and its magnitude in R14.
+    fn divide(&mut self, divisor: u16) {
+        let magnitude = (divisor as i16).unsigned_abs();
+        let shift = magnitude.trailing_zeros() as u16;
+        if shift > 0 {
+            let positive = self.new_label("DIV_POSITIVE");
+            let end = self.new_label("DIV_END");
+            self.a("R13");
+            self.c(Dest::M, Comp::D);
+            self.a(&positive);
+            self.jump(Comp::D, Jump::JGE);
+            self.c(Dest::D, Comp::MinusD);
+            self.program.label(&positive);
+            self.a("R14");
+            self.c(Dest::M, Comp::D);
+            self.a("R15");
+            self.c(Dest::M, Comp::Zero);
+            for bit in shift..16 {
+                let clear = self.new_label("DIV_CLEAR");
+                self.a("R14");
+                self.c(Dest::D, Comp::M);
+                // The top bit can't be loaded into A, but is the sign.
+                if bit == 15 {
+                    self.a(&clear);
+                    self.jump(Comp::D, Jump::JGE);
+                } else {
+                    self.a_value(1 << bit);
+                    self.c(Dest::D, Comp::DAndA);
+                    self.a(&clear);
+                    self.jump(Comp::D, Jump::JEQ);
+                }
+                self.a_value(1 << (bit - shift));
+                self.c(Dest::D, Comp::A);
+                self.a("R15");
+                self.c(Dest::M, Comp::DPlusM);
+                self.program.label(&clear);
+            }
+            self.a("R13");
+            self.c(Dest::D, Comp::M);
+            self.a(&end);
+            self.jump(Comp::D, Jump::JGE);
+            self.a("R15");
+            self.c(Dest::M, Comp::MinusM);
+            self.program.label(&end);
+            self.a("R15");
+            self.c(Dest::D, Comp::M);
+        }
+        if (divisor as i16) < 0 {
+            self.c(Dest::D, Comp::MinusD);
+        }
+    }
+
+    fn term(&mut self, term: &'a Term) {
+        match term {
+            Term::Integer(value, _) => self.constant(*value),
+            Term::String(string, span) => {
+                let chars: Vec<u16> = string.encode_utf16().collect();
+                self.call(
+                    "String.new",
+                    vec![Argument::Constant(chars.len() as u16)],
+                    *span,
+                );
+                for c in chars {
+                    self.call(
+                        "String.appendChar",
+                        vec![Argument::D, Argument::Constant(c)],
+                        *span,
+                    );
+                }
+            }
+            Term::Keyword(keyword, _) => match (keyword, self.this) {
+                (KeywordConstant::True, _) => self.c(Dest::D, Comp::MinusOne),
+                (KeywordConstant::This, Some(this)) => self.load(Place::Slot(this)),
+                _ => self.c(Dest::D, Comp::Zero),
+            },
+            Term::Variable(name) => {
+                if let Some((place, _)) = self.variable(&name.name, name.span) {
+                    self.load(place);
+                }
+            }
+            Term::Index(name, index, _) => {
+                self.element(&name.name, name.span, index);
+                self.c(Dest::D, Comp::M);
+            }
+            Term::Call(call) => self.subroutine_call(call),
+            Term::Parenthesized(expression, _) => self.expression(expression),
+            Term::Unary(op, term, _) => {
+                self.term(term);
+                let comp = match op {
+                    UnaryOp::Neg => Comp::MinusD,
+                    UnaryOp::Not => Comp::NotD,
+                };
+                self.c(Dest::D, comp);
+            }
+        }
+    }
+
+    // A call through a variable or with no receiver is a method call, and
+    // passes the object first. Any other receiver names a class.
+    fn subroutine_call(&mut self, call: &'a SubroutineCall) {
+        let mut arguments = vec![];
+        let class = match &call.receiver {
+            None => {
+                arguments.push(match self.this {
+                    Some(this) => Argument::Place(Place::Slot(this)),
+                    None => Argument::Constant(0),
+                });
+                self.class.name.name.clone()
+            }
+            Some(receiver) => match self.variables.get(receiver.name.as_str()).copied() {
+                Some((place, Type::Class(class))) => {
+                    arguments.push(Argument::Place(place));
+                    class.clone()
+                }
+                Some((_, ty)) => {
+                    self.errors.push(CompileError::NotAnObject(
+                        receiver.span,
+                        receiver.name.clone(),
+                    ));
+                    ty.name().to_string()
+                }
+                None => receiver.name.clone(),
+            },
+        };
+        arguments.extend(call.arguments.iter().map(Argument::Expression));
+        let name = format!("{}.{}", class, call.name.name);
+        self.call(&name, arguments, call.span);
+    }
+
+    // Leaves the result in D.
+    fn call(&mut self, name: &str, arguments: Vec<Argument<'a>>, span: Span) {
+        let frame = match self.context.frames.get(name) {
+            Some(frame) => *frame,
+            None => {
+                return self
+                    .errors
+                    .push(CompileError::UndefinedSubroutine(span, name.to_string()))
+            }
+        };
+        if frame.parameters as usize != arguments.len() {
+            return self.errors.push(CompileError::ArgumentCount(
+                span,
+                name.to_string(),
+                frame.parameters as usize,
+                arguments.len(),
+            ));
+        }
+        self.callees.push(name.to_string());
+        match frame.base {
+            // Arguments are stored straight into the callee's frame, unless
+            // a call made while working out a later one could overwrite them.
+            Some(base) => {
+                let mut waiting = vec![];
+                for (i, argument) in arguments.iter().enumerate() {
+                    self.argument(argument);
+                    if arguments[i + 1..].iter().any(Argument::has_call) {
+                        let temp = self.temp();
+                        self.store(Place::Slot(temp));
+                        waiting.push((temp, base + i as u16));
+                    } else {
+                        self.store(Place::Slot(Slot::Fixed(base + i as u16)));
+                    }
+                }
+                for &(temp, address) in &waiting {
+                    self.load(Place::Slot(temp));
+                    self.store(Place::Slot(Slot::Fixed(address)));
+                }
+                for _ in waiting {
+                    self.release();
+                }
+            }
+            None => {
+                for argument in &arguments {
+                    self.argument(argument);
+                    self.a("SP");
+                    self.c(Dest::AM, Comp::MPlusOne);
+                    self.c(Dest::A, Comp::AMinusOne);
+                    self.c(Dest::M, Comp::D);
+                }
+            }
+        }
+        let label = self.new_label("ret.");
+        self.a(&label);
+        self.c(Dest::D, Comp::A);
+        self.goto(name);
+        self.program.label(&label);
+    }
+
+    fn argument(&mut self, argument: &Argument<'a>) {
+        match argument {
+            Argument::D => {}
+            Argument::Constant(value) => self.constant(*value),
+            Argument::Place(place) => self.load(*place),
+            Argument::Expression(expression) => self.expression(expression),
+            Argument::Term(term) => self.term(term),
+        }
+    }
+}
+
+fn generate<'a>(
+    context: &'a Context<'a>,
+    class: &'a Class,
+    subroutine: &'a Subroutine,
+) -> Generator<'a> {
+    let mut generator = Generator::new(context, class, subroutine);
+    generator.subroutine(subroutine);
+    generator
+}
+
+// Whether the function can be active more than once at a time.
+fn recursive(function: &str, callees: &HashMap<String, Vec<String>>) -> bool {
+    let mut pending: Vec<&str> = callees[function].iter().map(String::as_str).collect();
+    let mut seen = HashSet::new();
+    while let Some(name) = pending.pop() {
+        if name == function {
+            return true;
+        }
+        if seen.insert(name) {
+            pending.extend(callees[name].iter().map(String::as_str));
+        }
+    }
+    false
+}
+
+// The classes of a program, where later classes replace earlier ones of the
+// same name.
+fn program_classes(classes: &[Class]) -> Vec<&Class> {
+    let mut program: Vec<&Class> = vec![];
+    for class in classes {
+        match program
+            .iter_mut()
+            .find(|other| other.name.name == class.name.name)
+        {
+            Some(other) => *other = class,
+            None => program.push(class),
+        }
+    }
+    program
+}
+
+// The address of each static, named Class.variable, and the name the VM
+// translator would give it, Class.index.
+fn static_addresses(program: &[&Class]) -> Vec<(String, String, u16)> {
+    let mut statics = vec![];
+    for class in program {
+        let names = class
+            .variables
+            .iter()
+            .filter(|variable| variable.kind == ClassVarKind::Static)
+            .flat_map(|variable| &variable.names);
+        for (index, name) in names.enumerate() {
+            statics.push((
+                format!("{}.{}", class.name.name, name.name),
+                format!("{}.{}", class.name.name, index),
+                STATICS + statics.len() as u16,
+            ));
+        }
+    }
+    statics
+}
+
+// Compiles a whole program, which includes the OS classes, straight into
+// Hack assembly that sets up the stack and calls Sys.init. Later classes
+// replace earlier ones of the same name, and subroutines that no call
+// reaches are left out. Errors are given with the name of their class.
+pub fn compile_program(
+    classes: &[Class],
+    optimize: bool,
+) -> Result<Program, Vec<(String, CompileError)>> {
+    let program = program_classes(classes);
+    let statics = static_addresses(&program)
+        .into_iter()
+        .map(|(name, _, address)| (name, address))
+        .collect::<HashMap<_, _>>();
+    let mut fields = HashMap::new();
+    for class in &program {
+        let count = class
+            .variables
+            .iter()
+            .filter(|variable| variable.kind == ClassVarKind::Field)
+            .map(|variable| variable.names.len() as u16)
+            .sum::<u16>();
+        fields.insert(class.name.name.as_str(), count);
+    }
+    let subroutines: Vec<(&Class, &Subroutine, String)> = program
+        .iter()
+        .flat_map(|class| {
+            class.subroutines.iter().map(move |subroutine| {
+                let name = format!("{}.{}", class.name.name, subroutine.name.name);
+                (*class, subroutine, name)
+            })
+        })
+        .collect();
+
+    // The calls are found with every frame at 0, which is where the
+    // temporaries of each frame are counted next.
+    let frames = subroutines
+        .iter()
+        .map(|(_, subroutine, name)| {
+            let method = subroutine.kind == SubroutineKind::Method;
+            let parameters = subroutine.parameters.len() as u16 + method as u16;
+            let frame = Frame {
+                parameters,
+                base: Some(0),
+            };
+            (name.clone(), frame)
+        })
+        .collect();
+    let mut context = Context {
+        statics,
+        frames,
+        fields,
+        optimize,
+    };
+    let mut callees = HashMap::new();
+    let mut errors = vec![];
+    for (class, subroutine, name) in &subroutines {
+        let generator = generate(&context, class, subroutine);
+        errors.extend(
+            generator
+                .errors
+                .into_iter()
+                .map(|error| (class.name.name.clone(), error)),
+        );
+        callees.insert(name.clone(), generator.callees);
+    }
+    if !context.frames.contains_key("Sys.init") {
+        let error = CompileError::UndefinedSubroutine(Span::default(), "Sys.init".to_string());
+        errors.push(("Sys".to_string(), error));
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut reachable = HashSet::new();
+    let mut pending = vec!["Sys.init"];
+    while let Some(name) = pending.pop() {
+        if reachable.insert(name) {
+            pending.extend(callees[name].iter().map(String::as_str));
+        }
+    }
+    let subroutines: Vec<_> = subroutines
+        .into_iter()
+        .filter(|(_, _, name)| reachable.contains(name.as_str()))
+        .collect();
+    let mut sizes = HashMap::new();
+    for (_, _, name) in &subroutines {
+        if recursive(name, &callees) {
+            context.frames.get_mut(name).unwrap().base = None;
+        }
+    }
+    for (class, subroutine, name) in &subroutines {
+        sizes.insert(
+            name.as_str(),
+            generate(&context, class, subroutine).size as usize,
+        );
+    }
+
+    // A fixed frame goes past the fixed frames of every caller, as the
+    // frames on a chain of calls are all in use at once.
+    let mut starts: HashMap<&str, usize> = HashMap::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (_, _, name) in &subroutines {
+            let start = starts.get(name.as_str()).copied().unwrap_or(0);
+            let end = match context.frames[name].base {
+                Some(_) => start + sizes[name.as_str()],
+                None => start,
+            };
+            for callee in &callees[name] {
+                let callee_start = starts.entry(callee.as_str()).or_insert(0);
+                if *callee_start < end {
+                    *callee_start = end;
+                    changed = true;
+                }
+            }
+        }
+    }
+    let frames_start = STATICS as usize + context.statics.len();
+    let mut stack = frames_start;
+    for (class, subroutine, name) in &subroutines {
+        let frame = context.frames.get_mut(name).unwrap();
+        if frame.base.is_none() {
+            continue;
+        }
+        let start = frames_start + starts.get(name.as_str()).copied().unwrap_or(0);
+        let end = start + sizes[name.as_str()];
+        if end >= HEAP as usize {
+            let error = CompileError::OutOfRam(subroutine.name.span, name.clone());
+            return Err(vec![(class.name.name.clone(), error)]);
+        }
+        frame.base = Some(start as u16);
+        stack = stack.max(end);
+    }
+
+    let mut output = Program::new();
+    output.comment(&format!(
+        "statics from {}, fixed frames from {}, stack from {}",
+        STATICS, frames_start, stack
+    ));
+    output.comment("bootstrap");
+    output.a_value(stack as u16);
+    output.c(Some(Dest::D), Comp::A, None);
+    output.a("SP");
+    output.c(Some(Dest::M), Comp::D, None);
+    output.a("Bootstrap$ret");
+    output.c(Some(Dest::D), Comp::A, None);
+    output.a("Sys.init");
+    output.c(None, Comp::Zero, Some(Jump::JMP));
+    output.label("Bootstrap$ret");
+    output.a("Bootstrap$ret");
+    output.c(None, Comp::Zero, Some(Jump::JMP));
+    for (class, subroutine, _) in &subroutines {
+        output.extend(generate(&context, class, subroutine).program);
+    }
+    Ok(output)
+}
+
+// The labels of a program compiled from the classes, and its statics under
+// the names the VM translator gives them, as the assembler would list them.
+pub fn symbol_file(classes: &[Class], program: &Program) -> SymbolFile {
+    let mut symbols = program.symbols().symbol_file();
+    symbols.variables.extend(
+        static_addresses(&program_classes(classes))
+            .into_iter()
+            .map(|(_, name, address)| (name, address)),
+    );
+    symbols
+}
+
+// Runs a program from reset until it enters Sys.halt, giving the number of
+// cycles that took, or None if it didn't within `max_cycles`.
+pub fn run<S: Screen, K: Keyboard>(
+    computer: &mut Computer<S, K>,
+    program: &Program,
+    max_cycles: u64,
+) -> Option<u64> {
+    let (instructions, symbols) = program.assemble();
+    let halt = symbols.get("Sys.halt")?;
+    if instructions.len() > 32768 {
+        return None;
+    }
+    let words: Vec<u16> = instructions
+        .iter()
+        .map(|instruction| instruction.as_raw())
+        .collect();
+    computer.set_rom(Rom::from_words(&words));
+    computer.tick(true);
+    for cycles in 0..max_cycles {
+        if computer.pc().as_raw() == halt {
+            return Some(cycles);
+        }
+        computer.tick(false);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        codegen::compile,
+        os,
+        parser::{parse, parse_dialect},
+        token::Dialect,
+    };
+    use computer::{keyboard::DummyKeyboard, screen::DummyScreen};
+    use vm::{loader::translate, optimize::remove_unreachable};
+
+    const MAIN: &str = "class Main {
+        static int sum, fib, product, quotient, length, total, odds;
+        function int fib(int n) {
+            if (n < 2) { return n; }
+            return Main.fib(n - 1) + Main.fib(n - 2);
+        }
+        function int add(int x, int y) { return x + y; }
+        function void main() {
+            var Array a;
+            var List list;
+            var String s;
+            var int i;
+            let a = Array.new(5);
+            while (i < 5) { let a[i] = i * i; let i = i + 1; }
+            let list = List.new(a[4], List.new(a[3], null));
+            let sum = list.sum();
+            let fib = Main.fib(10);
+            let product = -7 * 123;
+            let quotient = -1000 / 8;
+            let s = \"Jack\";
+            let length = s.length();
+            let total = Main.add(Main.add(1, 2), Main.add(3, 4));
+            for (i = 0; i < 100; i += 1) {
+                if (i > 9) { break; }
+                if (i & 1 = 0) { continue; }
+                let odds += i;
+            }
+            return;
+        }
+    }";
+
+    const LIST: &str = "class List {
+        field int head;
+        field List tail;
+        constructor List new(int h, List t) { let head = h; let tail = t; return this; }
+        method int sum() {
+            if (tail = null) { return head; }
+            return head + tail.sum();
+        }
+    }";
+
+    // Skips initializing the screen and the font, which takes most of the
+    // cycles of a small program.
+    const SYS: &str = "class Sys {
+        function void init() {
+            do Memory.init();
+            do Math.init();
+            do Main.main();
+            do Sys.halt();
+            return;
+        }
+        function void halt() { while (true) {} return; }
+        function void error(int code) { do Sys.halt(); return; }
+    }";
+
+    fn program() -> Vec<Class> {
+        let mut classes = vec![
+            parse_dialect(MAIN, Dialect::Extended).unwrap(),
+            parse(LIST).unwrap(),
+        ];
+        classes.extend(os::classes());
+        classes.push(parse(SYS).unwrap());
+        classes
+    }
+
+    // Runs the program, returning Main's statics, the ROM size and cycles.
+    fn run_program(program: &Program) -> (Vec<i16>, usize, u64) {
+        let mut computer = Computer::<DummyScreen, DummyKeyboard>::new();
+        let cycles = run(&mut computer, program, 1_000_000).expect("the program halts");
+        let statics = (16..23).map(|address| computer.peek(address) as i16);
+        (statics.collect(), program.len(), cycles)
+    }
+
+    #[test]
+    fn compiled_programs_run() {
+        for optimize in [false, true] {
+            let program = compile_program(&program(), optimize).unwrap();
+            let (statics, _, _) = run_program(&program);
+            assert_eq!(statics, [25, 55, -861, -125, 4, 10, 25], "{}", optimize);
+        }
+    }
+
+    #[test]
+    fn direct_code_is_smaller_and_faster_than_vm_code() {
+        let classes = program();
+        let modules: Vec<_> = classes
+            .iter()
+            .map(|class| compile(class).unwrap())
+            .collect();
+        let vm = translate(&remove_unreachable(&modules, true), true);
+        let (expected, vm_size, vm_cycles) = run_program(&vm);
+        let (statics, size, cycles) = run_program(&compile_program(&classes, false).unwrap());
+        assert_eq!(statics, expected);
+        assert!(size * 2 < vm_size, "{} {}", size, vm_size);
+        assert!(cycles * 2 < vm_cycles, "{} {}", cycles, vm_cycles);
+    }
+
+    #[test]
+    fn comparisons_at_the_extremes_match_the_vm() {
+        let main = parse(
+            "class Main {
+                static int constants, lt, gt, left, right;
+                function int id(int x) { return x; }
+                function void main() {
+                    var Array values;
+                    var int i, j, x, y;
+                    let values = Array.new(4);
+                    let values[0] = -32767 - 1;
+                    let values[1] = -1;
+                    let values[2] = 1;
+                    let values[3] = 32767;
+                    while (i < 4) {
+                        let x = values[i];
+                        let constants = constants + constants - (x < 1);
+                        let constants = constants + constants - (x > -1);
+                        let constants = constants + constants - (x < -32767);
+                        let constants = constants + constants - (x > 32766);
+                        let j = 0;
+                        while (j < 4) {
+                            let y = values[j];
+                            let lt = lt + lt - (x < y);
+                            let gt = gt + gt - (x > y);
+                            let left = left + left;
+                            if (Main.id(x) > y) { let left = left + 1; }
+                            let right = right + right;
+                            if (~(x < Main.id(y))) {} else { let right = right + 1; }
+                            let j = j + 1;
+                        }
+                        let i = i + 1;
+                    }
+                    return;
+                }
+            }",
+        )
+        .unwrap();
+        let mut classes = vec![main];
+        classes.extend(os::classes());
+        classes.push(parse(SYS).unwrap());
+        let values = [-32768, -1, 1, 32767];
+        let mask = |bits: Vec<bool>| bits.iter().fold(0u16, |mask, bit| mask << 1 | *bit as u16);
+        let pairs = |f: fn(i16, i16) -> bool| {
+            mask(
+                values
+                    .iter()
+                    .flat_map(|x| values.iter().map(move |y| f(*x, *y)))
+                    .collect(),
+            )
+        };
+        let constants = mask(
+            values
+                .iter()
+                .flat_map(|x| vec![*x < 1, *x > -1, *x < -32767, *x > 32766])
+                .collect(),
+        );
+        let lt = pairs(|x, y| x < y);
+        let gt = pairs(|x, y| x > y);
+        let expected = [constants, lt, gt, gt, lt].map(|mask| mask as i16);
+        let modules: Vec<_> = classes
+            .iter()
+            .map(|class| compile(class).unwrap())
+            .collect();
+        let vm = translate(&remove_unreachable(&modules, true), true);
+        assert_eq!(run_program(&vm).0[..5], expected);
+        for optimize in [false, true] {
+            let program = compile_program(&classes, optimize).unwrap();
+            assert_eq!(run_program(&program).0[..5], expected, "{}", optimize);
+        }
+    }
+
+    #[test]
+    fn only_recursive_functions_use_the_stack() {
+        let program = compile_program(&program(), false).unwrap().to_string();
+        let function = |name: &str| {
+            let start = program.find(&format!("({})", name)).unwrap();
+            let end = program[start..].find("// function").unwrap();
+            program[start..start + end].to_string()
+        };
+        assert!(function("Main.fib").contains("@ARG"));
+        assert!(function("List.sum").contains("@ARG"));
+        assert!(!function("Main.main").contains("@ARG"));
+        assert!(!function("Main.add").contains("@ARG"));
+    }
+
+    #[test]
+    fn calls_are_checked_against_the_program() {
+        let main = parse(
+            "class Main {
+                function void main() {
+                    do Main.missing();
+                    do Math.abs(1, 2);
+                    return;
+                }
+            }",
+        )
+        .unwrap();
+        let mut classes = vec![main];
+        classes.extend(os::classes());
+        let errors: Vec<(String, String)> = compile_program(&classes, false)
+            .unwrap_err()
+            .into_iter()
+            .map(|(class, error)| (class, error.to_string()))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (
+                    "Main".to_string(),
+                    "3:24: Main.missing is not defined".to_string()
+                ),
+                (
+                    "Main".to_string(),
+                    "4:24: Math.abs takes 1 arguments but is given 2".to_string()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,502 @@
+use crate::{
+    ast::{
+        Class, ClassVarKind, Expression, Statement, Subroutine, SubroutineCall, SubroutineKind,
+        Term,
+    },
+    parser::{parse_tokens, ParseError},
+    token::{Comment, Dialect, Keyword, Token, TokenKind},
+    tokenizer::Tokenizer,
+};
+
+const INDENT: &str = "    ";
+const WIDTH: usize = 100;
+
+// Reprints a class of standard Jack in the style of the OS sources: four
+// spaces of indentation, a line for each declaration and statement, single
+// spaces around binary operators and after commas, and a blank line between
+// subroutines. Comments stay before or after the code they were written
+// next to, and single blank lines between statements are kept.
+pub fn format(source: &str) -> Result<String, Vec<ParseError>> {
+    let mut tokenizer = Tokenizer::new(source);
+    let mut tokens = vec![];
+    let mut errors = vec![];
+    for result in tokenizer.by_ref() {
+        match result {
+            Ok(token) => tokens.push(token),
+            Err(error) => errors.push(ParseError::Tokenize(error)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let class = match parse_tokens(&tokens, Dialect::Standard) {
+        (Some(class), errors) if errors.is_empty() => class,
+        (_, errors) => return Err(errors),
+    };
+    let mut printer = Printer {
+        source,
+        tokens: &tokens,
+        comments: tokenizer.comments(),
+        next_comment: 0,
+        text: String::new(),
+        depth: 0,
+        end: 0,
+        opened: true,
+        separate: false,
+    };
+    printer.class(&class);
+    Ok(printer.text)
+}
+
+struct Printer<'a> {
+    source: &'a str,
+    tokens: &'a [Token],
+    comments: &'a [Comment],
+    next_comment: usize,
+    text: String,
+    depth: usize,
+    // Where the last line printed ends in the source.
+    end: usize,
+    // Right after an opening brace, where blank lines are dropped.
+    opened: bool,
+    // Whether the next line starts a subroutine after a class variable or
+    // another subroutine, which always has a blank line before it.
+    separate: bool,
+}
+
+impl Printer<'_> {
+    // The offset of the first `symbol` at or after `offset`.
+    fn symbol_after(&self, offset: usize, symbol: char) -> usize {
+        let first = self
+            .tokens
+            .partition_point(|token| token.span.start < offset);
+        self.tokens[first..]
+            .iter()
+            .find(|token| token.kind == TokenKind::Symbol(symbol))
+            .map_or(self.source.len(), |token| token.span.start)
+    }
+
+    fn indent(&mut self) {
+        self.text.push_str(&INDENT.repeat(self.depth));
+    }
+
+    // Starts a line for what begins at `start` in the source, after a blank
+    // line if one was there or a subroutine starts.
+    fn start_line(&mut self, start: usize) {
+        let blank = self
+            .source
+            .get(self.end..start)
+            .is_some_and(|gap| gap.matches('\n').count() > 1);
+        if self.separate || (blank && !self.opened) {
+            self.text.push('\n');
+        }
+        self.opened = false;
+        self.separate = false;
+        self.indent();
+    }
+
+    // Prints the comments that start before `offset`. Trailing comments go
+    // at the end of the last line, others on lines of their own, with the
+    // lines of block comments indented as they were relative to the first.
+    fn comments_before(&mut self, offset: usize) {
+        while let Some(comment) = self
+            .comments
+            .get(self.next_comment)
+            .filter(|comment| comment.span.start < offset)
+        {
+            self.next_comment += 1;
+            if comment.trailing && self.text.ends_with('\n') {
+                self.text.pop();
+                self.text.push(' ');
+            } else {
+                self.start_line(comment.span.start);
+            }
+            let mut lines = comment.text.lines();
+            self.text.push_str(lines.next().unwrap_or_default());
+            self.text.push('\n');
+            for line in lines {
+                let margin = line
+                    .char_indices()
+                    .take(comment.span.column - 1)
+                    .take_while(|(_, c)| c.is_whitespace())
+                    .map(|(i, c)| i + c.len_utf8())
+                    .last()
+                    .unwrap_or(0);
+                self.indent();
+                self.text.push_str(&line[margin..]);
+                self.text.push('\n');
+            }
+            self.end = comment.span.end;
+        }
+    }
+
+    // Prints a line for code from `start` to `end` in the source, along with
+    // the comments before and inside it.
+    fn line(&mut self, start: usize, end: usize, text: &str) {
+        self.comments_before(start);
+        self.start_line(start);
+        self.text.push_str(text);
+        self.text.push('\n');
+        self.end = end;
+        self.comments_before(end);
+    }
+
+    // Prints the line of a block ending in the brace at `brace`.
+    fn open(&mut self, start: usize, brace: usize, text: &str) {
+        self.line(start, brace + 1, text);
+        self.depth += 1;
+        self.opened = true;
+    }
+
+    // Closes a block with the brace at `brace`, and `rest` on its line.
+    fn close(&mut self, brace: usize, rest: &str) {
+        self.comments_before(brace);
+        self.depth -= 1;
+        self.opened = false;
+        self.indent();
+        self.text.push('}');
+        self.text.push_str(rest);
+        self.text.push('\n');
+        self.end = brace + 1;
+    }
+
+    fn class(&mut self, class: &Class) {
+        let brace = self.symbol_after(class.name.span.end, '{');
+        self.open(
+            class.span.start,
+            brace,
+            &format!("class {} {{", class.name.name),
+        );
+        for variable in &class.variables {
+            let names = variable
+                .names
+                .iter()
+                .map(|name| name.name.as_str())
+                .collect::<Vec<_>>();
+            let kind = match variable.kind {
+                ClassVarKind::Static => Keyword::Static,
+                ClassVarKind::Field => Keyword::Field,
+            };
+            let text = format!(
+                "{} {} {};",
+                kind.name(),
+                variable.ty.name(),
+                names.join(", ")
+            );
+            self.line(variable.span.start, variable.span.end, &text);
+        }
+        for (i, subroutine) in class.subroutines.iter().enumerate() {
+            self.separate = i > 0 || !class.variables.is_empty();
+            self.subroutine(subroutine);
+        }
+        self.close(class.span.end - 1, "");
+        self.comments_before(usize::MAX);
+    }
+
+    fn subroutine(&mut self, subroutine: &Subroutine) {
+        let parameters = subroutine
+            .parameters
+            .iter()
+            .map(|parameter| format!("{} {}", parameter.ty.name(), parameter.name.name))
+            .collect::<Vec<_>>();
+        let kind = match subroutine.kind {
+            SubroutineKind::Constructor => Keyword::Constructor,
+            SubroutineKind::Function => Keyword::Function,
+            SubroutineKind::Method => Keyword::Method,
+        };
+        let prefix = format!(
+            "{} {} {}(",
+            kind.name(),
+            subroutine
+                .return_type
+                .as_ref()
+                .map_or("void", |ty| ty.name()),
+            subroutine.name.name,
+        );
+        let header = self.wrap_list(&prefix, &parameters, ") {");
+        let brace = self.symbol_after(subroutine.name.span.end, '{');
+        self.open(subroutine.span.start, brace, &header);
+        for local in &subroutine.locals {
+            let names = local
+                .names
+                .iter()
+                .map(|name| name.name.as_str())
+                .collect::<Vec<_>>();
+            let text = format!("var {} {};", local.ty.name(), names.join(", "));
+            self.line(local.span.start, local.span.end, &text);
+        }
+        self.statements(&subroutine.body);
+        self.close(subroutine.span.end - 1, "");
+    }
+
+    // Joins `parts` with spaces into as few lines as fit in the width, with
+    // the widest of them as narrow as it can be. Lines after the first start
+    // with `continuation`.
+    fn wrap(&self, parts: &[String], continuation: &str) -> String {
+        let lines = |width: usize| {
+            let mut lines: Vec<String> = vec![];
+            for part in parts {
+                let margin = if lines.len() > 1 {
+                    continuation.len()
+                } else {
+                    0
+                };
+                match lines.last_mut() {
+                    Some(line) if margin + line.len() + 1 + part.len() <= width => {
+                        line.push(' ');
+                        line.push_str(part);
+                    }
+                    _ => lines.push(part.clone()),
+                }
+            }
+            lines
+        };
+        let width = WIDTH.saturating_sub(INDENT.len() * self.depth);
+        let count = lines(width).len();
+        let width = (0..width)
+            .find(|&width| lines(width).len() <= count)
+            .unwrap_or(width);
+        let indent = format!("\n{}{}", INDENT.repeat(self.depth), continuation);
+        lines(width).join(&indent)
+    }
+
+    // An expression between `prefix` and `suffix`, broken before its
+    // operators and going on a level further in.
+    fn wrap_expression(&self, prefix: &str, expression: &Expression, suffix: &str) -> String {
+        let mut parts = vec![format!("{}{}", prefix, term(&expression.first))];
+        parts.extend(
+            expression
+                .rest
+                .iter()
+                .map(|(op, term)| format!("{} {}", op.symbol(), self::term(term))),
+        );
+        parts.last_mut().unwrap().push_str(suffix);
+        self.wrap(&parts, INDENT)
+    }
+
+    // A list ending in `suffix` after `prefix`, which ends in its opening
+    // parenthesis, broken after commas and going on under the first item.
+    fn wrap_list(&self, prefix: &str, items: &[String], suffix: &str) -> String {
+        let mut parts = items
+            .iter()
+            .map(|item| format!("{},", item))
+            .collect::<Vec<_>>();
+        match parts.last_mut() {
+            Some(last) => {
+                last.pop();
+                last.push_str(suffix);
+                parts[0].insert_str(0, prefix);
+            }
+            None => parts.push(format!("{}{}", prefix, suffix)),
+        }
+        self.wrap(&parts, &" ".repeat(prefix.len()))
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        let span = statement.span();
+        let text = match statement {
+            Statement::Let {
+                name, index, value, ..
+            } => {
+                let target = match index {
+                    Some(index) => format!("let {}[{}] = ", name.name, expression(index)),
+                    None => format!("let {} = ", name.name),
+                };
+                self.wrap_expression(&target, value, ";")
+            }
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                let brace = self.symbol_after(condition.span.end, '{');
+                let text = self.wrap_expression("if (", condition, ") {");
+                self.open(span.start, brace, &text);
+                self.statements(then);
+                if let Some(otherwise) = otherwise {
+                    let last = then.last().map_or(brace, |statement| statement.span().end);
+                    let close = self.symbol_after(last, '}');
+                    self.close(close, " else {");
+                    self.depth += 1;
+                    self.opened = true;
+                    self.statements(otherwise);
+                }
+                self.close(span.end - 1, "");
+                return;
+            }
+            Statement::While {
+                condition, body, ..
+            } => {
+                let brace = self.symbol_after(condition.span.end, '{');
+                let text = self.wrap_expression("while (", condition, ") {");
+                self.open(span.start, brace, &text);
+                self.statements(body);
+                self.close(span.end - 1, "");
+                return;
+            }
+            Statement::Do { call, .. } => {
+                let arguments = call.arguments.iter().map(expression).collect::<Vec<_>>();
+                self.wrap_list(&format!("do {}(", callee(call)), &arguments, ");")
+            }
+            Statement::Return { value, .. } => match value {
+                Some(value) => self.wrap_expression("return ", value, ";"),
+                None => "return;".to_string(),
+            },
+            Statement::Break { .. } => "break;".to_string(),
+            Statement::Continue { .. } => "continue;".to_string(),
+        };
+        self.line(span.start, span.end, &text);
+    }
+}
+
+fn expression(expression: &Expression) -> String {
+    let mut text = term(&expression.first);
+    for (op, term) in &expression.rest {
+        text.push_str(&format!(" {} {}", op.symbol(), self::term(term)));
+    }
+    text
+}
+
+fn term(term: &Term) -> String {
+    match term {
+        Term::Integer(value, _) => value.to_string(),
+        Term::String(text, _) => format!("\"{}\"", text),
+        Term::Keyword(keyword, _) => keyword.name().to_string(),
+        Term::Variable(name) => name.name.clone(),
+        Term::Index(name, index, _) => format!("{}[{}]", name.name, expression(index)),
+        Term::Call(call) => self::call(call),
+        Term::Parenthesized(expression, _) => format!("({})", self::expression(expression)),
+        Term::Unary(op, term, _) => format!("{}{}", op.symbol(), self::term(term)),
+    }
+}
+
+// The name of the subroutine a call calls, with its receiver.
+fn callee(call: &SubroutineCall) -> String {
+    match &call.receiver {
+        Some(receiver) => format!("{}.{}", receiver.name, call.name.name),
+        None => call.name.name.clone(),
+    }
+}
+
+fn call(call: &SubroutineCall) -> String {
+    let arguments = call.arguments.iter().map(expression).collect::<Vec<_>>();
+    format!("{}({})", callee(call), arguments.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{os, parser::parse, xml};
+
+    #[test]
+    fn format_reprints_in_the_os_style() {
+        let source = "// Counts.
+class  Main{
+  field int count,total ; static boolean on;
+  /** Adds
+      things up. */
+  method void add(int n,Array a){var int i;
+    let count=count+(-n*2);let a[i]=~on;  // Both.
+
+
+    if(on){do Output.printInt(count);}else{
+        // Nothing.
+    }
+    while (i<n) { let i = i+1; } // Loop.
+    return;
+  }
+  function Main new() { return this; }
+}
+";
+        let formatted = "// Counts.
+class Main {
+    field int count, total;
+    static boolean on;
+
+    /** Adds
+        things up. */
+    method void add(int n, Array a) {
+        var int i;
+        let count = count + (-n * 2);
+        let a[i] = ~on; // Both.
+
+        if (on) {
+            do Output.printInt(count);
+        } else {
+            // Nothing.
+        }
+        while (i < n) {
+            let i = i + 1;
+        } // Loop.
+        return;
+    }
+
+    function Main new() {
+        return this;
+    }
+}
+";
+        assert_eq!(format(source).unwrap(), formatted);
+        assert_eq!(format(formatted).unwrap(), formatted);
+        assert_eq!(
+            xml::class(&parse(source).unwrap()),
+            xml::class(&parse(formatted).unwrap())
+        );
+        assert!(format("class Main { let }").is_err());
+    }
+
+    #[test]
+    fn format_breaks_long_lines_evenly() {
+        let source = "class Main { function void draw(int left, int top, int right, int bottom, \
+            int color, boolean fill, int pattern) { if ((left < 0) | (top < 0) | (right > 511) | \
+            (bottom > 255) | (left > right) | (top > bottom)) { do Screen.drawRectangle(left + 1, \
+            top + 1, right - 1, bottom - 1, color, pattern, fill, true); } return; } }";
+        let formatted = "class Main {
+    function void draw(int left, int top, int right, int bottom,
+                       int color, boolean fill, int pattern) {
+        if ((left < 0) | (top < 0) | (right > 511)
+            | (bottom > 255) | (left > right) | (top > bottom)) {
+            do Screen.drawRectangle(left + 1, top + 1, right - 1,
+                                    bottom - 1, color, pattern, fill, true);
+        }
+        return;
+    }
+}
+";
+        assert_eq!(format(source).unwrap(), formatted);
+    }
+
+    #[test]
+    fn format_separates_subroutines_but_not_the_first_of_a_class() {
+        let source = "class Main {
+
+            function void a() { return; }
+            function void b() { return; }
+        }";
+        let formatted = "class Main {
+    function void a() {
+        return;
+    }
+
+    function void b() {
+        return;
+    }
+}
+";
+        assert_eq!(format(source).unwrap(), formatted);
+    }
+
+    #[test]
+    fn format_leaves_the_os_unchanged() {
+        for (name, source) in &os::SOURCES {
+            assert_eq!(&format(source).unwrap(), source, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use std::fmt;
+
+use crate::{ast::ClassVarKind, os, parser::parse};
+
+pub const HEAP: u16 = 2048;
+pub const SCREEN: u16 = 16384;
+
+// The symbol of the static holding the OS allocator's free list, under which
+// the symbol files of both translated VM code and the direct backend list
+// its address.
+pub fn free_list_symbol() -> String {
+    let (_, source) = os::SOURCES
+        .iter()
+        .find(|(name, _)| *name == "Memory")
+        .expect("the OS has a Memory class");
+    let memory = parse(source).expect("the OS parses");
+    let index = memory
+        .variables
+        .iter()
+        .filter(|variable| variable.kind == ClassVarKind::Static)
+        .flat_map(|variable| &variable.names)
+        .position(|name| name.name == "freeList")
+        .expect("Memory keeps a free list");
+    format!("Memory.{}", index)
+}
+
+// A segment of the heap: its header's address and length, which counts the
+// header. Allocated objects start after the header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Block {
+    pub address: u16,
+    pub length: u16,
+    pub free: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Problem {
+    // A header whose length is zero or runs past the heap, which ends the
+    // walk through the blocks.
+    BadLength { address: u16, length: u16 },
+    // A free list entry inside a block rather than at its start.
+    Overlap { free: u16, block: u16 },
+    OutsideHeap { free: u16 },
+    // A free list entry at or before the one pointing to it, which would
+    // loop or lose the order that merging relies on.
+    Unordered { free: u16, previous: u16 },
+    // Neighbouring free blocks, which deallocation would have merged.
+    Unmerged { first: u16, second: u16 },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::BadLength { address, length } => write!(
+                f,
+                "The block at {} has length {}, which does not fit in the heap",
+                address, length
+            ),
+            Problem::Overlap { free, block } => write!(
+                f,
+                "The free list entry {} is inside the block at {}",
+                free, block
+            ),
+            Problem::OutsideHeap { free } => {
+                write!(f, "The free list entry {} is outside the heap", free)
+            }
+            Problem::Unordered { free, previous } => {
+                write!(f, "The free list goes back from {} to {}", previous, free)
+            }
+            Problem::Unmerged { first, second } => write!(
+                f,
+                "The free blocks at {} and {} are neighbours",
+                first, second
+            ),
+        }
+    }
+}
+
+// The blocks of the heap, in address order, and what is wrong with them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Heap {
+    pub blocks: Vec<Block>,
+    pub problems: Vec<Problem>,
+}
+
+impl Heap {
+    // Walks the blocks from their headers, then marks those on the free
+    // list starting at `free_list`.
+    pub fn walk(mut read: impl FnMut(u16) -> u16, free_list: u16) -> Self {
+        let mut heap = Heap::default();
+        let mut address = HEAP;
+        while address < SCREEN {
+            let length = read(address);
+            if length == 0 || length > SCREEN - address {
+                heap.problems.push(Problem::BadLength { address, length });
+                break;
+            }
+            heap.blocks.push(Block {
+                address,
+                length,
+                free: false,
+            });
+            address += length;
+        }
+        let mut previous = None;
+        let mut free = free_list;
+        while free != 0 {
+            if let Some(previous) = previous.filter(|&previous| free <= previous) {
+                heap.problems.push(Problem::Unordered { free, previous });
+                break;
+            }
+            if !(HEAP..SCREEN).contains(&free) {
+                heap.problems.push(Problem::OutsideHeap { free });
+                break;
+            }
+            match heap
+                .blocks
+                .binary_search_by_key(&free, |block| block.address)
+            {
+                Ok(i) => heap.blocks[i].free = true,
+                Err(0) => {}
+                Err(i) => {
+                    let block = heap.blocks[i - 1];
+                    if free < block.address + block.length {
+                        heap.problems.push(Problem::Overlap {
+                            free,
+                            block: block.address,
+                        });
+                    }
+                }
+            }
+            previous = Some(free);
+            free = read(free + 1);
+        }
+        for pair in heap.blocks.windows(2) {
+            if pair[0].free && pair[1].free {
+                heap.problems.push(Problem::Unmerged {
+                    first: pair[0].address,
+                    second: pair[1].address,
+                });
+            }
+        }
+        heap
+    }
+
+    pub fn allocated(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.iter().filter(|block| !block.free)
+    }
+
+    pub fn free(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.iter().filter(|block| block.free)
+    }
+
+    // The share of free words outside the largest free block, which no
+    // single allocation can use together.
+    pub fn fragmentation(&self) -> f64 {
+        let total = self.free().map(|block| block.length as u32).sum::<u32>();
+        let largest = self.free().map(|block| block.length).max().unwrap_or(0);
+        match total {
+            0 => 0.0,
+            _ => 1.0 - largest as f64 / total as f64,
+        }
+    }
+}
+
+// Lists the allocated blocks by the address of their object, then sums up
+// the heap and its problems.
+impl fmt::Display for Heap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for block in self.allocated() {
+            writeln!(f, "{:>6} {:>6} words", block.address + 1, block.length - 1)?;
+        }
+        let words = |blocks: &mut dyn Iterator<Item = &Block>| {
+            blocks.fold((0, 0), |(count, words), block| {
+                (count + 1, words + block.length as u32)
+            })
+        };
+        let (allocated, allocated_words) = words(&mut self.allocated());
+        let (free, free_words) = words(&mut self.free());
+        writeln!(
+            f,
+            "{} allocated blocks of {} words, {} free blocks of {} words",
+            allocated, allocated_words, free, free_words
+        )?;
+        writeln!(
+            f,
+            "Largest free block: {} words, fragmentation {:.1}%",
+            self.free().map(|block| block.length).max().unwrap_or(0),
+            self.fragmentation() * 100.0
+        )?;
+        for problem in &self.problems {
+            writeln!(f, "{}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ast::Class,
+        codegen::compile,
+        direct::{compile_program, run, symbol_file},
+    };
+    use computer::{keyboard::DummyKeyboard, screen::DummyScreen, Computer};
+    use vm::loader::translate;
+
+    #[test]
+    fn heap_of_a_running_program_lists_its_blocks() {
+        let main = "class Main {
+            function void main() {
+                var Array a, b, c;
+                let a = Array.new(10);
+                let b = Array.new(20);
+                let c = Array.new(5);
+                do b.dispose();
+                return;
+            }
+        }";
+        let sys = "class Sys {
+            function void init() {
+                do Memory.init();
+                do Main.main();
+                do Sys.halt();
+                return;
+            }
+            function void halt() { while (true) {} return; }
+            function void error(int code) { do Sys.halt(); return; }
+        }";
+        let mut classes: Vec<Class> = os::classes()
+            .into_iter()
+            .filter(|class| ["Memory", "Array"].contains(&class.name.name.as_str()))
+            .collect();
+        classes.push(parse(main).unwrap());
+        classes.push(parse(sys).unwrap());
+        let modules: Vec<_> = classes
+            .iter()
+            .map(|class| compile(class).unwrap())
+            .collect();
+        let program = translate(&modules, true);
+        let (_, symbols) = program.assemble();
+        let pointer = symbols.get(&free_list_symbol()).unwrap();
+        let mut computer = Computer::<DummyScreen, DummyKeyboard>::new();
+        run(&mut computer, &program, 100_000).expect("the program halts");
+        let free_list = computer.peek(pointer);
+        let heap = Heap::walk(|address| computer.peek(address), free_list);
+        let block = |address, length, free| Block {
+            address,
+            length,
+            free,
+        };
+        assert_eq!(
+            heap.blocks,
+            [
+                block(2048, 14298, true),
+                block(16346, 6, false),
+                block(16352, 21, true),
+                block(16373, 11, false),
+            ]
+        );
+        assert!(heap.problems.is_empty());
+        assert!((heap.fragmentation() - 21.0 / 14319.0).abs() < 1e-9);
+        let report = heap.to_string();
+        assert!(report.starts_with(" 16347      5 words\n 16374     10 words\n"));
+        assert!(report.contains("2 allocated blocks of 17 words, 2 free blocks of 14319 words"));
+        assert!(report.contains("fragmentation 0.1%"));
+
+        // The direct backend lists the free list under the same symbol.
+        let program = compile_program(&classes, false).unwrap();
+        let symbols = symbol_file(&classes, &program);
+        let pointer = symbols
+            .variables
+            .iter()
+            .find(|(name, _)| *name == free_list_symbol())
+            .map(|&(_, address)| address)
+            .unwrap();
+        let mut computer = Computer::<DummyScreen, DummyKeyboard>::new();
+        run(&mut computer, &program, 100_000).expect("the program halts");
+        let free_list = computer.peek(pointer);
+        let direct = Heap::walk(|address| computer.peek(address), free_list);
+        assert_eq!(direct.blocks, heap.blocks);
+    }
+
+    #[test]
+    fn heap_walk_reports_corruption() {
+        let heap = |words: &[(u16, u16)], free_list| {
+            let mut ram = vec![0; SCREEN as usize];
+            for &(address, value) in words {
+                ram[address as usize] = value;
+            }
+            Heap::walk(|address| ram[address as usize], free_list).problems
+        };
+        // A free list running into an allocated block and then backwards.
+        let problems = heap(
+            &[(2048, 10), (2049, 2060), (2058, 14326), (2061, 2048)],
+            2048,
+        );
+        assert_eq!(
+            problems,
+            [
+                Problem::Overlap {
+                    free: 2060,
+                    block: 2058
+                },
+                Problem::Unordered {
+                    free: 2048,
+                    previous: 2060
+                },
+            ]
+        );
+        // A header running past the screen, and free neighbours.
+        let problems = heap(&[(2048, 10), (2049, 2058), (2058, 10), (2068, 20000)], 2048);
+        assert_eq!(
+            problems,
+            [
+                Problem::BadLength {
+                    address: 2068,
+                    length: 20000
+                },
+                Problem::Unmerged {
+                    first: 2048,
+                    second: 2058
+                },
+            ]
+        );
+        assert_eq!(
+            heap(&[(2048, 14336)], 100),
+            [Problem::OutsideHeap { free: 100 }]
+        );
+        assert_eq!(
+            Problem::Unordered {
+                free: 2048,
+                previous: 2060
+            }
+            .to_string(),
+            "The free list goes back from 2060 to 2048"
+        );
+    }
+}
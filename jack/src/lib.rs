@@ -0,0 +1,16 @@
+pub mod ast;
+pub mod checker;
+pub mod codegen;
+pub mod debug;
+pub mod direct;
+pub mod format;
+pub mod heap;
+pub mod optimize;
+pub mod os;
+pub mod parser;
+pub mod span;
+pub mod symbols;
+pub mod testing;
+pub mod token;
+pub mod tokenizer;
+pub mod xml;
@@ -0,0 +1,383 @@
+use std::{
+    error::Error,
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+
+use clap::{
+    app_from_crate, crate_authors, crate_description, crate_name, crate_version, Arg, ArgMatches,
+};
+
+use computer::{keyboard::DummyKeyboard, screen::DummyScreen, Computer};
+use jack::{
+    ast::Class,
+    checker::{CheckError, Checker},
+    codegen::{compile_debug, CompileError},
+    debug::DebugInfo,
+    direct::{self, compile_program},
+    format::format,
+    optimize::optimize,
+    os,
+    parser::parse_tokens,
+    testing,
+    token::Dialect,
+    tokenizer::Tokenizer,
+    xml,
+};
+use vm::{
+    loader::{translate, Module},
+    optimize::remove_unreachable,
+};
+
+// A .jack file, or every .jack file in a directory in name order.
+fn sources(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    files.retain(|file| file.is_file() && file.extension().is_some_and(|ext| ext == "jack"));
+    files.sort();
+    Ok(files)
+}
+
+// A directory Prog/ stands for the file Prog/Prog.
+fn output_stem(path: &Path) -> io::Result<PathBuf> {
+    if path.is_dir() {
+        let name = path
+            .canonicalize()?
+            .file_name()
+            .unwrap_or_default()
+            .to_owned();
+        Ok(path.join(name))
+    } else {
+        Ok(path.to_path_buf())
+    }
+}
+
+// xxx.jack has its outputs named xxx<suffix>.
+fn output_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}{}", stem, suffix))
+}
+
+// Parses one source file and writes its XML outputs, failing with the errors
+// found in it.
+fn parse_file(
+    path: &Path,
+    args: &ArgMatches,
+) -> Result<Result<Class, Vec<String>>, Box<dyn Error>> {
+    let source = fs::read_to_string(path)?;
+    let dialect = if args.is_present("extended") {
+        Dialect::Extended
+    } else {
+        Dialect::Standard
+    };
+    let mut tokens = vec![];
+    let mut errors = vec![];
+    for result in Tokenizer::with_dialect(&source, dialect) {
+        match result {
+            Ok(token) => tokens.push(token),
+            Err(error) => errors.push(error.to_string()),
+        }
+    }
+    if args.is_present("tokens") {
+        fs::write(output_path(path, "T.xml"), xml::tokens(&tokens))?;
+    }
+    // Tokens that failed to scan would only add follow-on parse errors.
+    if !errors.is_empty() {
+        return Ok(Err(errors));
+    }
+    let class = match parse_tokens(&tokens, dialect) {
+        (Some(class), errors) if errors.is_empty() => class,
+        (_, errors) => return Ok(Err(errors.iter().map(ToString::to_string).collect())),
+    };
+    if args.is_present("xml") {
+        fs::write(output_path(path, ".xml"), xml::class(&class))?;
+    }
+    Ok(Ok(class))
+}
+
+// Formats the files in place, or with `check` lists those that aren't
+// formatted, failing if any of them are or don't parse.
+fn format_files(path: &Path, check: bool) -> Result<(), Box<dyn Error>> {
+    let mut failed = false;
+    for path in sources(path)? {
+        let source = fs::read_to_string(&path)?;
+        match format(&source) {
+            Ok(formatted) if formatted == source => {}
+            Ok(formatted) if check => {
+                let line = source
+                    .lines()
+                    .zip(formatted.lines())
+                    .take_while(|(line, formatted)| line == formatted)
+                    .count();
+                println!("{}:{}: Not formatted", path.display(), line + 1);
+                failed = true;
+            }
+            Ok(formatted) => fs::write(&path, formatted)?,
+            Err(errors) => {
+                for error in errors {
+                    eprintln!("{}:{}", path.display(), error);
+                }
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+// Reports the errors of compiling the program straight into Hack, which are
+// given by class, and fails.
+fn report_direct(paths: &[PathBuf], classes: &[Class], errors: Vec<(String, CompileError)>) -> ! {
+    for (class, error) in errors {
+        let path = paths
+            .iter()
+            .zip(classes)
+            .find(|(_, other)| other.name.name == class)
+            .map_or_else(
+                || PathBuf::from(format!("{}.jack", class)),
+                |(path, _)| path.clone(),
+            );
+        eprintln!("{}:{}", path.display(), error);
+    }
+    process::exit(1);
+}
+
+fn write_vm(path: &Path, module: &Module) -> io::Result<()> {
+    let mut vm = String::new();
+    for command in &module.commands {
+        vm.push_str(&format!("{}\n", command));
+    }
+    fs::write(path, vm)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = app_from_crate!()
+        .arg(
+            Arg::with_name("path")
+                .help("The Jack file, or a directory of Jack files, to compile into .vm files")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("tokens")
+                .long("tokens")
+                .short("t")
+                .help("Write the tokens of each file into xxxT.xml"),
+        )
+        .arg(
+            Arg::with_name("xml")
+                .long("xml")
+                .short("x")
+                .help("Write the parse tree of each file into xxx.xml"),
+        )
+        .arg(
+            Arg::with_name("extended")
+                .long("extended")
+                .short("e")
+                .help("Accept for loops, break and continue, const declarations, compound assignment, else if, character literals and * and / binding tighter than + and -"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .short("f")
+                .conflicts_with("extended")
+                .help("Rewrite each file in the canonical style instead of compiling it"),
+        )
+        .arg(
+            Arg::with_name("check")
+                .long("check")
+                .requires("format")
+                .help("Leave the files as they are, listing the first line of each that --format would change"),
+        )
+        .arg(
+            Arg::with_name("no-check")
+                .long("no-check")
+                .help("Compile without checking the program for semantic errors"),
+        )
+        .arg(
+            Arg::with_name("optimize")
+                .long("optimize")
+                .short("O")
+                .help("Fold constants, inline small functions and multiply by constants in place"),
+        )
+        .arg(
+            Arg::with_name("no-os")
+                .long("no-os")
+                .help("Do not write the .vm files of the OS classes the program uses"),
+        )
+        .arg(
+            Arg::with_name("debug")
+                .long("debug")
+                .short("g")
+                .conflicts_with("hack")
+                .help("Also write xxx.dbg with the Jack line of each VM command and where each subroutine keeps its variables"),
+        )
+        .arg(
+            Arg::with_name("hack")
+                .long("hack")
+                .conflicts_with("no-os")
+                .help("Compile the program and the OS straight into one .asm file instead of .vm files, with its labels and statics in a .sym file"),
+        )
+        .arg(
+            Arg::with_name("compare")
+                .long("compare")
+                .help("Print the ROM size and the cycles to Sys.halt of the program compiled through VM code and straight into Hack"),
+        )
+        .arg(
+            Arg::with_name("test")
+                .long("test")
+                .conflicts_with_all(&["hack", "compare", "debug"])
+                .help("Run the test functions of the classes named xxxTest, each on a fresh computer, instead of writing .vm files"),
+        )
+        .arg(
+            Arg::with_name("max-cycles")
+                .long("max-cycles")
+                .takes_value(true)
+                .help("Stop running each program or test after this many cycles [default: 100000000]"),
+        )
+        .get_matches();
+
+    if args.is_present("format") {
+        return format_files(
+            Path::new(args.value_of("path").unwrap()),
+            args.is_present("check"),
+        );
+    }
+
+    let mut failed = false;
+    let mut report = |path: &Path, errors: &[String]| {
+        for error in errors {
+            eprintln!("{}:{}", path.display(), error);
+        }
+        failed |= !errors.is_empty();
+    };
+    let mut paths = vec![];
+    let mut classes = vec![];
+    for path in sources(Path::new(args.value_of("path").unwrap()))? {
+        match parse_file(&path, &args)? {
+            Ok(class) => {
+                paths.push(path);
+                classes.push(class);
+            }
+            Err(errors) => report(&path, &errors),
+        }
+    }
+
+    // Calls are checked against every class that parsed, and the OS.
+    let tests = args.is_present("test");
+    let mut program = os::classes();
+    if tests {
+        program.push(testing::assert_class());
+    }
+    program.extend(classes.iter().cloned());
+    let checker = Checker::new(&program);
+    // Inlining works across the whole program, so it's optimized as one.
+    let optimized = args.is_present("optimize");
+    let hack = args.is_present("hack");
+    if optimized {
+        optimize(&mut program);
+    }
+    let mut modules = vec![];
+    let mut debug_info = DebugInfo::new();
+    let os_classes = program.len() - classes.len();
+    for ((path, class), program_class) in paths.iter().zip(&classes).zip(&program[os_classes..]) {
+        if !args.is_present("no-check") {
+            let (warnings, errors): (Vec<_>, Vec<_>) = checker
+                .check(class)
+                .into_iter()
+                .partition(CheckError::is_warning);
+            for warning in warnings {
+                eprintln!("{}:{} (warning)", path.display(), warning);
+            }
+            if !errors.is_empty() {
+                report(
+                    path,
+                    &errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+                );
+                continue;
+            }
+        }
+        let class = if optimized { program_class } else { class };
+        match compile_debug(class, optimized, &path.display().to_string()) {
+            Ok((module, info)) => {
+                if !(hack || tests) {
+                    write_vm(&path.with_extension("vm"), &module)?;
+                }
+                modules.push(module);
+                debug_info.classes.push(info);
+            }
+            Err(errors) => report(
+                path,
+                &errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            ),
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+    if args.is_present("debug") {
+        let stem = output_stem(Path::new(args.value_of("path").unwrap()))?;
+        fs::write(stem.with_extension("dbg"), debug_info.to_string())?;
+    }
+
+    // The OS goes next to the program, as if copied there.
+    let no_os = args.is_present("no-os") || hack || tests;
+    if let (Some(path), false) = (paths.first(), no_os) {
+        for module in os::link(&modules, optimized) {
+            write_vm(&path.with_file_name(format!("{}.vm", module.name)), &module)?;
+        }
+    }
+
+    let max_cycles = args
+        .value_of("max-cycles")
+        .map(str::parse)
+        .transpose()?
+        .unwrap_or(100_000_000);
+    if tests {
+        let tests = testing::discover(&classes);
+        let mut failures = 0;
+        for test in &tests {
+            let outcome = testing::run(&program, test, optimized, max_cycles)
+                .unwrap_or_else(|errors| report_direct(&paths, &classes, errors));
+            println!("{} {}", test, outcome);
+            failures += !outcome.passed() as usize;
+        }
+        println!("{} passed, {} failed", tests.len() - failures, failures);
+        if failures > 0 {
+            process::exit(1);
+        }
+        return Ok(());
+    }
+    if !(hack || args.is_present("compare")) {
+        return Ok(());
+    }
+
+    let direct = compile_program(&program, optimized)
+        .unwrap_or_else(|errors| report_direct(&paths, &classes, errors));
+    if hack {
+        let stem = output_stem(Path::new(args.value_of("path").unwrap()))?;
+        fs::write(stem.with_extension("asm"), direct.to_string())?;
+        let symbols = direct::symbol_file(&program, &direct);
+        fs::write(stem.with_extension("sym"), symbols.to_string())?;
+    }
+    if args.is_present("compare") {
+        modules.extend(os::link(&modules, optimized));
+        let vm = translate(&remove_unreachable(&modules, true), true);
+        println!("{:>6} {:>10}", "ROM", "cycles");
+        for (name, program) in &[("VM", vm), ("Hack", direct)] {
+            let mut computer = Computer::<DummyScreen, DummyKeyboard>::new();
+            let cycles = match direct::run(&mut computer, program, max_cycles) {
+                Some(cycles) => cycles.to_string(),
+                None => "-".to_string(),
+            };
+            println!("{:>6} {:>10} {}", program.len(), cycles, name);
+        }
+    }
+    Ok(())
+}
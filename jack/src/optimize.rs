@@ -0,0 +1,610 @@
+use std::collections::{HashMap, HashSet};
+
+use vm::command::Arithmetic;
+
+use crate::ast::{
+    BinaryOp, Class, Expression, KeywordConstant, Statement, Subroutine, SubroutineCall,
+    SubroutineKind, Term, UnaryOp,
+};
+
+// The largest function body, counted in terms, that is inlined.
+const INLINE_TERMS: usize = 8;
+
+pub fn apply(op: BinaryOp, x: u16, y: u16) -> Option<u16> {
+    let arithmetic = match op {
+        BinaryOp::Mul => return Some((x as i16).wrapping_mul(y as i16) as u16),
+        BinaryOp::Div if y == 0 => return None,
+        BinaryOp::Div => return Some((x as i16).wrapping_div(y as i16) as u16),
+        BinaryOp::Add => Arithmetic::Add,
+        BinaryOp::Sub => Arithmetic::Sub,
+        BinaryOp::And => Arithmetic::And,
+        BinaryOp::Or => Arithmetic::Or,
+        BinaryOp::Lt => Arithmetic::Lt,
+        BinaryOp::Gt => Arithmetic::Gt,
+        BinaryOp::Eq => Arithmetic::Eq,
+    };
+    Some(arithmetic.apply(x, y))
+}
+
+// The value of a term known at compile time.
+pub fn constant(term: &Term) -> Option<u16> {
+    match term {
+        Term::Integer(value, _) => Some(*value),
+        Term::Keyword(KeywordConstant::True, _) => Some(0xFFFF),
+        Term::Keyword(KeywordConstant::False, _) | Term::Keyword(KeywordConstant::Null, _) => {
+            Some(0)
+        }
+        Term::Parenthesized(expression, _) => constant_expression(expression),
+        Term::Unary(op, term, _) => constant(term).map(|value| match op {
+            UnaryOp::Neg => value.wrapping_neg(),
+            UnaryOp::Not => !value,
+        }),
+        _ => None,
+    }
+}
+
+pub fn constant_expression(expression: &Expression) -> Option<u16> {
+    expression
+        .rest
+        .iter()
+        .try_fold(constant(&expression.first)?, |x, (op, term)| {
+            apply(*op, x, constant(term)?)
+        })
+}
+
+// The variables a statement may assign.
+fn assigned<'a>(statements: &'a [Statement], names: &mut HashSet<&'a str>) {
+    for statement in statements {
+        match statement {
+            Statement::Let {
+                name, index: None, ..
+            } => {
+                names.insert(&name.name);
+            }
+            Statement::If {
+                then, otherwise, ..
+            } => {
+                assigned(then, names);
+                if let Some(otherwise) = otherwise {
+                    assigned(otherwise, names);
+                }
+            }
+            Statement::While { body, .. } => assigned(body, names),
+            _ => {}
+        }
+    }
+}
+
+// Folds constant expressions, replacing the locals and arguments whose
+// values are known, and drops the branches that can never run.
+struct Folder {
+    // The locals and arguments, which only the subroutine itself can change.
+    scalars: HashSet<String>,
+    known: HashMap<String, u16>,
+}
+
+impl Folder {
+    fn statements(&mut self, statements: Vec<Statement>) -> Vec<Statement> {
+        let mut folded = vec![];
+        for statement in statements {
+            self.statement(statement, &mut folded);
+        }
+        folded
+    }
+
+    fn statement(&mut self, statement: Statement, folded: &mut Vec<Statement>) {
+        match statement {
+            Statement::Let {
+                name,
+                mut index,
+                mut value,
+                span,
+            } => {
+                if let Some(index) = &mut index {
+                    self.expression(index);
+                }
+                self.expression(&mut value);
+                if index.is_none() && self.scalars.contains(&name.name) {
+                    match constant_expression(&value) {
+                        Some(value) => self.known.insert(name.name.clone(), value),
+                        None => self.known.remove(&name.name),
+                    };
+                }
+                folded.push(Statement::Let {
+                    name,
+                    index,
+                    value,
+                    span,
+                });
+            }
+            Statement::If {
+                mut condition,
+                then,
+                otherwise,
+                span,
+            } => {
+                self.expression(&mut condition);
+                match constant_expression(&condition) {
+                    Some(0) => {
+                        for statement in otherwise.into_iter().flatten() {
+                            self.statement(statement, folded);
+                        }
+                    }
+                    Some(_) => {
+                        for statement in then {
+                            self.statement(statement, folded);
+                        }
+                    }
+                    None => {
+                        // Only what both branches agree on is known after.
+                        let known = self.known.clone();
+                        let then = self.statements(then);
+                        let after_then = std::mem::replace(&mut self.known, known);
+                        let otherwise = otherwise.map(|otherwise| self.statements(otherwise));
+                        self.known
+                            .retain(|name, value| after_then.get(name) == Some(value));
+                        folded.push(Statement::If {
+                            condition,
+                            then,
+                            otherwise,
+                            span,
+                        });
+                    }
+                }
+            }
+            Statement::While {
+                mut condition,
+                body,
+                span,
+            } => {
+                let mut names = HashSet::new();
+                assigned(&body, &mut names);
+                for name in names {
+                    self.known.remove(name);
+                }
+                self.expression(&mut condition);
+                if constant_expression(&condition) == Some(0) {
+                    return;
+                }
+                let known = self.known.clone();
+                let body = self.statements(body);
+                self.known = known;
+                folded.push(Statement::While {
+                    condition,
+                    body,
+                    span,
+                });
+            }
+            Statement::Do { mut call, span } => {
+                self.call(&mut call);
+                folded.push(Statement::Do { call, span });
+            }
+            Statement::Return { mut value, span } => {
+                if let Some(value) = &mut value {
+                    self.expression(value);
+                }
+                folded.push(Statement::Return { value, span });
+            }
+            Statement::Break { .. } | Statement::Continue { .. } => folded.push(statement),
+        }
+    }
+
+    fn call(&mut self, call: &mut SubroutineCall) {
+        for argument in &mut call.arguments {
+            self.expression(argument);
+        }
+    }
+
+    // Folds the constant terms at the start of the expression, since Jack
+    // evaluates from left to right.
+    fn expression(&mut self, expression: &mut Expression) {
+        self.term(&mut expression.first);
+        for (_, term) in &mut expression.rest {
+            self.term(term);
+        }
+        while let Some((op, term)) = expression.rest.first() {
+            let value = match (constant(&expression.first), constant(term)) {
+                (Some(x), Some(y)) => apply(*op, x, y),
+                _ => None,
+            };
+            match value {
+                Some(value) => {
+                    let span = expression.first.span().to(term.span());
+                    expression.first = Term::Integer(value, span);
+                    expression.rest.remove(0);
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn term(&mut self, term: &mut Term) {
+        match term {
+            Term::Variable(name) => {
+                if let Some(&value) = self.known.get(&name.name) {
+                    *term = Term::Integer(value, name.span);
+                }
+            }
+            Term::Index(_, index, _) => self.expression(index),
+            Term::Call(call) => self.call(call),
+            Term::Parenthesized(expression, _) => {
+                self.expression(expression);
+                if expression.rest.is_empty() {
+                    *term = expression.first.clone();
+                }
+            }
+            Term::Unary(_, inner, span) => {
+                self.term(inner);
+                let span = *span;
+                if let Some(value) = constant(term) {
+                    *term = Term::Integer(value, span);
+                }
+            }
+            Term::Integer(..) | Term::String(..) | Term::Keyword(..) => {}
+        }
+    }
+}
+
+// Calls f on every term of the expression, nested ones included.
+fn each_term(expression: &Expression, f: &mut dyn FnMut(&Term)) {
+    for term in std::iter::once(&expression.first).chain(expression.rest.iter().map(|(_, t)| t)) {
+        each(term, f);
+    }
+}
+
+fn each(term: &Term, f: &mut dyn FnMut(&Term)) {
+    f(term);
+    match term {
+        Term::Index(_, index, _) => each_term(index, f),
+        Term::Call(call) => {
+            for argument in &call.arguments {
+                each_term(argument, f);
+            }
+        }
+        Term::Parenthesized(expression, _) => each_term(expression, f),
+        Term::Unary(_, term, _) => each(term, f),
+        Term::Integer(..) | Term::String(..) | Term::Keyword(..) | Term::Variable(_) => {}
+    }
+}
+
+// A function that only returns an expression of its parameters.
+struct Inlinable {
+    parameters: Vec<String>,
+    body: Expression,
+    // The parameters used as arrays, which must be passed variables.
+    indexed: HashSet<String>,
+}
+
+fn inlinable(subroutine: &Subroutine) -> Option<Inlinable> {
+    let body = match subroutine.body.as_slice() {
+        [Statement::Return {
+            value: Some(body), ..
+        }] => body,
+        _ => return None,
+    };
+    if subroutine.kind != SubroutineKind::Function || !subroutine.locals.is_empty() {
+        return None;
+    }
+    let parameters: Vec<String> = subroutine
+        .parameters
+        .iter()
+        .map(|parameter| parameter.name.name.clone())
+        .collect();
+    // Statics are out of reach from other classes, and calls and strings
+    // would be evaluated anew at every use of a parameter.
+    let mut terms = 0;
+    let mut simple = true;
+    let mut indexed = HashSet::new();
+    each_term(body, &mut |term| {
+        terms += 1;
+        match term {
+            Term::Variable(name) => simple &= parameters.contains(&name.name),
+            Term::Index(name, _, _) => {
+                simple &= parameters.contains(&name.name);
+                indexed.insert(name.name.clone());
+            }
+            Term::Call(_) | Term::String(..) | Term::Keyword(KeywordConstant::This, _) => {
+                simple = false
+            }
+            _ => {}
+        }
+    });
+    if !simple || terms > INLINE_TERMS {
+        return None;
+    }
+    Some(Inlinable {
+        parameters,
+        body: body.clone(),
+        indexed,
+    })
+}
+
+fn substitute(expression: &mut Expression, arguments: &HashMap<&str, Term>) {
+    substitute_term(&mut expression.first, arguments);
+    for (_, term) in &mut expression.rest {
+        substitute_term(term, arguments);
+    }
+}
+
+fn substitute_term(term: &mut Term, arguments: &HashMap<&str, Term>) {
+    match term {
+        Term::Variable(name) => *term = arguments[name.name.as_str()].clone(),
+        Term::Index(name, index, _) => {
+            substitute(index, arguments);
+            if let Term::Variable(argument) = &arguments[name.name.as_str()] {
+                *name = argument.clone();
+            }
+        }
+        Term::Parenthesized(expression, _) => substitute(expression, arguments),
+        Term::Unary(_, term, _) => substitute_term(term, arguments),
+        _ => {}
+    }
+}
+
+// Replaces calls to inlinable functions whose arguments are free to evaluate
+// more than once, or not at all.
+struct Inliner<'a> {
+    functions: &'a HashMap<String, Inlinable>,
+    // The variables in scope, which shadow class names as call receivers.
+    variables: HashSet<String>,
+}
+
+impl<'a> Inliner<'a> {
+    fn statements(&self, statements: &mut [Statement]) {
+        for statement in statements {
+            match statement {
+                Statement::Let { index, value, .. } => {
+                    if let Some(index) = index {
+                        self.expression(index);
+                    }
+                    self.expression(value);
+                }
+                Statement::If {
+                    condition,
+                    then,
+                    otherwise,
+                    ..
+                } => {
+                    self.expression(condition);
+                    self.statements(then);
+                    if let Some(otherwise) = otherwise {
+                        self.statements(otherwise);
+                    }
+                }
+                Statement::While {
+                    condition, body, ..
+                } => {
+                    self.expression(condition);
+                    self.statements(body);
+                }
+                Statement::Do { call, .. } => self.arguments(call),
+                Statement::Return { value, .. } => {
+                    if let Some(value) = value {
+                        self.expression(value);
+                    }
+                }
+                Statement::Break { .. } | Statement::Continue { .. } => {}
+            }
+        }
+    }
+
+    fn arguments(&self, call: &mut SubroutineCall) {
+        for argument in &mut call.arguments {
+            self.expression(argument);
+        }
+    }
+
+    fn expression(&self, expression: &mut Expression) {
+        self.term(&mut expression.first);
+        for (_, term) in &mut expression.rest {
+            self.term(term);
+        }
+    }
+
+    fn term(&self, term: &mut Term) {
+        match term {
+            Term::Index(_, index, _) => self.expression(index),
+            Term::Call(call) => {
+                self.arguments(call);
+                if let Some(inlined) = self.inline(call) {
+                    *term = inlined;
+                }
+            }
+            Term::Parenthesized(expression, _) => self.expression(expression),
+            Term::Unary(_, term, _) => self.term(term),
+            Term::Integer(..) | Term::String(..) | Term::Keyword(..) | Term::Variable(_) => {}
+        }
+    }
+
+    fn inline(&self, call: &SubroutineCall) -> Option<Term> {
+        let class = call
+            .receiver
+            .as_ref()
+            .filter(|receiver| !self.variables.contains(&receiver.name))?;
+        let function = self
+            .functions
+            .get(&format!("{}.{}", class.name, call.name.name))?;
+        if function.parameters.len() != call.arguments.len() {
+            return None;
+        }
+        let mut arguments = HashMap::new();
+        for (parameter, argument) in function.parameters.iter().zip(&call.arguments) {
+            let argument = match argument.rest.is_empty() {
+                true => &argument.first,
+                false => return None,
+            };
+            let simple = match argument {
+                Term::Variable(_) => true,
+                _ if function.indexed.contains(parameter) => false,
+                Term::Keyword(..) => true,
+                _ => constant(argument).is_some(),
+            };
+            if !simple {
+                return None;
+            }
+            arguments.insert(parameter.as_str(), argument.clone());
+        }
+        let mut body = function.body.clone();
+        substitute(&mut body, &arguments);
+        Some(Term::Parenthesized(Box::new(body), call.span))
+    }
+}
+
+// Inlines small functions across the classes, then folds the constants in
+// every subroutine. The classes are the whole program, OS included.
+pub fn optimize(classes: &mut [Class]) {
+    let mut functions = HashMap::new();
+    for class in classes.iter() {
+        for subroutine in &class.subroutines {
+            if let Some(function) = inlinable(subroutine) {
+                let name = format!("{}.{}", class.name.name, subroutine.name.name);
+                functions.insert(name, function);
+            }
+        }
+    }
+    for class in classes.iter_mut() {
+        let fields: Vec<String> = class
+            .variables
+            .iter()
+            .flat_map(|variable| variable.names.iter().map(|name| name.name.clone()))
+            .collect();
+        for subroutine in &mut class.subroutines {
+            let parameters = subroutine
+                .parameters
+                .iter()
+                .map(|parameter| parameter.name.name.clone());
+            let locals: Vec<String> = subroutine
+                .locals
+                .iter()
+                .flat_map(|local| local.names.iter().map(|name| name.name.clone()))
+                .collect();
+            let scalars: HashSet<String> = parameters.chain(locals.iter().cloned()).collect();
+            let inliner = Inliner {
+                functions: &functions,
+                variables: scalars.iter().chain(&fields).cloned().collect(),
+            };
+            inliner.statements(&mut subroutine.body);
+            // The VM starts every local at 0.
+            let mut folder = Folder {
+                scalars,
+                known: locals.into_iter().map(|name| (name, 0)).collect(),
+            };
+            subroutine.body = folder.statements(std::mem::take(&mut subroutine.body));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        codegen::{compile, compile_optimized},
+        os::{self, link},
+        parser::parse,
+    };
+    use computer::{keyboard::DummyKeyboard, screen::DummyScreen};
+    use vm::{command::Command, interpreter::Interpreter, loader::Module};
+
+    const MAIN: &str = "class Main {
+        static int a, b, c, d, e, f, g;
+        function int scale(int x, int y) { return (x * 3) + y; }
+        function void main() {
+            var int i, n, debug;
+            let n = 4 * 5 - 2;
+            if (debug) { do Output.printInt(n); }
+            while (i < 40) {
+                let a = a + (Main.scale(i, n) * -12);
+                let b = b + ((a / 8) + (a / -1) + (n * 33));
+                let i = i + 1;
+            }
+            let c = -30001 * 1;
+            let d = (c - 3) / 4;
+            let e = (~n) * 1024;
+            let f = Math.abs(i - 50) / 3;
+            let g = Math.max(n, i);
+            return;
+        }
+    }";
+
+    // Runs the program on its OS, returning its statics and step count.
+    fn run(modules: Vec<Module>) -> (Vec<u16>, u64) {
+        let mut vm = Interpreter::<DummyScreen, DummyKeyboard>::new(&modules).unwrap();
+        vm.bootstrap().unwrap();
+        vm.run(Some(10_000_000));
+        assert_eq!(vm.function(), Some("Sys.halt"));
+        (
+            (16..23).map(|address| vm.peek(address)).collect(),
+            vm.steps(),
+        )
+    }
+
+    #[test]
+    fn optimized_programs_compute_the_same_in_fewer_steps() {
+        let main = compile(&parse(MAIN).unwrap()).unwrap();
+        let mut modules = vec![main];
+        modules.extend(link(&modules, false));
+        let (expected, steps) = run(modules);
+
+        let mut program = os::classes();
+        program.push(parse(MAIN).unwrap());
+        optimize(&mut program);
+        let main = compile_optimized(program.last().unwrap()).unwrap();
+        // The debug output is never compiled, and no multiplication is left
+        // to the OS.
+        assert!(!main.commands.iter().any(|command| matches!(
+            command,
+            Command::Call(name, _) if name == "Output.printInt" || name == "Math.multiply"
+        )));
+        let mut modules = vec![main];
+        modules.extend(link(&modules, true));
+        let (statics, optimized_steps) = run(modules);
+        assert_eq!(statics, expected);
+        assert!(optimized_steps * 2 < steps, "{} {}", optimized_steps, steps);
+    }
+
+    #[test]
+    fn constants_are_folded_and_propagated() {
+        let mut classes = vec![parse(
+            "class Main {
+                function int f(int x) {
+                    var int k;
+                    let k = 2 + 3;
+                    while (x > k) { let x = x - k; }
+                    if (k = 5) { return x + (k * 2); } else { return 0; }
+                }
+            }",
+        )
+        .unwrap()];
+        optimize(&mut classes);
+        let commands: Vec<String> = compile_optimized(&classes[0])
+            .unwrap()
+            .commands
+            .iter()
+            .map(|command| command.to_string())
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                "function Main.f 1",
+                "push constant 5",
+                "pop local 0",
+                "label WHILE_EXP0",
+                "push argument 0",
+                "push constant 5",
+                "gt",
+                "not",
+                "if-goto WHILE_END0",
+                "push argument 0",
+                "push constant 5",
+                "sub",
+                "pop argument 0",
+                "goto WHILE_EXP0",
+                "label WHILE_END0",
+                "push argument 0",
+                "push constant 10",
+                "add",
+                "return",
+            ]
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::HashSet;
+
+use vm::{
+    command::Command,
+    loader::{defines, Module},
+};
+
+use crate::{
+    ast::Class,
+    codegen::{compile, compile_optimized},
+    optimize::optimize,
+    parser::parse,
+};
+
+// The Jack OS, in the order Sys.init initializes it.
+pub const SOURCES: [(&str, &str); 8] = [
+    ("Memory", include_str!("../os/Memory.jack")),
+    ("Math", include_str!("../os/Math.jack")),
+    ("Screen", include_str!("../os/Screen.jack")),
+    ("Output", include_str!("../os/Output.jack")),
+    ("Keyboard", include_str!("../os/Keyboard.jack")),
+    ("String", include_str!("../os/String.jack")),
+    ("Array", include_str!("../os/Array.jack")),
+    ("Sys", include_str!("../os/Sys.jack")),
+];
+
+pub fn classes() -> Vec<Class> {
+    SOURCES
+        .iter()
+        .map(|(name, source)| {
+            parse(source).unwrap_or_else(|errors| panic!("{}.jack:{}", name, errors[0]))
+        })
+        .collect()
+}
+
+fn called_classes(module: &Module) -> impl Iterator<Item = &str> {
+    module.commands.iter().filter_map(|command| match command {
+        Command::Call(name, _) => name.split('.').next(),
+        _ => None,
+    })
+}
+
+// The compiled OS classes the modules call into, directly or through other
+// OS classes, and don't define themselves. Programs without a Sys.init of
+// their own are started by the OS one, which initializes every other class.
+pub fn link(modules: &[Module], optimized: bool) -> Vec<Module> {
+    let mut classes = classes();
+    if optimized {
+        optimize(&mut classes);
+    }
+    let os: Vec<Module> = classes
+        .iter()
+        .map(|class| match optimized {
+            true => compile_optimized(class),
+            false => compile(class),
+        })
+        .map(|module| module.expect("the OS compiles"))
+        .collect();
+    let mut wanted: Vec<&str> = modules.iter().flat_map(called_classes).collect();
+    if !defines(modules, "Sys.init") {
+        wanted.push("Sys");
+    }
+    let mut linked = HashSet::new();
+    while let Some(class) = wanted.pop() {
+        if modules.iter().any(|module| module.name == class) || linked.contains(class) {
+            continue;
+        }
+        if let Some(module) = os.iter().find(|module| module.name == class) {
+            linked.insert(class);
+            wanted.extend(called_classes(module));
+        }
+    }
+    os.iter()
+        .filter(|module| linked.contains(module.name.as_str()))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::Checker;
+    use computer::{keyboard::DummyKeyboard, screen::DummyScreen};
+    use vm::interpreter::Interpreter;
+
+    #[test]
+    fn os_passes_the_checker() {
+        let mut classes = classes();
+        classes.push(parse("class Main { function void main() { return; } }").unwrap());
+        let checker = Checker::new(&classes);
+        for class in &classes {
+            assert_eq!(checker.check(class), [], "{}", class.name.name);
+        }
+    }
+
+    #[test]
+    fn linked_programs_run_on_the_os() {
+        let main = compile(
+            &parse(
+                "class Main {
+                    static int product, quotient, root, number, reused;
+                    function void main() {
+                        var String s;
+                        var Array a, b;
+                        let product = -7 * 123;
+                        let quotient = -1000 / 7;
+                        let root = Math.sqrt(30000);
+                        let s = \"-12345\";
+                        let number = s.intValue();
+                        let a = Array.new(10);
+                        do a.dispose();
+                        let b = Array.new(5);
+                        let reused = b - a;
+                        do Output.printString(\"Hi\");
+                        do Screen.drawLine(0, 100, 15, 100);
+                        return;
+                    }
+                }",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let mut modules = vec![main.clone()];
+        let os = link(&modules, false);
+        assert_eq!(os.len(), SOURCES.len());
+        modules.extend(os);
+        let mut vm = Interpreter::<DummyScreen, DummyKeyboard>::new(&modules).unwrap();
+        vm.bootstrap().unwrap();
+        vm.run(Some(2_000_000));
+        assert_eq!(vm.function(), Some("Sys.halt"));
+        let statics: Vec<i16> = (16..21).map(|address| vm.peek(address) as i16).collect();
+        assert_eq!(statics, [-861, -142, 173, -12345, 5]);
+        // The second line of "Hi", with "i" in the high byte.
+        assert_eq!(vm.peek(16384 + 32), 34 | 8 << 8);
+        assert_eq!(vm.peek(16384 + 100 * 32), 0xFFFF);
+
+        // The interpreter's built-in OS behaves the same.
+        let mut builtin =
+            Interpreter::<DummyScreen, DummyKeyboard>::with_builtins(&[main]).unwrap();
+        builtin.bootstrap().unwrap();
+        builtin.run(Some(10_000));
+        for address in (16..21).chain(16384..16384 + 101 * 32) {
+            assert_eq!(builtin.peek(address), vm.peek(address), "{}", address);
+        }
+    }
+}
@@ -0,0 +1,1058 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::{
+    ast::{
+        BinaryOp, Class, ClassVarDec, ClassVarKind, Expression, Identifier, KeywordConstant,
+        Parameter, Statement, Subroutine, SubroutineCall, SubroutineKind, Term, Type, UnaryOp,
+        VarDec,
+    },
+    optimize::constant_expression,
+    span::Span,
+    token::{Dialect, Keyword, Token, TokenKind},
+    tokenizer::{TokenizeError, Tokenizer},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum ParseError {
+    #[error(transparent)]
+    Tokenize(#[from] TokenizeError),
+
+    #[error("{0}: Expected {1}, found \"{2}\"")]
+    Expected(Span, String, String),
+
+    #[error("{0}: Expected {1}, found the end of the file")]
+    UnexpectedEnd(Span, String),
+
+    #[error("{0}: {1} outside a loop")]
+    OutsideLoop(Span, Keyword),
+
+    #[error("{0}: The value of {1} is not a constant")]
+    NotConstant(Span, String),
+
+    #[error("{0}: {1} is a constant")]
+    AssignedConstant(Span, String),
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::Tokenize(error) => error.span(),
+            ParseError::Expected(span, ..)
+            | ParseError::UnexpectedEnd(span, _)
+            | ParseError::OutsideLoop(span, _)
+            | ParseError::NotConstant(span, _)
+            | ParseError::AssignedConstant(span, _) => *span,
+        }
+    }
+}
+pub type Result<T> = std::result::Result<T, ParseError>;
+
+const STATEMENT_KEYWORDS: [Keyword; 8] = [
+    Keyword::Let,
+    Keyword::If,
+    Keyword::While,
+    Keyword::Do,
+    Keyword::Return,
+    Keyword::For,
+    Keyword::Break,
+    Keyword::Continue,
+];
+
+const SUBROUTINE_KEYWORDS: [Keyword; 3] =
+    [Keyword::Constructor, Keyword::Function, Keyword::Method];
+
+// The extended dialect is desugared into standard Jack as it's parsed, so
+// the rest of the compiler only has break and continue to learn about.
+pub struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    errors: Vec<ParseError>,
+    dialect: Dialect,
+    // The loops the statement being parsed is in.
+    loops: usize,
+    // The values of the class's constants.
+    constants: HashMap<String, u16>,
+    // The subroutine's variables, which shadow the class's constants, and
+    // its own constants.
+    scope: HashMap<String, Option<u16>>,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(tokens: &'a [Token]) -> Self {
+        Self::with_dialect(tokens, Dialect::Standard)
+    }
+
+    pub fn with_dialect(tokens: &'a [Token], dialect: Dialect) -> Self {
+        Self {
+            tokens,
+            position: 0,
+            errors: vec![],
+            dialect,
+            loops: 0,
+            constants: HashMap::new(),
+            scope: HashMap::new(),
+        }
+    }
+
+    fn peek(&self) -> Option<&'a TokenKind> {
+        self.tokens.get(self.position).map(|token| &token.kind)
+    }
+
+    fn is_symbol(&self, symbol: char) -> bool {
+        self.peek() == Some(&TokenKind::Symbol(symbol))
+    }
+
+    fn is_keyword(&self, keywords: &[Keyword]) -> bool {
+        matches!(self.peek(), Some(TokenKind::Keyword(keyword)) if keywords.contains(keyword))
+    }
+
+    // The span of the next token, or an empty one after the last.
+    fn span(&self) -> Span {
+        match self.tokens.get(self.position) {
+            Some(token) => token.span,
+            None => self.tokens.last().map_or_else(Span::default, |token| Span {
+                start: token.span.end,
+                column: token.span.column + (token.span.end - token.span.start),
+                ..token.span
+            }),
+        }
+    }
+
+    // The span from `start` to the end of the last consumed token.
+    fn since(&self, start: Span) -> Span {
+        match self.position.checked_sub(1).map(|i| self.tokens[i].span) {
+            Some(last) if last.end >= start.start => start.to(last),
+            _ => start,
+        }
+    }
+
+    fn error(&self, expected: &str) -> ParseError {
+        match self.peek() {
+            Some(kind) => ParseError::Expected(self.span(), expected.to_string(), kind.to_string()),
+            None => ParseError::UnexpectedEnd(self.span(), expected.to_string()),
+        }
+    }
+
+    fn symbol(&mut self, symbol: char) -> Result<Span> {
+        if self.is_symbol(symbol) {
+            let span = self.span();
+            self.position += 1;
+            Ok(span)
+        } else {
+            Err(self.error(&format!("\"{}\"", symbol)))
+        }
+    }
+
+    fn keyword(&mut self, keyword: Keyword) -> Result<Span> {
+        if self.is_keyword(&[keyword]) {
+            let span = self.span();
+            self.position += 1;
+            Ok(span)
+        } else {
+            Err(self.error(&format!("\"{}\"", keyword)))
+        }
+    }
+
+    fn identifier(&mut self) -> Result<Identifier> {
+        match self.peek() {
+            Some(TokenKind::Identifier(name)) => {
+                let span = self.span();
+                self.position += 1;
+                Ok(Identifier {
+                    name: name.clone(),
+                    span,
+                })
+            }
+            _ => Err(self.error("an identifier")),
+        }
+    }
+
+    fn ty(&mut self) -> Result<Type> {
+        let ty = match self.peek() {
+            Some(TokenKind::Keyword(Keyword::Int)) => Type::Int,
+            Some(TokenKind::Keyword(Keyword::Char)) => Type::Char,
+            Some(TokenKind::Keyword(Keyword::Boolean)) => Type::Boolean,
+            Some(TokenKind::Identifier(name)) => Type::Class(name.clone()),
+            _ => return Err(self.error("a type")),
+        };
+        self.position += 1;
+        Ok(ty)
+    }
+
+    fn constant_value(&self, name: &str) -> Option<u16> {
+        match self.scope.get(name) {
+            Some(value) => *value,
+            None => self.constants.get(name).copied(),
+        }
+    }
+
+    // `const type name = value;`, whose value is put in place of the name
+    // wherever it's used.
+    fn constant(&mut self) -> Result<(String, u16)> {
+        self.keyword(Keyword::Const)?;
+        self.ty()?;
+        let name = self.identifier()?;
+        self.symbol('=')?;
+        let value = self.expression()?;
+        self.symbol(';')?;
+        let value = constant_expression(&value).unwrap_or_else(|| {
+            self.errors
+                .push(ParseError::NotConstant(value.span, name.name.clone()));
+            0
+        });
+        Ok((name.name, value))
+    }
+
+    // One or more comma separated names ending with a semicolon.
+    fn names(&mut self) -> Result<Vec<Identifier>> {
+        let mut names = vec![self.identifier()?];
+        while self.is_symbol(',') {
+            self.position += 1;
+            names.push(self.identifier()?);
+        }
+        self.symbol(';')?;
+        Ok(names)
+    }
+
+    // Skips to a point where parsing can resume: past a semicolon, or before
+    // a closing brace, a statement or a subroutine. Blocks are skipped whole.
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        while let Some(kind) = self.peek() {
+            match kind {
+                TokenKind::Symbol('{') => depth += 1,
+                TokenKind::Symbol('}') if depth == 0 => return,
+                TokenKind::Symbol('}') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.position += 1;
+                        return;
+                    }
+                }
+                TokenKind::Symbol(';') if depth == 0 => {
+                    self.position += 1;
+                    return;
+                }
+                TokenKind::Keyword(keyword)
+                    if depth == 0
+                        && (STATEMENT_KEYWORDS.contains(keyword)
+                            || SUBROUTINE_KEYWORDS.contains(keyword)) =>
+                {
+                    return
+                }
+                _ => {}
+            }
+            self.position += 1;
+        }
+    }
+
+    // Records the error and synchronizes, making sure to move past a token
+    // that cannot start anything in the current context.
+    fn recover(&mut self, error: ParseError, resumes: impl Fn(&Self) -> bool) {
+        self.errors.push(error);
+        let position = self.position;
+        self.synchronize();
+        if self.position == position && self.peek().is_some() && !resumes(self) {
+            self.position += 1;
+        }
+    }
+
+    pub fn class(&mut self) -> Result<Class> {
+        let start = self.span();
+        self.keyword(Keyword::Class)?;
+        let name = self.identifier()?;
+        self.symbol('{')?;
+        let mut variables = vec![];
+        let mut subroutines = vec![];
+        loop {
+            let result = if self.is_keyword(&[Keyword::Static, Keyword::Field]) {
+                self.class_var_dec().map(|dec| variables.push(dec))
+            } else if self.is_keyword(&[Keyword::Const]) {
+                self.constant().map(|(name, value)| {
+                    self.constants.insert(name, value);
+                })
+            } else if self.is_keyword(&SUBROUTINE_KEYWORDS) {
+                self.subroutine()
+                    .map(|subroutine| subroutines.push(subroutine))
+            } else if self.is_symbol('}') || self.peek().is_none() {
+                break;
+            } else {
+                Err(self.error("a class variable or subroutine"))
+            };
+            if let Err(error) = result {
+                self.recover(error, |parser| {
+                    parser.is_symbol('}')
+                        || parser.is_keyword(&SUBROUTINE_KEYWORDS)
+                        || parser.is_keyword(&[Keyword::Static, Keyword::Field, Keyword::Const])
+                });
+            }
+        }
+        self.symbol('}')?;
+        if self.peek().is_some() {
+            return Err(self.error("the end of the file"));
+        }
+        Ok(Class {
+            name,
+            variables,
+            subroutines,
+            span: self.since(start),
+        })
+    }
+
+    fn class_var_dec(&mut self) -> Result<ClassVarDec> {
+        let start = self.span();
+        let kind = if self.is_keyword(&[Keyword::Static]) {
+            ClassVarKind::Static
+        } else {
+            ClassVarKind::Field
+        };
+        self.position += 1;
+        let ty = self.ty()?;
+        let names = self.names()?;
+        Ok(ClassVarDec {
+            kind,
+            ty,
+            names,
+            span: self.since(start),
+        })
+    }
+
+    fn subroutine(&mut self) -> Result<Subroutine> {
+        let start = self.span();
+        let kind = match self.peek() {
+            Some(TokenKind::Keyword(Keyword::Constructor)) => SubroutineKind::Constructor,
+            Some(TokenKind::Keyword(Keyword::Function)) => SubroutineKind::Function,
+            _ => SubroutineKind::Method,
+        };
+        self.position += 1;
+        let return_type = if self.is_keyword(&[Keyword::Void]) {
+            self.position += 1;
+            None
+        } else {
+            Some(self.ty()?)
+        };
+        let name = self.identifier()?;
+        self.symbol('(')?;
+        self.scope.clear();
+        let mut parameters = vec![];
+        if !self.is_symbol(')') {
+            loop {
+                let ty = self.ty()?;
+                let name = self.identifier()?;
+                self.scope.insert(name.name.clone(), None);
+                parameters.push(Parameter { ty, name });
+                if !self.is_symbol(',') {
+                    break;
+                }
+                self.position += 1;
+            }
+        }
+        self.symbol(')')?;
+        self.symbol('{')?;
+        let mut locals = vec![];
+        while self.is_keyword(&[Keyword::Var, Keyword::Const]) {
+            if self.is_keyword(&[Keyword::Const]) {
+                match self.constant() {
+                    Ok((name, value)) => {
+                        self.scope.insert(name, Some(value));
+                    }
+                    Err(error) => self.recover(error, |parser| parser.is_symbol('}')),
+                }
+                continue;
+            }
+            let start = self.span();
+            self.position += 1;
+            let result = self.ty().and_then(|ty| Ok((ty, self.names()?)));
+            match result {
+                Ok((ty, names)) => {
+                    for name in &names {
+                        self.scope.insert(name.name.clone(), None);
+                    }
+                    locals.push(VarDec {
+                        ty,
+                        names,
+                        span: self.since(start),
+                    })
+                }
+                Err(error) => self.recover(error, |parser| parser.is_symbol('}')),
+            }
+        }
+        let body = self.statements();
+        self.symbol('}')?;
+        Ok(Subroutine {
+            kind,
+            return_type,
+            name,
+            parameters,
+            locals,
+            body,
+            span: self.since(start),
+        })
+    }
+
+    // Statements up to a closing brace, recovering from errors in them.
+    fn statements(&mut self) -> Vec<Statement> {
+        let mut statements = vec![];
+        while !self.is_symbol('}') && !self.is_keyword(&SUBROUTINE_KEYWORDS) {
+            if self.peek().is_none() {
+                break;
+            }
+            let result = if self.is_keyword(&[Keyword::For]) {
+                self.for_statement()
+            } else {
+                self.statement().map(|statement| vec![statement])
+            };
+            match result {
+                Ok(statement) => statements.extend(statement),
+                Err(error) => self.recover(error, |parser| {
+                    parser.is_symbol('}')
+                        || parser.is_keyword(&STATEMENT_KEYWORDS)
+                        || parser.is_keyword(&SUBROUTINE_KEYWORDS)
+                }),
+            }
+        }
+        statements
+    }
+
+    fn block(&mut self) -> Result<Vec<Statement>> {
+        self.symbol('{')?;
+        let statements = self.statements();
+        self.symbol('}')?;
+        Ok(statements)
+    }
+
+    // The body of a loop, in which break and continue may be used.
+    fn loop_body(&mut self) -> Result<Vec<Statement>> {
+        self.loops += 1;
+        let body = self.block();
+        self.loops -= 1;
+        body
+    }
+
+    // An operator right before `=`, as in `+=`.
+    fn compound_op(&self) -> Option<BinaryOp> {
+        if self.dialect != Dialect::Extended {
+            return None;
+        }
+        let op = self.tokens.get(self.position)?;
+        let equals = self.tokens.get(self.position + 1)?;
+        match (&op.kind, &equals.kind) {
+            (
+                TokenKind::Symbol(symbol @ ('+' | '-' | '*' | '/' | '&' | '|')),
+                TokenKind::Symbol('='),
+            ) if op.span.end == equals.span.start => BinaryOp::from_symbol(*symbol),
+            _ => None,
+        }
+    }
+
+    // What a let statement assigns, without the keyword and semicolon.
+    // `x op= value` assigns `x op (value)`, so an index is evaluated twice.
+    fn assignment(&mut self) -> Result<(Identifier, Option<Box<Expression>>, Expression)> {
+        let name = self.identifier()?;
+        if self.constant_value(&name.name).is_some() {
+            self.errors
+                .push(ParseError::AssignedConstant(name.span, name.name.clone()));
+        }
+        let index = if self.is_symbol('[') {
+            self.position += 1;
+            let index = self.expression()?;
+            self.symbol(']')?;
+            Some(Box::new(index))
+        } else {
+            None
+        };
+        let target_span = self.since(name.span);
+        let op = self.compound_op();
+        if op.is_some() {
+            self.position += 1;
+        }
+        self.symbol('=')?;
+        let mut value = self.expression()?;
+        if let Some(op) = op {
+            let target = match &index {
+                Some(index) => Term::Index(name.clone(), index.clone(), target_span),
+                None => Term::Variable(name.clone()),
+            };
+            let span = value.span;
+            let operand = if value.rest.is_empty() {
+                value.first
+            } else {
+                Term::Parenthesized(Box::new(value), span)
+            };
+            value = Expression {
+                first: target,
+                rest: vec![(op, operand)],
+                span,
+            };
+        }
+        Ok((name, index, value))
+    }
+
+    // `for (init; condition; update) { body }` is parsed as
+    // `init; while (condition) { body update }`, with the update also run
+    // before each continue in the body. Every part in brackets is optional,
+    // and the assignments may leave out `let`.
+    fn for_statement(&mut self) -> Result<Vec<Statement>> {
+        let start = self.span();
+        self.keyword(Keyword::For)?;
+        self.symbol('(')?;
+        let init = self.for_clause(';')?;
+        self.symbol(';')?;
+        let condition = if self.is_symbol(';') {
+            let span = self.span();
+            Expression {
+                first: Term::Keyword(KeywordConstant::True, span),
+                rest: vec![],
+                span,
+            }
+        } else {
+            self.expression()?
+        };
+        self.symbol(';')?;
+        let update = self.for_clause(')')?.into_iter().collect::<Vec<_>>();
+        self.symbol(')')?;
+        let mut body = self.loop_body()?;
+        if !update.is_empty() {
+            body = continue_with(body, &update);
+            body.extend(update);
+        }
+        let mut statements = init.into_iter().collect::<Vec<_>>();
+        statements.push(Statement::While {
+            condition,
+            body,
+            span: self.since(start),
+        });
+        Ok(statements)
+    }
+
+    fn for_clause(&mut self, end: char) -> Result<Option<Statement>> {
+        if self.is_symbol(end) {
+            return Ok(None);
+        }
+        let start = self.span();
+        if self.is_keyword(&[Keyword::Let]) {
+            self.position += 1;
+        }
+        let (name, index, value) = self.assignment()?;
+        Ok(Some(Statement::Let {
+            name,
+            index,
+            value,
+            span: self.since(start),
+        }))
+    }
+
+    fn statement(&mut self) -> Result<Statement> {
+        let start = self.span();
+        let keyword = match self.peek() {
+            Some(TokenKind::Keyword(keyword))
+                if STATEMENT_KEYWORDS.contains(keyword) && *keyword != Keyword::For =>
+            {
+                *keyword
+            }
+            _ => return Err(self.error("a statement")),
+        };
+        self.position += 1;
+        let statement = match keyword {
+            Keyword::Let => {
+                let (name, index, value) = self.assignment()?;
+                self.symbol(';')?;
+                Statement::Let {
+                    name,
+                    index,
+                    value,
+                    span: self.since(start),
+                }
+            }
+            Keyword::If => {
+                self.symbol('(')?;
+                let condition = self.expression()?;
+                self.symbol(')')?;
+                let then = self.block()?;
+                let otherwise = if self.is_keyword(&[Keyword::Else]) {
+                    self.position += 1;
+                    if self.dialect == Dialect::Extended && self.is_keyword(&[Keyword::If]) {
+                        Some(vec![self.statement()?])
+                    } else {
+                        Some(self.block()?)
+                    }
+                } else {
+                    None
+                };
+                Statement::If {
+                    condition,
+                    then,
+                    otherwise,
+                    span: self.since(start),
+                }
+            }
+            Keyword::While => {
+                self.symbol('(')?;
+                let condition = self.expression()?;
+                self.symbol(')')?;
+                let body = self.loop_body()?;
+                Statement::While {
+                    condition,
+                    body,
+                    span: self.since(start),
+                }
+            }
+            Keyword::Do => {
+                let name = self.identifier()?;
+                let call = self.call(name)?;
+                self.symbol(';')?;
+                Statement::Do {
+                    call,
+                    span: self.since(start),
+                }
+            }
+            Keyword::Break | Keyword::Continue => {
+                if self.loops == 0 {
+                    return Err(ParseError::OutsideLoop(start, keyword));
+                }
+                self.symbol(';')?;
+                let span = self.since(start);
+                if keyword == Keyword::Break {
+                    Statement::Break { span }
+                } else {
+                    Statement::Continue { span }
+                }
+            }
+            _ => {
+                let value = if self.is_symbol(';') {
+                    None
+                } else {
+                    Some(self.expression()?)
+                };
+                self.symbol(';')?;
+                Statement::Return {
+                    value,
+                    span: self.since(start),
+                }
+            }
+        };
+        Ok(statement)
+    }
+
+    // The rest of a call whose first name has been read.
+    fn call(&mut self, first: Identifier) -> Result<SubroutineCall> {
+        let (receiver, name) = if self.is_symbol('.') {
+            self.position += 1;
+            (Some(first.clone()), self.identifier()?)
+        } else {
+            (None, first.clone())
+        };
+        self.symbol('(')?;
+        let mut arguments = vec![];
+        if !self.is_symbol(')') {
+            arguments.push(self.expression()?);
+            while self.is_symbol(',') {
+                self.position += 1;
+                arguments.push(self.expression()?);
+            }
+        }
+        self.symbol(')')?;
+        Ok(SubroutineCall {
+            receiver,
+            name,
+            arguments,
+            span: self.since(first.span),
+        })
+    }
+
+    fn binary_op(&self) -> Option<BinaryOp> {
+        match self.peek() {
+            Some(TokenKind::Symbol(symbol)) => BinaryOp::from_symbol(*symbol),
+            _ => None,
+        }
+    }
+
+    // Jack evaluates operators from left to right. The extended dialect
+    // groups products and quotients into parenthesized terms first.
+    fn expression(&mut self) -> Result<Expression> {
+        let start = self.span();
+        let first = self.operand()?;
+        let mut rest = vec![];
+        while let Some(op) = self.binary_op() {
+            self.position += 1;
+            rest.push((op, self.operand()?));
+        }
+        Ok(Expression {
+            first,
+            rest,
+            span: self.since(start),
+        })
+    }
+
+    fn operand(&mut self) -> Result<Term> {
+        if self.dialect == Dialect::Standard {
+            return self.term();
+        }
+        let start = self.span();
+        let first = self.term()?;
+        let mut rest = vec![];
+        while let Some(op @ (BinaryOp::Mul | BinaryOp::Div)) = self.binary_op() {
+            self.position += 1;
+            rest.push((op, self.term()?));
+        }
+        if rest.is_empty() {
+            return Ok(first);
+        }
+        let span = self.since(start);
+        let product = Expression { first, rest, span };
+        Ok(Term::Parenthesized(Box::new(product), span))
+    }
+
+    fn term(&mut self) -> Result<Term> {
+        let start = self.span();
+        let kind = match self.peek() {
+            Some(kind) => kind,
+            None => return Err(self.error("a term")),
+        };
+        let term = match kind {
+            TokenKind::IntegerConstant(value) => {
+                self.position += 1;
+                Term::Integer(*value, start)
+            }
+            TokenKind::StringConstant(string) => {
+                self.position += 1;
+                Term::String(string.clone(), start)
+            }
+            TokenKind::CharConstant(code) => {
+                self.position += 1;
+                Term::Integer(*code, start)
+            }
+            TokenKind::Keyword(keyword) => {
+                let constant = match keyword {
+                    Keyword::True => KeywordConstant::True,
+                    Keyword::False => KeywordConstant::False,
+                    Keyword::Null => KeywordConstant::Null,
+                    Keyword::This => KeywordConstant::This,
+                    _ => return Err(self.error("a term")),
+                };
+                self.position += 1;
+                Term::Keyword(constant, start)
+            }
+            TokenKind::Identifier(_) => {
+                let name = self.identifier()?;
+                match self.peek() {
+                    Some(TokenKind::Symbol('[')) => {
+                        self.position += 1;
+                        let index = self.expression()?;
+                        self.symbol(']')?;
+                        Term::Index(name, Box::new(index), self.since(start))
+                    }
+                    Some(TokenKind::Symbol('(')) | Some(TokenKind::Symbol('.')) => {
+                        Term::Call(self.call(name)?)
+                    }
+                    _ => match self.constant_value(&name.name) {
+                        Some(value) => constant_term(value, name.span),
+                        None => Term::Variable(name),
+                    },
+                }
+            }
+            TokenKind::Symbol('(') => {
+                self.position += 1;
+                let expression = self.expression()?;
+                self.symbol(')')?;
+                Term::Parenthesized(Box::new(expression), self.since(start))
+            }
+            TokenKind::Symbol(symbol @ ('-' | '~')) => {
+                let op = if *symbol == '-' {
+                    UnaryOp::Neg
+                } else {
+                    UnaryOp::Not
+                };
+                self.position += 1;
+                let term = self.term()?;
+                Term::Unary(op, Box::new(term), self.since(start))
+            }
+            _ => return Err(self.error("a term")),
+        };
+        Ok(term)
+    }
+}
+
+// A constant as a term of standard Jack, where literals stop at 32767.
+fn constant_term(value: u16, span: Span) -> Term {
+    match value {
+        0..=32767 => Term::Integer(value, span),
+        0x8000 => Term::Unary(UnaryOp::Not, Box::new(Term::Integer(32767, span)), span),
+        _ => Term::Unary(
+            UnaryOp::Neg,
+            Box::new(Term::Integer(value.wrapping_neg(), span)),
+            span,
+        ),
+    }
+}
+
+// Runs a for loop's update before the continues that belong to the loop
+// rather than to a loop in it.
+fn continue_with(statements: Vec<Statement>, update: &[Statement]) -> Vec<Statement> {
+    let mut result = vec![];
+    for statement in statements {
+        match statement {
+            Statement::Continue { .. } => {
+                result.extend(update.iter().cloned());
+                result.push(statement);
+            }
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+                span,
+            } => result.push(Statement::If {
+                condition,
+                then: continue_with(then, update),
+                otherwise: otherwise.map(|otherwise| continue_with(otherwise, update)),
+                span,
+            }),
+            _ => result.push(statement),
+        }
+    }
+    result
+}
+
+// Parses a class, carrying on after errors to report as many as possible.
+// The class is returned whenever its outline could be read, even if some of
+// its declarations or statements were dropped.
+pub fn parse_tokens(tokens: &[Token], dialect: Dialect) -> (Option<Class>, Vec<ParseError>) {
+    let mut parser = Parser::with_dialect(tokens, dialect);
+    let class = parser.class();
+    let mut errors = parser.errors;
+    match class {
+        Ok(class) => (Some(class), errors),
+        Err(error) => {
+            errors.push(error);
+            (None, errors)
+        }
+    }
+}
+
+// Tokenizes and parses a source file, failing with every error found.
+pub fn parse(source: &str) -> std::result::Result<Class, Vec<ParseError>> {
+    parse_dialect(source, Dialect::Standard)
+}
+
+pub fn parse_dialect(
+    source: &str,
+    dialect: Dialect,
+) -> std::result::Result<Class, Vec<ParseError>> {
+    let mut tokens = vec![];
+    let mut errors = vec![];
+    for result in Tokenizer::with_dialect(source, dialect) {
+        match result {
+            Ok(token) => tokens.push(token),
+            Err(error) => errors.push(ParseError::from(error)),
+        }
+    }
+    let (class, parse_errors) = parse_tokens(&tokens, dialect);
+    errors.extend(parse_errors);
+    errors.sort_by_key(|error| error.span().start);
+    match class {
+        Some(class) if errors.is_empty() => Ok(class),
+        _ => Err(errors),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xml;
+
+    #[test]
+    fn parser_builds_the_ast() {
+        let class = parse(
+            "class Point {
+                field int x, y;
+                static Point origin;
+                constructor Point new(int ax, int ay) {
+                    let x = ax;
+                    let y = ay;
+                    return this;
+                }
+                method int distance(Point other) {
+                    var int dx;
+                    let dx = Math.abs(x - other.getX());
+                    if (~(dx < 0)) { let a[dx + 1] = -dx; } else { do draw(); }
+                    while (true) { return dx * (2 + y); }
+                    return 0;
+                }
+            }",
+        )
+        .unwrap();
+        assert_eq!(class.name.name, "Point");
+        assert_eq!(class.variables.len(), 2);
+        assert_eq!(class.variables[0].names.len(), 2);
+        assert_eq!(class.variables[1].ty, Type::Class("Point".to_string()));
+        let constructor = &class.subroutines[0];
+        assert_eq!(constructor.kind, SubroutineKind::Constructor);
+        assert_eq!(constructor.parameters.len(), 2);
+        let method = &class.subroutines[1];
+        assert_eq!(method.return_type, Some(Type::Int));
+        assert_eq!(method.locals[0].names[0].name, "dx");
+        assert_eq!(method.body.len(), 4);
+        match &method.body[0] {
+            Statement::Let { value, .. } => match &value.first {
+                Term::Call(call) => {
+                    assert_eq!(call.receiver.as_ref().unwrap().name, "Math");
+                    assert_eq!(call.name.name, "abs");
+                    let argument = &call.arguments[0];
+                    assert!(matches!(argument.rest[0], (BinaryOp::Sub, Term::Call(_))));
+                }
+                term => panic!("unexpected {:?}", term),
+            },
+            statement => panic!("unexpected {:?}", statement),
+        }
+        match &method.body[1] {
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                assert!(matches!(condition.first, Term::Unary(UnaryOp::Not, ..)));
+                assert!(matches!(then[0], Statement::Let { index: Some(_), .. }));
+                assert!(otherwise.is_some());
+            }
+            statement => panic!("unexpected {:?}", statement),
+        }
+        let span = method.body[3].span();
+        assert_eq!((span.line, span.end - span.start), (14, 9));
+    }
+
+    #[test]
+    fn parser_recovers_to_report_every_error() {
+        let errors = parse(
+            "class Main {
+                field int;
+                function void main() {
+                    let x = ;
+                    if (x +) { let y = 1; }
+                    do Output.print(1 2);
+                    let z = 3;
+                }
+                method void ok() { return; }
+                function
+            }",
+        )
+        .unwrap_err();
+        let lines = errors
+            .iter()
+            .map(|error| error.span().line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [2, 4, 5, 6, 11]);
+        assert_eq!(errors[1].to_string(), "4:29: Expected a term, found \";\"");
+
+        let tokens = Tokenizer::new("class A { function void f() { let = 1; return; } }")
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .unwrap();
+        let (class, errors) = parse_tokens(&tokens, Dialect::Standard);
+        assert_eq!(errors.len(), 1);
+        let class = class.unwrap();
+        assert!(matches!(
+            class.subroutines[0].body[..],
+            [Statement::Return { .. }]
+        ));
+    }
+
+    #[test]
+    fn extended_dialect_desugars_into_standard_jack() {
+        let extended = parse_dialect(
+            "class Main {
+                const int SIZE = 'A' - 60;
+                function int main(int n) {
+                    const boolean DEBUG = ~false;
+                    var int i, sum;
+                    for (i = 0; i < SIZE; i += 1) {
+                        if (i = 1) { let sum *= 2 + n; }
+                        else if (i = 2) { let sum = sum - i * 3; }
+                        else { let sum[i] |= DEBUG; }
+                    }
+                    for (;;) { return 1 + n * 2 - 6 / n / 2 < 0; }
+                }
+            }",
+            Dialect::Extended,
+        )
+        .unwrap();
+        let standard = parse(
+            "class Main {
+                function int main(int n) {
+                    var int i, sum;
+                    let i = 0;
+                    while (i < 5) {
+                        if (i = 1) { let sum = sum * (2 + n); }
+                        else { if (i = 2) { let sum = sum - (i * 3); }
+                        else { let sum[i] = sum[i] | -1; } }
+                        let i = i + 1;
+                    }
+                    while (true) { return 1 + (n * 2) - (6 / n / 2) < 0; }
+                }
+            }",
+        )
+        .unwrap();
+        assert_eq!(xml::class(&extended), xml::class(&standard));
+
+        let errors = parse_dialect(
+            "class Main {
+                const int N = 1;
+                static int x;
+                const int M = x;
+                function void main(int M) {
+                    var int N;
+                    let N = M;
+                    let M = 1;
+                    break;
+                    for (;;) { if (N) { continue; } }
+                    return;
+                }
+                method void f() { let N = 2; return; }
+            }",
+            Dialect::Extended,
+        )
+        .unwrap_err();
+        let messages = errors.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            [
+                "4:31: The value of M is not a constant",
+                "9:21: break outside a loop",
+                "13:39: N is a constant",
+            ]
+        );
+    }
+
+    #[test]
+    fn continue_in_a_for_loop_runs_the_update_first() {
+        let class = parse_dialect(
+            "class Main {
+                function void main() {
+                    var int i;
+                    for (let i = 0; i < 9; let i = i + 1) {
+                        if (i = 3) { continue; }
+                        while (i) { continue; }
+                    }
+                    return;
+                }
+            }",
+            Dialect::Extended,
+        )
+        .unwrap();
+        let body = match &class.subroutines[0].body[1] {
+            Statement::While { body, .. } => body,
+            statement => panic!("unexpected {:?}", statement),
+        };
+        assert!(matches!(
+            &body[0],
+            Statement::If { then, .. }
+                if matches!(then[..], [Statement::Let { .. }, Statement::Continue { .. }])
+        ));
+        assert!(matches!(
+            &body[1],
+            Statement::While { body, .. } if matches!(body[..], [Statement::Continue { .. }])
+        ));
+        assert!(matches!(body[2], Statement::Let { .. }));
+    }
+}
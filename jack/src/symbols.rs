@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+
+use vm::command::Segment;
+
+use crate::ast::Type;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Static,
+    Field,
+    Argument,
+    Local,
+}
+
+impl Kind {
+    pub fn segment(&self) -> Segment {
+        match self {
+            Kind::Static => Segment::Static,
+            Kind::Field => Segment::This,
+            Kind::Argument => Segment::Argument,
+            Kind::Local => Segment::Local,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    pub ty: Type,
+    pub kind: Kind,
+    pub index: u16,
+}
+
+// The class scope holds the statics and fields, and the subroutine scope the
+// arguments and locals, which hide class variables of the same name.
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    class: HashMap<String, Symbol>,
+    subroutine: HashMap<String, Symbol>,
+    counts: [u16; 4],
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Clears the arguments and locals for the next subroutine.
+    pub fn start_subroutine(&mut self) {
+        self.subroutine.clear();
+        self.counts[Kind::Argument as usize] = 0;
+        self.counts[Kind::Local as usize] = 0;
+    }
+
+    pub fn define(&mut self, name: &str, ty: Type, kind: Kind) {
+        let index = self.counts[kind as usize];
+        self.counts[kind as usize] += 1;
+        let scope = match kind {
+            Kind::Static | Kind::Field => &mut self.class,
+            Kind::Argument | Kind::Local => &mut self.subroutine,
+        };
+        scope.insert(name.to_string(), Symbol { ty, kind, index });
+    }
+
+    pub fn count(&self, kind: Kind) -> u16 {
+        self.counts[kind as usize]
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.subroutine.get(name).or_else(|| self.class.get(name))
+    }
+}
@@ -0,0 +1,312 @@
+use std::fmt;
+
+use computer::{keyboard::DummyKeyboard, screen::DummyScreen, Computer};
+
+use crate::{
+    ast::{Class, Expression, Identifier, Statement, SubroutineCall, SubroutineKind, Term},
+    codegen::CompileError,
+    direct::{self, compile_program},
+    os,
+    parser::parse,
+    span::Span,
+};
+
+// The class tests check their results with.
+pub const ASSERT: &str = include_str!("../testing/Assert.jack");
+
+// Where Assert keeps the results of a test, as described in Assert.jack: the
+// first statics, which the direct backend lays out from 16.
+const RESULTS: u16 = 16;
+
+pub fn assert_class() -> Class {
+    parse(ASSERT).expect("Assert parses")
+}
+
+// A function of a test class, whose name ends in Test, taking no arguments
+// and named test...
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Test {
+    pub class: String,
+    pub function: String,
+}
+
+impl fmt::Display for Test {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.class, self.function)
+    }
+}
+
+// The tests of the classes, in the order they are declared.
+pub fn discover(classes: &[Class]) -> Vec<Test> {
+    classes
+        .iter()
+        .filter(|class| class.name.name.ends_with("Test"))
+        .flat_map(|class| {
+            class
+                .subroutines
+                .iter()
+                .filter(|subroutine| {
+                    subroutine.kind == SubroutineKind::Function
+                        && subroutine.name.name.starts_with("test")
+                        && subroutine.parameters.is_empty()
+                })
+                .map(move |subroutine| Test {
+                    class: class.name.name.clone(),
+                    function: subroutine.name.name.clone(),
+                })
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Passed {
+        assertions: u16,
+        cycles: u64,
+    },
+    // The first assertion to fail, counting from 1, and how many did.
+    Failed {
+        assertion: u16,
+        actual: u16,
+        expected: u16,
+        failures: u16,
+    },
+    Error(u16),
+    // The test called Sys.halt instead of returning.
+    Halted,
+    TimedOut,
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        matches!(self, Outcome::Passed { .. })
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Passed { assertions, cycles } => write!(
+                f,
+                "ok ({} assertion{}, {} cycles)",
+                assertions,
+                if *assertions == 1 { "" } else { "s" },
+                cycles
+            ),
+            Outcome::Failed {
+                assertion,
+                actual,
+                expected,
+                failures,
+            } => {
+                write!(
+                    f,
+                    "FAILED: assertion {} got {}, expected {}",
+                    assertion, *actual as i16, *expected as i16
+                )?;
+                if *failures > 1 {
+                    write!(f, " ({} assertions failed)", failures)?;
+                }
+                Ok(())
+            }
+            Outcome::Error(code) => write!(f, "FAILED: Sys.error({})", code),
+            Outcome::Halted => write!(f, "FAILED: halted without returning"),
+            Outcome::TimedOut => write!(f, "FAILED: did not return in time"),
+        }
+    }
+}
+
+fn identifier(name: &str) -> Identifier {
+    Identifier {
+        name: name.to_string(),
+        span: Span::default(),
+    }
+}
+
+fn call(class: &str, function: &str, arguments: Vec<Expression>) -> Statement {
+    Statement::Do {
+        call: SubroutineCall {
+            receiver: Some(identifier(class)),
+            name: identifier(function),
+            arguments,
+            span: Span::default(),
+        },
+        span: Span::default(),
+    }
+}
+
+// The OS Sys class, starting the test instead of Main.main and letting Assert
+// know when it returns or runs into an error.
+fn sys(test: &Test) -> Class {
+    let mut sys = os::classes()
+        .into_iter()
+        .find(|class| class.name.name == "Sys")
+        .expect("the OS has a Sys class");
+    for subroutine in &mut sys.subroutines {
+        match subroutine.name.name.as_str() {
+            "init" => {
+                let main = subroutine
+                    .body
+                    .iter()
+                    .position(|statement| {
+                        matches!(statement, Statement::Do { call, .. } if call.name.name == "main")
+                    })
+                    .expect("Sys.init calls Main.main");
+                let calls = vec![
+                    call(&test.class, &test.function, vec![]),
+                    call("Assert", "finish", vec![]),
+                ];
+                subroutine.body.splice(main..=main, calls);
+            }
+            "error" => {
+                let code = Expression {
+                    first: Term::Variable(subroutine.parameters[0].name.clone()),
+                    rest: vec![],
+                    span: Span::default(),
+                };
+                subroutine
+                    .body
+                    .insert(0, call("Assert", "error", vec![code]));
+            }
+            _ => {}
+        }
+    }
+    sys
+}
+
+// Runs a test of the program, made of the OS, Assert and the program's
+// classes, on a fresh computer for at most `max_cycles`.
+pub fn run(
+    program: &[Class],
+    test: &Test,
+    optimize: bool,
+    max_cycles: u64,
+) -> Result<Outcome, Vec<(String, CompileError)>> {
+    // Assert goes first, so that its statics hold the results at RESULTS.
+    let (mut classes, rest): (Vec<Class>, Vec<Class>) = program
+        .iter()
+        .cloned()
+        .partition(|class| class.name.name == "Assert");
+    classes.extend(rest);
+    classes.push(sys(test));
+    let program = compile_program(&classes, optimize)?;
+    let mut computer = Computer::<DummyScreen, DummyKeyboard>::new();
+    let cycles = direct::run(&mut computer, &program, max_cycles);
+    let results: Vec<u16> = (RESULTS..RESULTS + 8)
+        .map(|address| computer.peek(address))
+        .collect();
+    Ok(match (cycles, results.as_slice()) {
+        (_, &[_, failures, assertion, actual, expected, ..]) if failures > 0 => Outcome::Failed {
+            assertion,
+            actual,
+            expected,
+            failures,
+        },
+        (_, &[.., 0xFFFF, code]) => Outcome::Error(code),
+        (None, _) => Outcome::TimedOut,
+        (Some(cycles), &[assertions, _, _, _, _, 0xFFFF, ..]) => {
+            Outcome::Passed { assertions, cycles }
+        }
+        (Some(_), _) => Outcome::Halted,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tests_run_and_report_their_assertions() {
+        let counter = "class Counter {
+            function int twice(int n) { return n + n; }
+        }";
+        let tests = "class CounterTest {
+            function void testTwice() {
+                do Assert.equals(Counter.twice(21), 42);
+                do Assert.isTrue(Counter.twice(0) = 0);
+                return;
+            }
+            function void testWrong() {
+                do Assert.isFalse(false);
+                do Assert.equals(Counter.twice(-3), 6);
+                do Assert.equals(1, 2);
+                return;
+            }
+            function void testError() {
+                do Assert.equals(1 / 0, 0);
+                return;
+            }
+            function void testForever() {
+                while (true) {}
+                return;
+            }
+            function void testHalt() {
+                do Sys.halt();
+                return;
+            }
+            function void helper() { return; }
+            function void testArguments(int n) { return; }
+        }";
+        let mut program = os::classes();
+        program.push(assert_class());
+        program.push(parse(counter).unwrap());
+        program.push(parse(tests).unwrap());
+        let tests = discover(&program);
+        let names = tests.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "CounterTest.testTwice",
+                "CounterTest.testWrong",
+                "CounterTest.testError",
+                "CounterTest.testForever",
+                "CounterTest.testHalt",
+            ]
+        );
+        let outcomes = tests
+            .iter()
+            .map(|test| run(&program, test, false, 200_000).unwrap())
+            .collect::<Vec<_>>();
+        assert!(matches!(outcomes[0], Outcome::Passed { assertions: 2, .. }));
+        assert_eq!(
+            outcomes[1],
+            Outcome::Failed {
+                assertion: 2,
+                actual: -6i16 as u16,
+                expected: 6,
+                failures: 2,
+            }
+        );
+        assert_eq!(
+            outcomes[1].to_string(),
+            "FAILED: assertion 2 got -6, expected 6 (2 assertions failed)"
+        );
+        assert_eq!(outcomes[2], Outcome::Error(3));
+        assert_eq!(outcomes[3], Outcome::TimedOut);
+        assert_eq!(outcomes[4], Outcome::Halted);
+    }
+
+    #[test]
+    fn results_survive_clearing_the_screen() {
+        let tests = "class ScreenTest {
+            function void testClear() {
+                do Assert.equals(1, 2);
+                do Screen.clearScreen();
+                return;
+            }
+        }";
+        let mut program = os::classes();
+        program.push(assert_class());
+        program.push(parse(tests).unwrap());
+        let tests = discover(&program);
+        assert_eq!(
+            run(&program, &tests[0], true, 1_000_000).unwrap(),
+            Outcome::Failed {
+                assertion: 1,
+                actual: 1,
+                expected: 2,
+                failures: 1,
+            }
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use std::fmt;
+
+use crate::span::Span;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Keyword {
+    Class,
+    Constructor,
+    Function,
+    Method,
+    Field,
+    Static,
+    Var,
+    Int,
+    Char,
+    Boolean,
+    Void,
+    True,
+    False,
+    Null,
+    This,
+    Let,
+    Do,
+    If,
+    Else,
+    While,
+    Return,
+    // The extended dialect's keywords, which are names in standard Jack.
+    For,
+    Break,
+    Continue,
+    Const,
+}
+
+impl Keyword {
+    pub const ALL: [Keyword; 25] = [
+        Keyword::Class,
+        Keyword::Constructor,
+        Keyword::Function,
+        Keyword::Method,
+        Keyword::Field,
+        Keyword::Static,
+        Keyword::Var,
+        Keyword::Int,
+        Keyword::Char,
+        Keyword::Boolean,
+        Keyword::Void,
+        Keyword::True,
+        Keyword::False,
+        Keyword::Null,
+        Keyword::This,
+        Keyword::Let,
+        Keyword::Do,
+        Keyword::If,
+        Keyword::Else,
+        Keyword::While,
+        Keyword::Return,
+        Keyword::For,
+        Keyword::Break,
+        Keyword::Continue,
+        Keyword::Const,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|keyword| keyword.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Keyword::Class => "class",
+            Keyword::Constructor => "constructor",
+            Keyword::Function => "function",
+            Keyword::Method => "method",
+            Keyword::Field => "field",
+            Keyword::Static => "static",
+            Keyword::Var => "var",
+            Keyword::Int => "int",
+            Keyword::Char => "char",
+            Keyword::Boolean => "boolean",
+            Keyword::Void => "void",
+            Keyword::True => "true",
+            Keyword::False => "false",
+            Keyword::Null => "null",
+            Keyword::This => "this",
+            Keyword::Let => "let",
+            Keyword::Do => "do",
+            Keyword::If => "if",
+            Keyword::Else => "else",
+            Keyword::While => "while",
+            Keyword::Return => "return",
+            Keyword::For => "for",
+            Keyword::Break => "break",
+            Keyword::Continue => "continue",
+            Keyword::Const => "const",
+        }
+    }
+
+    pub fn is_extended(&self) -> bool {
+        matches!(
+            self,
+            Keyword::For | Keyword::Break | Keyword::Continue | Keyword::Const
+        )
+    }
+}
+
+impl fmt::Display for Keyword {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// Standard Jack, or Jack with for loops, break and continue, constants,
+// compound assignment, else if chains, character literals and the usual
+// precedence of * and / over + and -.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Dialect {
+    #[default]
+    Standard,
+    Extended,
+}
+
+pub const SYMBOLS: &str = "{}()[].,;+-*/&|<>=~";
+
+// The escapes in character literals and the Hack codes they stand for.
+pub const CHAR_ESCAPES: [(char, u16); 4] = [('n', 128), ('b', 129), ('\\', 92), ('\'', 39)];
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    Keyword(Keyword),
+    Symbol(char),
+    IntegerConstant(u16),
+    StringConstant(String),
+    Identifier(String),
+    // A character literal of the extended dialect, as its Hack code.
+    CharConstant(u16),
+}
+
+impl TokenKind {
+    // The element name in the course's XML formats.
+    pub fn tag(&self) -> &'static str {
+        match self {
+            TokenKind::Keyword(_) => "keyword",
+            TokenKind::Symbol(_) => "symbol",
+            TokenKind::IntegerConstant(_) => "integerConstant",
+            TokenKind::StringConstant(_) => "stringConstant",
+            TokenKind::Identifier(_) => "identifier",
+            TokenKind::CharConstant(_) => "charConstant",
+        }
+    }
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenKind::Keyword(keyword) => write!(f, "{}", keyword),
+            TokenKind::Symbol(symbol) => write!(f, "{}", symbol),
+            TokenKind::IntegerConstant(value) => write!(f, "{}", value),
+            TokenKind::StringConstant(string) => write!(f, "{}", string),
+            TokenKind::Identifier(name) => write!(f, "{}", name),
+            TokenKind::CharConstant(code) => match CHAR_ESCAPES.iter().find(|e| e.1 == *code) {
+                Some((escape, _)) => write!(f, "'\\{}'", escape),
+                None => write!(f, "'{}'", *code as u8 as char),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+// A comment as written, kept aside by the tokenizer. A trailing comment
+// follows a token on its line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Comment {
+    pub text: String,
+    pub span: Span,
+    pub trailing: bool,
+}
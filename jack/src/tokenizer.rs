@@ -0,0 +1,375 @@
+use std::{iter::Peekable, str::CharIndices};
+
+use thiserror::Error;
+
+use crate::{
+    span::Span,
+    token::{Comment, Dialect, Keyword, Token, TokenKind, CHAR_ESCAPES, SYMBOLS},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum TokenizeError {
+    #[error("{0}: Unterminated comment")]
+    UnterminatedComment(Span),
+
+    #[error("{0}: Unterminated string constant")]
+    UnterminatedString(Span),
+
+    #[error("{0}: Invalid character {1:?}")]
+    InvalidCharacter(Span, char),
+
+    #[error("{0}: {1} is larger than 32767")]
+    IntegerOutOfRange(Span, String),
+
+    #[error("{0}: Invalid character constant")]
+    InvalidCharConstant(Span),
+}
+
+impl TokenizeError {
+    pub fn span(&self) -> Span {
+        match self {
+            TokenizeError::UnterminatedComment(span)
+            | TokenizeError::UnterminatedString(span)
+            | TokenizeError::InvalidCharacter(span, _)
+            | TokenizeError::IntegerOutOfRange(span, _)
+            | TokenizeError::InvalidCharConstant(span) => *span,
+        }
+    }
+}
+
+// Yields tokens and errors in source order. Tokenizing carries on after an
+// error, skipping the offending characters.
+pub struct Tokenizer<'a> {
+    source: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    line: usize,
+    column: usize,
+    dialect: Dialect,
+    comments: Vec<Comment>,
+    // The line of the last token.
+    token_line: Option<usize>,
+}
+
+impl<'a> Tokenizer<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self::with_dialect(source, Dialect::Standard)
+    }
+
+    pub fn with_dialect(source: &'a str, dialect: Dialect) -> Self {
+        Self {
+            source,
+            chars: source.char_indices().peekable(),
+            line: 1,
+            column: 1,
+            dialect,
+            comments: vec![],
+            token_line: None,
+        }
+    }
+
+    // The comments skipped so far, in source order.
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
+    }
+
+    fn comment(&mut self, span: Span) {
+        let span = self.finish(span);
+        self.comments.push(Comment {
+            text: self.source[span.start..span.end].trim_end().to_string(),
+            span,
+            trailing: self.token_line == Some(span.line),
+        });
+    }
+
+    fn offset(&mut self) -> usize {
+        self.chars
+            .peek()
+            .map_or(self.source.len(), |&(offset, _)| offset)
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|&(_, c)| c)
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        let mut chars = self.chars.clone();
+        chars.next();
+        chars.next().map(|(_, c)| c)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let (_, c) = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn start(&mut self) -> Span {
+        let start = self.offset();
+        Span {
+            start,
+            end: start,
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    fn finish(&mut self, span: Span) -> Span {
+        Span {
+            end: self.offset(),
+            ..span
+        }
+    }
+
+    // Skips whitespace and comments.
+    fn skip_trivia(&mut self) -> Result<(), TokenizeError> {
+        loop {
+            match (self.peek(), self.peek_second()) {
+                (Some(c), _) if c.is_whitespace() => {
+                    self.bump();
+                }
+                (Some('/'), Some('/')) => {
+                    let span = self.start();
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.bump();
+                    }
+                    self.comment(span);
+                }
+                (Some('/'), Some('*')) => {
+                    let span = self.start();
+                    self.bump();
+                    self.bump();
+                    loop {
+                        match self.bump() {
+                            Some('*') if self.peek() == Some('/') => {
+                                self.bump();
+                                self.comment(span);
+                                break;
+                            }
+                            Some(_) => {}
+                            None => {
+                                return Err(TokenizeError::UnterminatedComment(self.finish(span)))
+                            }
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    // The Hack code of a character literal whose opening quote has been read:
+    // a printable character or an escape, then the closing quote. Carries on
+    // to the closing quote or the end of the line after an error.
+    fn char_constant(&mut self) -> Option<u16> {
+        let code = match self.bump() {
+            Some('\'') | Some('\n') | None => return None,
+            Some('\\') => {
+                let escape = self.bump();
+                CHAR_ESCAPES
+                    .iter()
+                    .find(|&&(c, _)| Some(c) == escape)
+                    .map(|&(_, code)| code)
+            }
+            Some(c @ ' '..='~') => Some(c as u16),
+            _ => None,
+        };
+        if self.peek() == Some('\'') {
+            self.bump();
+            return code;
+        }
+        while self.peek().is_some_and(|c| c != '\'' && c != '\n') {
+            self.bump();
+        }
+        if self.peek() == Some('\'') {
+            self.bump();
+        }
+        None
+    }
+
+    fn token(&mut self) -> Option<Result<Token, TokenizeError>> {
+        if let Err(error) = self.skip_trivia() {
+            return Some(Err(error));
+        }
+        let span = self.start();
+        let c = self.bump()?;
+        let kind = if SYMBOLS.contains(c) {
+            TokenKind::Symbol(c)
+        } else if c == '"' {
+            loop {
+                match self.peek() {
+                    Some('"') => {
+                        self.bump();
+                        break;
+                    }
+                    Some('\n') | None => {
+                        return Some(Err(TokenizeError::UnterminatedString(self.finish(span))))
+                    }
+                    Some(_) => {
+                        self.bump();
+                    }
+                }
+            }
+            let span = self.finish(span);
+            TokenKind::StringConstant(self.source[span.start + 1..span.end - 1].to_string())
+        } else if c.is_ascii_digit() {
+            while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                self.bump();
+            }
+            let span = self.finish(span);
+            let digits = &self.source[span.start..span.end];
+            match digits.parse::<u16>() {
+                Ok(value) if value <= 0x7FFF => TokenKind::IntegerConstant(value),
+                _ => {
+                    return Some(Err(TokenizeError::IntegerOutOfRange(
+                        span,
+                        digits.to_string(),
+                    )))
+                }
+            }
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            while self
+                .peek()
+                .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+            {
+                self.bump();
+            }
+            let span = self.finish(span);
+            let word = &self.source[span.start..span.end];
+            match Keyword::from_name(word) {
+                Some(keyword) if self.dialect == Dialect::Extended || !keyword.is_extended() => {
+                    TokenKind::Keyword(keyword)
+                }
+                _ => TokenKind::Identifier(word.to_string()),
+            }
+        } else if c == '\'' && self.dialect == Dialect::Extended {
+            match self.char_constant() {
+                Some(code) => TokenKind::CharConstant(code),
+                None => return Some(Err(TokenizeError::InvalidCharConstant(self.finish(span)))),
+            }
+        } else {
+            return Some(Err(TokenizeError::InvalidCharacter(self.finish(span), c)));
+        };
+        self.token_line = Some(span.line);
+        Some(Ok(Token {
+            kind,
+            span: self.finish(span),
+        }))
+    }
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = Result<Token, TokenizeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.token()
+    }
+}
+
+// Stops at the first error.
+pub fn tokenize(source: &str) -> Result<Vec<Token>, TokenizeError> {
+    Tokenizer::new(source).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenizer_reads_every_kind_of_token() {
+        let source =
+            "/** Doc */\nclass Main {\n  // comment\n  let s = \"a // b\"; do x.y(32767, _z1);\n}";
+        let tokens = tokenize(source).unwrap();
+        let kinds = tokens
+            .iter()
+            .map(|token| token.kind.clone())
+            .collect::<Vec<_>>();
+        let identifier = |name: &str| TokenKind::Identifier(name.to_string());
+        assert_eq!(
+            kinds,
+            [
+                TokenKind::Keyword(Keyword::Class),
+                identifier("Main"),
+                TokenKind::Symbol('{'),
+                TokenKind::Keyword(Keyword::Let),
+                identifier("s"),
+                TokenKind::Symbol('='),
+                TokenKind::StringConstant("a // b".to_string()),
+                TokenKind::Symbol(';'),
+                TokenKind::Keyword(Keyword::Do),
+                identifier("x"),
+                TokenKind::Symbol('.'),
+                identifier("y"),
+                TokenKind::Symbol('('),
+                TokenKind::IntegerConstant(32767),
+                TokenKind::Symbol(','),
+                identifier("_z1"),
+                TokenKind::Symbol(')'),
+                TokenKind::Symbol(';'),
+                TokenKind::Symbol('}'),
+            ]
+        );
+        let string = &tokens[6].span;
+        assert_eq!((string.line, string.column), (4, 11));
+        assert_eq!(&source[string.start..string.end], "\"a // b\"");
+    }
+
+    #[test]
+    fn tokenizer_keeps_comments_aside() {
+        let mut tokenizer = Tokenizer::new("/** Doc\n */\nclass Main { // Main  \n}");
+        assert_eq!(tokenizer.by_ref().count(), 4);
+        let comments = tokenizer.comments();
+        assert_eq!(comments.len(), 2);
+        assert_eq!(comments[0].text, "/** Doc\n */");
+        assert!(!comments[0].trailing);
+        assert_eq!(comments[1].text, "// Main");
+        assert_eq!((comments[1].span.line, comments[1].span.column), (3, 14));
+        assert!(comments[1].trailing);
+    }
+
+    #[test]
+    fn tokenizer_reports_errors_and_carries_on() {
+        let results =
+            Tokenizer::new("let x = 40000 # y;\n\"open\n/* never closed").collect::<Vec<_>>();
+        let errors = results
+            .iter()
+            .filter_map(|result| result.as_ref().err())
+            .collect::<Vec<_>>();
+        assert!(matches!(errors[0], TokenizeError::IntegerOutOfRange(span, _) if span.column == 9));
+        assert!(matches!(errors[1], TokenizeError::InvalidCharacter(_, '#')));
+        assert!(matches!(errors[2], TokenizeError::UnterminatedString(span) if span.line == 2));
+        assert!(matches!(errors[3], TokenizeError::UnterminatedComment(span) if span.line == 3));
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 5);
+    }
+
+    #[test]
+    fn extended_dialect_adds_keywords_and_character_literals() {
+        let kinds = |dialect| {
+            Tokenizer::with_dialect("for 'a' '\\n' '' 'ab'", dialect)
+                .map(|result| result.map(|token| token.kind))
+                .collect::<Vec<_>>()
+        };
+        let extended = kinds(Dialect::Extended);
+        assert_eq!(extended[0], Ok(TokenKind::Keyword(Keyword::For)));
+        assert_eq!(extended[1], Ok(TokenKind::CharConstant(97)));
+        assert_eq!(extended[2], Ok(TokenKind::CharConstant(128)));
+        assert!(
+            matches!(extended[3], Err(TokenizeError::InvalidCharConstant(span)) if span.column == 14)
+        );
+        assert!(
+            matches!(extended[4], Err(TokenizeError::InvalidCharConstant(span)) if span.column == 17)
+        );
+        assert_eq!(extended.len(), 5);
+        assert_eq!(TokenKind::CharConstant(128).to_string(), "'\\n'");
+        let standard = kinds(Dialect::Standard);
+        assert_eq!(standard[0], Ok(TokenKind::Identifier("for".to_string())));
+        assert!(matches!(
+            standard[1],
+            Err(TokenizeError::InvalidCharacter(_, '\''))
+        ));
+    }
+}
@@ -0,0 +1,402 @@
+use crate::{
+    ast::{
+        Class, ClassVarKind, Expression, Statement, Subroutine, SubroutineCall, SubroutineKind,
+        Term, Type,
+    },
+    token::{Keyword, Token, TokenKind},
+};
+
+pub fn escape(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '<' => "&lt;".to_string(),
+            '>' => "&gt;".to_string(),
+            '"' => "&quot;".to_string(),
+            '&' => "&amp;".to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+pub fn token(kind: &TokenKind) -> String {
+    format!("<{0}> {1} </{0}>", kind.tag(), escape(&kind.to_string()))
+}
+
+// The xxxT.xml format of project 10.
+pub fn tokens(tokens: &[Token]) -> String {
+    let mut xml = String::from("<tokens>\n");
+    for token in tokens {
+        xml.push_str(&self::token(&token.kind));
+        xml.push('\n');
+    }
+    xml.push_str("</tokens>\n");
+    xml
+}
+
+// Writes the xxx.xml parse tree of project 10, which interleaves the tokens
+// with an element for each nonterminal.
+struct Tree {
+    xml: String,
+    depth: usize,
+}
+
+impl Tree {
+    fn line(&mut self, text: &str) {
+        self.xml.push_str(&"  ".repeat(self.depth));
+        self.xml.push_str(text);
+        self.xml.push('\n');
+    }
+
+    fn open(&mut self, tag: &str) {
+        self.line(&format!("<{}>", tag));
+        self.depth += 1;
+    }
+
+    fn close(&mut self, tag: &str) {
+        self.depth -= 1;
+        self.line(&format!("</{}>", tag));
+    }
+
+    fn token(&mut self, kind: TokenKind) {
+        self.line(&token(&kind));
+    }
+
+    fn keyword(&mut self, keyword: Keyword) {
+        self.token(TokenKind::Keyword(keyword));
+    }
+
+    fn symbol(&mut self, symbol: char) {
+        self.token(TokenKind::Symbol(symbol));
+    }
+
+    fn identifier(&mut self, name: &str) {
+        self.token(TokenKind::Identifier(name.to_string()));
+    }
+
+    fn ty(&mut self, ty: &Type) {
+        match ty {
+            Type::Int => self.keyword(Keyword::Int),
+            Type::Char => self.keyword(Keyword::Char),
+            Type::Boolean => self.keyword(Keyword::Boolean),
+            Type::Class(name) => self.identifier(name),
+        }
+    }
+
+    fn names<'a>(&mut self, names: impl Iterator<Item = &'a str>) {
+        for (i, name) in names.enumerate() {
+            if i > 0 {
+                self.symbol(',');
+            }
+            self.identifier(name);
+        }
+        self.symbol(';');
+    }
+
+    fn class(&mut self, class: &Class) {
+        self.open("class");
+        self.keyword(Keyword::Class);
+        self.identifier(&class.name.name);
+        self.symbol('{');
+        for variable in &class.variables {
+            self.open("classVarDec");
+            self.keyword(match variable.kind {
+                ClassVarKind::Static => Keyword::Static,
+                ClassVarKind::Field => Keyword::Field,
+            });
+            self.ty(&variable.ty);
+            self.names(variable.names.iter().map(|name| name.name.as_str()));
+            self.close("classVarDec");
+        }
+        for subroutine in &class.subroutines {
+            self.subroutine(subroutine);
+        }
+        self.symbol('}');
+        self.close("class");
+    }
+
+    fn subroutine(&mut self, subroutine: &Subroutine) {
+        self.open("subroutineDec");
+        self.keyword(match subroutine.kind {
+            SubroutineKind::Constructor => Keyword::Constructor,
+            SubroutineKind::Function => Keyword::Function,
+            SubroutineKind::Method => Keyword::Method,
+        });
+        match &subroutine.return_type {
+            Some(ty) => self.ty(ty),
+            None => self.keyword(Keyword::Void),
+        }
+        self.identifier(&subroutine.name.name);
+        self.symbol('(');
+        self.open("parameterList");
+        for (i, parameter) in subroutine.parameters.iter().enumerate() {
+            if i > 0 {
+                self.symbol(',');
+            }
+            self.ty(&parameter.ty);
+            self.identifier(&parameter.name.name);
+        }
+        self.close("parameterList");
+        self.symbol(')');
+        self.open("subroutineBody");
+        self.symbol('{');
+        for local in &subroutine.locals {
+            self.open("varDec");
+            self.keyword(Keyword::Var);
+            self.ty(&local.ty);
+            self.names(local.names.iter().map(|name| name.name.as_str()));
+            self.close("varDec");
+        }
+        self.statements(&subroutine.body);
+        self.symbol('}');
+        self.close("subroutineBody");
+        self.close("subroutineDec");
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        self.open("statements");
+        for statement in statements {
+            self.statement(statement);
+        }
+        self.close("statements");
+    }
+
+    fn block(&mut self, statements: &[Statement]) {
+        self.symbol('{');
+        self.statements(statements);
+        self.symbol('}');
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let {
+                name, index, value, ..
+            } => {
+                self.open("letStatement");
+                self.keyword(Keyword::Let);
+                self.identifier(&name.name);
+                if let Some(index) = index {
+                    self.symbol('[');
+                    self.expression(index);
+                    self.symbol(']');
+                }
+                self.symbol('=');
+                self.expression(value);
+                self.symbol(';');
+                self.close("letStatement");
+            }
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                self.open("ifStatement");
+                self.keyword(Keyword::If);
+                self.symbol('(');
+                self.expression(condition);
+                self.symbol(')');
+                self.block(then);
+                if let Some(otherwise) = otherwise {
+                    self.keyword(Keyword::Else);
+                    self.block(otherwise);
+                }
+                self.close("ifStatement");
+            }
+            Statement::While {
+                condition, body, ..
+            } => {
+                self.open("whileStatement");
+                self.keyword(Keyword::While);
+                self.symbol('(');
+                self.expression(condition);
+                self.symbol(')');
+                self.block(body);
+                self.close("whileStatement");
+            }
+            Statement::Do { call, .. } => {
+                self.open("doStatement");
+                self.keyword(Keyword::Do);
+                self.call(call);
+                self.symbol(';');
+                self.close("doStatement");
+            }
+            Statement::Return { value, .. } => {
+                self.open("returnStatement");
+                self.keyword(Keyword::Return);
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+                self.symbol(';');
+                self.close("returnStatement");
+            }
+            Statement::Break { .. } => {
+                self.open("breakStatement");
+                self.keyword(Keyword::Break);
+                self.symbol(';');
+                self.close("breakStatement");
+            }
+            Statement::Continue { .. } => {
+                self.open("continueStatement");
+                self.keyword(Keyword::Continue);
+                self.symbol(';');
+                self.close("continueStatement");
+            }
+        }
+    }
+
+    fn call(&mut self, call: &SubroutineCall) {
+        if let Some(receiver) = &call.receiver {
+            self.identifier(&receiver.name);
+            self.symbol('.');
+        }
+        self.identifier(&call.name.name);
+        self.symbol('(');
+        self.open("expressionList");
+        for (i, argument) in call.arguments.iter().enumerate() {
+            if i > 0 {
+                self.symbol(',');
+            }
+            self.expression(argument);
+        }
+        self.close("expressionList");
+        self.symbol(')');
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        self.open("expression");
+        self.term(&expression.first);
+        for (op, term) in &expression.rest {
+            self.symbol(op.symbol());
+            self.term(term);
+        }
+        self.close("expression");
+    }
+
+    fn term(&mut self, term: &Term) {
+        self.open("term");
+        match term {
+            Term::Integer(value, _) => self.token(TokenKind::IntegerConstant(*value)),
+            Term::String(string, _) => self.token(TokenKind::StringConstant(string.clone())),
+            Term::Keyword(constant, _) => {
+                self.keyword(Keyword::from_name(constant.name()).unwrap())
+            }
+            Term::Variable(name) => self.identifier(&name.name),
+            Term::Index(name, index, _) => {
+                self.identifier(&name.name);
+                self.symbol('[');
+                self.expression(index);
+                self.symbol(']');
+            }
+            Term::Call(call) => self.call(call),
+            Term::Parenthesized(expression, _) => {
+                self.symbol('(');
+                self.expression(expression);
+                self.symbol(')');
+            }
+            Term::Unary(op, term, _) => {
+                self.symbol(op.symbol());
+                self.term(term);
+            }
+        }
+        self.close("term");
+    }
+}
+
+pub fn class(class: &Class) -> String {
+    let mut tree = Tree {
+        xml: String::new(),
+        depth: 0,
+    };
+    tree.class(class);
+    tree.xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::parse, tokenizer::tokenize};
+
+    #[test]
+    fn token_xml_escapes_symbols() {
+        let tokens = tokenize("if (x < \"a&b\") {}").unwrap();
+        assert_eq!(
+            self::tokens(&tokens),
+            "<tokens>\n\
+             <keyword> if </keyword>\n\
+             <symbol> ( </symbol>\n\
+             <identifier> x </identifier>\n\
+             <symbol> &lt; </symbol>\n\
+             <stringConstant> a&amp;b </stringConstant>\n\
+             <symbol> ) </symbol>\n\
+             <symbol> { </symbol>\n\
+             <symbol> } </symbol>\n\
+             </tokens>\n"
+        );
+    }
+
+    #[test]
+    fn parse_tree_xml_interleaves_tokens_and_nonterminals() {
+        let class =
+            parse("class A { function void f(int x) { do g(x[1], -x); return; } }").unwrap();
+        let expected = "\
+<class>
+  <keyword> class </keyword>
+  <identifier> A </identifier>
+  <symbol> { </symbol>
+  <subroutineDec>
+    <keyword> function </keyword>
+    <keyword> void </keyword>
+    <identifier> f </identifier>
+    <symbol> ( </symbol>
+    <parameterList>
+      <keyword> int </keyword>
+      <identifier> x </identifier>
+    </parameterList>
+    <symbol> ) </symbol>
+    <subroutineBody>
+      <symbol> { </symbol>
+      <statements>
+        <doStatement>
+          <keyword> do </keyword>
+          <identifier> g </identifier>
+          <symbol> ( </symbol>
+          <expressionList>
+            <expression>
+              <term>
+                <identifier> x </identifier>
+                <symbol> [ </symbol>
+                <expression>
+                  <term>
+                    <integerConstant> 1 </integerConstant>
+                  </term>
+                </expression>
+                <symbol> ] </symbol>
+              </term>
+            </expression>
+            <symbol> , </symbol>
+            <expression>
+              <term>
+                <symbol> - </symbol>
+                <term>
+                  <identifier> x </identifier>
+                </term>
+              </term>
+            </expression>
+          </expressionList>
+          <symbol> ) </symbol>
+          <symbol> ; </symbol>
+        </doStatement>
+        <returnStatement>
+          <keyword> return </keyword>
+          <symbol> ; </symbol>
+        </returnStatement>
+      </statements>
+      <symbol> } </symbol>
+    </subroutineBody>
+  </subroutineDec>
+  <symbol> } </symbol>
+</class>
+";
+        assert_eq!(self::class(&class), expected);
+    }
+}
@@ -0,0 +1,457 @@
+use std::fmt::Write;
+
+use assembler::{
+    flow::{BasicBlock, ControlFlowGraph, Exit, Target},
+    instruction::{Comp, Instruction, Jump},
+};
+
+const RUNTIME: &str = r#"pub const SCREEN: u16 = 0x4000;
+pub const KBD: u16 = 0x6000;
+
+pub trait Io {
+    fn keyboard(&mut self) -> u16 {
+        0
+    }
+
+    fn screen(&mut self, _address: u16, _value: u16) {}
+}
+
+pub struct NoIo;
+
+impl Io for NoIo {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Running,
+    Halted,
+}
+
+pub struct Machine<I: Io> {
+    pub a: u16,
+    pub d: u16,
+    pub pc: u16,
+    pub ram: Box<[u16; 32768]>,
+    pub io: I,
+    pub cycles: u64,
+}
+
+#[inline(always)]
+fn read<I: Io>(ram: &[u16; 32768], io: &mut I, address: u16) -> u16 {
+    let address = address & 0x7FFF;
+    if address < KBD {
+        ram[address as usize]
+    } else {
+        io.keyboard()
+    }
+}
+
+#[inline(always)]
+fn write<I: Io>(ram: &mut [u16; 32768], io: &mut I, address: u16, value: u16) {
+    let address = address & 0x7FFF;
+    if address < SCREEN {
+        ram[address as usize] = value;
+    } else if address < KBD {
+        ram[address as usize] = value;
+        io.screen(address, value);
+    }
+}
+
+fn alu(x: u16, y: u16, control: u16) -> u16 {
+    let x = if control & 0x20 != 0 { 0 } else { x };
+    let x = if control & 0x10 != 0 { !x } else { x };
+    let y = if control & 0x08 != 0 { 0 } else { y };
+    let y = if control & 0x04 != 0 { !y } else { y };
+    let output = if control & 0x02 != 0 { x.wrapping_add(y) } else { x & y };
+    if control & 0x01 != 0 {
+        !output
+    } else {
+        output
+    }
+}
+
+fn step<I: Io>(ram: &mut [u16; 32768], io: &mut I, a: &mut u16, d: &mut u16, pc: u16) -> u16 {
+    let instruction = ROM.get((pc & 0x7FFF) as usize).copied().unwrap_or(0);
+    if instruction & 0x8000 == 0 {
+        *a = instruction;
+        return pc.wrapping_add(1);
+    }
+    let y = if instruction & 0x1000 != 0 {
+        read(ram, io, *a)
+    } else {
+        *a
+    };
+    let r = alu(*d, y, instruction >> 6);
+    let target = *a;
+    if instruction & 0x08 != 0 {
+        write(ram, io, *a, r);
+    }
+    if instruction & 0x10 != 0 {
+        *d = r;
+    }
+    if instruction & 0x20 != 0 {
+        *a = r;
+    }
+    let jump = (instruction & 0x04 != 0 && (r as i16) < 0)
+        || (instruction & 0x02 != 0 && r == 0)
+        || (instruction & 0x01 != 0 && (r as i16) > 0);
+    if jump {
+        target
+    } else {
+        pc.wrapping_add(1)
+    }
+}
+"#;
+
+const MAIN: &str = r#"
+fn main() {
+    let budget = std::env::args()
+        .nth(1)
+        .and_then(|budget| budget.parse().ok())
+        .unwrap_or(u64::MAX);
+    let mut machine = Machine::new(NoIo);
+    let status = machine.run(budget);
+    println!("{:?} after {} cycles", status, machine.cycles);
+    for (i, value) in machine.ram[..16].iter().enumerate() {
+        println!("R{}: {}", i, *value as i16);
+    }
+}
+"#;
+
+pub fn recompile(
+    instructions: &[Instruction],
+    labels: &[(String, usize)],
+    source: &str,
+    with_main: bool,
+) -> String {
+    let graph = ControlFlowGraph::new(
+        instructions,
+        &labels
+            .iter()
+            .map(|&(_, address)| address)
+            .collect::<Vec<_>>(),
+    );
+    let mut output = String::new();
+    writeln!(output, "// Recompiled from {}. Do not edit.", source).unwrap();
+    writeln!(
+        output,
+        "#![allow(unreachable_code, unused_assignments, unused_mut, unused_variables, clippy::all)]\n"
+    )
+    .unwrap();
+    output += RUNTIME;
+    writeln!(output).unwrap();
+    write_rom(&mut output, instructions);
+    writeln!(output).unwrap();
+    write_machine(&mut output, instructions, labels, &graph);
+    if with_main {
+        output += MAIN;
+    }
+    output
+}
+
+fn write_rom(output: &mut String, instructions: &[Instruction]) {
+    writeln!(output, "const ROM: [u16; {}] = [", instructions.len()).unwrap();
+    instructions.chunks(8).for_each(|chunk| {
+        let words = chunk
+            .iter()
+            .map(|instruction| format!("0x{:04X},", instruction.as_raw()))
+            .collect::<Vec<_>>();
+        writeln!(output, "    {}", words.join(" ")).unwrap();
+    });
+    writeln!(output, "];").unwrap();
+}
+
+fn write_machine(
+    output: &mut String,
+    instructions: &[Instruction],
+    labels: &[(String, usize)],
+    graph: &ControlFlowGraph,
+) {
+    *output += r#"impl<I: Io> Machine<I> {
+    pub fn new(io: I) -> Self {
+        Self {
+            a: 0,
+            d: 0,
+            pc: 0,
+            ram: Box::new([0; 32768]),
+            io,
+            cycles: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.pc = 0;
+    }
+
+    pub fn run(&mut self, budget: u64) -> Status {
+        let ram = &mut *self.ram;
+        let io = &mut self.io;
+        let (mut a, mut d, mut pc) = (self.a, self.d, self.pc);
+        let mut cycles = self.cycles;
+        let limit = cycles.saturating_add(budget);
+        let mut status = Status::Running;
+        while cycles < limit {
+            pc = match pc & 0x7FFF {
+"#;
+    graph.blocks().iter().for_each(|block| {
+        labels
+            .iter()
+            .filter(|&&(_, address)| address == block.start)
+            .for_each(|(label, _)| {
+                writeln!(output, "                // ({})", label).unwrap();
+            });
+        writeln!(output, "                {} => {{", block.start).unwrap();
+        write_block(output, &instructions[block.start..block.end], block);
+        writeln!(output, "                }}").unwrap();
+    });
+    *output += r#"                _ => {
+                    cycles += 1;
+                    step(ram, io, &mut a, &mut d, pc)
+                }
+            };
+        }
+        self.a = a;
+        self.d = d;
+        self.pc = pc;
+        self.cycles = cycles;
+        status
+    }
+}
+"#;
+}
+
+fn write_block(output: &mut String, instructions: &[Instruction], block: &BasicBlock) {
+    const INDENT: &str = "                    ";
+    writeln!(output, "{}cycles += {};", INDENT, block.len()).unwrap();
+    if is_halt(instructions, block) {
+        writeln!(output, "{}a = {};", INDENT, block.start).unwrap();
+        writeln!(output, "{}pc = {};", INDENT, block.start).unwrap();
+        writeln!(output, "{}status = Status::Halted;", INDENT).unwrap();
+        writeln!(output, "{}break;", INDENT).unwrap();
+        return;
+    }
+    let mut condition = None;
+    instructions
+        .iter()
+        .for_each(|instruction| match instruction {
+            Instruction::A { value } => {
+                writeln!(output, "{}a = {}; // {}", INDENT, value, instruction).unwrap();
+            }
+            Instruction::C { comp, dest, jump } => {
+                writeln!(output, "{}// {}", INDENT, instruction).unwrap();
+                if comp.uses_m() {
+                    writeln!(output, "{}let m = read(ram, io, a);", INDENT).unwrap();
+                }
+                writeln!(output, "{}let r = {};", INDENT, comp_expression(comp)).unwrap();
+                if jump.is_some() {
+                    writeln!(output, "{}let target = a;", INDENT).unwrap();
+                }
+                if let Some(dest) = dest {
+                    if dest.contains_m() {
+                        writeln!(output, "{}write(ram, io, a, r);", INDENT).unwrap();
+                    }
+                    if dest.contains_d() {
+                        writeln!(output, "{}d = r;", INDENT).unwrap();
+                    }
+                    if dest.contains_a() {
+                        writeln!(output, "{}a = r;", INDENT).unwrap();
+                    }
+                }
+                condition = jump.as_ref().map(jump_condition);
+            }
+        });
+    let target = |target: Target| match target {
+        Target::Static(target) => target.to_string(),
+        Target::Dynamic => "target".to_string(),
+    };
+    match block.exit {
+        Exit::Next(next) => writeln!(output, "{}{}", INDENT, next).unwrap(),
+        Exit::Goto(to) => writeln!(output, "{}{}", INDENT, target(to)).unwrap(),
+        Exit::Branch {
+            target: to,
+            otherwise,
+            ..
+        } => writeln!(
+            output,
+            "{}if {} {{ {} }} else {{ {} }}",
+            INDENT,
+            condition.unwrap(),
+            target(to),
+            otherwise
+        )
+        .unwrap(),
+    }
+}
+
+fn is_halt(instructions: &[Instruction], block: &BasicBlock) -> bool {
+    block.exit == Exit::Goto(Target::Static(block.start))
+        && instructions.iter().all(|instruction| match instruction {
+            Instruction::A { .. } => true,
+            Instruction::C { dest, .. } => dest.is_none(),
+        })
+}
+
+fn comp_expression(comp: &Comp) -> &'static str {
+    match comp {
+        Comp::Zero => "0",
+        Comp::One => "1",
+        Comp::MinusOne => "0xFFFF",
+        Comp::D => "d",
+        Comp::A => "a",
+        Comp::M => "m",
+        Comp::NotD => "!d",
+        Comp::NotA => "!a",
+        Comp::NotM => "!m",
+        Comp::MinusD => "d.wrapping_neg()",
+        Comp::MinusA => "a.wrapping_neg()",
+        Comp::MinusM => "m.wrapping_neg()",
+        Comp::DPlusOne => "d.wrapping_add(1)",
+        Comp::APlusOne => "a.wrapping_add(1)",
+        Comp::MPlusOne => "m.wrapping_add(1)",
+        Comp::DMinusOne => "d.wrapping_sub(1)",
+        Comp::AMinusOne => "a.wrapping_sub(1)",
+        Comp::MMinusOne => "m.wrapping_sub(1)",
+        Comp::DPlusA => "d.wrapping_add(a)",
+        Comp::DPlusM => "d.wrapping_add(m)",
+        Comp::DMinusA => "d.wrapping_sub(a)",
+        Comp::AMinusD => "a.wrapping_sub(d)",
+        Comp::DMinusM => "d.wrapping_sub(m)",
+        Comp::MMinusD => "m.wrapping_sub(d)",
+        Comp::DAndA => "d & a",
+        Comp::DAndM => "d & m",
+        Comp::DOrA => "d | a",
+        Comp::DOrM => "d | m",
+    }
+}
+
+fn jump_condition(jump: &Jump) -> &'static str {
+    match jump {
+        Jump::JGT => "(r as i16) > 0",
+        Jump::JEQ => "r == 0",
+        Jump::JGE => "(r as i16) >= 0",
+        Jump::JLT => "(r as i16) < 0",
+        Jump::JNE => "r != 0",
+        Jump::JLE => "(r as i16) <= 0",
+        Jump::JMP => "true",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assembler::{parser::parse, symbol::SymbolTable};
+    use computer::{keyboard::DummyKeyboard, rom::Rom, screen::DummyScreen, Computer};
+    use std::{fs, path::PathBuf, process::Command};
+
+    /// A scratch directory unique to this process, removed on drop.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn assemble(lines: &[&str]) -> (Vec<Instruction>, Vec<(String, usize)>) {
+        let mut symbols = SymbolTable::new();
+        let instructions = parse(lines, &mut symbols).unwrap();
+        let instructions = symbols.resolve_symbols(&instructions);
+        let labels = symbols
+            .labels()
+            .iter()
+            .map(|&(label, address)| (label.to_string(), address as usize))
+            .collect::<Vec<_>>();
+        (instructions, labels)
+    }
+
+    fn recompile_asm(lines: &[&str]) -> String {
+        let (instructions, labels) = assemble(lines);
+        recompile(&instructions, &labels, "Test.asm", false)
+    }
+
+    #[test]
+    fn each_basic_block_becomes_a_match_arm() {
+        let output = recompile_asm(&[
+            "@0", "D=M", "@END", "D;JLE", "@1", "M=D", "(END)", "@END", "0;JMP",
+        ]);
+        assert!(output.contains("                0 => {\n"));
+        assert!(output.contains("                4 => {\n"));
+        assert!(output.contains("                // (END)\n                6 => {\n"));
+        assert!(output.contains("if (r as i16) <= 0 { 6 } else { 4 }"));
+        assert!(!output.contains("fn main()"));
+    }
+
+    #[test]
+    fn self_loop_without_side_effects_halts() {
+        let output = recompile_asm(&["(END)", "@END", "0;JMP"]);
+        assert!(output.contains("status = Status::Halted;"));
+    }
+
+    #[test]
+    fn memory_is_written_before_a_is_updated() {
+        let output = recompile_asm(&["@SP", "AM=M-1", "D=M"]);
+        let write = output.find("    write(ram, io, a, r);").unwrap();
+        let update = output.find("    a = r;").unwrap();
+        assert!(write < update);
+    }
+
+    #[test]
+    fn computed_jumps_use_a_before_the_instruction() {
+        let output = recompile_asm(&["@R14", "A=M", "A=A+1;JMP"]);
+        assert!(output.contains("let target = a;\n                    a = r;"));
+        assert!(output.contains("                    target\n"));
+    }
+
+    #[test]
+    fn recompiled_programs_run_like_the_computer() {
+        // Max of R0 = 3 and R1 = -5 into R2, then the sum of 1 to 100 into R3.
+        let (instructions, labels) = assemble(&[
+            "@3", "D=A", "@R0", "M=D", "@5", "D=-A", "@R1", "M=D", "@R0", "D=M", "@R1", "D=D-M",
+            "@FIRST", "D;JGT", "@R1", "D=M", "@SECOND", "0;JMP", "(FIRST)", "@R0", "D=M",
+            "(SECOND)", "@R2", "M=D", "@100", "D=A", "@R4", "M=D", "(LOOP)", "@R4", "D=M", "@END",
+            "D;JEQ", "@R3", "M=D+M", "@R4", "M=M-1", "@LOOP", "0;JMP", "(END)", "@END", "0;JMP",
+        ]);
+        let directory = TempDir::new("recompiler-run-test");
+        let source = directory.0.join("max.rs");
+        let binary = directory.0.join("max");
+        fs::write(&source, recompile(&instructions, &labels, "Max.asm", true)).unwrap();
+        let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+        let status = Command::new(rustc)
+            .args(["--edition", "2018", "-o"])
+            .arg(&binary)
+            .arg(&source)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let output = Command::new(&binary).output().unwrap();
+        let output = String::from_utf8(output.stdout).unwrap();
+        let mut lines = output.lines();
+        assert!(lines.next().unwrap().starts_with("Halted after"));
+        let registers = lines
+            .map(|line| line.split(": ").nth(1).unwrap().parse().unwrap())
+            .collect::<Vec<i16>>();
+        assert_eq!(&registers[..5], &[3, -5, 3, 5050, 0]);
+
+        let mut computer = Computer::<DummyScreen, DummyKeyboard>::new();
+        let words = instructions
+            .iter()
+            .map(Instruction::as_raw)
+            .collect::<Vec<_>>();
+        computer.set_rom(Rom::from_words(&words));
+        computer.tick(true);
+        for _ in 0..2000 {
+            computer.tick(false);
+        }
+        let expected = (0..16)
+            .map(|address| computer.peek(address) as i16)
+            .collect::<Vec<_>>();
+        assert_eq!(registers, expected);
+    }
+}
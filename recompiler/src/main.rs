@@ -0,0 +1,60 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use clap::{app_from_crate, crate_authors, crate_description, crate_name, crate_version, Arg};
+
+use assembler::{hack, parser::parse, symbol::SymbolTable};
+use recompiler::recompile;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = app_from_crate!()
+        .arg(
+            Arg::with_name("file")
+                .help("The machine language (.hack) or assembly (.asm) file")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .short("o")
+                .takes_value(true)
+                .help("Path to the generated Rust file"),
+        )
+        .arg(
+            Arg::with_name("main")
+                .long("main")
+                .help("Emit a main function which runs the program and prints R0-R15"),
+        )
+        .get_matches();
+    let file_name = Path::new(args.value_of("file").unwrap());
+    let reader = BufReader::new(File::open(file_name)?);
+    let lines = reader.lines().collect::<Result<Vec<_>, _>>()?;
+    let (instructions, labels) = if file_name.extension().is_some_and(|ext| ext == "asm") {
+        let mut symbols = SymbolTable::new();
+        let symbol_instructions = parse(&lines, &mut symbols)?;
+        let labels = symbols
+            .labels()
+            .iter()
+            .map(|&(label, address)| (label.to_string(), address as usize))
+            .collect();
+        (symbols.resolve_symbols(&symbol_instructions), labels)
+    } else {
+        (hack::read(&lines)?, vec![])
+    };
+    let source = recompile(
+        &instructions,
+        &labels,
+        &file_name.display().to_string(),
+        args.is_present("main"),
+    );
+    let output = args
+        .value_of("output")
+        .map_or_else(|| file_name.with_extension("rs"), |output| output.into());
+    let mut file = BufWriter::new(File::create(output)?);
+    file.write_all(source.as_bytes())?;
+
+    Ok(())
+}
@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+
+use crate::sat::Solver;
+
+// A literal is a node index shifted left by one, with the low bit set when
+// the node's value is inverted. Node 0 is the constant false.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Lit(u32);
+
+impl Lit {
+    pub const FALSE: Lit = Lit(0);
+    pub const TRUE: Lit = Lit(1);
+
+    pub fn constant(value: bool) -> Self {
+        if value {
+            Lit::TRUE
+        } else {
+            Lit::FALSE
+        }
+    }
+
+    pub fn node(self) -> usize {
+        (self.0 >> 1) as usize
+    }
+
+    pub fn is_inverted(self) -> bool {
+        self.0 & 1 == 1
+    }
+
+    pub fn as_constant(self) -> Option<bool> {
+        match self {
+            Lit::FALSE => Some(false),
+            Lit::TRUE => Some(true),
+            _ => None,
+        }
+    }
+
+    pub fn invert(self) -> Self {
+        Lit(self.0 ^ 1)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Node {
+    False,
+    Input,
+    And(Lit, Lit),
+}
+
+// An and-inverter graph. Gates are hashed so that structurally equal
+// expressions share a node, and gates over constants are folded away.
+pub struct Aig {
+    nodes: Vec<Node>,
+    gates: HashMap<(Lit, Lit), Lit>,
+}
+
+impl Default for Aig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Aig {
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![Node::False],
+            gates: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.len() == 1
+    }
+
+    pub fn node(&self, index: usize) -> Node {
+        self.nodes[index]
+    }
+
+    pub fn input(&mut self) -> Lit {
+        self.nodes.push(Node::Input);
+        Lit(((self.nodes.len() - 1) as u32) << 1)
+    }
+
+    pub fn and(&mut self, a: Lit, b: Lit) -> Lit {
+        let (a, b) = if a <= b { (a, b) } else { (b, a) };
+        if a == Lit::FALSE || a == b.invert() {
+            return Lit::FALSE;
+        }
+        if a == Lit::TRUE || a == b {
+            return b;
+        }
+        if let Some(&lit) = self.gates.get(&(a, b)) {
+            return lit;
+        }
+        self.nodes.push(Node::And(a, b));
+        let lit = Lit(((self.nodes.len() - 1) as u32) << 1);
+        self.gates.insert((a, b), lit);
+        lit
+    }
+
+    pub fn nand(&mut self, a: Lit, b: Lit) -> Lit {
+        self.and(a, b).invert()
+    }
+
+    pub fn or(&mut self, a: Lit, b: Lit) -> Lit {
+        self.and(a.invert(), b.invert()).invert()
+    }
+
+    pub fn xor(&mut self, a: Lit, b: Lit) -> Lit {
+        let both = self.and(a, b);
+        let neither = self.and(a.invert(), b.invert());
+        self.and(both.invert(), neither.invert())
+    }
+
+    pub fn mux(&mut self, a: Lit, b: Lit, selector: Lit) -> Lit {
+        let a = self.and(a, selector.invert());
+        let b = self.and(b, selector);
+        self.or(a, b)
+    }
+
+    // Evaluates every node, reading inputs from the given assignment.
+    pub fn evaluate(&self, inputs: &HashMap<usize, bool>) -> Vec<bool> {
+        let mut values = Vec::with_capacity(self.nodes.len());
+        for (index, node) in self.nodes.iter().enumerate() {
+            let value = match *node {
+                Node::False => false,
+                Node::Input => inputs.get(&index).copied().unwrap_or(false),
+                Node::And(a, b) => value_of(&values, a) && value_of(&values, b),
+            };
+            values.push(value);
+        }
+        values
+    }
+
+    // Evaluates a single literal, visiting only the gates it depends on.
+    pub fn value(&self, lit: Lit, inputs: &HashMap<usize, bool>) -> bool {
+        let mut values: HashMap<usize, bool> = HashMap::new();
+        let mut stack = vec![lit.node()];
+        while let Some(&node) = stack.last() {
+            if values.contains_key(&node) {
+                stack.pop();
+                continue;
+            }
+            let value = match self.nodes[node] {
+                Node::False => Some(false),
+                Node::Input => Some(inputs.get(&node).copied().unwrap_or(false)),
+                Node::And(a, b) => match (values.get(&a.node()), values.get(&b.node())) {
+                    (Some(&va), Some(&vb)) => {
+                        Some((va != a.is_inverted()) && (vb != b.is_inverted()))
+                    }
+                    (va, vb) => {
+                        if va.is_none() {
+                            stack.push(a.node());
+                        }
+                        if vb.is_none() {
+                            stack.push(b.node());
+                        }
+                        None
+                    }
+                },
+            };
+            if let Some(value) = value {
+                values.insert(node, value);
+                stack.pop();
+            }
+        }
+        values[&lit.node()] != lit.is_inverted()
+    }
+
+    // Finds an assignment of the inputs under which every constraint holds.
+    // Only the gates the constraints depend on are encoded.
+    pub fn solve(&self, constraints: &[Lit]) -> Option<HashMap<usize, bool>> {
+        let mut vars = HashMap::new();
+        let mut order = vec![];
+        let mut stack = constraints.iter().map(|lit| lit.node()).collect::<Vec<_>>();
+        while let Some(node) = stack.pop() {
+            if vars.contains_key(&node) {
+                continue;
+            }
+            vars.insert(node, order.len());
+            order.push(node);
+            if let Node::And(a, b) = self.nodes[node] {
+                stack.push(a.node());
+                stack.push(b.node());
+            }
+        }
+        let lit = |lit: Lit| Solver::lit(vars[&lit.node()], !lit.is_inverted());
+        let mut solver = Solver::new(order.len());
+        for &node in &order {
+            let gate = Solver::lit(vars[&node], true);
+            match self.nodes[node] {
+                Node::False => solver.add_clause(&[gate ^ 1]),
+                Node::Input => {}
+                Node::And(a, b) => {
+                    solver.add_clause(&[gate ^ 1, lit(a)]);
+                    solver.add_clause(&[gate ^ 1, lit(b)]);
+                    solver.add_clause(&[gate, lit(a) ^ 1, lit(b) ^ 1]);
+                }
+            }
+        }
+        constraints
+            .iter()
+            .for_each(|&constraint| solver.add_clause(&[lit(constraint)]));
+        let model = solver.solve()?;
+        Some(
+            order
+                .iter()
+                .filter(|&&node| self.nodes[node] == Node::Input)
+                .map(|&node| (node, model[vars[&node]]))
+                .collect(),
+        )
+    }
+}
+
+pub fn value_of(values: &[bool], lit: Lit) -> bool {
+    values[lit.node()] != lit.is_inverted()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constants_and_duplicates_are_folded() {
+        let mut aig = Aig::new();
+        let a = aig.input();
+        let b = aig.input();
+        assert_eq!(aig.and(a, Lit::TRUE), a);
+        assert_eq!(aig.and(a, Lit::FALSE), Lit::FALSE);
+        assert_eq!(aig.and(a, a.invert()), Lit::FALSE);
+        assert_eq!(aig.and(a, b), aig.and(b, a));
+        assert_eq!(aig.len(), 4);
+    }
+
+    #[test]
+    fn xor_evaluates_like_xor() {
+        let mut aig = Aig::new();
+        let a = aig.input();
+        let b = aig.input();
+        let x = aig.xor(a, b);
+        for &(va, vb) in &[(false, false), (false, true), (true, false), (true, true)] {
+            let inputs = [(a.node(), va), (b.node(), vb)].iter().copied().collect();
+            assert_eq!(value_of(&aig.evaluate(&inputs), x), va != vb);
+            assert_eq!(aig.value(x, &inputs), va != vb);
+        }
+    }
+
+    #[test]
+    fn solutions_satisfy_the_constraints() {
+        let mut aig = Aig::new();
+        let a = aig.input();
+        let b = aig.input();
+        let x = aig.xor(a, b);
+        let model = aig.solve(&[x, a.invert()]).unwrap();
+        assert_eq!((model[&a.node()], model[&b.node()]), (false, true));
+        assert_eq!(aig.solve(&[x, a, b]), None);
+    }
+}
@@ -0,0 +1,483 @@
+use std::collections::{HashMap, VecDeque};
+
+use assembler::instruction::Instruction;
+
+use crate::{
+    aig::{Aig, Lit},
+    word::{alu, Word},
+};
+
+const KBD: u16 = 0x6000;
+const ADDRESS_BITS: usize = 15;
+const QUANTUM: usize = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Input {
+    Ram(u16),
+    Keyboard(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Property {
+    Reach(u16),
+    WriteWithin { start: u16, end: u16 },
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub symbolic: Vec<u16>,
+    pub keyboard: bool,
+    pub max_steps: usize,
+    pub max_forks: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            symbolic: vec![],
+            keyboard: false,
+            max_steps: 1_000_000,
+            max_forks: 16,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Finding {
+    pub pc: u16,
+    pub address: Option<u16>,
+    pub steps: usize,
+    pub inputs: Vec<(Input, u16)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Found(Finding),
+    Unreachable { paths: usize },
+    OutOfBudget { paths: usize },
+    // Not reached on the paths explored, but values of A past `max_forks`
+    // were dropped, so the target may still be reachable.
+    Incomplete { paths: usize },
+}
+
+#[derive(Clone)]
+struct State {
+    pc: u16,
+    a: Word,
+    d: Word,
+    ram: HashMap<u16, Word>,
+    path: Vec<Lit>,
+    model: HashMap<usize, bool>,
+    inputs: Vec<(Input, Word)>,
+    keys: usize,
+    steps: usize,
+}
+
+enum Step {
+    Continue,
+    Fork(Vec<State>),
+    Finished,
+    Found(Finding),
+}
+
+pub struct Executor<'a> {
+    instructions: &'a [Instruction],
+    config: Config,
+    property: Property,
+    aig: Aig,
+    // Whether a fork dropped some values of A.
+    truncated: bool,
+}
+
+impl<'a> Executor<'a> {
+    pub fn new(instructions: &'a [Instruction], config: Config, property: Property) -> Self {
+        Self {
+            instructions,
+            config,
+            property,
+            aig: Aig::new(),
+            truncated: false,
+        }
+    }
+
+    pub fn run(&mut self) -> Outcome {
+        let mut initial = State {
+            pc: 0,
+            a: Word::constant(0),
+            d: Word::constant(0),
+            ram: HashMap::new(),
+            path: vec![],
+            model: HashMap::new(),
+            inputs: vec![],
+            keys: 0,
+            steps: 0,
+        };
+        for &address in &self.config.symbolic {
+            let value = Word::input(&mut self.aig);
+            initial.ram.insert(address & 0x7FFF, value);
+            initial.inputs.push((Input::Ram(address), value));
+        }
+        let mut queue = VecDeque::new();
+        queue.push_back(initial);
+        let mut steps = 0;
+        let mut paths = 0;
+        while let Some(mut state) = queue.pop_front() {
+            let mut quantum = 0;
+            loop {
+                if steps >= self.config.max_steps {
+                    return Outcome::OutOfBudget { paths };
+                }
+                if quantum == QUANTUM {
+                    queue.push_back(state);
+                    break;
+                }
+                steps += 1;
+                quantum += 1;
+                match self.step(&mut state) {
+                    Step::Continue => {}
+                    Step::Fork(states) => {
+                        queue.extend(states);
+                        break;
+                    }
+                    Step::Finished => {
+                        paths += 1;
+                        break;
+                    }
+                    Step::Found(finding) => return Outcome::Found(finding),
+                }
+            }
+        }
+        match self.truncated {
+            true => Outcome::Incomplete { paths },
+            false => Outcome::Unreachable { paths },
+        }
+    }
+
+    fn holds(&self, state: &State, condition: Lit) -> Option<HashMap<usize, bool>> {
+        if self.aig.value(condition, &state.model) {
+            return Some(state.model.clone());
+        }
+        let mut constraints = state.path.clone();
+        constraints.push(condition);
+        self.aig.solve(&constraints)
+    }
+
+    fn constrain(&self, state: &State, condition: Lit, model: HashMap<usize, bool>) -> State {
+        let mut state = state.clone();
+        if condition != Lit::TRUE {
+            state.path.push(condition);
+        }
+        state.model = model;
+        state
+    }
+
+    fn finding(
+        &self,
+        state: &State,
+        model: &HashMap<usize, bool>,
+        address: Option<u16>,
+    ) -> Finding {
+        Finding {
+            pc: state.pc,
+            address,
+            steps: state.steps,
+            inputs: state
+                .inputs
+                .iter()
+                .map(|(input, word)| {
+                    let value = word.0.iter().enumerate().fold(0, |value, (i, &bit)| {
+                        value | (self.aig.value(bit, model) as u16) << i
+                    });
+                    (*input, value)
+                })
+                .collect(),
+        }
+    }
+
+    // Splits the state on up to `max_forks` values of A's address bits,
+    // noting when A could take more.
+    fn concretize(&mut self, state: &State) -> Vec<State> {
+        let address = state.a.low_bits(ADDRESS_BITS);
+        let mut excluded = vec![];
+        let mut forks = vec![];
+        loop {
+            let mut constraints = state.path.clone();
+            constraints.extend(&excluded);
+            let model = match self.aig.solve(&constraints) {
+                Some(model) => model,
+                None => break,
+            };
+            if forks.len() == self.config.max_forks {
+                self.truncated = true;
+                break;
+            }
+            let value = address.0.iter().enumerate().fold(0, |value, (i, &bit)| {
+                value | (self.aig.value(bit, &model) as u16) << i
+            });
+            let equal = address.equals(&mut self.aig, &Word::constant(value));
+            excluded.push(equal.invert());
+            let mut fork = self.constrain(state, equal, model);
+            let mut bits = fork.a.0;
+            bits[..ADDRESS_BITS]
+                .iter_mut()
+                .enumerate()
+                .for_each(|(i, bit)| *bit = Lit::constant(value >> i & 1 == 1));
+            fork.a = Word(bits);
+            forks.push(fork);
+        }
+        forks
+    }
+
+    fn read(&mut self, state: &mut State, address: u16) -> Word {
+        if address >= KBD {
+            if !self.config.keyboard {
+                return Word::constant(0);
+            }
+            let value = Word::input(&mut self.aig);
+            state.inputs.push((Input::Keyboard(state.keys), value));
+            state.keys += 1;
+            return value;
+        }
+        state
+            .ram
+            .get(&address)
+            .copied()
+            .unwrap_or_else(|| Word::constant(0))
+    }
+
+    fn is_halt(&self, pc: u16, target: u16, instruction: &Instruction) -> bool {
+        let loops = match instruction {
+            Instruction::C { dest: None, .. } => target == pc,
+            _ => false,
+        };
+        let jumps_back = target.wrapping_add(1) == pc
+            && matches!(instruction, Instruction::C { dest: None, .. })
+            && self.instructions.get(target as usize) == Some(&Instruction::A { value: target });
+        loops || jumps_back
+    }
+
+    fn step(&mut self, state: &mut State) -> Step {
+        if self.property == Property::Reach(state.pc) {
+            return Step::Found(self.finding(state, &state.model, None));
+        }
+        let instruction = match self.instructions.get((state.pc & 0x7FFF) as usize) {
+            Some(instruction) => instruction.clone(),
+            None => return Step::Finished,
+        };
+        state.steps += 1;
+        let bits = match &instruction {
+            Instruction::A { value } => {
+                state.a = Word::constant(*value);
+                state.pc = state.pc.wrapping_add(1);
+                return Step::Continue;
+            }
+            Instruction::C { .. } => instruction.as_binary(),
+        };
+        let use_m = bits[3];
+        let control = [bits[4], bits[5], bits[6], bits[7], bits[8], bits[9]];
+        let (write_a, write_d, write_m) = (bits[10], bits[11], bits[12]);
+
+        // A symbolic A is only split when it addresses M or a jump that may
+        // be taken.
+        let symbolic = state.a.low_bits(ADDRESS_BITS).as_constant().is_none();
+        if (use_m || write_m) && symbolic {
+            state.steps -= 1;
+            if write_m {
+                if let Some(finding) = self.check_write(state) {
+                    return Step::Found(finding);
+                }
+            }
+            return Step::Fork(self.concretize(state));
+        }
+        let address = state.a.low_bits(ADDRESS_BITS).as_constant().unwrap_or(0);
+
+        let m = if use_m {
+            self.read(state, address)
+        } else {
+            Word::constant(0)
+        };
+        let y = if use_m { m } else { state.a };
+        let result = alu(&mut self.aig, &state.d, &y, control);
+        let zero_or_negative = self.aig.or(result.zero, result.negative);
+        let conditions = [
+            Lit::constant(bits[13]),
+            Lit::constant(bits[14]),
+            Lit::constant(bits[15]),
+        ];
+        let negative = self.aig.and(conditions[0], result.negative);
+        let zero = self.aig.and(conditions[1], result.zero);
+        let positive = self.aig.and(conditions[2], zero_or_negative.invert());
+        let jump = self.aig.or(negative, zero);
+        let jump = self.aig.or(jump, positive);
+        if symbolic && jump.as_constant() != Some(false) {
+            state.steps -= 1;
+            return Step::Fork(self.concretize(state));
+        }
+
+        if write_m {
+            if let Some(finding) = self.check_write(state) {
+                return Step::Found(finding);
+            }
+            if address < KBD {
+                state.ram.insert(address, result.out);
+            }
+        }
+        let target = address;
+        if write_a {
+            state.a = result.out;
+        }
+        if write_d {
+            state.d = result.out;
+        }
+
+        let next = state.pc.wrapping_add(1);
+        match jump.as_constant() {
+            Some(false) => {
+                state.pc = next;
+                Step::Continue
+            }
+            Some(true) => {
+                if self.is_halt(state.pc, target, &instruction) {
+                    return Step::Finished;
+                }
+                state.pc = target;
+                Step::Continue
+            }
+            None => {
+                let mut forks = vec![];
+                for &(condition, pc) in &[(jump, target), (jump.invert(), next)] {
+                    if let Some(model) = self.holds(state, condition) {
+                        let mut fork = self.constrain(state, condition, model);
+                        fork.pc = pc;
+                        forks.push(fork);
+                    }
+                }
+                Step::Fork(forks)
+            }
+        }
+    }
+
+    // Looks for inputs under which the write about to happen at A leaves the
+    // permitted range.
+    fn check_write(&mut self, state: &State) -> Option<Finding> {
+        let (start, end) = match self.property {
+            Property::WriteWithin { start, end } => (start, end),
+            _ => return None,
+        };
+        let address = state.a.low_bits(ADDRESS_BITS);
+        let below = address.less_than(&mut self.aig, &Word::constant(start));
+        let above = Word::constant(end).less_than(&mut self.aig, &address);
+        let outside = self.aig.or(below, above);
+        let model = self.holds(state, outside)?;
+        let value = address.0.iter().enumerate().fold(0, |value, (i, &bit)| {
+            value | (self.aig.value(bit, &model) as u16) << i
+        });
+        Some(self.finding(state, &model, Some(value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assembler::{parser::parse, symbol::SymbolTable};
+
+    fn assemble(lines: &[&str]) -> (Vec<Instruction>, SymbolTable) {
+        let mut symbols = SymbolTable::new();
+        let instructions = parse(lines, &mut symbols).unwrap();
+        (symbols.resolve_symbols(&instructions), symbols)
+    }
+
+    #[test]
+    fn finds_inputs_reaching_a_label() {
+        let (instructions, symbols) = assemble(&[
+            "@R0", "D=M", "@R1", "D=D+M", "@42", "D=D-A", "@HIT", "D;JEQ", "(END)", "@END",
+            "0;JMP", "(HIT)", "@HIT", "0;JMP",
+        ]);
+        let config = Config {
+            symbolic: vec![0, 1],
+            ..Config::default()
+        };
+        let target = symbols.get("HIT").unwrap();
+        let outcome = Executor::new(&instructions, config, Property::Reach(target)).run();
+        match outcome {
+            Outcome::Found(finding) => {
+                let r0 = finding.inputs[0].1;
+                let r1 = finding.inputs[1].1;
+                assert_eq!(r0.wrapping_add(r1), 42);
+            }
+            outcome => panic!("{:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn proves_a_label_unreachable() {
+        let (instructions, symbols) = assemble(&[
+            "@R0", "D=M", "@1", "D=D&A", "@R1", "M=D", "@2", "D=D-A", "@HIT", "D;JEQ", "(END)",
+            "@END", "0;JMP", "(HIT)", "@HIT", "0;JMP",
+        ]);
+        let config = Config {
+            symbolic: vec![0],
+            ..Config::default()
+        };
+        let target = symbols.get("HIT").unwrap();
+        let outcome = Executor::new(&instructions, config, Property::Reach(target)).run();
+        assert_eq!(outcome, Outcome::Unreachable { paths: 1 });
+    }
+
+    #[test]
+    fn does_not_prove_unreachable_past_the_fork_limit() {
+        // Reading through R0 forks on its value, and only R0 = 1000 reaches
+        // HIT.
+        let (instructions, symbols) = assemble(&[
+            "@R0", "A=M", "D=M", "@R0", "D=M", "@1000", "D=D-A", "@HIT", "D;JEQ", "(END)", "@END",
+            "0;JMP", "(HIT)", "@HIT", "0;JMP",
+        ]);
+        let target = symbols.get("HIT").unwrap();
+        let config = Config {
+            symbolic: vec![0],
+            max_forks: 2,
+            ..Config::default()
+        };
+        let outcome = Executor::new(&instructions, config, Property::Reach(target)).run();
+        assert_eq!(outcome, Outcome::Incomplete { paths: 2 });
+    }
+
+    #[test]
+    fn jumps_never_taken_do_not_fork_on_a() {
+        let (instructions, symbols) = assemble(&[
+            "@R0", "A=M", "0;JGT", "(END)", "@END", "0;JMP", "(HIT)", "@HIT", "0;JMP",
+        ]);
+        let target = symbols.get("HIT").unwrap();
+        let config = Config {
+            symbolic: vec![0],
+            ..Config::default()
+        };
+        let outcome = Executor::new(&instructions, config, Property::Reach(target)).run();
+        assert_eq!(outcome, Outcome::Unreachable { paths: 1 });
+    }
+
+    #[test]
+    fn finds_a_keypress_which_writes_outside_the_range() {
+        let (instructions, _) = assemble(&[
+            "@KBD", "D=M", "@100", "D=D-A", "@END", "D;JLT", "@KBD", "D=M", "@SCREEN", "A=D+A",
+            "M=-1", "(END)", "@END", "0;JMP",
+        ]);
+        let config = Config {
+            keyboard: true,
+            ..Config::default()
+        };
+        let property = Property::WriteWithin {
+            start: 0x4000,
+            end: 0x5FFF,
+        };
+        match Executor::new(&instructions, config, property).run() {
+            Outcome::Found(finding) => {
+                let (input, key) = finding.inputs[1];
+                assert_eq!(input, Input::Keyboard(1));
+                assert!(key & 0x7FFF >= 0x2000);
+                assert_eq!(finding.pc, 10);
+            }
+            outcome => panic!("{:?}", outcome),
+        }
+    }
+}
@@ -0,0 +1,4 @@
+pub mod aig;
+pub mod engine;
+pub mod sat;
+pub mod word;
@@ -0,0 +1,198 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use clap::{app_from_crate, crate_authors, crate_description, crate_name, crate_version, Arg};
+
+use assembler::{
+    hack,
+    parser::parse,
+    symbol::{SymbolFile, SymbolTable},
+};
+use symbolic::engine::{Config, Executor, Input, Outcome, Property};
+
+fn read_lines(path: &Path) -> std::io::Result<Vec<String>> {
+    BufReader::new(File::open(path)?).lines().collect()
+}
+
+struct Names {
+    predefined: SymbolTable,
+    symbols: SymbolFile,
+}
+
+impl Names {
+    fn resolve(&self, name: &str, rom: bool) -> Result<u16, String> {
+        if let Ok(address) = name.parse() {
+            return Ok(address);
+        }
+        let (defined, predefined) = if rom {
+            (&self.symbols.labels, None)
+        } else {
+            (&self.symbols.variables, self.predefined.get(name))
+        };
+        defined
+            .iter()
+            .find(|(symbol, _)| symbol == name)
+            .map(|&(_, address)| address)
+            .or(predefined)
+            .ok_or_else(|| format!("Unknown symbol: {}", name))
+    }
+
+    fn input(&self, input: Input) -> String {
+        match input {
+            Input::Ram(address) => match self.symbols.variable(address) {
+                Some(name) => name.to_string(),
+                None if address < 16 => format!("R{}", address),
+                None => format!("RAM[{}]", address),
+            },
+            Input::Keyboard(read) => format!("KBD#{}", read),
+        }
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = app_from_crate!()
+        .arg(
+            Arg::with_name("file")
+                .help("The machine language (.hack) or assembly (.asm) file")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("symbols")
+                .long("symbols")
+                .short("s")
+                .takes_value(true)
+                .help("Symbol file written by the assembler (defaults to <file>.sym if present)"),
+        )
+        .arg(
+            Arg::with_name("input")
+                .long("input")
+                .short("i")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("RAM cell (address or symbol) holding a symbolic value at start"),
+        )
+        .arg(
+            Arg::with_name("keyboard")
+                .long("keyboard")
+                .short("k")
+                .help("Treat every read of the keyboard as a fresh symbolic value"),
+        )
+        .arg(
+            Arg::with_name("reach")
+                .long("reach")
+                .short("r")
+                .takes_value(true)
+                .required_unless("write-within")
+                .help("ROM address or label to reach"),
+        )
+        .arg(
+            Arg::with_name("write-within")
+                .long("write-within")
+                .short("w")
+                .takes_value(true)
+                .conflicts_with("reach")
+                .help("Assert that every write lands in START..END (inclusive)"),
+        )
+        .arg(
+            Arg::with_name("max-steps")
+                .long("max-steps")
+                .takes_value(true)
+                .help("Instructions to execute over all paths before giving up"),
+        )
+        .arg(
+            Arg::with_name("max-forks")
+                .long("max-forks")
+                .takes_value(true)
+                .help("Values of a symbolic address to explore before leaving the rest unexplored"),
+        )
+        .get_matches();
+    let file_name = Path::new(args.value_of("file").unwrap());
+    let lines = read_lines(file_name)?;
+    let (instructions, symbols) = if file_name.extension().is_some_and(|ext| ext == "asm") {
+        let mut symbols = SymbolTable::new();
+        let symbol_instructions = parse(&lines, &mut symbols)?;
+        (
+            symbols.resolve_symbols(&symbol_instructions),
+            symbols.symbol_file(),
+        )
+    } else {
+        let symbols = match args.value_of("symbols") {
+            Some(path) => Some(Path::new(path).to_path_buf()),
+            None => Some(file_name.with_extension("sym")).filter(|path| path.exists()),
+        };
+        let symbols = match symbols {
+            Some(path) => SymbolFile::parse(&read_lines(&path)?)?,
+            None => SymbolFile::default(),
+        };
+        (hack::read(&lines)?, symbols)
+    };
+    let names = Names {
+        predefined: SymbolTable::new(),
+        symbols,
+    };
+
+    let mut config = Config {
+        keyboard: args.is_present("keyboard"),
+        ..Config::default()
+    };
+    if let Some(inputs) = args.values_of("input") {
+        for input in inputs {
+            config.symbolic.push(names.resolve(input, false)?);
+        }
+    }
+    if let Some(max_steps) = args.value_of("max-steps") {
+        config.max_steps = max_steps.parse()?;
+    }
+    if let Some(max_forks) = args.value_of("max-forks") {
+        config.max_forks = max_forks.parse()?;
+    }
+    let property = match (args.value_of("reach"), args.value_of("write-within")) {
+        (Some(target), _) => Property::Reach(names.resolve(target, true)?),
+        (None, Some(range)) => {
+            let mut bounds = range.splitn(2, "..");
+            match (bounds.next(), bounds.next()) {
+                (Some(start), Some(end)) => Property::WriteWithin {
+                    start: names.resolve(start, false)?,
+                    end: names.resolve(end, false)?,
+                },
+                _ => return Err(format!("Invalid range: {}", range).into()),
+            }
+        }
+        (None, None) => unreachable!(),
+    };
+
+    match Executor::new(&instructions, config, property).run() {
+        Outcome::Found(finding) => {
+            match finding.address {
+                Some(address) => println!(
+                    "Write to RAM[{}] at ROM {} after {} instructions",
+                    address, finding.pc, finding.steps
+                ),
+                None => println!(
+                    "Reached ROM {} after {} instructions",
+                    finding.pc, finding.steps
+                ),
+            }
+            for (input, value) in finding.inputs {
+                println!("  {} = {}", names.input(input), value as i16);
+            }
+        }
+        Outcome::Unreachable { paths } => {
+            println!("Unreachable on every path ({} explored)", paths)
+        }
+        Outcome::OutOfBudget { paths } => println!(
+            "Gave up after exploring {} paths; raise --max-steps to search further",
+            paths
+        ),
+        Outcome::Incomplete { paths } => println!(
+            "Not reached on {} paths, but some addresses were left unexplored; raise --max-forks to search further",
+            paths
+        ),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,349 @@
+use std::collections::BinaryHeap;
+
+// A conflict-driven clause learning solver. Variables are numbered from 0 and
+// a literal is a variable shifted left by one, with the low bit set when
+// negated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Value {
+    Unassigned,
+    True,
+    False,
+}
+
+pub struct Solver {
+    clauses: Vec<Vec<u32>>,
+    watches: Vec<Vec<usize>>,
+    values: Vec<Value>,
+    levels: Vec<usize>,
+    reasons: Vec<Option<usize>>,
+    phases: Vec<bool>,
+    activity: Vec<f64>,
+    increment: f64,
+    heap: BinaryHeap<(u64, usize)>,
+    trail: Vec<u32>,
+    trail_limits: Vec<usize>,
+    propagated: usize,
+    inconsistent: bool,
+}
+
+fn negate(lit: u32) -> u32 {
+    lit ^ 1
+}
+
+fn var(lit: u32) -> usize {
+    (lit >> 1) as usize
+}
+
+impl Solver {
+    pub fn new(vars: usize) -> Self {
+        Self {
+            clauses: vec![],
+            watches: vec![vec![]; vars * 2],
+            values: vec![Value::Unassigned; vars],
+            levels: vec![0; vars],
+            reasons: vec![None; vars],
+            phases: vec![false; vars],
+            activity: vec![0.0; vars],
+            increment: 1.0,
+            heap: (0..vars).map(|var| (0, var)).collect(),
+            trail: vec![],
+            trail_limits: vec![],
+            propagated: 0,
+            inconsistent: false,
+        }
+    }
+
+    pub fn lit(var: usize, positive: bool) -> u32 {
+        ((var as u32) << 1) | (!positive as u32)
+    }
+
+    fn value(&self, lit: u32) -> Value {
+        match (self.values[var(lit)], lit & 1 == 1) {
+            (Value::Unassigned, _) => Value::Unassigned,
+            (Value::True, false) | (Value::False, true) => Value::True,
+            _ => Value::False,
+        }
+    }
+
+    fn level(&self) -> usize {
+        self.trail_limits.len()
+    }
+
+    fn assign(&mut self, lit: u32, reason: Option<usize>) {
+        let v = var(lit);
+        self.values[v] = if lit & 1 == 0 {
+            Value::True
+        } else {
+            Value::False
+        };
+        self.levels[v] = self.level();
+        self.reasons[v] = reason;
+        self.trail.push(lit);
+    }
+
+    pub fn add_clause(&mut self, lits: &[u32]) {
+        if self.inconsistent {
+            return;
+        }
+        let mut clause = lits.to_vec();
+        clause.sort_unstable();
+        clause.dedup();
+        if clause.windows(2).any(|pair| pair[0] == negate(pair[1])) {
+            return;
+        }
+        clause.retain(|&lit| self.value(lit) != Value::False || self.levels[var(lit)] != 0);
+        if clause.iter().any(|&lit| self.value(lit) == Value::True) {
+            return;
+        }
+        match clause.len() {
+            0 => self.inconsistent = true,
+            1 => {
+                self.assign(clause[0], None);
+                if self.propagate().is_some() {
+                    self.inconsistent = true;
+                }
+            }
+            _ => {
+                self.watches[negate(clause[0]) as usize].push(self.clauses.len());
+                self.watches[negate(clause[1]) as usize].push(self.clauses.len());
+                self.clauses.push(clause);
+            }
+        }
+    }
+
+    // Returns the index of a conflicting clause, if any.
+    fn propagate(&mut self) -> Option<usize> {
+        while self.propagated < self.trail.len() {
+            let lit = self.trail[self.propagated];
+            self.propagated += 1;
+            let watching = std::mem::take(&mut self.watches[lit as usize]);
+            let mut kept = Vec::with_capacity(watching.len());
+            let mut conflict = None;
+            let mut rest = watching.into_iter();
+            for index in &mut rest {
+                let false_lit = negate(lit);
+                if self.clauses[index][0] == false_lit {
+                    self.clauses[index].swap(0, 1);
+                }
+                let first = self.clauses[index][0];
+                if self.value(first) == Value::True {
+                    kept.push(index);
+                    continue;
+                }
+                let replacement = (2..self.clauses[index].len())
+                    .find(|&k| self.value(self.clauses[index][k]) != Value::False);
+                match replacement {
+                    Some(k) => {
+                        self.clauses[index].swap(1, k);
+                        let watched = self.clauses[index][1];
+                        self.watches[negate(watched) as usize].push(index);
+                    }
+                    None => {
+                        kept.push(index);
+                        if self.value(first) == Value::False {
+                            conflict = Some(index);
+                            break;
+                        }
+                        self.assign(first, Some(index));
+                    }
+                }
+            }
+            kept.extend(rest);
+            self.watches[lit as usize].extend(kept);
+            if conflict.is_some() {
+                return conflict;
+            }
+        }
+        None
+    }
+
+    fn bump(&mut self, v: usize) {
+        self.activity[v] += self.increment;
+        if self.activity[v] > 1e100 {
+            self.activity
+                .iter_mut()
+                .for_each(|activity| *activity *= 1e-100);
+            self.increment *= 1e-100;
+            let activity = &self.activity;
+            self.heap = (0..activity.len())
+                .map(|v| (activity[v].to_bits(), v))
+                .collect();
+        }
+        self.heap.push((self.activity[v].to_bits(), v));
+    }
+
+    // First unique implication point learning.
+    fn analyze(&mut self, conflict: usize) -> (Vec<u32>, usize) {
+        let mut seen = vec![false; self.values.len()];
+        let mut learnt = vec![0];
+        let mut counter = 0;
+        let mut clause = conflict;
+        let mut index = self.trail.len();
+        let mut pivot = None;
+        loop {
+            let lits = self.clauses[clause].clone();
+            for &lit in lits.iter().skip(if pivot.is_some() { 1 } else { 0 }) {
+                let v = var(lit);
+                if !seen[v] && self.levels[v] > 0 {
+                    seen[v] = true;
+                    self.bump(v);
+                    if self.levels[v] == self.level() {
+                        counter += 1;
+                    } else {
+                        learnt.push(lit);
+                    }
+                }
+            }
+            let lit = loop {
+                index -= 1;
+                if seen[var(self.trail[index])] {
+                    break self.trail[index];
+                }
+            };
+            counter -= 1;
+            seen[var(lit)] = false;
+            pivot = Some(lit);
+            if counter == 0 {
+                break;
+            }
+            clause = self.reasons[var(lit)].unwrap();
+        }
+        learnt[0] = negate(pivot.unwrap());
+        let backjump = learnt[1..]
+            .iter()
+            .map(|&lit| self.levels[var(lit)])
+            .max()
+            .unwrap_or(0);
+        if learnt.len() > 1 {
+            let highest = (1..learnt.len())
+                .max_by_key(|&k| self.levels[var(learnt[k])])
+                .unwrap();
+            learnt.swap(1, highest);
+        }
+        self.increment /= 0.95;
+        (learnt, backjump)
+    }
+
+    fn backtrack(&mut self, level: usize) {
+        if self.level() <= level {
+            return;
+        }
+        let start = self.trail_limits[level];
+        for lit in self.trail.drain(start..) {
+            let v = var(lit);
+            self.phases[v] = lit & 1 == 0;
+            self.values[v] = Value::Unassigned;
+            self.reasons[v] = None;
+            self.heap.push((self.activity[v].to_bits(), v));
+        }
+        self.trail_limits.truncate(level);
+        self.propagated = self.trail.len();
+    }
+
+    fn decide(&mut self) -> Option<u32> {
+        while let Some((activity, v)) = self.heap.pop() {
+            if self.values[v] == Value::Unassigned && activity == self.activity[v].to_bits() {
+                return Some(Self::lit(v, self.phases[v]));
+            }
+        }
+        (0..self.values.len())
+            .find(|&v| self.values[v] == Value::Unassigned)
+            .map(|v| Self::lit(v, self.phases[v]))
+    }
+
+    // Returns a satisfying assignment of every variable, or None when the
+    // clauses are unsatisfiable.
+    pub fn solve(&mut self) -> Option<Vec<bool>> {
+        if self.inconsistent || self.propagate().is_some() {
+            self.inconsistent = true;
+            return None;
+        }
+        let mut conflicts = 0;
+        let mut restart = 100;
+        loop {
+            match self.propagate() {
+                Some(conflict) => {
+                    if self.level() == 0 {
+                        self.inconsistent = true;
+                        return None;
+                    }
+                    conflicts += 1;
+                    let (learnt, backjump) = self.analyze(conflict);
+                    self.backtrack(backjump);
+                    if learnt.len() == 1 {
+                        self.assign(learnt[0], None);
+                    } else {
+                        let index = self.clauses.len();
+                        self.watches[negate(learnt[0]) as usize].push(index);
+                        self.watches[negate(learnt[1]) as usize].push(index);
+                        let asserting = learnt[0];
+                        self.clauses.push(learnt);
+                        self.assign(asserting, Some(index));
+                    }
+                }
+                None => {
+                    if conflicts >= restart {
+                        conflicts = 0;
+                        restart += restart / 2;
+                        self.backtrack(0);
+                        continue;
+                    }
+                    match self.decide() {
+                        Some(lit) => {
+                            self.trail_limits.push(self.trail.len());
+                            self.assign(lit, None);
+                        }
+                        None => {
+                            return Some(self.values.iter().map(|&v| v == Value::True).collect())
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn satisfies(clauses: &[Vec<u32>], model: &[bool]) -> bool {
+        clauses
+            .iter()
+            .all(|clause| clause.iter().any(|&lit| model[var(lit)] == (lit & 1 == 0)))
+    }
+
+    #[test]
+    fn finds_a_model_of_satisfiable_clauses() {
+        let l = Solver::lit;
+        let clauses = vec![
+            vec![l(0, true), l(1, true)],
+            vec![l(0, false), l(2, true)],
+            vec![l(1, false), l(2, false)],
+            vec![l(2, true), l(3, false)],
+            vec![l(3, true), l(1, true)],
+        ];
+        let mut solver = Solver::new(4);
+        clauses.iter().for_each(|clause| solver.add_clause(clause));
+        let model = solver.solve().unwrap();
+        assert!(satisfies(&clauses, &model));
+    }
+
+    #[test]
+    fn three_pigeons_do_not_fit_in_two_holes() {
+        let l = Solver::lit;
+        let pigeon = |p: usize, h: usize| p * 2 + h;
+        let mut solver = Solver::new(6);
+        for p in 0..3 {
+            solver.add_clause(&[l(pigeon(p, 0), true), l(pigeon(p, 1), true)]);
+        }
+        for h in 0..2 {
+            for p in 0..3 {
+                for q in p + 1..3 {
+                    solver.add_clause(&[l(pigeon(p, h), false), l(pigeon(q, h), false)]);
+                }
+            }
+        }
+        assert_eq!(solver.solve(), None);
+    }
+}
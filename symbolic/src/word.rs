@@ -0,0 +1,222 @@
+use computer::{chip::arith, signal::Word as ConcreteWord};
+
+use crate::aig::{Aig, Lit};
+
+// A 16-bit value whose bits are literals of an and-inverter graph, least
+// significant bit first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Word(pub [Lit; 16]);
+
+impl Word {
+    pub fn constant(value: u16) -> Self {
+        let mut bits = [Lit::FALSE; 16];
+        bits.iter_mut()
+            .enumerate()
+            .for_each(|(i, bit)| *bit = Lit::constant(value >> i & 1 == 1));
+        Self(bits)
+    }
+
+    pub fn input(aig: &mut Aig) -> Self {
+        let mut bits = [Lit::FALSE; 16];
+        bits.iter_mut().for_each(|bit| *bit = aig.input());
+        Self(bits)
+    }
+
+    pub fn as_constant(&self) -> Option<u16> {
+        self.0.iter().enumerate().try_fold(0, |value, (i, bit)| {
+            bit.as_constant().map(|bit| value | (bit as u16) << i)
+        })
+    }
+
+    fn map(&self, mut f: impl FnMut(Lit) -> Lit) -> Self {
+        let mut bits = self.0;
+        bits.iter_mut().for_each(|bit| *bit = f(*bit));
+        Self(bits)
+    }
+
+    fn zip(&self, other: &Self, mut f: impl FnMut(Lit, Lit) -> Lit) -> Self {
+        let mut bits = self.0;
+        bits.iter_mut()
+            .zip(other.0.iter())
+            .for_each(|(bit, &other)| *bit = f(*bit, other));
+        Self(bits)
+    }
+
+    pub fn not(&self) -> Self {
+        self.map(Lit::invert)
+    }
+
+    pub fn and(&self, aig: &mut Aig, other: &Self) -> Self {
+        self.zip(other, |a, b| aig.and(a, b))
+    }
+
+    pub fn mux(&self, aig: &mut Aig, other: &Self, selector: Lit) -> Self {
+        self.zip(other, |a, b| aig.mux(a, b, selector))
+    }
+
+    pub fn add(&self, aig: &mut Aig, other: &Self) -> Self {
+        let mut carry = Lit::FALSE;
+        self.zip(other, |a, b| {
+            let half = aig.xor(a, b);
+            let sum = aig.xor(half, carry);
+            let generated = aig.and(a, b);
+            let propagated = aig.and(half, carry);
+            carry = aig.or(generated, propagated);
+            sum
+        })
+    }
+
+    pub fn is_zero(&self, aig: &mut Aig) -> Lit {
+        self.0
+            .iter()
+            .fold(Lit::TRUE, |zero, &bit| aig.and(zero, bit.invert()))
+    }
+
+    pub fn is_negative(&self) -> Lit {
+        self.0[15]
+    }
+
+    pub fn equals(&self, aig: &mut Aig, other: &Self) -> Lit {
+        self.0
+            .iter()
+            .zip(other.0.iter())
+            .fold(Lit::TRUE, |equal, (&a, &b)| {
+                let differ = aig.xor(a, b);
+                aig.and(equal, differ.invert())
+            })
+    }
+
+    // Unsigned comparison.
+    pub fn less_than(&self, aig: &mut Aig, other: &Self) -> Lit {
+        self.0
+            .iter()
+            .zip(other.0.iter())
+            .fold(Lit::FALSE, |less, (&a, &b)| {
+                let below = aig.and(a.invert(), b);
+                let differ = aig.xor(a, b);
+                let keep = aig.and(differ.invert(), less);
+                aig.or(below, keep)
+            })
+    }
+
+    pub fn low_bits(&self, count: usize) -> Self {
+        let mut bits = self.0;
+        bits[count..].iter_mut().for_each(|bit| *bit = Lit::FALSE);
+        Self(bits)
+    }
+
+    pub fn value(&self, values: &[bool]) -> u16 {
+        self.0.iter().enumerate().fold(0, |value, (i, &bit)| {
+            value | (crate::aig::value_of(values, bit) as u16) << i
+        })
+    }
+}
+
+pub struct AluOutput {
+    pub out: Word,
+    pub zero: Lit,
+    pub negative: Lit,
+}
+
+// The same gates as `chip::arith::alu`, over literals. Constant operands are
+// handed to the chip itself.
+pub fn alu(
+    aig: &mut Aig,
+    x: &Word,
+    y: &Word,
+    [zero_x, negate_x, zero_y, negate_y, f, negate_out]: [bool; 6],
+) -> AluOutput {
+    if let (Some(x), Some(y)) = (x.as_constant(), y.as_constant()) {
+        let (out, zero, negative) = arith::alu(
+            ConcreteWord::from(x),
+            ConcreteWord::from(y),
+            zero_x,
+            negate_x,
+            zero_y,
+            negate_y,
+            f,
+            negate_out,
+        );
+        return AluOutput {
+            out: Word::constant(out.as_raw()),
+            zero: Lit::constant(zero),
+            negative: Lit::constant(negative),
+        };
+    }
+    let x = x.and(aig, &Word::constant(if zero_x { 0 } else { 0xFFFF }));
+    let x = if negate_x { x.not() } else { x };
+    let y = y.and(aig, &Word::constant(if zero_y { 0 } else { 0xFFFF }));
+    let y = if negate_y { y.not() } else { y };
+    let out = if f { x.add(aig, &y) } else { x.and(aig, &y) };
+    let out = if negate_out { out.not() } else { out };
+    AluOutput {
+        out,
+        zero: out.is_zero(aig),
+        negative: out.is_negative(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn alu_over_literals_agrees_with_the_chip() {
+        let values = [0, 1, 2, 0x7FFF, 0x8000, 0xFFFF, 0x1234, 0xBEEF];
+        for control in 0..64u8 {
+            let flags = [
+                control & 32 != 0,
+                control & 16 != 0,
+                control & 8 != 0,
+                control & 4 != 0,
+                control & 2 != 0,
+                control & 1 != 0,
+            ];
+            let mut aig = Aig::new();
+            let x = Word::input(&mut aig);
+            let y = Word::input(&mut aig);
+            let symbolic = alu(&mut aig, &x, &y, flags);
+            for &a in &values {
+                for &b in &values {
+                    let mut inputs = HashMap::new();
+                    for i in 0..16 {
+                        inputs.insert(x.0[i].node(), a >> i & 1 == 1);
+                        inputs.insert(y.0[i].node(), b >> i & 1 == 1);
+                    }
+                    let evaluated = aig.evaluate(&inputs);
+                    let concrete = alu(&mut aig, &Word::constant(a), &Word::constant(b), flags);
+                    assert_eq!(
+                        symbolic.out.value(&evaluated),
+                        concrete.out.as_constant().unwrap()
+                    );
+                    assert_eq!(
+                        crate::aig::value_of(&evaluated, symbolic.zero),
+                        concrete.zero.as_constant().unwrap()
+                    );
+                    assert_eq!(
+                        crate::aig::value_of(&evaluated, symbolic.negative),
+                        concrete.negative.as_constant().unwrap()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unsigned_comparison() {
+        let mut aig = Aig::new();
+        let x = Word::input(&mut aig);
+        let bound = Word::constant(0x4000);
+        let below = x.less_than(&mut aig, &bound);
+        for &(value, expected) in &[(0, true), (0x3FFF, true), (0x4000, false), (0xFFFF, false)] {
+            let inputs = (0..16)
+                .map(|i| (x.0[i].node(), value >> i & 1 == 1))
+                .collect();
+            assert_eq!(
+                crate::aig::value_of(&aig.evaluate(&inputs), below),
+                expected
+            );
+        }
+    }
+}
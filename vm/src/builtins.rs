@@ -0,0 +1,682 @@
+use crate::loader::Module;
+
+const SCREEN: u16 = 0x4000;
+const KBD: u16 = 0x6000;
+const HEAP: u16 = 2048;
+
+const NEW_LINE: u16 = 128;
+const BACKSPACE: u16 = 129;
+
+// Why a built-in function did not return.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stop {
+    // Make the call again on the next step, as when waiting for a key.
+    Wait,
+    Halt,
+}
+
+pub trait Machine {
+    fn peek(&mut self, address: u16) -> u16;
+    fn poke(&mut self, address: u16, value: u16);
+}
+
+// The state the built-in classes keep outside the machine's RAM. The heap
+// and the objects on it are laid out as the bundled Jack OS lays them out.
+#[derive(Debug)]
+pub struct Os {
+    free_list: Option<u16>,
+    row: u16,
+    column: u16,
+    color: bool,
+    // A key pressed and not yet released.
+    key: Option<u16>,
+    // The characters read so far by readLine or readInt.
+    line: Option<Vec<u16>>,
+}
+
+impl Default for Os {
+    fn default() -> Self {
+        Self {
+            free_list: None,
+            row: 0,
+            column: 0,
+            color: true,
+            key: None,
+            line: None,
+        }
+    }
+}
+
+pub type Builtin = fn(&mut Call, &[u16]) -> Result<u16, Stop>;
+
+// A call of a built-in function on a machine.
+pub struct Call<'a> {
+    pub os: &'a mut Os,
+    pub machine: &'a mut dyn Machine,
+}
+
+impl<'a> Call<'a> {
+    fn peek(&mut self, address: u16) -> u16 {
+        self.machine.peek(address)
+    }
+
+    fn poke(&mut self, address: u16, value: u16) {
+        self.machine.poke(address, value);
+    }
+
+    // Prints the error code and halts, as Sys.error does.
+    fn error(&mut self, code: u16) -> Stop {
+        for c in format!("ERR{}", code).chars() {
+            self.print_char(c as u16);
+        }
+        Stop::Halt
+    }
+
+    // The heap is set up on first use, so that programs with their own
+    // Sys.init need not call Memory.init.
+    fn free_list(&mut self) -> u16 {
+        match self.os.free_list {
+            Some(free_list) => free_list,
+            None => {
+                self.poke(HEAP, SCREEN - HEAP);
+                self.poke(HEAP + 1, 0);
+                self.os.free_list = Some(HEAP);
+                HEAP
+            }
+        }
+    }
+
+    fn alloc(&mut self, size: u16) -> Result<u16, Stop> {
+        if size as i16 <= 0 {
+            return Err(self.error(5));
+        }
+        let length = size + 1;
+        let mut previous = None;
+        let mut segment = self.free_list();
+        while segment != 0 {
+            let available = self.peek(segment);
+            if available >= length {
+                if available - length > 1 {
+                    self.poke(segment, available - length);
+                    let block = segment + available - length;
+                    self.poke(block, length);
+                    return Ok(block + 1);
+                }
+                let next = self.peek(segment + 1);
+                match previous {
+                    Some(previous) => self.poke(previous + 1, next),
+                    None => self.os.free_list = Some(next),
+                }
+                return Ok(segment + 1);
+            }
+            previous = Some(segment);
+            segment = self.peek(segment + 1);
+        }
+        Err(self.error(6))
+    }
+
+    // Returns the block to the address ordered free list, merging it with
+    // its free neighbours.
+    fn de_alloc(&mut self, object: u16) {
+        let block = object.wrapping_sub(1);
+        let mut previous = None;
+        let mut next = self.free_list();
+        while next != 0 && next < block {
+            previous = Some(next);
+            next = self.peek(next + 1);
+        }
+        let length = self.peek(block);
+        if next != 0 && block + length == next {
+            let merged = length + self.peek(next);
+            self.poke(block, merged);
+            let after = self.peek(next + 1);
+            self.poke(block + 1, after);
+        } else {
+            self.poke(block + 1, next);
+        }
+        match previous {
+            Some(previous) if previous + self.peek(previous) == block => {
+                let merged = self.peek(previous) + self.peek(block);
+                self.poke(previous, merged);
+                let after = self.peek(block + 1);
+                self.poke(previous + 1, after);
+            }
+            Some(previous) => self.poke(previous + 1, block),
+            None => self.os.free_list = Some(block),
+        }
+    }
+
+    // Strings are objects with the fields chars, size and capacity.
+    fn new_string(&mut self, capacity: u16) -> Result<u16, Stop> {
+        if (capacity as i16) < 0 {
+            return Err(self.error(14));
+        }
+        let string = self.alloc(3)?;
+        let chars = if capacity > 0 {
+            self.alloc(capacity)?
+        } else {
+            0
+        };
+        self.poke(string, chars);
+        self.poke(string + 1, 0);
+        self.poke(string + 2, capacity);
+        Ok(string)
+    }
+
+    fn string_chars(&mut self, string: u16) -> Vec<u16> {
+        let chars = self.peek(string);
+        let size = self.peek(string + 1);
+        (0..size).map(|i| self.peek(chars + i)).collect()
+    }
+
+    fn set_string(&mut self, string: u16, value: &[u16]) -> Result<(), Stop> {
+        if value.len() > self.peek(string + 2) as usize {
+            return Err(self.error(19));
+        }
+        let chars = self.peek(string);
+        for (i, &c) in value.iter().enumerate() {
+            self.poke(chars + i as u16, c);
+        }
+        self.poke(string + 1, value.len() as u16);
+        Ok(())
+    }
+
+    fn string_index(&mut self, string: u16, index: u16, code: u16) -> Result<u16, Stop> {
+        if index >= self.peek(string + 1) {
+            return Err(self.error(code));
+        }
+        Ok(self.peek(string) + index)
+    }
+
+    // A character fills a byte of each of the 11 lines of its cell, the low
+    // byte in even columns and the high byte in odd ones.
+    fn draw_char(&mut self, c: u16) {
+        let map = match c {
+            32..=126 => FONT[c as usize - 32],
+            _ => [0, 127, 127, 127, 127, 127, 127, 127, 127, 0, 0],
+        };
+        let mut address = SCREEN + self.os.row * 352 + self.os.column / 2;
+        let high = self.os.column & 1 == 1;
+        for bits in map.iter() {
+            let (keep, bits) = if high {
+                (0x00FF, bits << 8)
+            } else {
+                (0xFF00, *bits)
+            };
+            let word = self.peek(address) & keep | bits;
+            self.poke(address, word);
+            address += 32;
+        }
+    }
+
+    fn print_char(&mut self, c: u16) {
+        match c {
+            NEW_LINE => self.println(),
+            BACKSPACE => self.backspace(),
+            _ => {
+                self.draw_char(c);
+                self.os.column += 1;
+                if self.os.column == 64 {
+                    self.println();
+                }
+            }
+        }
+    }
+
+    fn println(&mut self) {
+        self.os.column = 0;
+        self.os.row = (self.os.row + 1) % 23;
+    }
+
+    fn backspace(&mut self) {
+        if self.os.column > 0 {
+            self.os.column -= 1;
+        } else if self.os.row > 0 {
+            self.os.row -= 1;
+            self.os.column = 63;
+        }
+        self.draw_char(' ' as u16);
+    }
+
+    fn set_pixel(&mut self, x: i32, y: i32) {
+        let address = SCREEN + (y * 32 + x / 16) as u16;
+        let mask = 1 << (x % 16);
+        let word = self.peek(address);
+        let word = if self.os.color {
+            word | mask
+        } else {
+            word & !mask
+        };
+        self.poke(address, word);
+    }
+
+    fn draw_horizontal(&mut self, x1: i32, x2: i32, y: i32) {
+        for x in x1..=x2 {
+            self.set_pixel(x, y);
+        }
+    }
+
+    fn draw_line(&mut self, (mut x1, mut y1): (i32, i32), (x2, y2): (i32, i32)) {
+        let dx = (x2 - x1).abs();
+        let dy = -(y2 - y1).abs();
+        let (sx, sy) = ((x2 - x1).signum(), (y2 - y1).signum());
+        let mut error = dx + dy;
+        self.set_pixel(x1, y1);
+        while (x1, y1) != (x2, y2) {
+            if 2 * error >= dy {
+                error += dy;
+                x1 += sx;
+            }
+            if 2 * error <= dx {
+                error += dx;
+                y1 += sy;
+            }
+            self.set_pixel(x1, y1);
+        }
+    }
+
+    // Waits for a key to be pressed and released.
+    fn read_key(&mut self) -> Option<u16> {
+        let pressed = self.peek(KBD);
+        match self.os.key {
+            None if pressed != 0 => {
+                self.os.key = Some(pressed);
+                None
+            }
+            Some(key) if pressed == 0 => {
+                self.os.key = None;
+                Some(key)
+            }
+            _ => None,
+        }
+    }
+
+    // Reads and echoes characters up to a new line, after printing the
+    // message on the first call.
+    fn read_line(&mut self, message: u16) -> Result<Vec<u16>, Stop> {
+        if self.os.line.is_none() {
+            for c in self.string_chars(message) {
+                self.print_char(c);
+            }
+            self.os.line = Some(vec![]);
+        }
+        let c = self.read_key().ok_or(Stop::Wait)?;
+        self.print_char(c);
+        let line = self.os.line.as_mut().unwrap();
+        match c {
+            NEW_LINE => return Ok(self.os.line.take().unwrap()),
+            BACKSPACE => {
+                line.pop();
+            }
+            _ => line.push(c),
+        }
+        Err(Stop::Wait)
+    }
+}
+
+fn int_value(chars: &[u16]) -> u16 {
+    let (negative, digits) = match chars.first() {
+        Some(&c) if c == '-' as u16 => (true, &chars[1..]),
+        _ => (false, chars),
+    };
+    let value = digits
+        .iter()
+        .take_while(|&&c| ('0' as u16..='9' as u16).contains(&c))
+        .fold(0u16, |value, &c| {
+            value.wrapping_mul(10).wrapping_add(c - '0' as u16)
+        });
+    if negative {
+        value.wrapping_neg()
+    } else {
+        value
+    }
+}
+
+fn in_screen(x: u16, y: u16) -> bool {
+    (0..512).contains(&(x as i16)) && (0..256).contains(&(y as i16))
+}
+
+fn point(x: u16, y: u16) -> (i32, i32) {
+    (x as i16 as i32, y as i16 as i32)
+}
+
+// The Jack OS functions the interpreter can run natively, with their numbers
+// of arguments. Methods take the object first. Error codes are those of the
+// reference OS.
+pub const BUILTINS: &[(&str, u16, Builtin)] = &[
+    ("Math.init", 0, |_, _| Ok(0)),
+    (
+        "Math.abs",
+        1,
+        |_, a| Ok((a[0] as i16).wrapping_abs() as u16),
+    ),
+    ("Math.multiply", 2, |_, a| {
+        Ok((a[0] as i16).wrapping_mul(a[1] as i16) as u16)
+    }),
+    ("Math.divide", 2, |call, a| match a[1] {
+        0 => Err(call.error(3)),
+        _ => Ok((a[0] as i16).wrapping_div(a[1] as i16) as u16),
+    }),
+    ("Math.min", 2, |_, a| {
+        Ok((a[0] as i16).min(a[1] as i16) as u16)
+    }),
+    ("Math.max", 2, |_, a| {
+        Ok((a[0] as i16).max(a[1] as i16) as u16)
+    }),
+    ("Math.sqrt", 1, |call, a| {
+        if (a[0] as i16) < 0 {
+            return Err(call.error(4));
+        }
+        Ok((0..=181)
+            .take_while(|r| r * r <= a[0] as u32)
+            .last()
+            .unwrap() as u16)
+    }),
+    ("Memory.init", 0, |call, _| {
+        call.os.free_list = None;
+        call.free_list();
+        Ok(0)
+    }),
+    ("Memory.peek", 1, |call, a| Ok(call.peek(a[0]))),
+    ("Memory.poke", 2, |call, a| {
+        call.poke(a[0], a[1]);
+        Ok(0)
+    }),
+    ("Memory.alloc", 1, |call, a| call.alloc(a[0])),
+    ("Memory.deAlloc", 1, |call, a| {
+        call.de_alloc(a[0]);
+        Ok(0)
+    }),
+    ("Array.new", 1, |call, a| match a[0] as i16 {
+        size if size <= 0 => Err(call.error(2)),
+        _ => call.alloc(a[0]),
+    }),
+    ("Array.dispose", 1, |call, a| {
+        call.de_alloc(a[0]);
+        Ok(0)
+    }),
+    ("String.new", 1, |call, a| call.new_string(a[0])),
+    ("String.dispose", 1, |call, a| {
+        let chars = call.peek(a[0]);
+        if call.peek(a[0] + 2) > 0 {
+            call.de_alloc(chars);
+        }
+        call.de_alloc(a[0]);
+        Ok(0)
+    }),
+    ("String.length", 1, |call, a| Ok(call.peek(a[0] + 1))),
+    ("String.charAt", 2, |call, a| {
+        let address = call.string_index(a[0], a[1], 15)?;
+        Ok(call.peek(address))
+    }),
+    ("String.setCharAt", 3, |call, a| {
+        let address = call.string_index(a[0], a[1], 16)?;
+        call.poke(address, a[2]);
+        Ok(0)
+    }),
+    ("String.appendChar", 2, |call, a| {
+        let size = call.peek(a[0] + 1);
+        if size == call.peek(a[0] + 2) {
+            return Err(call.error(17));
+        }
+        let chars = call.peek(a[0]);
+        call.poke(chars + size, a[1]);
+        call.poke(a[0] + 1, size + 1);
+        Ok(a[0])
+    }),
+    ("String.eraseLastChar", 1, |call, a| {
+        let size = call.peek(a[0] + 1);
+        if size == 0 {
+            return Err(call.error(18));
+        }
+        call.poke(a[0] + 1, size - 1);
+        Ok(0)
+    }),
+    ("String.intValue", 1, |call, a| {
+        let chars = call.string_chars(a[0]);
+        Ok(int_value(&chars))
+    }),
+    ("String.setInt", 2, |call, a| {
+        let digits: Vec<u16> = (a[1] as i16)
+            .to_string()
+            .chars()
+            .map(|c| c as u16)
+            .collect();
+        call.set_string(a[0], &digits)?;
+        Ok(0)
+    }),
+    ("String.backSpace", 0, |_, _| Ok(BACKSPACE)),
+    ("String.doubleQuote", 0, |_, _| Ok('"' as u16)),
+    ("String.newLine", 0, |_, _| Ok(NEW_LINE)),
+    ("Output.init", 0, |call, _| {
+        call.os.row = 0;
+        call.os.column = 0;
+        Ok(0)
+    }),
+    ("Output.moveCursor", 2, |call, a| {
+        if a[0] > 22 || a[1] > 63 {
+            return Err(call.error(20));
+        }
+        call.os.row = a[0];
+        call.os.column = a[1];
+        Ok(0)
+    }),
+    ("Output.printChar", 1, |call, a| {
+        call.print_char(a[0]);
+        Ok(0)
+    }),
+    ("Output.printString", 1, |call, a| {
+        for c in call.string_chars(a[0]) {
+            call.print_char(c);
+        }
+        Ok(0)
+    }),
+    ("Output.printInt", 1, |call, a| {
+        for c in (a[0] as i16).to_string().chars() {
+            call.print_char(c as u16);
+        }
+        Ok(0)
+    }),
+    ("Output.println", 0, |call, _| {
+        call.println();
+        Ok(0)
+    }),
+    ("Output.backSpace", 0, |call, _| {
+        call.backspace();
+        Ok(0)
+    }),
+    ("Screen.init", 0, |call, _| {
+        call.os.color = true;
+        Ok(0)
+    }),
+    ("Screen.clearScreen", 0, |call, _| {
+        for address in SCREEN..KBD {
+            call.poke(address, 0);
+        }
+        Ok(0)
+    }),
+    ("Screen.setColor", 1, |call, a| {
+        call.os.color = a[0] != 0;
+        Ok(0)
+    }),
+    ("Screen.drawPixel", 2, |call, a| {
+        if !in_screen(a[0], a[1]) {
+            return Err(call.error(7));
+        }
+        let (x, y) = point(a[0], a[1]);
+        call.set_pixel(x, y);
+        Ok(0)
+    }),
+    ("Screen.drawLine", 4, |call, a| {
+        if !in_screen(a[0], a[1]) || !in_screen(a[2], a[3]) {
+            return Err(call.error(8));
+        }
+        call.draw_line(point(a[0], a[1]), point(a[2], a[3]));
+        Ok(0)
+    }),
+    ("Screen.drawRectangle", 4, |call, a| {
+        let ((x1, y1), (x2, y2)) = (point(a[0], a[1]), point(a[2], a[3]));
+        if !in_screen(a[0], a[1]) || !in_screen(a[2], a[3]) || x1 > x2 || y1 > y2 {
+            return Err(call.error(9));
+        }
+        for y in y1..=y2 {
+            call.draw_horizontal(x1, x2, y);
+        }
+        Ok(0)
+    }),
+    ("Screen.drawCircle", 3, |call, a| {
+        let ((x, y), r) = (point(a[0], a[1]), a[2] as i16 as i32);
+        if !(0..=181).contains(&r) {
+            return Err(call.error(13));
+        }
+        if x - r < 0 || x + r > 511 || y - r < 0 || y + r > 255 {
+            return Err(call.error(12));
+        }
+        for dy in -r..=r {
+            let half = (0..=r)
+                .take_while(|h| h * h <= r * r - dy * dy)
+                .last()
+                .unwrap();
+            call.draw_horizontal(x - half, x + half, y + dy);
+        }
+        Ok(0)
+    }),
+    ("Keyboard.init", 0, |_, _| Ok(0)),
+    ("Keyboard.keyPressed", 0, |call, _| Ok(call.peek(KBD))),
+    ("Keyboard.readChar", 0, |call, _| {
+        let c = call.read_key().ok_or(Stop::Wait)?;
+        call.print_char(c);
+        Ok(c)
+    }),
+    ("Keyboard.readLine", 1, |call, a| {
+        let line = call.read_line(a[0])?;
+        let string = call.new_string(line.len().max(1) as u16)?;
+        call.set_string(string, &line)?;
+        Ok(string)
+    }),
+    ("Keyboard.readInt", 1, |call, a| {
+        let line = call.read_line(a[0])?;
+        Ok(int_value(&line))
+    }),
+    ("Sys.halt", 0, |_, _| Err(Stop::Halt)),
+    ("Sys.error", 1, |call, a| Err(call.error(a[0]))),
+    // Returns at once rather than waiting.
+    ("Sys.wait", 1, |call, a| match a[0] as i16 {
+        duration if duration < 0 => Err(call.error(1)),
+        _ => Ok(0),
+    }),
+];
+
+// Sys.init calls back into the program, so it is VM code rather than Rust.
+// It initializes the OS, runs Main.main and halts.
+pub fn sys_init() -> Module {
+    let mut lines = vec!["function Sys.init 0".to_string()];
+    for class in &["Memory", "Math", "Screen", "Output", "Keyboard"] {
+        lines.push(format!("call {}.init 0", class));
+        lines.push("pop temp 0".to_string());
+    }
+    lines.push("call Main.main 0".to_string());
+    lines.push("pop temp 0".to_string());
+    lines.push("call Sys.halt 0".to_string());
+    Module::parse("Sys", &lines).unwrap()
+}
+
+// The bitmaps of the printable characters from ' ' to '~', the same as
+// the bundled Jack OS draws.
+const FONT: [[u16; 11]; 95] = [
+    [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 8, 8, 8, 8, 8, 0, 8, 0, 0, 0],
+    [0, 20, 20, 20, 0, 0, 0, 0, 0, 0, 0],
+    [0, 20, 20, 62, 20, 62, 20, 20, 0, 0, 0],
+    [0, 8, 60, 10, 28, 40, 30, 8, 0, 0, 0],
+    [0, 6, 38, 16, 8, 4, 50, 48, 0, 0, 0],
+    [0, 12, 18, 10, 4, 42, 18, 44, 0, 0, 0],
+    [0, 12, 8, 4, 0, 0, 0, 0, 0, 0, 0],
+    [0, 16, 8, 4, 4, 4, 8, 16, 0, 0, 0],
+    [0, 4, 8, 16, 16, 16, 8, 4, 0, 0, 0],
+    [0, 0, 20, 8, 62, 8, 20, 0, 0, 0, 0],
+    [0, 0, 8, 8, 62, 8, 8, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 12, 8, 4, 0, 0, 0],
+    [0, 0, 0, 0, 62, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 12, 12, 0, 0, 0],
+    [0, 0, 32, 16, 8, 4, 2, 0, 0, 0, 0],
+    [0, 28, 34, 50, 42, 38, 34, 28, 0, 0, 0],
+    [0, 8, 12, 8, 8, 8, 8, 28, 0, 0, 0],
+    [0, 28, 34, 32, 16, 8, 4, 62, 0, 0, 0],
+    [0, 62, 16, 8, 16, 32, 34, 28, 0, 0, 0],
+    [0, 16, 24, 20, 18, 62, 16, 16, 0, 0, 0],
+    [0, 62, 2, 30, 32, 32, 34, 28, 0, 0, 0],
+    [0, 24, 4, 2, 30, 34, 34, 28, 0, 0, 0],
+    [0, 62, 32, 16, 8, 4, 4, 4, 0, 0, 0],
+    [0, 28, 34, 34, 28, 34, 34, 28, 0, 0, 0],
+    [0, 28, 34, 34, 60, 32, 16, 12, 0, 0, 0],
+    [0, 0, 12, 12, 0, 12, 12, 0, 0, 0, 0],
+    [0, 0, 12, 12, 0, 12, 8, 4, 0, 0, 0],
+    [0, 16, 8, 4, 2, 4, 8, 16, 0, 0, 0],
+    [0, 0, 0, 62, 0, 62, 0, 0, 0, 0, 0],
+    [0, 4, 8, 16, 32, 16, 8, 4, 0, 0, 0],
+    [0, 28, 34, 32, 16, 8, 0, 8, 0, 0, 0],
+    [0, 28, 34, 32, 44, 42, 42, 28, 0, 0, 0],
+    [0, 28, 34, 34, 34, 62, 34, 34, 0, 0, 0],
+    [0, 30, 34, 34, 30, 34, 34, 30, 0, 0, 0],
+    [0, 28, 34, 2, 2, 2, 34, 28, 0, 0, 0],
+    [0, 14, 18, 34, 34, 34, 18, 14, 0, 0, 0],
+    [0, 62, 2, 2, 30, 2, 2, 62, 0, 0, 0],
+    [0, 62, 2, 2, 14, 2, 2, 2, 0, 0, 0],
+    [0, 28, 34, 2, 2, 50, 34, 28, 0, 0, 0],
+    [0, 34, 34, 34, 62, 34, 34, 34, 0, 0, 0],
+    [0, 28, 8, 8, 8, 8, 8, 28, 0, 0, 0],
+    [0, 56, 16, 16, 16, 16, 18, 12, 0, 0, 0],
+    [0, 34, 18, 10, 6, 10, 18, 34, 0, 0, 0],
+    [0, 2, 2, 2, 2, 2, 2, 62, 0, 0, 0],
+    [0, 34, 54, 42, 34, 34, 34, 34, 0, 0, 0],
+    [0, 34, 34, 38, 42, 50, 34, 34, 0, 0, 0],
+    [0, 28, 34, 34, 34, 34, 34, 28, 0, 0, 0],
+    [0, 30, 34, 34, 30, 2, 2, 2, 0, 0, 0],
+    [0, 28, 34, 34, 34, 42, 18, 44, 0, 0, 0],
+    [0, 30, 34, 34, 30, 10, 18, 34, 0, 0, 0],
+    [0, 60, 2, 2, 28, 32, 32, 30, 0, 0, 0],
+    [0, 62, 8, 8, 8, 8, 8, 8, 0, 0, 0],
+    [0, 34, 34, 34, 34, 34, 34, 28, 0, 0, 0],
+    [0, 34, 34, 34, 34, 34, 20, 8, 0, 0, 0],
+    [0, 34, 34, 34, 42, 42, 54, 34, 0, 0, 0],
+    [0, 34, 34, 20, 8, 20, 34, 34, 0, 0, 0],
+    [0, 34, 34, 20, 8, 8, 8, 8, 0, 0, 0],
+    [0, 62, 32, 16, 8, 4, 2, 62, 0, 0, 0],
+    [0, 28, 4, 4, 4, 4, 4, 28, 0, 0, 0],
+    [0, 0, 2, 4, 8, 16, 32, 0, 0, 0, 0],
+    [0, 28, 16, 16, 16, 16, 16, 28, 0, 0, 0],
+    [0, 8, 20, 34, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 62, 0, 0, 0],
+    [0, 4, 8, 16, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 28, 32, 60, 34, 60, 0, 0, 0],
+    [0, 2, 2, 26, 38, 34, 34, 30, 0, 0, 0],
+    [0, 0, 0, 28, 2, 2, 34, 28, 0, 0, 0],
+    [0, 32, 32, 44, 50, 34, 34, 60, 0, 0, 0],
+    [0, 0, 0, 28, 34, 62, 2, 28, 0, 0, 0],
+    [0, 24, 36, 4, 14, 4, 4, 4, 0, 0, 0],
+    [0, 0, 60, 34, 34, 60, 32, 28, 0, 0, 0],
+    [0, 2, 2, 26, 38, 34, 34, 34, 0, 0, 0],
+    [0, 8, 0, 12, 8, 8, 8, 28, 0, 0, 0],
+    [0, 16, 0, 24, 16, 16, 18, 12, 0, 0, 0],
+    [0, 2, 2, 18, 10, 6, 10, 18, 0, 0, 0],
+    [0, 12, 8, 8, 8, 8, 8, 28, 0, 0, 0],
+    [0, 0, 0, 22, 42, 42, 34, 34, 0, 0, 0],
+    [0, 0, 0, 26, 38, 34, 34, 34, 0, 0, 0],
+    [0, 0, 0, 28, 34, 34, 34, 28, 0, 0, 0],
+    [0, 0, 0, 30, 34, 30, 2, 2, 0, 0, 0],
+    [0, 0, 0, 44, 50, 60, 32, 32, 0, 0, 0],
+    [0, 0, 0, 26, 38, 2, 2, 2, 0, 0, 0],
+    [0, 0, 0, 28, 2, 28, 32, 30, 0, 0, 0],
+    [0, 4, 4, 14, 4, 4, 36, 24, 0, 0, 0],
+    [0, 0, 0, 34, 34, 34, 50, 44, 0, 0, 0],
+    [0, 0, 0, 34, 34, 34, 20, 8, 0, 0, 0],
+    [0, 0, 0, 34, 34, 42, 42, 20, 0, 0, 0],
+    [0, 0, 0, 34, 20, 8, 20, 34, 0, 0, 0],
+    [0, 0, 0, 34, 34, 60, 32, 28, 0, 0, 0],
+    [0, 0, 0, 62, 16, 8, 4, 62, 0, 0, 0],
+    [0, 16, 8, 8, 4, 8, 8, 16, 0, 0, 0],
+    [0, 8, 8, 8, 8, 8, 8, 8, 0, 0, 0],
+    [0, 4, 8, 8, 16, 8, 8, 4, 0, 0, 0],
+    [0, 0, 0, 4, 42, 16, 0, 0, 0, 0, 0],
+];
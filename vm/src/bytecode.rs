@@ -0,0 +1,464 @@
+use std::{collections::HashMap, convert::TryFrom};
+
+use thiserror::Error;
+
+use crate::{
+    command::{Arithmetic, Command, Segment},
+    loader::Module,
+    parser::is_symbol,
+};
+
+// Layout, little endian throughout:
+//
+//   "HVMB" version:u16
+//   strings:u16 { length:u16 utf8 }
+//   modules:u16 { name:u16 statics:u16 commands:u32 { command } }
+//
+// Each command is an opcode byte followed by its operands. Names refer to the
+// string table, and `statics` is the size of the module's static segment.
+const MAGIC: &[u8; 4] = b"HVMB";
+const VERSION: u16 = 1;
+
+const PUSH: u8 = 0x00;
+const POP: u8 = 0x01;
+const ARITHMETIC: u8 = 0x10;
+const LABEL: u8 = 0x20;
+const GOTO: u8 = 0x21;
+const IF_GOTO: u8 = 0x22;
+const FUNCTION: u8 = 0x30;
+const CALL: u8 = 0x31;
+const RETURN: u8 = 0x32;
+
+const SEGMENTS: [Segment; 8] = [
+    Segment::Argument,
+    Segment::Local,
+    Segment::Static,
+    Segment::Constant,
+    Segment::This,
+    Segment::That,
+    Segment::Pointer,
+    Segment::Temp,
+];
+
+#[derive(Debug, Error)]
+pub enum BytecodeError {
+    #[error("Not a VM bytecode file")]
+    InvalidMagic,
+
+    #[error("Unsupported bytecode version {0}")]
+    UnsupportedVersion(u16),
+
+    #[error("Unexpected end of file")]
+    UnexpectedEnd,
+
+    #[error("Offset {0}: Invalid opcode {1:#04x}")]
+    InvalidOpcode(usize, u8),
+
+    #[error("Offset {0}: Invalid segment {1}")]
+    InvalidSegment(usize, u8),
+
+    #[error("Offset {0}: Invalid operand {1}")]
+    InvalidOperand(usize, u16),
+
+    #[error("Offset {0}: Invalid string {1}")]
+    InvalidString(usize, u16),
+
+    #[error("Offset {0}: {1} is an invalid symbol")]
+    InvalidSymbol(usize, String),
+
+    #[error("{0}: Static index {1} is out of range")]
+    StaticOutOfRange(String, u16),
+
+    #[error("Too many strings to encode")]
+    TooManyStrings,
+
+    #[error("A string of {0} bytes is too long to encode")]
+    StringTooLong(usize),
+
+    #[error("Too many modules to encode")]
+    TooManyModules,
+
+    #[error("{0}: Too many commands to encode")]
+    TooManyCommands(String),
+
+    #[error("{0} bytes after the last module")]
+    TrailingBytes(usize),
+}
+pub type Result<T> = std::result::Result<T, BytecodeError>;
+
+struct Writer {
+    bytes: Vec<u8>,
+    strings: HashMap<String, u16>,
+    table: Vec<String>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, string: &str) -> Result<()> {
+        let index = match self.strings.get(string) {
+            Some(&index) => index,
+            None => {
+                // The count of strings must fit in a u16 too.
+                if self.table.len() >= u16::MAX as usize {
+                    return Err(BytecodeError::TooManyStrings);
+                }
+                let index = self.table.len() as u16;
+                self.strings.insert(string.to_string(), index);
+                self.table.push(string.to_string());
+                index
+            }
+        };
+        self.u16(index);
+        Ok(())
+    }
+
+    fn command(&mut self, command: &Command) -> Result<()> {
+        let segment = |segment| SEGMENTS.iter().position(|s| *s == segment).unwrap() as u8;
+        match command {
+            Command::Push(s, index) | Command::Pop(s, index) => {
+                let opcode = if let Command::Push(..) = command {
+                    PUSH
+                } else {
+                    POP
+                };
+                self.u8(opcode);
+                self.u8(segment(*s));
+                self.u16(*index);
+            }
+            Command::Arithmetic(op) => {
+                let index = Arithmetic::ALL
+                    .iter()
+                    .position(|other| other == op)
+                    .unwrap();
+                self.u8(ARITHMETIC + index as u8);
+            }
+            Command::Label(label) => {
+                self.u8(LABEL);
+                self.string(label)?;
+            }
+            Command::Goto(label) => {
+                self.u8(GOTO);
+                self.string(label)?;
+            }
+            Command::IfGoto(label) => {
+                self.u8(IF_GOTO);
+                self.string(label)?;
+            }
+            Command::Function(name, locals) => {
+                self.u8(FUNCTION);
+                self.string(name)?;
+                self.u16(*locals);
+            }
+            Command::Call(name, arguments) => {
+                self.u8(CALL);
+                self.string(name)?;
+                self.u16(*arguments);
+            }
+            Command::Return => self.u8(RETURN),
+        }
+        Ok(())
+    }
+}
+
+// The string table precedes the code that refers to it, so the code is
+// encoded first and the header is put in front of it.
+pub fn write(modules: &[Module]) -> Result<Vec<u8>> {
+    let mut writer = Writer {
+        bytes: vec![],
+        strings: HashMap::new(),
+        table: vec![],
+    };
+    let count = u16::try_from(modules.len()).map_err(|_| BytecodeError::TooManyModules)?;
+    writer.u16(count);
+    for module in modules {
+        let mut statics = 0;
+        for command in &module.commands {
+            if let Command::Push(Segment::Static, index) | Command::Pop(Segment::Static, index) =
+                command
+            {
+                let size = index
+                    .checked_add(1)
+                    .ok_or_else(|| BytecodeError::StaticOutOfRange(module.name.clone(), *index))?;
+                statics = statics.max(size);
+            }
+        }
+        let commands = u32::try_from(module.commands.len())
+            .map_err(|_| BytecodeError::TooManyCommands(module.name.clone()))?;
+        writer.string(&module.name)?;
+        writer.u16(statics);
+        writer.u32(commands);
+        for command in &module.commands {
+            writer.command(command)?;
+        }
+    }
+
+    let code = std::mem::take(&mut writer.bytes);
+    writer.bytes.extend_from_slice(MAGIC);
+    writer.u16(VERSION);
+    let count = u16::try_from(writer.table.len()).map_err(|_| BytecodeError::TooManyStrings)?;
+    writer.u16(count);
+    for string in std::mem::take(&mut writer.table) {
+        let length =
+            u16::try_from(string.len()).map_err(|_| BytecodeError::StringTooLong(string.len()))?;
+        writer.u16(length);
+        writer.bytes.extend_from_slice(string.as_bytes());
+    }
+    writer.bytes.extend(code);
+    Ok(writer.bytes)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    strings: Vec<String>,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset + count)
+            .ok_or(BytecodeError::UnexpectedEnd)?;
+        self.offset += count;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let offset = self.offset;
+        let index = self.u16()?;
+        self.strings
+            .get(index as usize)
+            .cloned()
+            .ok_or(BytecodeError::InvalidString(offset, index))
+    }
+
+    // A string naming a function or label, held to the rules of .vm files.
+    fn symbol(&mut self) -> Result<String> {
+        let offset = self.offset;
+        let symbol = self.string()?;
+        if is_symbol(&symbol) {
+            Ok(symbol)
+        } else {
+            Err(BytecodeError::InvalidSymbol(offset, symbol))
+        }
+    }
+
+    fn command(&mut self) -> Result<Command> {
+        let offset = self.offset;
+        let opcode = self.u8()?;
+        let command = match opcode {
+            PUSH | POP => {
+                let code = self.u8()?;
+                let segment = *SEGMENTS
+                    .get(code as usize)
+                    .ok_or(BytecodeError::InvalidSegment(offset + 1, code))?;
+                let index = self.u16()?;
+                if segment.size().is_some_and(|size| index >= size)
+                    || (opcode == POP && segment == Segment::Constant)
+                {
+                    return Err(BytecodeError::InvalidOperand(offset + 2, index));
+                }
+                if opcode == PUSH {
+                    Command::Push(segment, index)
+                } else {
+                    Command::Pop(segment, index)
+                }
+            }
+            _ if (ARITHMETIC..ARITHMETIC + Arithmetic::ALL.len() as u8).contains(&opcode) => {
+                Command::Arithmetic(Arithmetic::ALL[(opcode - ARITHMETIC) as usize])
+            }
+            LABEL => Command::Label(self.symbol()?),
+            GOTO => Command::Goto(self.symbol()?),
+            IF_GOTO => Command::IfGoto(self.symbol()?),
+            FUNCTION => Command::Function(self.symbol()?, self.u16()?),
+            CALL => Command::Call(self.symbol()?, self.u16()?),
+            RETURN => Command::Return,
+            _ => return Err(BytecodeError::InvalidOpcode(offset, opcode)),
+        };
+        Ok(command)
+    }
+}
+
+// Bytecode carries no line numbers, so every command reads as line 0.
+pub fn read(bytes: &[u8]) -> Result<Vec<Module>> {
+    let mut reader = Reader {
+        bytes,
+        offset: 0,
+        strings: vec![],
+    };
+    if reader.take(4).ok() != Some(&MAGIC[..]) {
+        return Err(BytecodeError::InvalidMagic);
+    }
+    let version = reader.u16()?;
+    if version != VERSION {
+        return Err(BytecodeError::UnsupportedVersion(version));
+    }
+    for _ in 0..reader.u16()? {
+        let length = reader.u16()? as usize;
+        let offset = reader.offset;
+        let string = std::str::from_utf8(reader.take(length)?)
+            .map_err(|_| BytecodeError::InvalidString(offset, reader.strings.len() as u16))?;
+        reader.strings.push(string.to_string());
+    }
+
+    let mut modules = vec![];
+    for _ in 0..reader.u16()? {
+        let name = reader.string()?;
+        let statics = reader.u16()?;
+        let count = reader.u32()? as usize;
+        let commands = (0..count)
+            .map(|_| reader.command())
+            .collect::<Result<Vec<_>>>()?;
+        for command in &commands {
+            if let Command::Push(Segment::Static, index) | Command::Pop(Segment::Static, index) =
+                command
+            {
+                if *index >= statics {
+                    return Err(BytecodeError::StaticOutOfRange(name, *index));
+                }
+            }
+        }
+        modules.push(Module {
+            name,
+            lines: vec![0; commands.len()],
+            commands,
+        });
+    }
+    if reader.offset < bytes.len() {
+        return Err(BytecodeError::TrailingBytes(bytes.len() - reader.offset));
+    }
+    Ok(modules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytecode_round_trips_modules() {
+        let main = Module::parse(
+            "Main",
+            &[
+                "function Main.main 2",
+                "push constant 32767",
+                "pop static 4",
+                "label LOOP",
+                "push static 4",
+                "not",
+                "if-goto LOOP",
+                "call Main.main 0",
+                "goto LOOP",
+                "return",
+            ],
+        )
+        .unwrap();
+        let sys = Module::parse("Sys", &["function Sys.init 0", "call Main.main 0"]).unwrap();
+        let modules = vec![main, sys];
+        let bytes = write(&modules).unwrap();
+        let read = read(&bytes).unwrap();
+        assert_eq!(read.len(), 2);
+        for (read, module) in read.iter().zip(modules.iter()) {
+            assert_eq!(read.name, module.name);
+            assert_eq!(read.commands, module.commands);
+        }
+        // "Main.main" is stored once.
+        assert_eq!(
+            bytes
+                .windows(9)
+                .filter(|window| window == b"Main.main")
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn corrupt_bytecode_is_rejected() {
+        let module = Module::parse("Main", &["push static 1", "add"]).unwrap();
+        let bytes = write(&[module]).unwrap();
+        assert!(matches!(
+            read(&bytes[1..]),
+            Err(BytecodeError::InvalidMagic)
+        ));
+        assert!(matches!(
+            read(&bytes[..bytes.len() - 1]),
+            Err(BytecodeError::UnexpectedEnd)
+        ));
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert!(matches!(read(&extra), Err(BytecodeError::TrailingBytes(1))));
+        let mut invalid = bytes.clone();
+        *invalid.last_mut().unwrap() = 0xFF;
+        assert!(matches!(
+            read(&invalid),
+            Err(BytecodeError::InvalidOpcode(_, 0xFF))
+        ));
+        // The static count sits right after the module name.
+        let statics = bytes.len() - 4 - 4 - 1 - 2;
+        let mut shrunk = bytes;
+        shrunk[statics] = 1;
+        assert!(matches!(
+            read(&shrunk),
+            Err(BytecodeError::StaticOutOfRange(_, 1))
+        ));
+    }
+
+    #[test]
+    fn invalid_names_are_rejected() {
+        let module = Module::parse("Main", &["label L", "goto L"]).unwrap();
+        let mut bytes = write(&[module]).unwrap();
+        let label = bytes.iter().position(|&byte| byte == b'L').unwrap();
+        bytes[label] = b'1';
+        assert!(matches!(
+            read(&bytes),
+            Err(BytecodeError::InvalidSymbol(_, name)) if name == "1"
+        ));
+    }
+
+    #[test]
+    fn modules_that_do_not_fit_are_rejected() {
+        let module = Module::parse("Main", &["push static 65535"]).unwrap();
+        assert!(matches!(
+            write(&[module]),
+            Err(BytecodeError::StaticOutOfRange(_, 65535))
+        ));
+        let label = "L".repeat(70_000);
+        let module = Module::parse("Main", &[format!("label {}", label)]).unwrap();
+        assert!(matches!(
+            write(&[module]),
+            Err(BytecodeError::StringTooLong(70_000))
+        ));
+        let labels = (0..u16::MAX)
+            .map(|i| format!("label L{}", i))
+            .collect::<Vec<_>>();
+        let module = Module::parse("Main", &labels).unwrap();
+        assert!(matches!(
+            write(&[module]),
+            Err(BytecodeError::TooManyStrings)
+        ));
+    }
+}
@@ -0,0 +1,156 @@
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Segment {
+    Argument,
+    Local,
+    Static,
+    Constant,
+    This,
+    That,
+    Pointer,
+    Temp,
+}
+
+impl Segment {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "argument" => Some(Segment::Argument),
+            "local" => Some(Segment::Local),
+            "static" => Some(Segment::Static),
+            "constant" => Some(Segment::Constant),
+            "this" => Some(Segment::This),
+            "that" => Some(Segment::That),
+            "pointer" => Some(Segment::Pointer),
+            "temp" => Some(Segment::Temp),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Segment::Argument => "argument",
+            Segment::Local => "local",
+            Segment::Static => "static",
+            Segment::Constant => "constant",
+            Segment::This => "this",
+            Segment::That => "that",
+            Segment::Pointer => "pointer",
+            Segment::Temp => "temp",
+        }
+    }
+
+    pub fn size(&self) -> Option<u16> {
+        match self {
+            Segment::Pointer => Some(2),
+            Segment::Temp => Some(8),
+            Segment::Constant => Some(0x8000),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Arithmetic {
+    Add,
+    Sub,
+    Neg,
+    Eq,
+    Gt,
+    Lt,
+    And,
+    Or,
+    Not,
+}
+
+impl Arithmetic {
+    pub const ALL: [Arithmetic; 9] = [
+        Arithmetic::Add,
+        Arithmetic::Sub,
+        Arithmetic::Neg,
+        Arithmetic::Eq,
+        Arithmetic::Gt,
+        Arithmetic::Lt,
+        Arithmetic::And,
+        Arithmetic::Or,
+        Arithmetic::Not,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|op| op.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Arithmetic::Add => "add",
+            Arithmetic::Sub => "sub",
+            Arithmetic::Neg => "neg",
+            Arithmetic::Eq => "eq",
+            Arithmetic::Gt => "gt",
+            Arithmetic::Lt => "lt",
+            Arithmetic::And => "and",
+            Arithmetic::Or => "or",
+            Arithmetic::Not => "not",
+        }
+    }
+
+    pub fn is_unary(&self) -> bool {
+        matches!(self, Arithmetic::Neg | Arithmetic::Not)
+    }
+
+    pub fn apply(&self, x: u16, y: u16) -> u16 {
+        let truth = |b: bool| if b { 0xFFFF } else { 0 };
+        match self {
+            Arithmetic::Add => x.wrapping_add(y),
+            Arithmetic::Sub => x.wrapping_sub(y),
+            Arithmetic::Neg => y.wrapping_neg(),
+            Arithmetic::Eq => truth(x == y),
+            Arithmetic::Gt => truth((x as i16) > (y as i16)),
+            Arithmetic::Lt => truth((x as i16) < (y as i16)),
+            Arithmetic::And => x & y,
+            Arithmetic::Or => x | y,
+            Arithmetic::Not => !y,
+        }
+    }
+}
+
+impl fmt::Display for Arithmetic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Command {
+    Push(Segment, u16),
+    Pop(Segment, u16),
+    Arithmetic(Arithmetic),
+    Label(String),
+    Goto(String),
+    IfGoto(String),
+    Function(String, u16),
+    Call(String, u16),
+    Return,
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Command::Push(segment, index) => write!(f, "push {} {}", segment, index),
+            Command::Pop(segment, index) => write!(f, "pop {} {}", segment, index),
+            Command::Arithmetic(op) => write!(f, "{}", op),
+            Command::Label(label) => write!(f, "label {}", label),
+            Command::Goto(label) => write!(f, "goto {}", label),
+            Command::IfGoto(label) => write!(f, "if-goto {}", label),
+            Command::Function(name, locals) => write!(f, "function {} {}", name, locals),
+            Command::Call(name, arguments) => write!(f, "call {} {}", name, arguments),
+            Command::Return => write!(f, "return"),
+        }
+    }
+}
@@ -0,0 +1,604 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use computer::{keyboard::Keyboard, screen::Screen, signal::Word};
+
+use crate::{
+    builtins::{self, Call, Machine, Os, Stop, BUILTINS},
+    command::{Arithmetic, Command, Segment},
+    loader::{defines, Module},
+};
+
+const SP: u16 = 0;
+const LCL: u16 = 1;
+const ARG: u16 = 2;
+const THIS: u16 = 3;
+const THAT: u16 = 4;
+const TEMP: u16 = 5;
+const STATIC: u16 = 16;
+const SCREEN: u16 = 0x4000;
+const KBD: u16 = 0x6000;
+
+#[derive(Debug, Error)]
+pub enum LinkError {
+    #[error("{0}: Undefined label \"{1}\"")]
+    UndefinedLabel(String, String),
+
+    #[error("{0}: Undefined function \"{1}\"")]
+    UndefinedFunction(String, String),
+
+    #[error("Function \"{0}\" is defined twice")]
+    DuplicateFunction(String),
+
+    #[error("{0}: Too many static variables")]
+    TooManyStatics(String),
+
+    #[error("{0}: Built-in function \"{1}\" takes {2} arguments")]
+    BuiltinArguments(String, String, u16),
+
+    #[error("{0} commands are too many for return addresses to reach")]
+    TooManyCommands(usize),
+}
+
+// Commands with their labels, functions and static variables resolved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Push(Segment, u16),
+    Pop(Segment, u16),
+    Arithmetic(Arithmetic),
+    Label,
+    Goto(usize),
+    IfGoto(usize),
+    Function(u16),
+    Call(usize, u16),
+    Builtin(usize, u16),
+    Return,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    Running,
+    // Execution ran off the end of the program or looped on a single label.
+    Halted,
+}
+
+pub struct Interpreter<S: Screen, K: Keyboard> {
+    ops: Vec<Op>,
+    commands: Vec<Command>,
+    functions: Vec<Option<String>>,
+    ram: Vec<u16>,
+    screen: S,
+    keyboard: K,
+    os: Os,
+    pc: usize,
+    steps: u64,
+}
+
+impl<S: Screen, K: Keyboard> Interpreter<S, K> {
+    pub fn new(modules: &[Module]) -> Result<Self, LinkError> {
+        Self::link(modules, false)
+    }
+
+    // Functions the modules call without defining run as built-in Rust
+    // functions where there is one, like the VMEmulator's built-in OS. A
+    // Main.main without a Sys.init gets the built-in Sys.init to run it.
+    pub fn with_builtins(modules: &[Module]) -> Result<Self, LinkError> {
+        if defines(modules, "Sys.init") || !defines(modules, "Main.main") {
+            return Self::link(modules, true);
+        }
+        let mut modules = modules.to_vec();
+        modules.push(builtins::sys_init());
+        Self::link(&modules, true)
+    }
+
+    // Static variables are allocated from 16 in order of first reference, the
+    // same addresses the assembler gives the symbols of translated code.
+    fn link(modules: &[Module], builtins: bool) -> Result<Self, LinkError> {
+        // Return addresses are pushed as words, and the bootstrap's points
+        // one past the end of the program.
+        let count = Self::flatten(modules).count();
+        if count >= u16::MAX as usize {
+            return Err(LinkError::TooManyCommands(count));
+        }
+        let mut entries = HashMap::new();
+        for (pc, (_, command)) in Self::flatten(modules).enumerate() {
+            if let Command::Function(name, _) = command {
+                if entries.insert(name.clone(), pc).is_some() {
+                    return Err(LinkError::DuplicateFunction(name.clone()));
+                }
+            }
+        }
+        // Labels outside functions are scoped by their module.
+        let mut labels = HashMap::new();
+        let mut pc = 0;
+        for module in modules {
+            let mut function = None;
+            for command in &module.commands {
+                match command {
+                    Command::Function(name, _) => function = Some(name.as_str()),
+                    Command::Label(label) => {
+                        labels.insert((module.name.as_str(), function, label.as_str()), pc);
+                    }
+                    _ => {}
+                }
+                pc += 1;
+            }
+        }
+
+        let mut statics = HashMap::new();
+        let mut ops = vec![];
+        let mut commands = vec![];
+        let mut functions = vec![];
+        for module in modules {
+            let mut function: Option<&str> = None;
+            for command in &module.commands {
+                let context = function.unwrap_or(&module.name).to_string();
+                let label = |label: &String| {
+                    labels
+                        .get(&(module.name.as_str(), function, label.as_str()))
+                        .copied()
+                        .ok_or_else(|| LinkError::UndefinedLabel(context.clone(), label.clone()))
+                };
+                let op = match command {
+                    Command::Push(Segment::Static, index)
+                    | Command::Pop(Segment::Static, index) => {
+                        let next = STATIC + statics.len() as u16;
+                        let address = *statics.entry((&module.name, *index)).or_insert(next);
+                        if address >= 256 {
+                            return Err(LinkError::TooManyStatics(module.name.clone()));
+                        }
+                        match command {
+                            Command::Push(..) => Op::Push(Segment::Static, address),
+                            _ => Op::Pop(Segment::Static, address),
+                        }
+                    }
+                    Command::Push(segment, index) => Op::Push(*segment, *index),
+                    Command::Pop(segment, index) => Op::Pop(*segment, *index),
+                    Command::Arithmetic(op) => Op::Arithmetic(*op),
+                    Command::Label(_) => Op::Label,
+                    Command::Goto(target) => Op::Goto(label(target)?),
+                    Command::IfGoto(target) => Op::IfGoto(label(target)?),
+                    Command::Function(name, locals) => {
+                        function = Some(name);
+                        Op::Function(*locals)
+                    }
+                    Command::Call(name, arguments) => {
+                        let builtin = BUILTINS.iter().position(|(builtin, ..)| builtin == name);
+                        match (entries.get(name), builtin) {
+                            (Some(&entry), _) => Op::Call(entry, *arguments),
+                            (None, Some(index)) if builtins => {
+                                let expected = BUILTINS[index].1;
+                                if expected != *arguments {
+                                    return Err(LinkError::BuiltinArguments(
+                                        context,
+                                        name.clone(),
+                                        expected,
+                                    ));
+                                }
+                                Op::Builtin(index, *arguments)
+                            }
+                            _ => return Err(LinkError::UndefinedFunction(context, name.clone())),
+                        }
+                    }
+                    Command::Return => Op::Return,
+                };
+                ops.push(op);
+                commands.push(command.clone());
+                functions.push(function.map(str::to_string));
+            }
+        }
+
+        Ok(Self {
+            ops,
+            commands,
+            functions,
+            ram: vec![0; SCREEN as usize],
+            screen: S::new(),
+            keyboard: K::new(),
+            os: Os::default(),
+            pc: 0,
+            steps: 0,
+        })
+    }
+
+    fn flatten(modules: &[Module]) -> impl Iterator<Item = (&Module, &Command)> {
+        modules
+            .iter()
+            .flat_map(|module| module.commands.iter().map(move |command| (module, command)))
+    }
+
+    // Sets SP to 256 and calls Sys.init, which returns past the end of the
+    // program.
+    pub fn bootstrap(&mut self) -> Result<(), LinkError> {
+        let entry = self
+            .commands
+            .iter()
+            .position(|command| matches!(command, Command::Function(name, _) if name == "Sys.init"))
+            .ok_or_else(|| {
+                LinkError::UndefinedFunction("Bootstrap".to_string(), "Sys.init".to_string())
+            })?;
+        self.poke(SP, 256);
+        self.pc = self.ops.len();
+        self.call(entry, 0);
+        Ok(())
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn command(&self) -> Option<&Command> {
+        self.commands.get(self.pc)
+    }
+
+    pub fn function(&self) -> Option<&str> {
+        self.functions.get(self.pc).and_then(|f| f.as_deref())
+    }
+
+    pub fn screen(&self) -> &S {
+        &self.screen
+    }
+
+    pub fn set_keystate(&mut self, state: K::State) {
+        self.keyboard.set_state(state);
+    }
+
+    pub fn peek(&mut self, address: u16) -> u16 {
+        let address = address & 0x7FFF;
+        if address < SCREEN {
+            self.ram[address as usize]
+        } else if address < KBD {
+            self.screen
+                .tick(&Self::screen_address(address), false, Word::from(0));
+            self.screen.get_output().as_raw()
+        } else {
+            self.keyboard.get_output().as_raw()
+        }
+    }
+
+    pub fn poke(&mut self, address: u16, value: u16) {
+        let address = address & 0x7FFF;
+        if address < SCREEN {
+            self.ram[address as usize] = value;
+        } else if address < KBD {
+            self.screen
+                .tick(&Self::screen_address(address), true, Word::from(value));
+        }
+    }
+
+    fn screen_address(address: u16) -> [bool; 13] {
+        let mut bits = [false; 13];
+        bits.iter_mut()
+            .enumerate()
+            .for_each(|(i, bit)| *bit = address >> (12 - i) & 1 == 1);
+        bits
+    }
+
+    fn push(&mut self, value: u16) {
+        let sp = self.peek(SP);
+        self.poke(sp, value);
+        self.poke(SP, sp.wrapping_add(1));
+    }
+
+    fn pop(&mut self) -> u16 {
+        let sp = self.peek(SP).wrapping_sub(1);
+        self.poke(SP, sp);
+        self.peek(sp)
+    }
+
+    fn address(&mut self, segment: Segment, index: u16) -> u16 {
+        match segment {
+            Segment::Local => self.peek(LCL).wrapping_add(index),
+            Segment::Argument => self.peek(ARG).wrapping_add(index),
+            Segment::This => self.peek(THIS).wrapping_add(index),
+            Segment::That => self.peek(THAT).wrapping_add(index),
+            Segment::Pointer => THIS + index,
+            Segment::Temp => TEMP + index,
+            Segment::Static => index,
+            Segment::Constant => unreachable!(),
+        }
+    }
+
+    fn call(&mut self, entry: usize, arguments: u16) {
+        self.push(self.pc as u16 + 1);
+        for &base in &[LCL, ARG, THIS, THAT] {
+            let value = self.peek(base);
+            self.push(value);
+        }
+        let sp = self.peek(SP);
+        self.poke(ARG, sp.wrapping_sub(arguments + 5));
+        self.poke(LCL, sp);
+        self.pc = entry;
+    }
+
+    pub fn state(&self) -> State {
+        match self.ops.get(self.pc) {
+            None => State::Halted,
+            Some(Op::Goto(target)) if *target + 1 == self.pc => State::Halted,
+            Some(_) => State::Running,
+        }
+    }
+
+    pub fn step(&mut self) -> State {
+        let op = match self.ops.get(self.pc) {
+            Some(op) => *op,
+            None => return State::Halted,
+        };
+        self.steps += 1;
+        let mut next = self.pc + 1;
+        match op {
+            Op::Push(Segment::Constant, value) => self.push(value),
+            Op::Push(segment, index) => {
+                let address = self.address(segment, index);
+                let value = self.peek(address);
+                self.push(value);
+            }
+            Op::Pop(segment, index) => {
+                let address = self.address(segment, index);
+                let value = self.pop();
+                self.poke(address, value);
+            }
+            Op::Arithmetic(op) => {
+                let y = self.pop();
+                let x = if op.is_unary() { 0 } else { self.pop() };
+                self.push(op.apply(x, y));
+            }
+            Op::Label => {}
+            Op::Goto(target) => next = target,
+            Op::IfGoto(target) => {
+                if self.pop() != 0 {
+                    next = target;
+                }
+            }
+            Op::Function(locals) => {
+                for _ in 0..locals {
+                    self.push(0);
+                }
+            }
+            Op::Call(entry, arguments) => {
+                self.call(entry, arguments);
+                next = self.pc;
+            }
+            // The arguments stay on the stack until the function returns, so
+            // that a waiting function can be called again.
+            Op::Builtin(index, arguments) => {
+                let sp = self.peek(SP).wrapping_sub(arguments);
+                let args: Vec<u16> = (0..arguments).map(|i| self.peek(sp + i)).collect();
+                let mut os = std::mem::take(&mut self.os);
+                let result = (BUILTINS[index].2)(
+                    &mut Call {
+                        os: &mut os,
+                        machine: self,
+                    },
+                    &args,
+                );
+                self.os = os;
+                match result {
+                    Ok(value) => {
+                        self.poke(SP, sp);
+                        self.push(value);
+                    }
+                    Err(Stop::Wait) => next = self.pc,
+                    Err(Stop::Halt) => next = self.ops.len(),
+                }
+            }
+            Op::Return => {
+                let frame = self.peek(LCL);
+                let return_address = self.peek(frame.wrapping_sub(5));
+                let value = self.pop();
+                let arg = self.peek(ARG);
+                self.poke(arg, value);
+                self.poke(SP, arg.wrapping_add(1));
+                for (offset, &base) in [THAT, THIS, ARG, LCL].iter().enumerate() {
+                    let value = self.peek(frame.wrapping_sub(offset as u16 + 1));
+                    self.poke(base, value);
+                }
+                next = return_address as usize;
+            }
+        }
+        self.pc = next;
+        self.state()
+    }
+
+    // Runs until the program halts or `max_steps` commands have executed.
+    pub fn run(&mut self, max_steps: Option<u64>) -> State {
+        let mut state = self.state();
+        let mut remaining = max_steps;
+        while state == State::Running && remaining != Some(0) {
+            state = self.step();
+            remaining = remaining.map(|remaining| remaining - 1);
+        }
+        state
+    }
+}
+
+impl<S: Screen, K: Keyboard> Machine for Interpreter<S, K> {
+    fn peek(&mut self, address: u16) -> u16 {
+        Interpreter::peek(self, address)
+    }
+
+    fn poke(&mut self, address: u16, value: u16) {
+        Interpreter::poke(self, address, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader;
+    use computer::{keyboard::DummyKeyboard, screen::DummyScreen};
+
+    type Vm = Interpreter<DummyScreen, DummyKeyboard>;
+
+    fn module(name: &str, lines: &[&str]) -> Module {
+        Module::parse(name, lines).unwrap()
+    }
+
+    fn fibonacci() -> Vec<Module> {
+        vec![
+            module(
+                "Main",
+                &[
+                    "function Main.fibonacci 0",
+                    "push argument 0",
+                    "push constant 2",
+                    "lt",
+                    "if-goto IF_TRUE",
+                    "goto IF_FALSE",
+                    "label IF_TRUE",
+                    "push argument 0",
+                    "return",
+                    "label IF_FALSE",
+                    "push argument 0",
+                    "push constant 2",
+                    "sub",
+                    "call Main.fibonacci 1",
+                    "push argument 0",
+                    "push constant 1",
+                    "sub",
+                    "call Main.fibonacci 1",
+                    "add",
+                    "return",
+                ],
+            ),
+            module(
+                "Sys",
+                &[
+                    "function Sys.init 0",
+                    "push constant 10",
+                    "call Main.fibonacci 1",
+                    "pop static 3",
+                    "push constant 16384",
+                    "pop pointer 1",
+                    "push constant 255",
+                    "pop that 0",
+                    "label END",
+                    "goto END",
+                ],
+            ),
+        ]
+    }
+
+    #[test]
+    fn interpreter_runs_functions_until_halted() {
+        let mut vm = Vm::new(&fibonacci()).unwrap();
+        vm.bootstrap().unwrap();
+        assert_eq!(vm.run(Some(100_000)), State::Halted);
+        assert_eq!(vm.function(), Some("Sys.init"));
+        assert_eq!(vm.command(), Some(&Command::Goto("END".to_string())));
+        assert_eq!(vm.peek(STATIC), 55);
+        assert_eq!(vm.peek(SP), 261);
+        assert_eq!(vm.peek(SCREEN), 255);
+    }
+
+    #[test]
+    fn interpreter_agrees_with_the_translator_on_static_addresses() {
+        let modules = fibonacci();
+        let program = loader::translate(&modules, true);
+        let symbols = program.symbols();
+        let mut vm = Vm::new(&modules).unwrap();
+        vm.bootstrap().unwrap();
+        vm.run(Some(100_000));
+        let address = symbols.get("Sys.3").unwrap();
+        assert_eq!(vm.peek(address), 55);
+    }
+
+    #[test]
+    fn unresolved_references_are_link_errors() {
+        let scoped = [module(
+            "Main",
+            &[
+                "function Main.a 0",
+                "label L",
+                "function Main.b 0",
+                "goto L",
+            ],
+        )];
+        assert!(matches!(
+            Vm::new(&scoped),
+            Err(LinkError::UndefinedLabel(function, label)) if function == "Main.b" && label == "L"
+        ));
+        let missing = [module("Main", &["call Math.multiply 2"])];
+        assert!(matches!(
+            Vm::new(&missing),
+            Err(LinkError::UndefinedFunction(..))
+        ));
+        let huge = [module("Main", &["push constant 0"; 65535])];
+        assert!(matches!(
+            Vm::new(&huge),
+            Err(LinkError::TooManyCommands(65535))
+        ));
+    }
+
+    #[test]
+    fn labels_outside_functions_are_scoped_by_module() {
+        let modules = [
+            module("A", &["goto L", "label L"]),
+            module("B", &["label L", "goto L"]),
+        ];
+        let mut vm = Vm::new(&modules).unwrap();
+        vm.step();
+        assert_eq!(vm.pc(), 1);
+        vm.step();
+        vm.step();
+        vm.step();
+        assert_eq!(vm.pc(), 2);
+    }
+
+    #[test]
+    fn builtins_stand_in_for_the_os() {
+        let main = module(
+            "Main",
+            &[
+                "function Main.main 1",
+                "push constant 2",
+                "call String.new 1",
+                "push constant 72",
+                "call String.appendChar 2",
+                "push constant 105",
+                "call String.appendChar 2",
+                "pop local 0",
+                "push local 0",
+                "call Output.printString 1",
+                "pop temp 0",
+                "push constant 300",
+                "push constant 7",
+                "neg",
+                "call Math.multiply 2",
+                "pop static 0",
+                "push constant 0",
+                "push constant 100",
+                "push constant 15",
+                "push constant 100",
+                "call Screen.drawLine 4",
+                "pop temp 0",
+                "push constant 0",
+                "return",
+            ],
+        );
+        assert!(matches!(
+            Vm::new(std::slice::from_ref(&main)),
+            Err(LinkError::UndefinedFunction(..))
+        ));
+        let mut vm = Vm::with_builtins(&[main]).unwrap();
+        vm.bootstrap().unwrap();
+        assert_eq!(vm.run(Some(1000)), State::Halted);
+        assert_eq!(vm.peek(STATIC) as i16, -2100);
+        // The second line of "Hi", with "i" in the high byte.
+        assert_eq!(vm.peek(SCREEN + 32), 34 | 8 << 8);
+        assert_eq!(vm.peek(SCREEN + 100 * 32), 0xFFFF);
+        // The string's characters were allocated at the end of the heap.
+        assert_eq!(vm.peek(2048), 14336 - 4 - 3);
+
+        let wrong = module("Main", &["function Main.main 0", "call Math.sqrt 2"]);
+        assert!(matches!(
+            Vm::with_builtins(&[wrong]),
+            Err(LinkError::BuiltinArguments(_, name, 1)) if name == "Math.sqrt"
+        ));
+    }
+}
@@ -0,0 +1,9 @@
+pub mod builtins;
+pub mod bytecode;
+pub mod command;
+pub mod interpreter;
+pub mod loader;
+pub mod optimize;
+pub mod parser;
+pub mod source_map;
+pub mod translator;
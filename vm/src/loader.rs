@@ -0,0 +1,125 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::{
+    bytecode::{self, BytecodeError},
+    command::Command,
+    optimize::remove_unreachable,
+    parser::{parse_numbered, ParseError},
+    translator::{Translation, Translator},
+};
+use assembler::program::Program;
+
+#[derive(Debug, Error)]
+pub enum LoadError {
+    #[error("{0}: {1}")]
+    Io(PathBuf, io::Error),
+
+    #[error("{0}: {1}")]
+    Parse(PathBuf, ParseError),
+
+    #[error("{0}: {1}")]
+    Bytecode(PathBuf, BytecodeError),
+
+    #[error("{0}: No .vm files found")]
+    Empty(PathBuf),
+}
+
+// A parsed .vm file. Its name prefixes the static variables it declares, and
+// `lines` holds the line number of each command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Module {
+    pub name: String,
+    pub commands: Vec<Command>,
+    pub lines: Vec<usize>,
+}
+
+impl Module {
+    pub fn parse<S: AsRef<str>>(name: &str, lines: &[S]) -> Result<Self, ParseError> {
+        let (lines, commands) = parse_numbered(lines)?.into_iter().unzip();
+        Ok(Self {
+            name: name.to_string(),
+            commands,
+            lines,
+        })
+    }
+
+    pub fn read(path: &Path) -> Result<Self, LoadError> {
+        let io_error = |error| LoadError::Io(path.to_path_buf(), error);
+        let reader = BufReader::new(File::open(path).map_err(io_error)?);
+        let lines = reader
+            .lines()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(io_error)?;
+        let name = path
+            .file_stem()
+            .map_or_else(String::new, |stem| stem.to_string_lossy().to_string());
+        Self::parse(&name, &lines).map_err(|error| LoadError::Parse(path.to_path_buf(), error))
+    }
+}
+
+// Reads a .vm file, a .vmb bytecode file, or every .vm file in a directory in
+// name order.
+pub fn load(path: &Path) -> Result<Vec<Module>, LoadError> {
+    if path.extension().is_some_and(|ext| ext == "vmb") {
+        let bytes = fs::read(path).map_err(|error| LoadError::Io(path.to_path_buf(), error))?;
+        return bytecode::read(&bytes)
+            .map_err(|error| LoadError::Bytecode(path.to_path_buf(), error));
+    }
+    if !path.is_dir() {
+        return Ok(vec![Module::read(path)?]);
+    }
+    let io_error = |error| LoadError::Io(path.to_path_buf(), error);
+    let mut files = fs::read_dir(path)
+        .map_err(io_error)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(io_error)?;
+    files.retain(|file| file.is_file() && file.extension().is_some_and(|ext| ext == "vm"));
+    files.sort();
+    if files.is_empty() {
+        return Err(LoadError::Empty(path.to_path_buf()));
+    }
+    files.iter().map(|file| Module::read(file)).collect()
+}
+
+pub fn defines(modules: &[Module], function: &str) -> bool {
+    modules.iter().any(|module| {
+        module
+            .commands
+            .iter()
+            .any(|command| matches!(command, Command::Function(name, _) if name == function))
+    })
+}
+
+pub fn translate(modules: &[Module], bootstrap: bool) -> Program {
+    translate_with_details(modules, bootstrap, false).program
+}
+
+// Optimizing for size also removes the functions that are never called.
+pub fn translate_with_details(modules: &[Module], bootstrap: bool, optimize: bool) -> Translation {
+    let mut translator = if optimize {
+        Translator::optimizing()
+    } else {
+        Translator::new()
+    };
+    if bootstrap {
+        translator.bootstrap();
+    }
+    let reachable;
+    let modules = if optimize {
+        reachable = remove_unreachable(modules, bootstrap);
+        &reachable
+    } else {
+        modules
+    };
+    for module in modules {
+        translator.translate_lines(&module.name, &module.commands, &module.lines);
+    }
+    translator.finish_with_details()
+}
@@ -0,0 +1,223 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::{
+    app_from_crate, crate_authors, crate_description, crate_name, crate_version, AppSettings, Arg,
+    SubCommand,
+};
+
+use assembler::hack;
+use computer::{keyboard::DummyKeyboard, screen::DummyScreen};
+use vm::{
+    bytecode,
+    interpreter::{Interpreter, State},
+    loader::{defines, load, translate_with_details},
+};
+
+// A directory Prog/ stands for the file Prog/Prog.
+fn output_stem(path: &Path) -> io::Result<PathBuf> {
+    if path.is_dir() {
+        let name = path
+            .canonicalize()?
+            .file_name()
+            .unwrap_or_default()
+            .to_owned();
+        Ok(path.join(name))
+    } else {
+        Ok(path.to_path_buf())
+    }
+}
+
+// Text goes to bytecode in one .vmb file, and bytecode back to one .vm file
+// per module.
+fn convert(input: &Path, output: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
+    let modules = load(input)?;
+    if input.extension().is_some_and(|ext| ext == "vmb") {
+        let directory = output
+            .or_else(|| input.parent())
+            .unwrap_or_else(|| Path::new("."));
+        fs::create_dir_all(directory)?;
+        for module in &modules {
+            let mut file =
+                BufWriter::new(File::create(directory.join(format!("{}.vm", module.name)))?);
+            for command in &module.commands {
+                writeln!(file, "{}", command)?;
+            }
+        }
+    } else {
+        let output = match output {
+            Some(output) => output.to_path_buf(),
+            None => output_stem(input)?.with_extension("vmb"),
+        };
+        fs::write(output, bytecode::write(&modules)?)?;
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = app_from_crate!()
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .setting(AppSettings::ArgsNegateSubcommands)
+        .subcommand(
+            SubCommand::with_name("convert")
+                .about("Convert between VM text and bytecode (.vmb)")
+                .arg(
+                    Arg::with_name("input")
+                        .help("A .vm file or a directory of them, or a .vmb file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .takes_value(true)
+                        .help("The .vmb file, or the directory for the .vm files"),
+                ),
+        )
+        .arg(
+            Arg::with_name("file")
+                .help("The VM file (.vm or .vmb), or a directory of VM files")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("no-bootstrap")
+                .long("no-bootstrap")
+                .help("Do not call Sys.init even if it is defined"),
+        )
+        .arg(
+            Arg::with_name("run")
+                .long("run")
+                .conflicts_with_all(&["hack", "output"])
+                .help("Interpret the program instead of translating it"),
+        )
+        .arg(
+            Arg::with_name("max-steps")
+                .long("max-steps")
+                .takes_value(true)
+                .requires("run")
+                .help("Stop interpreting after this many commands"),
+        )
+        .arg(
+            Arg::with_name("no-builtins")
+                .long("no-builtins")
+                .requires("run")
+                .help("Do not run undefined OS functions as built-in functions"),
+        )
+        .arg(
+            Arg::with_name("optimize")
+                .long("optimize")
+                .short("O")
+                .help("Optimize the generated code for size"),
+        )
+        .arg(
+            Arg::with_name("report")
+                .long("report")
+                .help("Print the number of ROM words generated for each function"),
+        )
+        .arg(
+            Arg::with_name("hack")
+                .long("hack")
+                .help("Assemble into machine language (.hack) instead of assembly (.asm)"),
+        )
+        .arg(
+            Arg::with_name("symbols")
+                .long("symbols")
+                .short("s")
+                .requires("hack")
+                .help("Also write the labels and variables into a .sym file"),
+        )
+        .arg(
+            Arg::with_name("map")
+                .long("map")
+                .short("m")
+                .help("Also write a .map file locating the VM command of each ROM address"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .short("o")
+                .takes_value(true)
+                .help("Path to the translated file"),
+        )
+        .get_matches();
+    if let Some(args) = args.subcommand_matches("convert") {
+        let input = Path::new(args.value_of("input").unwrap());
+        return convert(input, args.value_of("output").map(Path::new));
+    }
+
+    let path = Path::new(args.value_of("file").unwrap());
+    let modules = load(path)?;
+    let bootstrap = !args.is_present("no-bootstrap") && defines(&modules, "Sys.init");
+    if args.is_present("run") {
+        let max_steps = args.value_of("max-steps").map(str::parse).transpose()?;
+        let builtins = !args.is_present("no-builtins");
+        let mut vm = if builtins {
+            Interpreter::<DummyScreen, DummyKeyboard>::with_builtins(&modules)?
+        } else {
+            Interpreter::new(&modules)?
+        };
+        // The built-in Sys.init runs Main.main.
+        let bootstrap = bootstrap
+            || (!args.is_present("no-bootstrap") && builtins && defines(&modules, "Main.main"));
+        if bootstrap {
+            vm.bootstrap()?;
+        } else {
+            vm.poke(0, 256);
+        }
+        let state = vm.run(max_steps);
+        let sp = vm.peek(0);
+        println!(
+            "{} after {} commands",
+            if state == State::Halted {
+                "Halted"
+            } else {
+                "Stopped"
+            },
+            vm.steps()
+        );
+        if let Some(function) = vm.function() {
+            println!("In {}", function);
+        }
+        if sp > 256 {
+            println!("Top of stack: {}", vm.peek(sp - 1) as i16);
+        }
+        return Ok(());
+    }
+    let translation = translate_with_details(&modules, bootstrap, args.is_present("optimize"));
+    let program = &translation.program;
+    if args.is_present("report") {
+        let mut sizes = translation.sizes.clone();
+        sizes.sort_by(|(a, x), (b, y)| y.cmp(x).then_with(|| a.cmp(b)));
+        for (function, size) in &sizes {
+            println!("{:>6} {}", size, function);
+        }
+        println!("{:>6} total", program.len());
+    }
+    let file_name = output_stem(path)?;
+
+    let hack = args.is_present("hack");
+    let output = args.value_of("output").map_or_else(
+        || file_name.with_extension(if hack { "hack" } else { "asm" }),
+        |output| output.into(),
+    );
+    let mut file = BufWriter::new(File::create(&output)?);
+    if hack {
+        let (instructions, symbols) = program.assemble();
+        file.write_all(hack::write(&instructions).as_bytes())?;
+        if args.is_present("symbols") {
+            let mut file = BufWriter::new(File::create(output.with_extension("sym"))?);
+            file.write_all(symbols.symbol_file().to_string().as_bytes())?;
+        }
+    } else {
+        file.write_all(program.to_string().as_bytes())?;
+    }
+    if args.is_present("map") {
+        let mut file = BufWriter::new(File::create(output.with_extension("map"))?);
+        file.write_all(translation.source_map.to_string().as_bytes())?;
+    }
+
+    Ok(())
+}
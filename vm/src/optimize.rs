@@ -0,0 +1,162 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
+
+use crate::{command::Command, loader::Module};
+
+// Splits a module into the commands before its first function and one range
+// per function.
+fn functions(module: &Module) -> Vec<(Option<&str>, Range<usize>)> {
+    let mut starts = module
+        .commands
+        .iter()
+        .enumerate()
+        .filter_map(|(i, command)| match command {
+            Command::Function(name, _) => Some((i, Some(name.as_str()))),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if starts.first().is_none_or(|&(i, _)| i > 0) {
+        starts.insert(0, (0, None));
+    }
+    starts
+        .iter()
+        .enumerate()
+        .map(|(n, &(start, name))| {
+            let end = starts
+                .get(n + 1)
+                .map_or(module.commands.len(), |&(end, _)| end);
+            (name, start..end)
+        })
+        .collect()
+}
+
+// Drops the functions that no call can reach. Execution starts at Sys.init
+// when bootstrapped and at the first command otherwise, from where commands
+// outside functions fall through into the function after them.
+pub fn remove_unreachable(modules: &[Module], bootstrap: bool) -> Vec<Module> {
+    let mut callees = HashMap::new();
+    let mut pending = vec![];
+    let mut entered = !bootstrap;
+    for module in modules {
+        for (name, range) in functions(module) {
+            let calls = module.commands[range]
+                .iter()
+                .filter_map(|command| match command {
+                    Command::Call(callee, _) => Some(callee.as_str()),
+                    _ => None,
+                });
+            match name {
+                Some(name) => {
+                    if entered {
+                        pending.push(name);
+                    }
+                    callees.insert(name, calls.collect::<Vec<_>>());
+                    entered = false;
+                }
+                None => {
+                    pending.extend(calls);
+                    entered = !bootstrap;
+                }
+            }
+        }
+    }
+    if bootstrap {
+        pending.push("Sys.init");
+    }
+
+    let mut reachable = HashSet::new();
+    while let Some(name) = pending.pop() {
+        if reachable.insert(name) {
+            pending.extend(callees.get(name).into_iter().flatten());
+        }
+    }
+    modules
+        .iter()
+        .map(|module| {
+            let ranges = functions(module)
+                .into_iter()
+                .filter(|(name, _)| name.is_none_or(|name| reachable.contains(name)))
+                .map(|(_, range)| range)
+                .collect::<Vec<_>>();
+            Module {
+                name: module.name.clone(),
+                commands: ranges
+                    .iter()
+                    .flat_map(|range| module.commands[range.clone()].iter().cloned())
+                    .collect(),
+                lines: ranges
+                    .iter()
+                    .flat_map(|range| module.lines[range.clone()].iter().copied())
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unreachable_functions_are_removed() {
+        let module = |name: &str, lines: &[&str]| Module::parse(name, lines).unwrap();
+        let modules = [
+            module(
+                "Main",
+                &[
+                    "function Main.main 0",
+                    "call Main.used 0",
+                    "return",
+                    "function Main.used 0",
+                    "call Main.used 0",
+                    "return",
+                    "function Main.unused 0",
+                    "call Sys.init 0",
+                    "return",
+                ],
+            ),
+            module(
+                "Sys",
+                &["function Sys.init 0", "call Main.main 0", "return"],
+            ),
+        ];
+        let names = |modules: Vec<Module>| {
+            modules
+                .iter()
+                .flat_map(|module| module.commands.iter())
+                .filter_map(|command| match command {
+                    Command::Function(name, _) => Some(name.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(remove_unreachable(&modules, true)),
+            ["Main.main", "Main.used", "Sys.init"]
+        );
+        assert_eq!(
+            names(remove_unreachable(&modules, false)),
+            ["Main.main", "Main.used"]
+        );
+    }
+
+    #[test]
+    fn top_level_commands_fall_through_into_the_next_function() {
+        let module = Module::parse(
+            "Main",
+            &[
+                "push constant 1",
+                "function Main.entered 0",
+                "return",
+                "function Main.skipped 0",
+                "return",
+            ],
+        )
+        .unwrap();
+        let commands = &remove_unreachable(&[module], false)[0].commands;
+        assert_eq!(commands.len(), 3);
+        assert!(matches!(&commands[1], Command::Function(name, _) if name == "Main.entered"));
+    }
+}
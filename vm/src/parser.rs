@@ -0,0 +1,195 @@
+use thiserror::Error;
+
+use crate::command::{Arithmetic, Command, Segment};
+
+#[derive(Debug, Error)]
+pub enum ParseError {
+    #[error("Line {0}: Unknown command \"{1}\"")]
+    UnknownCommand(usize, String),
+
+    #[error("Line {0}: Unknown segment \"{1}\"")]
+    UnknownSegment(usize, String),
+
+    #[error("Line {0}: \"{1}\" is not a valid index")]
+    InvalidIndex(usize, String),
+
+    #[error("Line {0}: Cannot pop into the constant segment")]
+    PopConstant(usize),
+
+    #[error("Line {0}: {1} is an invalid symbol")]
+    InvalidSymbol(usize, String),
+
+    #[error("Line {0}: Syntax error: {1}")]
+    InvalidSyntax(usize, String),
+}
+pub type Result<T> = std::result::Result<T, ParseError>;
+
+pub fn parse<S: AsRef<str>>(lines: &[S]) -> Result<Vec<Command>> {
+    Ok(parse_numbered(lines)?
+        .into_iter()
+        .map(|(_, command)| command)
+        .collect())
+}
+
+// Pairs each command with its line number.
+pub fn parse_numbered<S: AsRef<str>>(lines: &[S]) -> Result<Vec<(usize, Command)>> {
+    lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.as_ref().split("//").next().unwrap().trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(line_number, line)| Ok((line_number, parse_command(line_number, line)?)))
+        .collect()
+}
+
+// Function and label names.
+pub(crate) fn is_symbol(symbol: &str) -> bool {
+    !symbol.is_empty()
+        && symbol
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == ':')
+        && !symbol.starts_with(|c: char| c.is_ascii_digit())
+}
+
+fn parse_command(line_number: usize, line: &str) -> Result<Command> {
+    let words = line.split_whitespace().collect::<Vec<_>>();
+    let syntax_error = || ParseError::InvalidSyntax(line_number, line.to_string());
+    let symbol = |symbol: &str| {
+        if is_symbol(symbol) {
+            Ok(symbol.to_string())
+        } else {
+            Err(ParseError::InvalidSymbol(line_number, symbol.to_string()))
+        }
+    };
+    let count = |count: &str| {
+        count
+            .parse::<u16>()
+            .map_err(|_| ParseError::InvalidIndex(line_number, count.to_string()))
+    };
+    match words.as_slice() {
+        ["return"] => Ok(Command::Return),
+        ["label", label] => Ok(Command::Label(symbol(label)?)),
+        ["goto", label] => Ok(Command::Goto(symbol(label)?)),
+        ["if-goto", label] => Ok(Command::IfGoto(symbol(label)?)),
+        ["function", name, locals] => Ok(Command::Function(symbol(name)?, count(locals)?)),
+        ["call", name, arguments] => Ok(Command::Call(symbol(name)?, count(arguments)?)),
+        [name] => Arithmetic::from_name(name)
+            .map(Command::Arithmetic)
+            .ok_or_else(|| ParseError::UnknownCommand(line_number, name.to_string())),
+        [command @ ("push" | "pop"), segment, index] => {
+            let segment = Segment::from_name(segment)
+                .ok_or_else(|| ParseError::UnknownSegment(line_number, segment.to_string()))?;
+            let index = index
+                .parse::<u16>()
+                .ok()
+                .filter(|&index| segment.size().is_none_or(|size| index < size))
+                .ok_or_else(|| ParseError::InvalidIndex(line_number, index.to_string()))?;
+            if *command == "push" {
+                Ok(Command::Push(segment, index))
+            } else if segment == Segment::Constant {
+                Err(ParseError::PopConstant(line_number))
+            } else {
+                Ok(Command::Pop(segment, index))
+            }
+        }
+        [name, ..]
+            if matches!(
+                *name,
+                "push" | "pop" | "label" | "goto" | "if-goto" | "function" | "call" | "return"
+            ) || Arithmetic::from_name(name).is_some() =>
+        {
+            Err(syntax_error())
+        }
+        [name, ..] => Err(ParseError::UnknownCommand(line_number, name.to_string())),
+        [] => Err(syntax_error()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parser_reads_memory_access_and_arithmetic() {
+        let lines = [
+            "// SimpleAdd",
+            "push constant 7",
+            "  push local 2 // second local",
+            "",
+            "add",
+            "pop temp 7",
+        ];
+        assert_eq!(
+            parse(&lines).unwrap(),
+            [
+                Command::Push(Segment::Constant, 7),
+                Command::Push(Segment::Local, 2),
+                Command::Arithmetic(Arithmetic::Add),
+                Command::Pop(Segment::Temp, 7),
+            ]
+        );
+    }
+
+    #[test]
+    fn parser_reads_program_flow_and_function_commands() {
+        let lines = [
+            "function Main.fibonacci 0",
+            "label IF_TRUE",
+            "if-goto IF_TRUE",
+            "goto END:1",
+            "call Math.multiply 2",
+            "return",
+        ];
+        assert_eq!(
+            parse(&lines).unwrap(),
+            [
+                Command::Function("Main.fibonacci".to_string(), 0),
+                Command::Label("IF_TRUE".to_string()),
+                Command::IfGoto("IF_TRUE".to_string()),
+                Command::Goto("END:1".to_string()),
+                Command::Call("Math.multiply".to_string(), 2),
+                Command::Return,
+            ]
+        );
+        assert!(matches!(
+            parse(&["label 1ABC"]),
+            Err(ParseError::InvalidSymbol(1, _))
+        ));
+        assert!(matches!(
+            parse(&["goto A$B"]),
+            Err(ParseError::InvalidSymbol(1, _))
+        ));
+        assert!(matches!(
+            parse(&["call Main.main"]),
+            Err(ParseError::InvalidSyntax(1, _))
+        ));
+    }
+
+    #[test]
+    fn parser_rejects_invalid_commands() {
+        assert!(matches!(
+            parse(&["pop constant 1"]),
+            Err(ParseError::PopConstant(1))
+        ));
+        assert!(matches!(
+            parse(&["push temp 8"]),
+            Err(ParseError::InvalidIndex(1, _))
+        ));
+        assert!(matches!(
+            parse(&["push constant 32768"]),
+            Err(ParseError::InvalidIndex(1, _))
+        ));
+        assert!(matches!(
+            parse(&["", "push heap 1"]),
+            Err(ParseError::UnknownSegment(2, _))
+        ));
+        assert!(matches!(
+            parse(&["mul"]),
+            Err(ParseError::UnknownCommand(1, _))
+        ));
+        assert!(matches!(
+            parse(&["add 1"]),
+            Err(ParseError::InvalidSyntax(1, _))
+        ));
+    }
+}
@@ -0,0 +1,150 @@
+use std::{fmt, str::FromStr};
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SourceMapError {
+    #[error("Line {0}: Invalid entry \"{1}\"")]
+    InvalidEntry(usize, String),
+
+    #[error("Line {0}: Addresses must increase")]
+    Unordered(usize),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Source {
+    pub file: String,
+    pub line: usize,
+    pub function: Option<String>,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)?;
+        if let Some(function) = &self.function {
+            write!(f, " ({})", function)?;
+        }
+        Ok(())
+    }
+}
+
+// Maps ROM addresses to the VM commands they were generated from. Each entry
+// covers the addresses up to the next one.
+//
+// The text format has one tab separated entry per line:
+// `<address> <file>:<line> <function or ->`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    entries: Vec<(u16, Source)>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self { entries: vec![] }
+    }
+
+    pub fn entries(&self) -> &[(u16, Source)] {
+        &self.entries
+    }
+
+    // An entry at the address of the last one replaces it, since that one
+    // covers no addresses.
+    pub fn push(&mut self, address: u16, source: Source) {
+        match self.entries.last_mut() {
+            Some((last, previous)) if *last == address => *previous = source,
+            _ => self.entries.push((address, source)),
+        }
+    }
+
+    pub fn get(&self, address: u16) -> Option<&Source> {
+        let index = self
+            .entries
+            .partition_point(|(start, _)| *start <= address)
+            .checked_sub(1)?;
+        Some(&self.entries[index].1)
+    }
+}
+
+impl fmt::Display for SourceMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (address, source) in &self.entries {
+            writeln!(
+                f,
+                "{}\t{}:{}\t{}",
+                address,
+                source.file,
+                source.line,
+                source.function.as_deref().unwrap_or("-")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SourceMap {
+    type Err = SourceMapError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut map = SourceMap::new();
+        for (i, line) in text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.is_empty())
+        {
+            let invalid = || SourceMapError::InvalidEntry(i + 1, line.to_string());
+            let fields = line.split('\t').collect::<Vec<_>>();
+            let (address, location, function) = match fields.as_slice() {
+                [address, location, function] => (address, location, function),
+                _ => return Err(invalid()),
+            };
+            let address = address.parse::<u16>().map_err(|_| invalid())?;
+            let (file, line) = location.rsplit_once(':').ok_or_else(invalid)?;
+            let line = line.parse::<usize>().map_err(|_| invalid())?;
+            if map.entries.last().is_some_and(|(last, _)| *last >= address) {
+                return Err(SourceMapError::Unordered(i + 1));
+            }
+            let function = Some(function.to_string()).filter(|function| function != "-");
+            map.push(
+                address,
+                Source {
+                    file: file.to_string(),
+                    line,
+                    function,
+                },
+            );
+        }
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn source_maps_round_trip_through_text() {
+        let source = |file: &str, line, function: Option<&str>| Source {
+            file: file.to_string(),
+            line,
+            function: function.map(str::to_string),
+        };
+        let mut map = SourceMap::new();
+        map.push(0, source("(bootstrap)", 0, None));
+        map.push(4, source("Main.vm", 1, Some("Main.main")));
+        map.push(4, source("Main.vm", 2, Some("Main.main")));
+        map.push(10, source("Main.vm", 3, Some("Main.main")));
+        assert_eq!(map.entries().len(), 3);
+        assert_eq!(map.get(3), Some(&source("(bootstrap)", 0, None)));
+        assert_eq!(map.get(9).unwrap().line, 2);
+        assert_eq!(map.get(1000).unwrap().line, 3);
+        assert_eq!(map.to_string().parse::<SourceMap>().unwrap(), map);
+        assert!(matches!(
+            "4\tMain.vm:1\t-\n2\tMain.vm:2\t-".parse::<SourceMap>(),
+            Err(SourceMapError::Unordered(2))
+        ));
+        assert!(matches!(
+            "4\tMain.vm\t-".parse::<SourceMap>(),
+            Err(SourceMapError::InvalidEntry(1, _))
+        ));
+    }
+}
@@ -0,0 +1,975 @@
+use std::collections::HashMap;
+
+use assembler::{
+    instruction::{Comp, Dest, Jump},
+    program::Program,
+};
+
+use crate::{
+    command::{Arithmetic, Command, Segment},
+    source_map::{Source, SourceMap},
+};
+
+const TEMP: u16 = 5;
+const POINTER: u16 = 3;
+
+// Subroutines shared by every call site when optimizing for size.
+const RUNTIME: [&str; 5] = ["$CALL", "$RETURN", "$EQ", "$GT", "$LT"];
+
+pub struct Translation {
+    pub program: Program,
+    // ROM words per function in order of appearance. Code outside functions
+    // is counted under its file.
+    pub sizes: Vec<(String, usize)>,
+    pub source_map: SourceMap,
+}
+
+pub struct Translator {
+    program: Program,
+    file: String,
+    function: Option<String>,
+    labels: usize,
+    calls: HashMap<String, usize>,
+    optimize: bool,
+    runtime: [bool; 5],
+    sizes: Vec<(String, usize)>,
+    source_map: SourceMap,
+}
+
+impl Default for Translator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Translator {
+    pub fn new() -> Self {
+        Self {
+            program: Program::new(),
+            file: String::new(),
+            function: None,
+            labels: 0,
+            calls: HashMap::new(),
+            optimize: false,
+            runtime: [false; 5],
+            sizes: vec![],
+            source_map: SourceMap::new(),
+        }
+    }
+
+    // Trades speed for ROM space: calls, returns and comparisons jump to
+    // shared subroutines, and some command pairs are fused.
+    pub fn optimizing() -> Self {
+        Self {
+            optimize: true,
+            ..Self::new()
+        }
+    }
+
+    // Sets SP to 256 and calls Sys.init.
+    pub fn bootstrap(&mut self) {
+        self.map("(bootstrap)", 0, None);
+        self.program.comment("bootstrap");
+        self.a_value(256);
+        self.c(Dest::D, Comp::A);
+        self.a("SP");
+        self.c(Dest::M, Comp::D);
+        self.program.comment("call Sys.init 0");
+        self.call("Sys.init", 0, "Bootstrap$ret.0");
+        self.count("(bootstrap)", self.program.len());
+    }
+
+    pub fn finish(self) -> Program {
+        self.finish_with_details().program
+    }
+
+    pub fn finish_with_details(mut self) -> Translation {
+        let start = self.program.len();
+        for (i, name) in RUNTIME.iter().enumerate() {
+            if self.runtime[i] {
+                self.map("(runtime)", 0, Some(name.to_string()));
+                self.program
+                    .comment(&format!("shared {}", &name[1..].to_lowercase()));
+                self.program.label(name);
+                self.subroutine(name);
+            }
+        }
+        let size = self.program.len() - start;
+        if size > 0 {
+            self.count("(runtime)", size);
+        }
+        Translation {
+            program: self.program,
+            sizes: self.sizes,
+            source_map: self.source_map,
+        }
+    }
+
+    fn map(&mut self, file: &str, line: usize, function: Option<String>) {
+        let source = Source {
+            file: file.to_string(),
+            line,
+            function,
+        };
+        self.source_map.push(self.program.len() as u16, source);
+    }
+
+    fn count(&mut self, name: &str, size: usize) {
+        match self.sizes.iter_mut().find(|(other, _)| other == name) {
+            Some((_, total)) => *total += size,
+            None => self.sizes.push((name.to_string(), size)),
+        }
+    }
+
+    // `file` is the name of the .vm file without its extension, which
+    // prefixes the symbols of its static segment.
+    pub fn translate(&mut self, file: &str, commands: &[Command]) {
+        self.translate_lines(file, commands, &[]);
+    }
+
+    // `lines` holds the line number of each command for the source map.
+    pub fn translate_lines(&mut self, file: &str, commands: &[Command], lines: &[usize]) {
+        self.file = file.to_string();
+        self.function = None;
+        let mut i = 0;
+        while i < commands.len() {
+            let first = i;
+            let start = self.program.len();
+            self.program.comment(&commands[i].to_string());
+            if self.optimize && i + 1 < commands.len() && self.fuse(&commands[i], &commands[i + 1])
+            {
+                i += 2;
+            } else {
+                self.command(&commands[i]);
+                i += 1;
+            }
+            let name = self.function.clone().unwrap_or_else(|| self.file.clone());
+            self.count(&name, self.program.len() - start);
+            if self.program.len() > start {
+                let source = Source {
+                    file: format!("{}.vm", file),
+                    line: lines.get(first).copied().unwrap_or(0),
+                    function: self.function.clone(),
+                };
+                self.source_map.push(start as u16, source);
+            }
+        }
+    }
+
+    // Translates two commands at once without going through the stack.
+    fn fuse(&mut self, first: &Command, second: &Command) -> bool {
+        match (first, second) {
+            (
+                Command::Push(Segment::Constant, 1),
+                Command::Arithmetic(op @ (Arithmetic::Add | Arithmetic::Sub)),
+            ) => {
+                self.program.comment(&second.to_string());
+                self.a("SP");
+                self.c(Dest::A, Comp::MMinusOne);
+                let comp = if *op == Arithmetic::Add {
+                    Comp::MPlusOne
+                } else {
+                    Comp::MMinusOne
+                };
+                self.c(Dest::M, comp);
+                true
+            }
+            (
+                Command::Push(Segment::Constant, value),
+                Command::Arithmetic(op @ (Arithmetic::Add | Arithmetic::Sub)),
+            ) => {
+                self.program.comment(&second.to_string());
+                self.a_value(*value);
+                self.c(Dest::D, Comp::A);
+                self.a("SP");
+                self.c(Dest::A, Comp::MMinusOne);
+                let comp = if *op == Arithmetic::Add {
+                    Comp::DPlusM
+                } else {
+                    Comp::MMinusD
+                };
+                self.c(Dest::M, comp);
+                true
+            }
+            (Command::Push(from, index), Command::Pop(to, to_index)) => {
+                self.program.comment(&second.to_string());
+                let indirect = Self::base(*to).filter(|_| *to_index > 1);
+                if let Some(base) = indirect {
+                    self.segment_address(base, *to_index);
+                    self.c(Dest::D, Comp::A);
+                    self.a("R13");
+                    self.c(Dest::M, Comp::D);
+                }
+                self.load_d(*from, *index);
+                match indirect {
+                    Some(_) => {
+                        self.a("R13");
+                        self.c(Dest::A, Comp::M);
+                    }
+                    None => self.address(*to, *to_index),
+                }
+                self.c(Dest::M, Comp::D);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn command(&mut self, command: &Command) {
+        match command {
+            Command::Push(segment, index) => self.push(*segment, *index),
+            Command::Pop(segment, index) => self.pop(*segment, *index),
+            Command::Arithmetic(op) => self.arithmetic(*op),
+            Command::Label(label) => {
+                let label = self.mangle(label);
+                self.program.label(&label);
+            }
+            Command::Goto(label) => {
+                let label = self.mangle(label);
+                self.a(&label);
+                self.jump(Comp::Zero, Jump::JMP);
+            }
+            Command::IfGoto(label) => {
+                let label = self.mangle(label);
+                self.pop_d();
+                self.a(&label);
+                self.jump(Comp::D, Jump::JNE);
+            }
+            Command::Function(name, locals) => self.function(name, *locals),
+            Command::Call(name, arguments) => {
+                let caller = self.function.clone().unwrap_or_else(|| self.file.clone());
+                let count = self.calls.entry(caller.clone()).or_insert(0);
+                let label = format!("{}$ret.{}", caller, count);
+                *count += 1;
+                self.call(name, *arguments, &label);
+            }
+            Command::Return => self.return_(),
+        }
+    }
+
+    // Labels are scoped by the function they appear in.
+    fn mangle(&self, label: &str) -> String {
+        match &self.function {
+            Some(function) => format!("{}${}", function, label),
+            None => label.to_string(),
+        }
+    }
+
+    fn a(&mut self, symbol: &str) {
+        self.program.a(symbol);
+    }
+
+    fn a_value(&mut self, value: u16) {
+        self.program.a_value(value);
+    }
+
+    fn c(&mut self, dest: Dest, comp: Comp) {
+        self.program.c(Some(dest), comp, None);
+    }
+
+    fn jump(&mut self, comp: Comp, jump: Jump) {
+        self.program.c(None, comp, Some(jump));
+    }
+
+    fn base(segment: Segment) -> Option<&'static str> {
+        match segment {
+            Segment::Local => Some("LCL"),
+            Segment::Argument => Some("ARG"),
+            Segment::This => Some("THIS"),
+            Segment::That => Some("THAT"),
+            _ => None,
+        }
+    }
+
+    fn fixed_address(&mut self, segment: Segment, index: u16) {
+        match segment {
+            Segment::Static => {
+                let symbol = format!("{}.{}", self.file, index);
+                self.a(&symbol);
+            }
+            Segment::Temp => self.a_value(TEMP + index),
+            Segment::Pointer => self.a_value(POINTER + index),
+            _ => unreachable!(),
+        }
+    }
+
+    // Points A at the segment entry.
+    fn segment_address(&mut self, base: &str, index: u16) {
+        match index {
+            0 => {
+                self.a(base);
+                self.c(Dest::A, Comp::M);
+            }
+            1 => {
+                self.a(base);
+                self.c(Dest::A, Comp::MPlusOne);
+            }
+            _ => {
+                self.a_value(index);
+                self.c(Dest::D, Comp::A);
+                self.a(base);
+                self.c(Dest::A, Comp::DPlusM);
+            }
+        }
+    }
+
+    fn push_d(&mut self) {
+        self.a("SP");
+        self.c(Dest::AM, Comp::MPlusOne);
+        self.c(Dest::A, Comp::AMinusOne);
+        self.c(Dest::M, Comp::D);
+    }
+
+    fn pop_d(&mut self) {
+        self.a("SP");
+        self.c(Dest::AM, Comp::MMinusOne);
+        self.c(Dest::D, Comp::M);
+    }
+
+    fn load_d(&mut self, segment: Segment, index: u16) {
+        match segment {
+            Segment::Constant => {
+                self.a_value(index);
+                self.c(Dest::D, Comp::A);
+            }
+            Segment::Static | Segment::Temp | Segment::Pointer => {
+                self.fixed_address(segment, index);
+                self.c(Dest::D, Comp::M);
+            }
+            _ => {
+                self.segment_address(Self::base(segment).unwrap(), index);
+                self.c(Dest::D, Comp::M);
+            }
+        }
+    }
+
+    // Points A at a segment entry without touching D, which is only possible
+    // for fixed segments and the first two entries of the others.
+    fn address(&mut self, segment: Segment, index: u16) {
+        match segment {
+            Segment::Static | Segment::Temp | Segment::Pointer => {
+                self.fixed_address(segment, index)
+            }
+            _ => self.segment_address(Self::base(segment).unwrap(), index),
+        }
+    }
+
+    fn push(&mut self, segment: Segment, index: u16) {
+        self.load_d(segment, index);
+        self.push_d();
+    }
+
+    fn pop(&mut self, segment: Segment, index: u16) {
+        match segment {
+            Segment::Constant => unreachable!(),
+            Segment::Static | Segment::Temp | Segment::Pointer => {
+                self.pop_d();
+                self.address(segment, index);
+                self.c(Dest::M, Comp::D);
+            }
+            _ if index <= 1 => {
+                self.pop_d();
+                self.address(segment, index);
+                self.c(Dest::M, Comp::D);
+            }
+            _ => {
+                self.segment_address(Self::base(segment).unwrap(), index);
+                self.c(Dest::D, Comp::A);
+                self.a("R13");
+                self.c(Dest::M, Comp::D);
+                self.pop_d();
+                self.a("R13");
+                self.c(Dest::A, Comp::M);
+                self.c(Dest::M, Comp::D);
+            }
+        }
+    }
+
+    fn function(&mut self, name: &str, locals: u16) {
+        self.function = Some(name.to_string());
+        self.program.label(name);
+        if locals > 0 {
+            self.c(Dest::D, Comp::Zero);
+        }
+        for _ in 0..locals {
+            self.push_d();
+        }
+    }
+
+    fn call(&mut self, function: &str, arguments: u16, return_label: &str) {
+        if self.optimize {
+            // $CALL takes the return address in D, the frame offset of ARG
+            // in R13 and the callee in R14.
+            self.a_value(arguments + 5);
+            self.c(Dest::D, Comp::A);
+            self.a("R13");
+            self.c(Dest::M, Comp::D);
+            self.a(function);
+            self.c(Dest::D, Comp::A);
+            self.a("R14");
+            self.c(Dest::M, Comp::D);
+            self.a(return_label);
+            self.c(Dest::D, Comp::A);
+            self.shared("$CALL");
+            self.program.label(return_label);
+            return;
+        }
+        self.a(return_label);
+        self.c(Dest::D, Comp::A);
+        self.push_d();
+        self.push_frame();
+        self.a("SP");
+        self.c(Dest::D, Comp::M);
+        self.a_value(arguments + 5);
+        self.c(Dest::D, Comp::DMinusA);
+        self.a("ARG");
+        self.c(Dest::M, Comp::D);
+        self.set_lcl();
+        self.a(function);
+        self.jump(Comp::Zero, Jump::JMP);
+        self.program.label(return_label);
+    }
+
+    fn push_frame(&mut self) {
+        for base in &["LCL", "ARG", "THIS", "THAT"] {
+            self.a(base);
+            self.c(Dest::D, Comp::M);
+            self.push_d();
+        }
+    }
+
+    fn set_lcl(&mut self) {
+        self.a("SP");
+        self.c(Dest::D, Comp::M);
+        self.a("LCL");
+        self.c(Dest::M, Comp::D);
+    }
+
+    fn shared(&mut self, name: &str) {
+        let index = RUNTIME.iter().position(|other| *other == name).unwrap();
+        self.runtime[index] = true;
+        self.a(name);
+        self.jump(Comp::Zero, Jump::JMP);
+    }
+
+    fn subroutine(&mut self, name: &str) {
+        match name {
+            "$CALL" => {
+                self.push_d();
+                self.push_frame();
+                self.a("SP");
+                self.c(Dest::D, Comp::M);
+                self.a("R13");
+                self.c(Dest::D, Comp::DMinusM);
+                self.a("ARG");
+                self.c(Dest::M, Comp::D);
+                self.set_lcl();
+                self.a("R14");
+                self.c(Dest::A, Comp::M);
+                self.jump(Comp::Zero, Jump::JMP);
+            }
+            "$RETURN" => self.return_inline(),
+            _ => {
+                // The return address arrives in D.
+                self.a("R15");
+                self.c(Dest::M, Comp::D);
+                self.pop_d();
+                self.c(Dest::A, Comp::AMinusOne);
+                let jump = match name {
+                    "$EQ" => Jump::JEQ,
+                    "$GT" => Jump::JGT,
+                    _ => Jump::JLT,
+                };
+                let label = format!("{}.TRUE", name);
+                self.compare(jump, &label);
+                self.program.label(&label);
+                self.a("R15");
+                self.c(Dest::A, Comp::M);
+                self.jump(Comp::Zero, Jump::JMP);
+            }
+        }
+    }
+
+    // The frame pointer lives in R13 and the return address in R14, since
+    // the return value may overwrite the latter when there are no arguments.
+    fn return_(&mut self) {
+        if self.optimize {
+            self.shared("$RETURN");
+        } else {
+            self.return_inline();
+        }
+    }
+
+    fn return_inline(&mut self) {
+        self.a("LCL");
+        self.c(Dest::D, Comp::M);
+        self.a("R13");
+        self.c(Dest::M, Comp::D);
+        self.a_value(5);
+        self.c(Dest::A, Comp::DMinusA);
+        self.c(Dest::D, Comp::M);
+        self.a("R14");
+        self.c(Dest::M, Comp::D);
+        self.pop_d();
+        self.a("ARG");
+        self.c(Dest::A, Comp::M);
+        self.c(Dest::M, Comp::D);
+        self.a("ARG");
+        self.c(Dest::D, Comp::MPlusOne);
+        self.a("SP");
+        self.c(Dest::M, Comp::D);
+        for base in &["THAT", "THIS", "ARG", "LCL"] {
+            self.a("R13");
+            self.c(Dest::AM, Comp::MMinusOne);
+            self.c(Dest::D, Comp::M);
+            self.a(base);
+            self.c(Dest::M, Comp::D);
+        }
+        self.a("R14");
+        self.c(Dest::A, Comp::M);
+        self.jump(Comp::Zero, Jump::JMP);
+    }
+
+    fn arithmetic(&mut self, op: Arithmetic) {
+        if op.is_unary() {
+            self.a("SP");
+            self.c(Dest::A, Comp::MMinusOne);
+            let comp = match op {
+                Arithmetic::Neg => Comp::MinusM,
+                _ => Comp::NotM,
+            };
+            self.c(Dest::M, comp);
+            return;
+        }
+        let comparison = matches!(op, Arithmetic::Eq | Arithmetic::Gt | Arithmetic::Lt);
+        if !(self.optimize && comparison) {
+            self.pop_d();
+            self.c(Dest::A, Comp::AMinusOne);
+        }
+        let jump = match op {
+            Arithmetic::Add => return self.c(Dest::M, Comp::DPlusM),
+            Arithmetic::Sub => return self.c(Dest::M, Comp::MMinusD),
+            Arithmetic::And => return self.c(Dest::M, Comp::DAndM),
+            Arithmetic::Or => return self.c(Dest::M, Comp::DOrM),
+            Arithmetic::Eq => Jump::JEQ,
+            Arithmetic::Gt => Jump::JGT,
+            Arithmetic::Lt => Jump::JLT,
+            Arithmetic::Neg | Arithmetic::Not => unreachable!(),
+        };
+        // VM labels cannot contain "$", so this one clashes with none of them.
+        let label = format!(
+            "${}.{}.{}",
+            self.file,
+            op.name().to_uppercase(),
+            self.labels
+        );
+        self.labels += 1;
+        if self.optimize {
+            self.a(&label);
+            self.c(Dest::D, Comp::A);
+            self.shared(&format!("${}", op.name().to_uppercase()));
+        } else {
+            self.compare(jump, &label);
+        }
+        self.program.label(&label);
+    }
+
+    // Assumes true, and corrects the result when the comparison fails. The
+    // operands are at the top of the stack, with y in D and A pointing at x.
+    // Subtracting operands of opposite signs can overflow, so lt and gt only
+    // subtract when the signs match and otherwise decide on the sign of y.
+    fn compare(&mut self, jump: Jump, label: &str) {
+        if jump == Jump::JEQ {
+            self.c(Dest::D, Comp::MMinusD);
+            self.c(Dest::M, Comp::MinusOne);
+            self.a(label);
+            self.jump(Comp::D, jump);
+            self.a("SP");
+            self.c(Dest::A, Comp::MMinusOne);
+            self.c(Dest::M, Comp::Zero);
+            return;
+        }
+        // x > y exactly when y is the negative one.
+        let differing = match jump {
+            Jump::JGT => Jump::JLT,
+            _ => Jump::JGE,
+        };
+        let negative = format!("{}.NEGATIVE", label);
+        let same = format!("{}.SAME", label);
+        let differ = format!("{}.DIFFER", label);
+        let false_ = format!("{}.FALSE", label);
+        self.a("R13");
+        self.c(Dest::M, Comp::D);
+        self.a("SP");
+        self.c(Dest::A, Comp::MMinusOne);
+        self.c(Dest::D, Comp::M);
+        self.a(&negative);
+        self.jump(Comp::D, Jump::JLT);
+        self.a("R13");
+        self.c(Dest::D, Comp::M);
+        self.a(&differ);
+        self.jump(Comp::D, Jump::JLT);
+        self.a(&same);
+        self.jump(Comp::Zero, Jump::JMP);
+        self.program.label(&negative);
+        self.a("R13");
+        self.c(Dest::D, Comp::M);
+        self.a(&differ);
+        self.jump(Comp::D, Jump::JGE);
+        // D holds y on both paths.
+        self.program.label(&same);
+        self.a("SP");
+        self.c(Dest::A, Comp::MMinusOne);
+        self.c(Dest::D, Comp::MMinusD);
+        self.c(Dest::M, Comp::MinusOne);
+        self.a(label);
+        self.jump(Comp::D, jump);
+        self.a(&false_);
+        self.jump(Comp::Zero, Jump::JMP);
+        self.program.label(&differ);
+        self.a("SP");
+        self.c(Dest::A, Comp::MMinusOne);
+        self.c(Dest::M, Comp::MinusOne);
+        self.a(label);
+        self.jump(Comp::D, differing);
+        self.program.label(&false_);
+        self.a("SP");
+        self.c(Dest::A, Comp::MMinusOne);
+        self.c(Dest::M, Comp::Zero);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use assembler::instruction::Instruction;
+
+    fn execute(program: Program, ram: &mut [i16], steps: usize) {
+        let (instructions, _) = program.assemble();
+        let mut a: u16 = 0;
+        let mut d: u16 = 0;
+        let mut pc = 0;
+        for _ in 0..steps {
+            if pc >= instructions.len() {
+                break;
+            }
+            match &instructions[pc] {
+                Instruction::A { value } => {
+                    a = *value;
+                    pc += 1;
+                }
+                Instruction::C { comp, dest, jump } => {
+                    let m = ram[a as usize] as u16;
+                    let out = match comp {
+                        Comp::Zero => 0,
+                        Comp::One => 1,
+                        Comp::MinusOne => 0xFFFF,
+                        Comp::A => a,
+                        Comp::D => d,
+                        Comp::M => m,
+                        Comp::MinusM => m.wrapping_neg(),
+                        Comp::NotM => !m,
+                        Comp::MPlusOne => m.wrapping_add(1),
+                        Comp::MMinusOne => m.wrapping_sub(1),
+                        Comp::AMinusOne => a.wrapping_sub(1),
+                        Comp::DPlusM => d.wrapping_add(m),
+                        Comp::DMinusA => d.wrapping_sub(a),
+                        Comp::MMinusD => m.wrapping_sub(d),
+                        Comp::DMinusM => d.wrapping_sub(m),
+                        Comp::DAndM => d & m,
+                        Comp::DOrM => d | m,
+                        comp => panic!("unexpected {}", comp),
+                    };
+                    if dest.as_ref().is_some_and(|dest| dest.contains_m()) {
+                        ram[a as usize] = out as i16;
+                    }
+                    let old_a = a;
+                    if dest.as_ref().is_some_and(|dest| dest.contains_a()) {
+                        a = out;
+                    }
+                    if dest.as_ref().is_some_and(|dest| dest.contains_d()) {
+                        d = out;
+                    }
+                    let out = out as i16;
+                    let taken = match jump {
+                        None => false,
+                        Some(Jump::JEQ) => out == 0,
+                        Some(Jump::JGT) => out > 0,
+                        Some(Jump::JLT) => out < 0,
+                        Some(Jump::JGE) => out >= 0,
+                        Some(Jump::JNE) => out != 0,
+                        Some(Jump::JMP) => true,
+                        Some(jump) => panic!("unexpected {}", jump),
+                    };
+                    pc = if taken { old_a as usize } else { pc + 1 };
+                }
+            }
+        }
+    }
+
+    fn run(lines: &[&str], ram: &mut [i16]) {
+        let mut translator = Translator::new();
+        translator.translate("Test", &parse(lines).unwrap());
+        execute(translator.finish(), ram, 10_000);
+    }
+
+    #[test]
+    fn arithmetic_and_comparisons() {
+        let mut ram = [0; 512];
+        ram[0] = 256;
+        run(
+            &[
+                "push constant 7",
+                "push constant 8",
+                "add",
+                "push constant 20",
+                "sub",
+                "neg",
+                "push constant 5",
+                "push constant 5",
+                "eq",
+                "push constant 3",
+                "push constant 4",
+                "gt",
+                "push constant 3",
+                "push constant 4",
+                "lt",
+                "push constant 12",
+                "push constant 10",
+                "and",
+                "not",
+            ],
+            &mut ram,
+        );
+        assert_eq!(ram[0], 261);
+        assert_eq!(&ram[256..261], &[5, -1, 0, -1, !8]);
+    }
+
+    #[test]
+    fn comparisons_at_the_extremes_match_the_vm() {
+        let values: [i16; 6] = [-32768, -32767, -1, 0, 1, 32767];
+        for translator in &[Translator::new, Translator::optimizing] {
+            for op in &[Arithmetic::Eq, Arithmetic::Gt, Arithmetic::Lt] {
+                let mut lines = vec![];
+                for x in &values {
+                    for y in &values {
+                        for value in &[x, y] {
+                            if **value < 0 {
+                                lines.push(format!("push constant {}", -(**value as i32) - 1));
+                                lines.push("not".to_string());
+                            } else {
+                                lines.push(format!("push constant {}", value));
+                            }
+                        }
+                        lines.push(op.name().to_string());
+                    }
+                }
+                // Keeps execution out of the shared subroutines at the end.
+                lines.push("label END".to_string());
+                lines.push("goto END".to_string());
+                let lines: Vec<_> = lines.iter().map(String::as_str).collect();
+                let mut translator = translator();
+                translator.translate("Test", &parse(&lines).unwrap());
+                let mut ram = vec![0; 32768];
+                ram[0] = 256;
+                execute(translator.finish(), &mut ram, 100_000);
+                let mut expected = vec![];
+                for x in &values {
+                    for y in &values {
+                        expected.push(op.apply(*x as u16, *y as u16) as i16);
+                    }
+                }
+                assert_eq!(&ram[256..256 + expected.len()], &expected[..], "{}", op);
+            }
+        }
+    }
+
+    #[test]
+    fn segments_are_addressed_through_their_bases() {
+        let mut ram = [0; 4096];
+        ram[0] = 256;
+        ram[1] = 300;
+        ram[2] = 400;
+        ram[401] = 42;
+        run(
+            &[
+                "push argument 1",
+                "pop local 5",
+                "push constant 3000",
+                "pop pointer 1",
+                "push local 5",
+                "pop that 2",
+                "push constant 9",
+                "pop temp 6",
+                "push constant 11",
+                "pop static 0",
+                "push that 2",
+                "push temp 6",
+                "push static 0",
+            ],
+            &mut ram,
+        );
+        assert_eq!(ram[305], 42);
+        assert_eq!(ram[4], 3000);
+        assert_eq!(ram[3002], 42);
+        assert_eq!(ram[11], 9);
+        assert_eq!(ram[16], 11);
+        assert_eq!(&ram[256..259], &[42, 9, 11]);
+        assert_eq!(ram[0], 259);
+    }
+
+    #[test]
+    fn loops_branch_on_the_popped_value() {
+        let mut ram = [0; 512];
+        ram[0] = 256;
+        ram[1] = 300;
+        ram[2] = 400;
+        ram[400] = 5;
+        run(
+            &[
+                "push constant 0",
+                "pop local 0",
+                "label LOOP_START",
+                "push argument 0",
+                "push local 0",
+                "add",
+                "pop local 0",
+                "push argument 0",
+                "push constant 1",
+                "sub",
+                "pop argument 0",
+                "push argument 0",
+                "if-goto LOOP_START",
+                "push local 0",
+            ],
+            &mut ram,
+        );
+        assert_eq!(ram[256], 15);
+        assert_eq!(ram[0], 257);
+    }
+
+    fn fibonacci(mut translator: Translator) -> Translation {
+        let main = parse(&[
+            "function Main.fibonacci 0",
+            "push argument 0",
+            "push constant 2",
+            "lt",
+            "if-goto IF_TRUE",
+            "goto IF_FALSE",
+            "label IF_TRUE",
+            "push argument 0",
+            "return",
+            "label IF_FALSE",
+            "push argument 0",
+            "push constant 2",
+            "sub",
+            "call Main.fibonacci 1",
+            "push argument 0",
+            "push constant 1",
+            "sub",
+            "call Main.fibonacci 1",
+            "add",
+            "return",
+        ])
+        .unwrap();
+        let sys = parse(&[
+            "function Sys.init 1",
+            "push constant 10",
+            "call Main.fibonacci 1",
+            "pop static 0",
+            "label END",
+            "goto END",
+        ])
+        .unwrap();
+        translator.bootstrap();
+        translator.translate("Main", &main);
+        translator.translate_lines("Sys", &sys, &[1, 2, 3, 4, 5, 6]);
+        translator.finish_with_details()
+    }
+
+    #[test]
+    fn functions_call_and_return_across_files() {
+        let Translation {
+            program,
+            source_map,
+            ..
+        } = fibonacci(Translator::new());
+        let symbols = program.symbols();
+        let entry = |symbol| source_map.get(symbols.get(symbol).unwrap()).unwrap();
+        assert_eq!(entry("Sys.init").to_string(), "Sys.vm:1 (Sys.init)");
+        assert_eq!(entry("Sys.init$END").to_string(), "Sys.vm:6 (Sys.init)");
+        assert_eq!(source_map.get(0).unwrap().file, "(bootstrap)");
+        assert!(symbols.get("Main.fibonacci$IF_TRUE").is_some());
+        assert!(symbols.get("Main.fibonacci$ret.1").is_some());
+        assert!(symbols.get("Sys.init$END").is_some());
+        let mut ram = [0; 1024];
+        execute(program, &mut ram, 100_000);
+        assert_eq!(ram[16], 55);
+        assert_eq!(ram[0], 262);
+    }
+
+    #[test]
+    fn optimized_code_is_smaller_and_computes_the_same() {
+        let program = fibonacci(Translator::new()).program;
+        let unoptimized = fibonacci(Translator::new()).sizes;
+        let Translation {
+            program: optimized,
+            sizes,
+            source_map,
+        } = fibonacci(Translator::optimizing());
+        assert!(optimized.len() < program.len());
+        assert!(sizes[1].1 * 2 < unoptimized[1].1);
+        let runtime = optimized.symbols().get("$RETURN").unwrap();
+        assert_eq!(
+            source_map.get(runtime).unwrap().to_string(),
+            "(runtime):0 ($RETURN)"
+        );
+        assert_eq!(
+            sizes
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>(),
+            ["(bootstrap)", "Main.fibonacci", "Sys.init", "(runtime)"]
+        );
+        assert_eq!(
+            sizes.iter().map(|(_, size)| size).sum::<usize>(),
+            optimized.len()
+        );
+        let mut ram = [0; 1024];
+        execute(optimized, &mut ram, 100_000);
+        assert_eq!(ram[16], 55);
+        assert_eq!(ram[0], 262);
+    }
+
+    #[test]
+    fn fused_commands_skip_the_stack() {
+        let commands = parse(&[
+            "push constant 3",
+            "push constant 1",
+            "add",
+            "push constant 7",
+            "sub",
+            "pop local 3",
+            "push local 3",
+            "pop that 5",
+            "push constant 9",
+            "pop temp 1",
+            "push temp 1",
+            "pop local 0",
+        ])
+        .unwrap();
+        let mut translator = Translator::optimizing();
+        translator.translate("Test", &commands);
+        let mut ram = [0; 512];
+        ram[0] = 256;
+        ram[1] = 300;
+        ram[4] = 400;
+        execute(translator.finish(), &mut ram, 10_000);
+        assert_eq!(ram[0], 256);
+        assert_eq!(ram[303], -3);
+        assert_eq!(ram[405], -3);
+        assert_eq!(ram[6], 9);
+        assert_eq!(ram[300], 9);
+    }
+}